@@ -0,0 +1,50 @@
+use std::{env, process::Command};
+
+/// Runs a command and returns its trimmed output, `unknown` if it fails.
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map_or_else(|| "unknown".to_owned(), |output| output.trim().to_owned())
+}
+
+/// Selects the K-Bus implementation and records the build info.
+///
+/// The real K-Bus is only used on the ARM target with the `real-kbus` feature,
+/// the mock otherwise (e.g. for development and CI on x86_64).
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(mock_kbus)");
+
+    let real_kbus = env::var_os("CARGO_FEATURE_REAL_KBUS").is_some()
+        && env::var_os("CARGO_FEATURE_MOCK_KBUS").is_none()
+        && env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "arm");
+    if !real_kbus {
+        println!("cargo::rustc-cfg=mock_kbus");
+    }
+
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+    println!("cargo::rustc-env=BUILD_FEATURES={}", features.join(","));
+    println!(
+        "cargo::rustc-env=BUILD_GIT_HASH={}",
+        command_output("git", &["rev-parse", "--short", "HEAD"])
+    );
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    println!(
+        "cargo::rustc-env=BUILD_RUSTC_VERSION={}",
+        command_output(&rustc, &["--version"])
+    );
+    println!(
+        "cargo::rustc-env=BUILD_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+}
@@ -0,0 +1,136 @@
+//! # Bus Cycle Behavior
+//!
+//! By default a simulated bus cycle doesn't change the process images. To test
+//! higher-level logic like debouncing or counters realistically, a device can be
+//! configured with:
+//!
+//! - loopback, the input image mirrors the output image after every cycle
+//! - scripted input sequences, applied at given bus cycles
+//! - noise channels, input bits toggling randomly
+//! - a latency added to every bus cycle
+
+use std::{collections::VecDeque, time::Duration};
+
+use bitvec::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+/// A step of a scripted input sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStep {
+    /// Number of bus cycles to wait before applying the step, counted from the
+    /// previous step (or from the start of the script)
+    pub delay: u32,
+    /// Bit offset of the input in the input image
+    pub bit_offset: u32,
+    /// Value the input is set to
+    pub value: bool,
+}
+
+impl InputStep {
+    /// Creates a step setting the input `bit_offset` to `value` after `delay` bus cycles.
+    pub fn new(delay: u32, bit_offset: u32, value: bool) -> InputStep {
+        InputStep {
+            delay,
+            bit_offset,
+            value,
+        }
+    }
+}
+
+/// A channel toggling randomly.
+#[derive(Debug, Clone, Copy)]
+struct Noise {
+    bit_offset: u32,
+    /// Probability of a toggle in every bus cycle
+    probability: f64,
+}
+
+/// Behavior of the simulated bus cycles of a device.
+#[derive(Debug)]
+pub(crate) struct CycleBehavior {
+    pub(crate) loopback: bool,
+    pub(crate) latency: Duration,
+    script: VecDeque<InputStep>,
+    noise: Vec<Noise>,
+    /// State of the xorshift generator driving the noise, deterministic for a seed
+    rng: u64,
+}
+
+impl Default for CycleBehavior {
+    fn default() -> Self {
+        Self {
+            loopback: false,
+            latency: Duration::ZERO,
+            script: VecDeque::new(),
+            noise: Vec::new(),
+            rng: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+impl CycleBehavior {
+    /// Appends steps to the scripted input sequence.
+    pub(crate) fn play(&mut self, steps: impl IntoIterator<Item = InputStep>) {
+        self.script.extend(steps);
+    }
+
+    /// Returns `true` if all scripted steps were applied.
+    pub(crate) fn script_finished(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    /// Adds a noise channel, replacing a previous one on the same input.
+    pub(crate) fn set_noise(&mut self, bit_offset: u32, probability: f64) {
+        self.noise.retain(|noise| noise.bit_offset != bit_offset);
+        if probability > 0.0 {
+            self.noise.push(Noise {
+                bit_offset,
+                probability: probability.min(1.0),
+            });
+        }
+    }
+
+    /// Seeds the noise generator, so noisy tests are reproducible.
+    pub(crate) fn seed(&mut self, seed: u64) {
+        // Zero is a fixed point of xorshift
+        self.rng = seed.max(1);
+    }
+
+    /// Updates the process images for one bus cycle.
+    pub(crate) fn run(&mut self, inputs: &mut BitVec<u8>, outputs: &BitVec<u8>) {
+        if self.loopback {
+            let len = inputs.len().min(outputs.len());
+            inputs[..len].copy_from_bitslice(&outputs[..len]);
+        }
+
+        while let Some(step) = self.script.front_mut() {
+            if step.delay > 0 {
+                step.delay -= 1;
+                break;
+            }
+            if let Some(mut bit) = inputs.get_mut(step.bit_offset as usize) {
+                *bit = step.value;
+            }
+            self.script.pop_front();
+        }
+
+        for i in 0..self.noise.len() {
+            let noise = self.noise[i];
+            if self.next_random() < noise.probability {
+                if let Some(mut bit) = inputs.get_mut(noise.bit_offset as usize) {
+                    *bit = !*bit;
+                }
+            }
+        }
+    }
+
+    /// Returns a pseudo-random number in `[0, 1)`.
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,90 @@
+use super::*;
+
+fn images() -> (BitVec<u8>, BitVec<u8>) {
+    (bitvec![u8, LocalBits; 0; 16], bitvec![u8, LocalBits; 0; 16])
+}
+
+#[test]
+fn test_default_no_change() {
+    let mut behavior = CycleBehavior::default();
+    let (mut inputs, mut outputs) = images();
+    outputs.set(3, true);
+
+    behavior.run(&mut inputs, &outputs);
+    assert!(inputs.not_any());
+}
+
+#[test]
+fn test_loopback() {
+    let mut behavior = CycleBehavior {
+        loopback: true,
+        ..CycleBehavior::default()
+    };
+    let (mut inputs, mut outputs) = images();
+    outputs.set(3, true);
+    inputs.set(4, true);
+
+    behavior.run(&mut inputs, &outputs);
+    assert_eq!(inputs, outputs);
+}
+
+#[test]
+fn test_script() {
+    let mut behavior = CycleBehavior::default();
+    behavior.play([
+        InputStep::new(0, 1, true),
+        InputStep::new(0, 2, true),
+        InputStep::new(2, 1, false),
+        // Out of range steps are skipped
+        InputStep::new(1, 100, true),
+    ]);
+    let (mut inputs, outputs) = images();
+
+    behavior.run(&mut inputs, &outputs);
+    assert!(inputs[1] && inputs[2]);
+    behavior.run(&mut inputs, &outputs);
+    assert!(inputs[1]);
+    behavior.run(&mut inputs, &outputs);
+    assert!(!inputs[1] && inputs[2]);
+    assert!(!behavior.script_finished());
+    behavior.run(&mut inputs, &outputs);
+    assert!(behavior.script_finished());
+}
+
+#[test]
+fn test_noise() {
+    let mut behavior = CycleBehavior::default();
+    behavior.seed(42);
+    behavior.set_noise(5, 0.5);
+    let (mut inputs, outputs) = images();
+
+    let mut toggles = 0;
+    for _ in 0..1000 {
+        let before = inputs[5];
+        behavior.run(&mut inputs, &outputs);
+        toggles += usize::from(inputs[5] != before);
+        // Other inputs are not affected
+        assert_eq!(inputs.count_ones(), usize::from(inputs[5]));
+    }
+    assert!((400..600).contains(&toggles), "{toggles}");
+
+    // Same seed, same sequence
+    let mut first = CycleBehavior::default();
+    let mut second = CycleBehavior::default();
+    for behavior in [&mut first, &mut second] {
+        behavior.seed(7);
+        behavior.set_noise(5, 0.5);
+    }
+    let (mut first_inputs, _) = images();
+    let (mut second_inputs, _) = images();
+    for _ in 0..100 {
+        first.run(&mut first_inputs, &outputs);
+        second.run(&mut second_inputs, &outputs);
+        assert_eq!(first_inputs, second_inputs);
+    }
+
+    behavior.set_noise(5, 0.0);
+    let before = inputs.clone();
+    behavior.run(&mut inputs, &outputs);
+    assert_eq!(inputs, before);
+}
@@ -5,7 +5,15 @@
 use thiserror::Error;
 
 /// Error types returned by the kbus-mock library.
+///
+/// With the `serde` feature, errors serialize as their snake case variant name,
+/// with the data of the variant if any, e.g. `{"device_busy": "libpackbus"}`.
 #[derive(Debug, Error)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Error {
     /// The function is not implemented.
     #[error("function is not implemented")]
@@ -13,9 +21,24 @@ pub enum Error {
     /// The specified device was not found.
     #[error("device not found")]
     DeviceNotFound,
+    /// A device with the name is already registered.
+    #[error("device '{0}' is already registered")]
+    DeviceBusy(String),
+    /// The DAL is already initialized by another K-Bus instance. Never returned by
+    /// the mock, which can open any number of devices, but matched by applications.
+    #[error("DAL already initialized")]
+    AlreadyInitialized,
     /// A generic operation error.
     #[error("operation failed: {0}")]
     OperationFailed(String),
+    /// The DAL lacks the named function. Never returned by the mock, which provides
+    /// all functions, but matched by applications.
+    #[error("DAL function {0} missing")]
+    MissingDalFunction(&'static str),
+    /// The DAL listed a device without a name. Never returned by the mock, whose
+    /// devices always have names, but matched by applications.
+    #[error("DAL device list contains a device without a name")]
+    NullDeviceName,
 }
 
 /// A convenient type alias for results returned by the kbus-mock library.
@@ -0,0 +1,143 @@
+//! # Test Control Handle
+//!
+//! A [`KBusHandle`] gives tests access to the process images of a simulated device,
+//! e.g. to set inputs and check outputs written by the code under test.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    cycle::InputStep,
+    error::Result,
+    kbus::KBus,
+    layout::{PlacedTerminal, Terminal},
+    state::{self, KBusState, SharedState},
+};
+
+/// A cloneable handle to the state of a simulated device.
+#[derive(Clone, Default)]
+pub struct KBusHandle {
+    state: SharedState,
+}
+
+impl KBusHandle {
+    /// Creates a handle to a new device with all inputs and outputs off.
+    pub fn new() -> KBusHandle {
+        KBusHandle::default()
+    }
+
+    /// Creates a handle to a new device with process images of `inputs` and
+    /// `outputs` bits, e.g. to simulate a large rack.
+    pub fn with_sizes(inputs: usize, outputs: usize) -> KBusHandle {
+        KBusHandle {
+            state: Arc::new(Mutex::new(KBusState::with_sizes(inputs, outputs))),
+        }
+    }
+
+    /// Creates a handle to a new device made of the terminals in plugging order.
+    ///
+    /// The process images are sized to the terminals, see [`KBusHandle::terminals`]
+    /// for the offsets of their channels.
+    pub fn with_terminals(terminals: impl IntoIterator<Item = Terminal>) -> KBusHandle {
+        KBusHandle {
+            state: Arc::new(Mutex::new(KBusState::with_terminals(terminals))),
+        }
+    }
+
+    /// Creates a handle to a new device and registers it under `name`, so
+    /// [`KBus::open`] with that name connects to it.
+    ///
+    /// The device is unregistered when the last handle and device are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DeviceBusy`](crate::Error::DeviceBusy) if a device with
+    /// that name is still registered.
+    pub fn register(name: &str) -> Result<KBusHandle> {
+        let handle = KBusHandle::new();
+        state::register(name, &handle.state)?;
+        Ok(handle)
+    }
+
+    pub(crate) fn from_state(state: SharedState) -> KBusHandle {
+        KBusHandle { state }
+    }
+
+    /// Opens a new connection to the device.
+    pub fn kbus(&self) -> KBus {
+        KBus::with_state(self.state.clone())
+    }
+
+    /// Sets a simulated input bit value.
+    pub fn set_input_bit(&self, bit_offset: u32, value: bool) -> Result<()> {
+        let mut state = state::lock(&self.state);
+        KBusState::set_bit(&mut state.input_data, bit_offset, value)
+    }
+
+    /// Returns the current state of an output bit.
+    pub fn get_output_bit(&self, bit_offset: u32) -> Result<bool> {
+        let state = state::lock(&self.state);
+        KBusState::get_bit(&state.output_data, bit_offset)
+    }
+
+    /// Enables or disables loopback, the input image mirrors the output image
+    /// after every bus cycle.
+    pub fn set_loopback(&self, enabled: bool) {
+        state::lock(&self.state).behavior.loopback = enabled;
+    }
+
+    /// Appends steps to the scripted input sequence applied by the bus cycles.
+    pub fn play(&self, steps: impl IntoIterator<Item = InputStep>) {
+        state::lock(&self.state).behavior.play(steps);
+    }
+
+    /// Returns `true` if all scripted input steps were applied.
+    pub fn script_finished(&self) -> bool {
+        state::lock(&self.state).behavior.script_finished()
+    }
+
+    /// Makes the input `bit_offset` toggle with the given probability in every
+    /// bus cycle (0 disables the noise).
+    pub fn set_noise(&self, bit_offset: u32, probability: f64) {
+        state::lock(&self.state)
+            .behavior
+            .set_noise(bit_offset, probability);
+    }
+
+    /// Seeds the noise generator, so noisy tests are reproducible.
+    pub fn seed_noise(&self, seed: u64) {
+        state::lock(&self.state).behavior.seed(seed);
+    }
+
+    /// Sets the latency added to every bus cycle.
+    pub fn set_cycle_latency(&self, latency: Duration) {
+        state::lock(&self.state).behavior.latency = latency;
+    }
+
+    /// Returns the terminals of a device created by [`KBusHandle::with_terminals`]
+    /// with the offsets of their channels.
+    pub fn terminals(&self) -> Vec<PlacedTerminal> {
+        state::lock(&self.state).terminals.clone()
+    }
+
+    /// Overrides the input and output process image sizes in bytes reported by the
+    /// device, e.g. to simulate broken firmware, the simulated process images keep
+    /// their size.
+    pub fn set_io_sizes(&self, input: u32, output: u32) {
+        state::lock(&self.state).io_sizes = (input, output);
+    }
+
+    /// Returns the number of bus cycles triggered on the device.
+    pub fn cycles(&self) -> u64 {
+        state::lock(&self.state).cycles
+    }
+
+    /// Resets all simulated I/O data to default values.
+    pub fn reset(&self) {
+        let mut state = state::lock(&self.state);
+        state.input_data.fill(false);
+        state.output_data.fill(false);
+    }
+}
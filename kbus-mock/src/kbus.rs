@@ -2,75 +2,47 @@
 //!
 //! This module provides a mock implementation of the K-Bus API for testing.
 
-use std::sync::{Arc, Mutex, LazyLock};
+use std::thread;
 
-use crate::error::{Error, Result};
 use bitvec::prelude::*;
 
-// Shared state for simulating I/O
-struct KBusState {
-    input_data: BitVec<u8>,
-    output_data: BitVec<u8>,
-}
+use crate::{
+    error::{Error, Result},
+    handle::KBusHandle,
+    state::{self, KBusState, SharedState},
+};
 
-impl Default for KBusState {
-    fn default() -> Self {
-        Self {
-            input_data: bitvec![u8, LocalBits; 0; 90],
-            output_data: bitvec![u8, LocalBits; 0; 90],
-        }
-    }
-}
-
-// Global state shared between all instances
-static KBUS_STATE: LazyLock<Arc<Mutex<KBusState>>> =
-    LazyLock::new(|| Arc::new(Mutex::new(KBusState::default())));
+#[cfg(test)]
+mod tests;
 
 /// A writer handle for process data.
 pub struct Writer<'a> {
-    _dev: &'a mut KBus,
+    dev: &'a mut KBus,
     _task_id: u32,
 }
 
 impl<'a> Writer<'a> {
     /// Creates a new writer and initiates the write sequence.
-    fn new(_dev: &'a mut KBus, _task_id: u32) -> Result<Writer<'a>> {
-        Ok(Writer {
-            _dev,
-            _task_id,
-        })
+    fn new(dev: &'a mut KBus, _task_id: u32) -> Result<Writer<'a>> {
+        Ok(Writer { dev, _task_id })
     }
 
     /// Writes a single bit at the specified offset.
     pub fn write_bit(&mut self, bit_offset: u32, data: &mut u8) -> Result<()> {
-        let mut state = KBUS_STATE.lock().unwrap();
-        let bit_offset = bit_offset as usize;
-
-        if bit_offset >= state.output_data.len() {
-            return Err(Error::OperationFailed("Offset out of range".to_string()));
-        }
-
-        state.output_data.set(bit_offset, *data & 1 != 0);
-        Ok(())
+        let mut state = state::lock(&self.dev.state);
+        KBusState::set_bit(&mut state.output_data, bit_offset, *data & 1 != 0)
     }
 
     /// Writes a boolean value at the specified offset.
     pub fn write_bool(&mut self, bit_offset: u32, value: bool) -> Result<()> {
-        let mut state = KBUS_STATE.lock().unwrap();
-        let bit_offset = bit_offset as usize;
-
-        if bit_offset >= state.output_data.len() {
-            return Err(Error::OperationFailed("Offset out of range".to_string()));
-        }
-
-        state.output_data.set(bit_offset, value);
-        Ok(())
+        let mut state = state::lock(&self.dev.state);
+        KBusState::set_bit(&mut state.output_data, bit_offset, value)
     }
 
-    /// Writes a series of bytes starting at the given offset.
+    /// Writes a series of bytes starting at the given byte offset.
     pub fn write_bytes(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
-        let mut state = KBUS_STATE.lock().unwrap();
-        let bit_offset = offset as usize;
+        let mut state = state::lock(&self.dev.state);
+        let bit_offset = offset as usize * 8;
 
         // Check if we have enough space (each byte is 8 bits)
         if bit_offset + (data.len() * 8) > state.output_data.len() {
@@ -94,49 +66,34 @@ impl<'a> Writer<'a> {
 
 /// A reader handle for process data.
 pub struct Reader<'a> {
-    _dev: &'a mut KBus,
+    dev: &'a mut KBus,
     _task_id: u32,
 }
 
 impl<'a> Reader<'a> {
     /// Creates a new reader and initiates the read sequence.
-    fn new(_dev: &'a mut KBus, _task_id: u32) -> Result<Reader<'a>> {
-        Ok(Reader {
-            _dev,
-            _task_id,
-        })
+    fn new(dev: &'a mut KBus, _task_id: u32) -> Result<Reader<'a>> {
+        Ok(Reader { dev, _task_id })
     }
 
     /// Reads a single bit from the specified offset.
     pub fn read_bit(&mut self, bit_offset: u32, data: &mut u8) -> Result<()> {
-        let state = KBUS_STATE.lock().unwrap();
-        let bit_offset = bit_offset as usize;
-
-        if bit_offset >= state.input_data.len() {
-            return Err(Error::OperationFailed("Offset out of range".to_string()));
-        }
-
-        *data = state.input_data[bit_offset] as u8;
+        let state = state::lock(&self.dev.state);
+        *data = KBusState::get_bit(&state.input_data, bit_offset)? as u8;
         Ok(())
     }
 
     /// Reads a boolean value from the specified offset.
     pub fn read_bool(&mut self, bit_offset: u32, value: &mut bool) -> Result<()> {
-        let state = KBUS_STATE.lock().unwrap();
-        let bit_offset = bit_offset as usize;
-
-        if bit_offset >= state.input_data.len() {
-            return Err(Error::OperationFailed("Offset out of range".to_string()));
-        }
-
-        *value = state.input_data[bit_offset];
+        let state = state::lock(&self.dev.state);
+        *value = KBusState::get_bit(&state.input_data, bit_offset)?;
         Ok(())
     }
 
-    /// Reads a series of bytes starting at the given offset.
+    /// Reads a series of bytes starting at the given byte offset.
     pub fn read_bytes(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
-        let state = KBUS_STATE.lock().unwrap();
-        let bit_offset = offset as usize;
+        let state = state::lock(&self.dev.state);
+        let bit_offset = offset as usize * 8;
 
         // Check if we have enough bits (each byte is 8 bits)
         if bit_offset > state.input_data.len() {
@@ -162,17 +119,77 @@ impl<'a> Reader<'a> {
 
         Ok(())
     }
+
+    /// Reads `len_bits` bits of the input process image starting at bit `offset_bits`.
+    pub fn read_range(&mut self, offset_bits: u32, len_bits: usize) -> Result<BitVec<u8>> {
+        let state = state::lock(&self.dev.state);
+        let offset_bits = offset_bits as usize;
+        state
+            .input_data
+            .get(offset_bits..offset_bits + len_bits)
+            .map(BitSlice::to_bitvec)
+            .ok_or_else(|| Error::OperationFailed("Read exceeds buffer size".to_string()))
+    }
+
+    /// Reads the whole input process image, sized from the input size reported by
+    /// the device (in bytes) like in the kbus crate, so an override by
+    /// [`KBusHandle::set_io_sizes`] applies.
+    ///
+    /// [`KBusHandle::set_io_sizes`]: crate::KBusHandle::set_io_sizes
+    pub fn read_all(&mut self) -> Result<BitVec<u8>> {
+        let (input_size, _) = state::lock(&self.dev.state).io_sizes;
+        let mut data = vec![0; input_size as usize];
+        self.read_bytes(0, &mut data)?;
+        Ok(BitVec::from_vec(data))
+    }
+}
+
+/// Optional DAL functions provided by the firmware, as in the kbus crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub write_bit: bool,
+    pub write_bool: bool,
+    pub write_bytes: bool,
+    pub read_bit: bool,
+    pub read_bool: bool,
+    pub read_bytes: bool,
+    pub io_sizes: bool,
 }
 
 /// The primary type representing a mock connection to a K-Bus device.
+///
+/// Every device has its own process images unless it is opened by the name of a
+/// registered [`KBusHandle`], which then controls the device.
 pub struct KBus {
+    state: SharedState,
     is_open: bool,
 }
 
 impl KBus {
     /// Creates a new instance of [`KBus`] simulating a device named "libpackbus".
     pub fn new() -> Result<KBus> {
-        Ok(KBus { is_open: true })
+        KBus::open("libpackbus")
+    }
+
+    /// Opens the simulated device `name`.
+    ///
+    /// Shares the state of the [`KBusHandle`] registered under `name`, if any,
+    /// otherwise the device gets a fresh state.
+    pub fn open(name: &str) -> Result<KBus> {
+        let state = state::lookup(name).unwrap_or_default();
+        Ok(KBus::with_state(state))
+    }
+
+    pub(crate) fn with_state(state: SharedState) -> KBus {
+        KBus {
+            state,
+            is_open: true,
+        }
+    }
+
+    /// Returns a handle controlling the state of this device.
+    pub fn handle(&self) -> KBusHandle {
+        KBusHandle::from_state(self.state.clone())
     }
 
     /// Sets the application state to "Running".
@@ -192,29 +209,51 @@ impl KBus {
 
     /// Simulates triggering a K-Bus cycle.
     ///
-    /// In this mock implementation, it copies output data to input data
-    /// to simulate a loopback behavior.
+    /// The process images are updated according to the cycle behavior configured
+    /// through the [`KBusHandle`] of the device, the call blocks for the configured
+    /// cycle latency.
     pub fn trigger_bus_cycle(&mut self) -> Result<()> {
-        // let mut state = KBUS_STATE.lock().unwrap();
-        // let output_data = state.output_data.clone();
-        // state.input_data.clone_from(&output_data);
+        let latency = state::lock(&self.state).cycle();
+        if !latency.is_zero() {
+            thread::sleep(latency);
+        }
         Ok(())
     }
 
-    /// Returns fixed I/O sizes for the mock device.
+    /// Simulates rescanning the device libraries, the mock device never changes.
+    pub fn rescan(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the optional DAL functions, the mock provides all of them.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            write_bit: true,
+            write_bool: true,
+            write_bytes: true,
+            read_bit: true,
+            read_bool: true,
+            read_bytes: true,
+            io_sizes: true,
+        }
+    }
+
+    /// Returns the input and output process image sizes of the mock device in bytes,
+    /// unless overridden by [`KBusHandle::set_io_sizes`].
+    ///
+    /// [`KBusHandle::set_io_sizes`]: crate::KBusHandle::set_io_sizes
     pub fn io_sizes(&mut self) -> Result<(u32, u32)> {
-        // Return fixed sizes that match your expectations
-        Ok((90, 90))
+        Ok(state::lock(&self.state).io_sizes)
     }
 
     /// Creates a new [`Writer`] handle to begin a process data write operation.
-    pub fn writer(&mut self) -> Result<Writer> {
+    pub fn writer(&mut self) -> Result<Writer<'_>> {
         let task_id = 0;
         Writer::new(self, task_id)
     }
 
     /// Creates a new [`Reader`] handle to begin a process data read operation.
-    pub fn reader(&mut self) -> Result<Reader> {
+    pub fn reader(&mut self) -> Result<Reader<'_>> {
         let task_id = 0;
         Reader::new(self, task_id)
     }
@@ -225,37 +264,3 @@ impl Drop for KBus {
         self.is_open = false;
     }
 }
-
-// Public helper functions for tests
-
-/// Set a simulated input bit value, useful for tests.
-pub fn set_input_bit(bit_offset: u32, value: bool) -> Result<()> {
-    let mut state = KBUS_STATE.lock().unwrap();
-    let bit_offset = bit_offset as usize;
-
-    if bit_offset >= state.input_data.len() {
-        return Err(Error::OperationFailed("Offset out of range".to_string()));
-    }
-
-    state.input_data.set(bit_offset, value);
-    Ok(())
-}
-
-/// Get the current state of an output bit, useful for tests.
-pub fn get_output_bit(bit_offset: u32) -> Result<bool> {
-    let state = KBUS_STATE.lock().unwrap();
-    let bit_offset = bit_offset as usize;
-
-    if bit_offset >= state.output_data.len() {
-        return Err(Error::OperationFailed("Offset out of range".to_string()));
-    }
-
-    Ok(state.output_data[bit_offset])
-}
-
-/// Reset all simulated I/O data to default values, useful between tests.
-pub fn reset_state() {
-    let mut state = KBUS_STATE.lock().unwrap();
-    state.input_data.fill(false);
-    state.output_data.fill(false);
-}
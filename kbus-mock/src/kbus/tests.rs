@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use super::*;
+use crate::{Terminal, state::IO_SIZE};
+
+#[test]
+fn test_separate_devices() {
+    let first = KBusHandle::new();
+    let second = KBusHandle::new();
+    first.set_input_bit(5, true).unwrap();
+
+    let mut value = false;
+    first
+        .kbus()
+        .reader()
+        .unwrap()
+        .read_bool(5, &mut value)
+        .unwrap();
+    assert!(value);
+    second
+        .kbus()
+        .reader()
+        .unwrap()
+        .read_bool(5, &mut value)
+        .unwrap();
+    assert!(!value);
+
+    second
+        .kbus()
+        .writer()
+        .unwrap()
+        .write_bool(10, true)
+        .unwrap();
+    assert!(!first.get_output_bit(10).unwrap());
+    assert!(second.get_output_bit(10).unwrap());
+
+    second.reset();
+    assert!(!second.get_output_bit(10).unwrap());
+}
+
+#[test]
+fn test_registry() {
+    // Unregistered devices get a fresh state
+    let kbus = KBus::open("test_registry").unwrap();
+    kbus.handle().set_input_bit(1, true).unwrap();
+    let mut data = [0; 1];
+    KBus::open("test_registry")
+        .unwrap()
+        .reader()
+        .unwrap()
+        .read_bytes(0, &mut data)
+        .unwrap();
+    assert_eq!(data, [0]);
+
+    let handle = KBusHandle::register("test_registry").unwrap();
+    assert!(matches!(
+        KBusHandle::register("test_registry"),
+        Err(Error::DeviceBusy(_))
+    ));
+    handle.set_input_bit(1, true).unwrap();
+    KBus::open("test_registry")
+        .unwrap()
+        .reader()
+        .unwrap()
+        .read_bytes(0, &mut data)
+        .unwrap();
+    assert_eq!(data, [0b10]);
+
+    // The name can be reused once the device is gone
+    drop(handle);
+    KBusHandle::register("test_registry").unwrap();
+}
+
+#[test]
+fn test_read_range() {
+    let handle = KBusHandle::new();
+    let mut kbus = handle.kbus();
+    handle.set_input_bit(9, true).unwrap();
+    handle.set_input_bit(17, true).unwrap();
+
+    let mut reader = kbus.reader().unwrap();
+    let all = reader.read_all().unwrap();
+    // The image is read in whole bytes, as reported by the device
+    assert_eq!(all.len(), IO_SIZE.div_ceil(8) * 8);
+    assert_eq!(all.iter_ones().collect::<Vec<_>>(), [9, 17]);
+
+    let range = reader.read_range(8, 10).unwrap();
+    assert_eq!(range.len(), 10);
+    assert_eq!(range.iter_ones().collect::<Vec<_>>(), [1, 9]);
+    assert!(reader.read_range(80, 11).is_err());
+
+    // Byte reads start at byte offsets, like in the DAL
+    let mut data = [0; 2];
+    reader.read_bytes(1, &mut data).unwrap();
+    assert_eq!(data, [0b10, 0b10]);
+}
+
+#[test]
+fn test_out_of_range() {
+    let mut kbus = KBusHandle::new().kbus();
+    assert!(
+        kbus.writer()
+            .unwrap()
+            .write_bool(IO_SIZE as u32, true)
+            .is_err()
+    );
+    let mut value = false;
+    assert!(
+        kbus.reader()
+            .unwrap()
+            .read_bool(IO_SIZE as u32, &mut value)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_io_sizes() {
+    let mut kbus = KBusHandle::new().kbus();
+    assert_eq!(kbus.io_sizes().unwrap(), (12, 12));
+
+    // A large rack
+    let handle = KBusHandle::with_sizes(1000, 20);
+    let mut kbus = handle.kbus();
+    assert_eq!(kbus.io_sizes().unwrap(), (125, 3));
+    handle.set_input_bit(999, true).unwrap();
+    assert!(handle.set_input_bit(1000, true).is_err());
+    assert_eq!(kbus.reader().unwrap().read_all().unwrap().len(), 1000);
+    assert!(kbus.writer().unwrap().write_bool(20, true).is_err());
+
+    // Firmware reporting wrong sizes
+    handle.set_io_sizes(12000, 12000);
+    assert_eq!(kbus.io_sizes().unwrap(), (12000, 12000));
+    assert!(handle.set_input_bit(1000, true).is_err());
+    // The whole image is read with the reported size
+    handle.set_io_sizes(2, 3);
+    let all = kbus.reader().unwrap().read_all().unwrap();
+    assert_eq!(all.len(), 16);
+    assert!(all.not_any());
+    handle.set_input_bit(3, true).unwrap();
+    let all = kbus.reader().unwrap().read_all().unwrap();
+    assert_eq!(all.iter_ones().collect::<Vec<_>>(), [3]);
+}
+
+#[test]
+fn test_terminals() {
+    let handle = KBusHandle::with_terminals([
+        Terminal::digital_inputs("750-430", 8),
+        Terminal::digital_outputs("750-530", 8),
+        Terminal::digital_inputs("750-402", 4),
+    ]);
+    let mut kbus = handle.kbus();
+    assert_eq!(kbus.io_sizes().unwrap(), (2, 1));
+
+    let terminals = handle.terminals();
+    assert_eq!(terminals.len(), 3);
+    let third = &terminals[2];
+    assert_eq!((third.position, third.input_offset), (3, 8));
+    handle
+        .set_input_bit(third.input_offset as u32 + 3, true)
+        .unwrap();
+    assert_eq!(
+        kbus.reader()
+            .unwrap()
+            .read_all()
+            .unwrap()
+            .iter_ones()
+            .collect::<Vec<_>>(),
+        [11]
+    );
+    assert!(KBusHandle::new().terminals().is_empty());
+}
+
+#[test]
+fn test_cycle_behavior() {
+    let handle = KBusHandle::new();
+    let mut kbus = handle.kbus();
+    handle.set_loopback(true);
+    handle.set_cycle_latency(Duration::from_millis(5));
+
+    kbus.writer().unwrap().write_bool(7, true).unwrap();
+    let start = std::time::Instant::now();
+    kbus.trigger_bus_cycle().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert_eq!(handle.cycles(), 1);
+
+    let mut value = false;
+    kbus.reader().unwrap().read_bool(7, &mut value).unwrap();
+    assert!(value);
+}
@@ -0,0 +1,78 @@
+//! # Module Layouts
+//!
+//! A real device maps the channels of its terminals (I/O modules) into the process
+//! images in the order they are plugged. A mock device can be built from a list of
+//! fake terminals, so tests cover racks of any size and know where the channels of
+//! each terminal are. The kbus crate can't query the layout, the DAL doesn't expose
+//! it, so the layout is only known to the [`KBusHandle`](crate::KBusHandle).
+
+#[cfg(test)]
+mod tests;
+
+/// A fake terminal (I/O module) of a simulated device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminal {
+    /// Order number, e.g. `750-430`
+    pub name: String,
+    /// Number of bits in the input process image
+    pub inputs: usize,
+    /// Number of bits in the output process image
+    pub outputs: usize,
+}
+
+impl Terminal {
+    /// Creates a terminal with `inputs` and `outputs` bits.
+    pub fn new(name: &str, inputs: usize, outputs: usize) -> Terminal {
+        Terminal {
+            name: name.to_owned(),
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Creates a digital input terminal with `channels` channels, e.g. a 750-430.
+    pub fn digital_inputs(name: &str, channels: usize) -> Terminal {
+        Terminal::new(name, channels, 0)
+    }
+
+    /// Creates a digital output terminal with `channels` channels, e.g. a 750-530.
+    pub fn digital_outputs(name: &str, channels: usize) -> Terminal {
+        Terminal::new(name, 0, channels)
+    }
+}
+
+/// A terminal at its place in the process images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedTerminal {
+    pub terminal: Terminal,
+    /// Position in the rack, starting at 1 next to the controller
+    pub position: usize,
+    /// Bit offset of the first input in the input process image
+    pub input_offset: usize,
+    /// Bit offset of the first output in the output process image
+    pub output_offset: usize,
+}
+
+/// Places the terminals in plugging order, returns them with the input and output
+/// process image sizes in bits.
+pub(crate) fn place(
+    terminals: impl IntoIterator<Item = Terminal>,
+) -> (Vec<PlacedTerminal>, usize, usize) {
+    let (mut inputs, mut outputs) = (0, 0);
+    let placed = terminals
+        .into_iter()
+        .enumerate()
+        .map(|(index, terminal)| {
+            let placed = PlacedTerminal {
+                position: index + 1,
+                input_offset: inputs,
+                output_offset: outputs,
+                terminal,
+            };
+            inputs += placed.terminal.inputs;
+            outputs += placed.terminal.outputs;
+            placed
+        })
+        .collect();
+    (placed, inputs, outputs)
+}
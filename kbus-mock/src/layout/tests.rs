@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn test_place() {
+    let (placed, inputs, outputs) = place([
+        Terminal::digital_inputs("750-430", 8),
+        Terminal::digital_outputs("750-530", 8),
+        Terminal::new("750-1506", 8, 8),
+        Terminal::digital_inputs("750-402", 4),
+    ]);
+    assert_eq!((inputs, outputs), (20, 16));
+    let offsets: Vec<_> = placed
+        .iter()
+        .map(|placed| (placed.position, placed.input_offset, placed.output_offset))
+        .collect();
+    assert_eq!(offsets, [(1, 0, 0), (2, 8, 0), (3, 8, 8), (4, 16, 16)]);
+    assert_eq!(placed[2].terminal.name, "750-1506");
+
+    assert_eq!(place([]), (vec![], 0, 0));
+}
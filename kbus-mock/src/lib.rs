@@ -1,9 +1,25 @@
 //! # kbus-mock Library
 //!
 //! Mock implementation of the kbus crate for testing.
+//!
+//! Every [`KBus`] simulates its own device, so tests can run in parallel. Tests
+//! control a device through a [`KBusHandle`], either by opening the device from
+//! the handle or by registering the handle under the name of the device.
+//!
+//! Devices have process images of [`IO_SIZE`] bits by default. Handles created with
+//! [`KBusHandle::with_sizes`] or from a list of fake [`Terminal`]s with
+//! [`KBusHandle::with_terminals`] simulate racks of any size.
 
+mod cycle;
 mod error;
+mod handle;
 mod kbus;
+mod layout;
+mod state;
 
+pub use cycle::InputStep;
 pub use error::Error;
-pub use kbus::{KBus, get_output_bit, reset_state, set_input_bit};
+pub use handle::KBusHandle;
+pub use kbus::{Capabilities, KBus};
+pub use layout::{PlacedTerminal, Terminal};
+pub use state::IO_SIZE;
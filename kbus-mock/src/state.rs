@@ -0,0 +1,124 @@
+//! # Simulated Process Images
+//!
+//! Every mock device owns its process images, so tests using separate devices don't
+//! interfere with each other. Devices registered by name in the registry share their
+//! state with every [`KBus`](crate::KBus) opened with that name.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError, Weak},
+    time::Duration,
+};
+
+use bitvec::prelude::*;
+
+use crate::{
+    cycle::CycleBehavior,
+    error::{Error, Result},
+    layout::{self, PlacedTerminal, Terminal},
+};
+
+/// Default size of the simulated input and output process images in bits.
+pub const IO_SIZE: usize = 90;
+
+/// Process images of a simulated device.
+pub(crate) struct KBusState {
+    pub(crate) input_data: BitVec<u8>,
+    pub(crate) output_data: BitVec<u8>,
+    pub(crate) behavior: CycleBehavior,
+    /// Number of bus cycles triggered on the device
+    pub(crate) cycles: u64,
+    /// Input and output process image sizes in bytes reported by the device
+    pub(crate) io_sizes: (u32, u32),
+    /// Terminals the process images are made of, empty if built from sizes
+    pub(crate) terminals: Vec<PlacedTerminal>,
+}
+
+impl Default for KBusState {
+    fn default() -> Self {
+        Self::with_sizes(IO_SIZE, IO_SIZE)
+    }
+}
+
+impl KBusState {
+    /// Creates process images of `inputs` and `outputs` bits, reporting their sizes
+    /// in whole bytes like the DAL.
+    pub(crate) fn with_sizes(inputs: usize, outputs: usize) -> Self {
+        Self {
+            input_data: bitvec![u8, LocalBits; 0; inputs],
+            output_data: bitvec![u8, LocalBits; 0; outputs],
+            behavior: CycleBehavior::default(),
+            cycles: 0,
+            io_sizes: (inputs.div_ceil(8) as u32, outputs.div_ceil(8) as u32),
+            terminals: Vec::new(),
+        }
+    }
+
+    /// Creates the process images of the terminals.
+    pub(crate) fn with_terminals(terminals: impl IntoIterator<Item = Terminal>) -> Self {
+        let (terminals, inputs, outputs) = layout::place(terminals);
+        Self {
+            terminals,
+            ..Self::with_sizes(inputs, outputs)
+        }
+    }
+
+    /// Sets a single bit of `data`.
+    pub(crate) fn set_bit(data: &mut BitVec<u8>, bit_offset: u32, value: bool) -> Result<()> {
+        let bit_offset = bit_offset as usize;
+        if bit_offset >= data.len() {
+            return Err(Error::OperationFailed("Offset out of range".to_string()));
+        }
+
+        data.set(bit_offset, value);
+        Ok(())
+    }
+
+    /// Simulates a bus cycle, returns the latency of the cycle.
+    pub(crate) fn cycle(&mut self) -> Duration {
+        self.behavior.run(&mut self.input_data, &self.output_data);
+        self.cycles += 1;
+        self.behavior.latency
+    }
+
+    /// Returns a single bit of `data`.
+    pub(crate) fn get_bit(data: &BitVec<u8>, bit_offset: u32) -> Result<bool> {
+        data.get(bit_offset as usize)
+            .map(|bit| *bit)
+            .ok_or_else(|| Error::OperationFailed("Offset out of range".to_string()))
+    }
+}
+
+/// Shared reference to the state of a simulated device.
+pub(crate) type SharedState = Arc<Mutex<KBusState>>;
+
+/// Locks the state, a panicking test doesn't leave the process images inconsistent.
+pub(crate) fn lock(state: &SharedState) -> MutexGuard<'_, KBusState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Devices registered by name, entries are dropped with the last handle and device.
+static REGISTRY: LazyLock<Mutex<HashMap<String, Weak<Mutex<KBusState>>>>> =
+    LazyLock::new(Default::default);
+
+/// Adds the state to the registry under `name`.
+///
+/// # Errors
+///
+/// Returns [`Error::DeviceBusy`] if a device with that name is still registered.
+pub(crate) fn register(name: &str, state: &SharedState) -> Result<()> {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.retain(|_, state| state.strong_count() > 0);
+    if registry.contains_key(name) {
+        return Err(Error::DeviceBusy(name.to_owned()));
+    }
+
+    registry.insert(name.to_owned(), Arc::downgrade(state));
+    Ok(())
+}
+
+/// Returns the state registered under `name`, if any.
+pub(crate) fn lookup(name: &str) -> Option<SharedState> {
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry.get(name).and_then(Weak::upgrade)
+}
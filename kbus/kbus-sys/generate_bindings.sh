@@ -24,4 +24,15 @@ bindgen wrapper.h \
   --sysroot="$PTXPROJ_PATH/platform-wago-pfcXXX/sysroot-target" \
   -I"$PTXPROJ_PATH/platform-wago-pfcXXX/sysroot-target/usr/include"
 
-echo "Bindings successfully generated to src/bindings.rs"
+# Stub bindings for builds without the DAL library: no extern block and no layout
+# tests, which only hold on the 32-bit ARM target
+bindgen wrapper.h \
+  --output src/stub_bindings.rs \
+  --no-layout-tests \
+  --blocklist-function adi_GetApplicationInterface \
+  -- \
+  --target="armv7-unknown-linux-gnueabihf" \
+  --sysroot="$PTXPROJ_PATH/platform-wago-pfcXXX/sysroot-target" \
+  -I"$PTXPROJ_PATH/platform-wago-pfcXXX/sysroot-target/usr/include"
+
+echo "Bindings successfully generated to src/bindings.rs and src/stub_bindings.rs"
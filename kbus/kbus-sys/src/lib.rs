@@ -2,4 +2,18 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+#[cfg(not(feature = "stub"))]
 include!("bindings.rs");
+
+#[cfg(feature = "stub")]
+include!("stub_bindings.rs");
+
+/// Stub of the DAL entry point, the DAL is never available in stub builds.
+///
+/// # Safety
+///
+/// Always safe to call, declared `unsafe` to match the real binding.
+#[cfg(feature = "stub")]
+pub unsafe fn adi_GetApplicationInterface() -> *mut tApplicationDeviceInterface {
+    ::std::ptr::null_mut()
+}
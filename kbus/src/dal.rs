@@ -10,6 +10,7 @@ use std::{
     collections::HashMap,
     ffi::{CStr, CString, c_void},
     mem,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use kbus_sys as ffi;
@@ -18,20 +19,74 @@ use crate::error::{DalResult, Error, Result};
 
 const MAX_DAL_DEVICES_COUNT: usize = 10;
 
+/// Set while an [`ApplicationDeviceInterface`] exists, the DAL can only be
+/// initialized once per process.
+static DAL_IN_USE: AtomicBool = AtomicBool::new(false);
+
 /// A helper macro that calls a DAL method and converts its return code into a [`Result<()>`].
 ///
 /// The macro expects that the method returns an integer which can be interpreted
-/// using [`DalResult`].
+/// using [`DalResult`]. A method missing in the function table is reported with
+/// [`missing_function`].
 macro_rules! dal_method {
     ($obj: ident . $method: ident ($($args: expr),*)) => {
-        match unsafe { (*$obj.ptr).$method.unwrap()($($args),*) }.into() {
-            DalResult::Success => Ok(()),
-            DalResult::Failure => Err(Error::DalError),
-            DalResult::NotUsed => Err(Error::Unimplemented),
+        match unsafe { (*$obj.ptr).$method } {
+            Some(method) => match unsafe { method($($args),*) }.into() {
+                DalResult::Success => Ok(()),
+                DalResult::Failure => Err(Error::DalError),
+                DalResult::NotUsed => Err(Error::Unimplemented),
+            },
+            None => Err(missing_function(stringify!($method))),
         }
     };
 }
 
+/// Returns the error for the DAL function `name` missing in the function table.
+///
+/// # Panics
+///
+/// Without the `panic-free` feature, a missing function is considered a build for
+/// the wrong firmware and panics.
+fn missing_function(name: &'static str) -> Error {
+    if cfg!(feature = "panic-free") {
+        Error::MissingDalFunction(name)
+    } else {
+        panic!("DAL function {name} missing")
+    }
+}
+
+/// Returns the error for a device listed by the DAL without a name.
+///
+/// # Panics
+///
+/// Without the `panic-free` feature, like a missing function.
+fn null_device_name() -> Error {
+    if cfg!(feature = "panic-free") {
+        Error::NullDeviceName
+    } else {
+        panic!("DAL device list contains a device without a name")
+    }
+}
+
+/// Optional DAL functions provided by the firmware.
+///
+/// Unusual firmware may lack some of the process data functions. With the
+/// `panic-free` feature, the interface is created anyway and calling a missing
+/// function fails with [`Error::MissingDalFunction`], so applications can check
+/// the capabilities up front and fall back, e.g. to `Writer::write_bit`
+/// if `write_bool` is missing. Without it, all of them are always present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub write_bit: bool,
+    pub write_bool: bool,
+    pub write_bytes: bool,
+    pub read_bit: bool,
+    pub read_bool: bool,
+    pub read_bytes: bool,
+    /// `GetIoSizes`, used by [`crate::KBus::io_sizes`] and `Reader::read_all`
+    pub io_sizes: bool,
+}
+
 /// A simple wrapper for a device identifier.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct DeviceId(ffi::tDeviceId);
@@ -88,45 +143,107 @@ impl ApplicationDeviceInterface {
     ///
     /// # Errors
     ///
-    /// Returns an error if the initialization or scanning fails.
+    /// Returns [`Error::AlreadyInitialized`] if another interface exists in this
+    /// process, [`Error::DalError`] if the DAL initialization fails (e.g. because
+    /// another application uses it), [`Error::MissingDalFunction`] if a function
+    /// every device needs is missing (with the `panic-free` feature), or an error if
+    /// scanning fails.
+    ///
+    /// # Panics
+    ///
+    /// Without the `panic-free` feature, if any function besides `GetIoSizes` is
+    /// missing or the DAL lists a device without a name.
     pub(super) fn new() -> Result<ApplicationDeviceInterface> {
-        let ptr = unsafe {
-            let ptr = ffi::adi_GetApplicationInterface();
-
-            assert!(!ptr.is_null());
-            assert!((*ptr).Init.is_some());
-            assert!((*ptr).Exit.is_some());
-            assert!((*ptr).ScanDevices.is_some());
-            assert!((*ptr).GetDeviceList.is_some());
-            assert!((*ptr).OpenDevice.is_some());
-            assert!((*ptr).CloseDevice.is_some());
-            assert!((*ptr).WriteStart.is_some());
-            assert!((*ptr).WriteBit.is_some());
-            assert!((*ptr).WriteBool.is_some());
-            assert!((*ptr).WriteBytes.is_some());
-            assert!((*ptr).WriteEnd.is_some());
-            assert!((*ptr).ReadStart.is_some());
-            assert!((*ptr).ReadBit.is_some());
-            assert!((*ptr).ReadBool.is_some());
-            assert!((*ptr).ReadBytes.is_some());
-            assert!((*ptr).ReadEnd.is_some());
-            assert!((*ptr).ApplicationStateChanged.is_some());
-            assert!((*ptr).CallDeviceSpecificFunction.is_some());
-
-            ptr
-        };
+        let ptr = unsafe { ffi::adi_GetApplicationInterface() };
+        if ptr.is_null() {
+            return Err(Error::Unavailable);
+        }
+        let functions = unsafe { &*ptr };
+        // Name, present, required even with the `panic-free` feature
+        let checked = [
+            ("Init", functions.Init.is_some(), true),
+            ("Exit", functions.Exit.is_some(), true),
+            ("ScanDevices", functions.ScanDevices.is_some(), true),
+            ("GetDeviceList", functions.GetDeviceList.is_some(), true),
+            ("OpenDevice", functions.OpenDevice.is_some(), true),
+            ("CloseDevice", functions.CloseDevice.is_some(), true),
+            ("WriteStart", functions.WriteStart.is_some(), true),
+            ("WriteBit", functions.WriteBit.is_some(), false),
+            ("WriteBool", functions.WriteBool.is_some(), false),
+            ("WriteBytes", functions.WriteBytes.is_some(), false),
+            ("WriteEnd", functions.WriteEnd.is_some(), true),
+            ("ReadStart", functions.ReadStart.is_some(), true),
+            ("ReadBit", functions.ReadBit.is_some(), false),
+            ("ReadBool", functions.ReadBool.is_some(), false),
+            ("ReadBytes", functions.ReadBytes.is_some(), false),
+            ("ReadEnd", functions.ReadEnd.is_some(), true),
+            (
+                "ApplicationStateChanged",
+                functions.ApplicationStateChanged.is_some(),
+                true,
+            ),
+            (
+                "CallDeviceSpecificFunction",
+                functions.CallDeviceSpecificFunction.is_some(),
+                true,
+            ),
+        ];
+        for (name, present, required) in checked {
+            if !present && (required || !cfg!(feature = "panic-free")) {
+                return Err(missing_function(name));
+            }
+        }
 
+        if DAL_IN_USE.swap(true, Ordering::AcqRel) {
+            return Err(Error::AlreadyInitialized);
+        }
+        // From here on, dropping the interface releases the guard
         let mut adi = ApplicationDeviceInterface {
             ptr,
             devices_by_name: HashMap::with_capacity(MAX_DAL_DEVICES_COUNT),
         };
         adi.init()?;
-        adi.scan_devices()?;
-        for device in adi.get_device_list()? {
-            adi.devices_by_name
+        adi.refresh_device_list()?;
+        Ok(adi)
+    }
+
+    /// Scans the device library path again and rebuilds the cached device map.
+    ///
+    /// Picks up device libraries added, removed or replaced at runtime without
+    /// dropping the interface. Device ids may change, so ids obtained before must be
+    /// looked up again with [`ApplicationDeviceInterface::device_id`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scan fails, the cache is left unchanged then.
+    pub fn refresh_device_list(&mut self) -> Result<()> {
+        self.scan_devices()?;
+        let devices = self.get_device_list()?;
+        self.devices_by_name.clear();
+        for device in devices {
+            self.devices_by_name
                 .insert(device.name().into(), device.id());
         }
-        Ok(adi)
+        Ok(())
+    }
+
+    /// Returns the optional DAL functions provided by the firmware.
+    pub fn capabilities(&self) -> Capabilities {
+        let functions = unsafe { &*self.ptr };
+        Capabilities {
+            write_bit: functions.WriteBit.is_some(),
+            write_bool: functions.WriteBool.is_some(),
+            write_bytes: functions.WriteBytes.is_some(),
+            read_bit: functions.ReadBit.is_some(),
+            read_bool: functions.ReadBool.is_some(),
+            read_bytes: functions.ReadBytes.is_some(),
+            io_sizes: functions.GetIoSizes.is_some(),
+        }
+    }
+
+    /// Returns the id of the device `name` from the cached device map.
+    pub fn device_id(&self, name: &str) -> Option<DeviceId> {
+        self.devices_by_name.get(name).copied()
     }
 
     /// Initializes the DAL. Fails in case another instance is already running.
@@ -166,17 +283,19 @@ impl ApplicationDeviceInterface {
             device_list.as_mut_ptr(),
             &mut devices_found
         ))?;
-        Ok(device_list
+        device_list
             .iter()
-            .take(devices_found as usize)
+            .take(devices_found)
             .map(|d| {
-                assert!(!d.DeviceName.is_null());
-                DeviceInfo {
+                if d.DeviceName.is_null() {
+                    return Err(null_device_name());
+                }
+                Ok(DeviceInfo {
                     id: DeviceId(d.DeviceId),
                     name: unsafe { CStr::from_ptr(d.DeviceName).to_string_lossy().into_owned() },
-                }
+                })
             })
-            .collect())
+            .collect()
     }
 
     /// Opens the specified device.
@@ -347,5 +466,6 @@ impl ApplicationDeviceInterface {
 impl Drop for ApplicationDeviceInterface {
     fn drop(&mut self) {
         self.exit();
+        DAL_IN_USE.store(false, Ordering::Release);
     }
 }
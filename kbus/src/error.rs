@@ -4,7 +4,7 @@
 //! It includes conversion utilities for the raw DAL return values as well as
 //! error types for string conversion and device-related issues.
 
-use std::ffi::NulError;
+use std::{ffi::NulError, io};
 
 use thiserror::Error;
 
@@ -33,7 +33,15 @@ impl From<i32> for DalResult {
 }
 
 /// Error types returned by the kbus library.
+///
+/// With the `serde` feature, errors serialize as their snake case variant name,
+/// e.g. `"bus_cycle_failed"`.
 #[derive(Debug, Error)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Error {
     /// The function is not implemented by the device.
     #[error("function is not implemented by the device")]
@@ -44,9 +52,33 @@ pub enum Error {
     /// An interior nul byte was found in a string.
     #[error("interior nul byte found")]
     NulError,
+    /// The DAL interface is not available (e.g. in stub builds).
+    #[error("DAL interface not available")]
+    Unavailable,
+    /// A bus cycle of the shared K-Bus I/O thread failed.
+    #[error("bus cycle failed")]
+    BusCycleFailed,
+    /// The I/O thread of a [`crate::SharedKBus`] couldn't be spawned.
+    #[error("failed to spawn K-Bus I/O thread")]
+    ThreadSpawn(
+        #[source]
+        #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_display"))]
+        io::Error,
+    ),
     /// The specified device was not found.
     #[error("device not found")]
     DeviceNotFound,
+    /// The DAL is already initialized by another [`crate::KBus`] of this process, or
+    /// an application lock on it is held.
+    #[error("DAL already initialized by another K-Bus instance")]
+    AlreadyInitialized,
+    /// The DAL of the firmware lacks the named function (with the `panic-free`
+    /// feature, see [`crate::Capabilities`]).
+    #[error("DAL function {0} missing")]
+    MissingDalFunction(&'static str),
+    /// The DAL listed a device without a name (with the `panic-free` feature).
+    #[error("DAL device list contains a device without a name")]
+    NullDeviceName,
 }
 
 impl From<NulError> for Error {
@@ -55,5 +87,14 @@ impl From<NulError> for Error {
     }
 }
 
+/// Serializes an error without a `Serialize` implementation as its message.
+#[cfg(feature = "serde")]
+fn serialize_display<S: serde::Serializer>(
+    error: &io::Error,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(error)
+}
+
 /// A convenient type alias for results returned by the kbus library.
 pub type Result<T> = std::result::Result<T, Error>;
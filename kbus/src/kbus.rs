@@ -4,11 +4,16 @@
 //! with the K-Bus. It defines the main [`KBus`] type as well as helper types for reading and
 //! writing process data.
 
+use bitvec::prelude::*;
+
 use crate::{
-    dal::{ApplicationDeviceInterface, ApplicationState, DeviceId},
+    dal::{ApplicationDeviceInterface, ApplicationState, Capabilities, DeviceId},
     error::{DalResult, Error, Result},
 };
 
+/// Name of the K-Bus device in the DAL device list.
+const DEVICE_NAME: &str = "libpackbus";
+
 /// A writer handle for process data.
 ///
 /// When instantiated, it starts the write operation and commits the data when dropped.
@@ -89,6 +94,23 @@ impl<'a> Reader<'a> {
             .adi
             .read_bytes(self.dev.id, self.task_id, offset, data)
     }
+
+    /// Reads `len_bits` bits of the input process image starting at bit `offset_bits`.
+    ///
+    /// Bit `i` of the returned buffer is the input at bit offset `offset_bits + i`.
+    pub fn read_range(&mut self, offset_bits: u32, len_bits: usize) -> Result<BitVec<u8>> {
+        let first_bit = offset_bits as usize % 8;
+        let mut data = vec![0; (first_bit + len_bits).div_ceil(8)];
+        self.read_bytes(offset_bits / 8, &mut data)?;
+        Ok(data.view_bits::<Lsb0>()[first_bit..first_bit + len_bits].to_bitvec())
+    }
+
+    /// Reads the whole input process image, sized from the input size reported by
+    /// the device (in bytes).
+    pub fn read_all(&mut self) -> Result<BitVec<u8>> {
+        let (input_size, _) = self.dev.adi.get_io_sizes(self.dev.id)?;
+        self.read_range(0, input_size as usize * 8)
+    }
 }
 
 impl<'a> Drop for Reader<'a> {
@@ -114,16 +136,33 @@ impl KBus {
     /// Returns [`Error::DeviceNotFound`] if no matching device is found.
     pub fn new() -> Result<KBus> {
         let mut adi = ApplicationDeviceInterface::new()?;
-        for device in adi.get_device_list()? {
-            if device.name() == "libpackbus" {
-                adi.open_device(device.id())?;
-                return Ok(KBus {
-                    adi,
-                    id: device.id(),
-                });
-            }
+        let id = adi.device_id(DEVICE_NAME).ok_or(Error::DeviceNotFound)?;
+        adi.open_device(id)?;
+        Ok(KBus { adi, id })
+    }
+
+    /// Scans the device libraries again and reopens the K-Bus device if its id
+    /// changed, e.g. after the device library was updated at runtime.
+    ///
+    /// The application state should be "Stopped" while rescanning, set it to
+    /// "Running" again afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DeviceNotFound`] if the device is gone after the scan, the
+    /// previously opened device stays open then.
+    pub fn rescan(&mut self) -> Result<()> {
+        self.adi.refresh_device_list()?;
+        let id = self
+            .adi
+            .device_id(DEVICE_NAME)
+            .ok_or(Error::DeviceNotFound)?;
+        if id != self.id {
+            let _ = self.adi.close_device(self.id);
+            self.adi.open_device(id)?;
+            self.id = id;
         }
-        Err(Error::DeviceNotFound)
+        Ok(())
     }
 
     /// Sets the application state to "Running".
@@ -159,6 +198,11 @@ impl KBus {
         }
     }
 
+    /// Returns the optional DAL functions provided by the firmware, see [`Capabilities`].
+    pub fn capabilities(&self) -> Capabilities {
+        self.adi.capabilities()
+    }
+
     /// Retrieves the sizes of the device's input and output areas.
     ///
     /// **Note:** The values obtained may require further validation.
@@ -170,14 +214,14 @@ impl KBus {
     }
 
     /// Creates a new [`Writer`] handle to begin a process data write operation.
-    pub fn writer(&mut self) -> Result<Writer> {
+    pub fn writer(&mut self) -> Result<Writer<'_>> {
         // As doc says, task_id is currently unused.
         let task_id = 0;
         Writer::new(self, task_id)
     }
 
     /// Creates a new [`Reader`] handle to begin a process data read operation.
-    pub fn reader(&mut self) -> Result<Reader> {
+    pub fn reader(&mut self) -> Result<Reader<'_>> {
         // As doc says, task_id is currently unused.
         let task_id = 0;
         Reader::new(self, task_id)
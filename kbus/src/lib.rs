@@ -4,13 +4,16 @@
 //! WAGO devices. It is built on top of the low-level FFI bindings found in the `kbus-sys`
 //! crate, which wrap the WAGO Device Abstraction Layer (DAL).
 //!
-//! The main entry point to interact with the bus is the [`KBus`] type. For error handling,
-//! refer to the [`Error`] type.
+//! The main entry point to interact with the bus is the [`KBus`] type. To share the bus
+//! between threads, use [`SharedKBus`]. For error handling, refer to the [`Error`] type.
 use kbus_sys as ffi;
 
 mod dal;
 mod error;
 mod kbus;
+mod shared;
 
+pub use dal::Capabilities;
 pub use error::Error;
 pub use kbus::KBus;
+pub use shared::SharedKBus;
@@ -0,0 +1,177 @@
+//! # Shared K-Bus Handle
+//!
+//! The DAL interface is `Send` but not `Sync`, so a [`KBus`] can only have a single owner.
+//! This module provides [`SharedKBus`], a cloneable handle which serializes access to the
+//! device with a mutex and runs the bus cycles on a dedicated I/O thread, so several
+//! subsystems can read and write process data without funneling everything through
+//! one task.
+//!
+//! The I/O thread only needs to trigger bus cycles and stop the device, so it runs
+//! on a private `Device` trait, which the tests implement with a fake device: the
+//! DAL isn't available off-target.
+
+use std::{
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, Result},
+    kbus::KBus,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Device operations used by the I/O thread and on drop.
+trait Device: Send + 'static {
+    fn trigger_bus_cycle(&mut self) -> Result<()>;
+    fn stop(&mut self) -> Result<()>;
+}
+
+impl Device for KBus {
+    fn trigger_bus_cycle(&mut self) -> Result<()> {
+        KBus::trigger_bus_cycle(self)
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        KBus::stop(self)
+    }
+}
+
+struct Inner<D: Device> {
+    kbus: Mutex<D>,
+    /// Set by the I/O thread when a bus cycle fails, the thread stops afterwards.
+    failed: AtomicBool,
+}
+
+impl<D: Device> Drop for Inner<D> {
+    fn drop(&mut self) {
+        let kbus = self.kbus.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let _ = kbus.stop();
+    }
+}
+
+impl<D: Device> Inner<D> {
+    /// Takes over `kbus` and spawns the I/O thread triggering its bus cycles.
+    fn spawn(kbus: D, cycle_time: Duration) -> Result<Arc<Inner<D>>> {
+        let inner = Arc::new(Inner {
+            kbus: Mutex::new(kbus),
+            failed: AtomicBool::new(false),
+        });
+
+        let weak = Arc::downgrade(&inner);
+        thread::Builder::new()
+            .name("kbus-io".into())
+            .spawn(move || io_thread(weak, cycle_time))
+            .map_err(Error::ThreadSpawn)?;
+
+        Ok(inner)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, D>> {
+        if self.failed.load(Ordering::Acquire) {
+            return Err(Error::BusCycleFailed);
+        }
+        // A panic in a user closure doesn't leave the device in an invalid state,
+        // readers and writers are finalized when dropped.
+        Ok(self.kbus.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// A thread-safe, cloneable handle to the K-Bus.
+///
+/// Bus cycles are triggered periodically by a dedicated I/O thread. Process data
+/// written through any handle is transferred with the next bus cycle. The device
+/// is stopped and closed when the last handle is dropped.
+#[derive(Clone)]
+pub struct SharedKBus {
+    inner: Arc<Inner<KBus>>,
+}
+
+impl SharedKBus {
+    /// Opens the K-Bus device, sets the application state to "Running" and spawns
+    /// the I/O thread triggering a bus cycle every `cycle_time`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device can't be opened or started, or
+    /// [`Error::ThreadSpawn`] if the I/O thread can't be spawned.
+    pub fn new(cycle_time: Duration) -> Result<SharedKBus> {
+        let mut kbus = KBus::new()?;
+        kbus.start()?;
+
+        Ok(SharedKBus {
+            inner: Inner::spawn(kbus, cycle_time)?,
+        })
+    }
+
+    /// Runs `f` with exclusive access to the K-Bus.
+    ///
+    /// Bus cycles and calls from other handles are blocked until `f` returns, so
+    /// `f` should only perform a short read or write transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BusCycleFailed`] if the I/O thread stopped after a failed
+    /// bus cycle, or the error returned by `f`.
+    pub fn with<R>(&self, f: impl FnOnce(&mut KBus) -> Result<R>) -> Result<R> {
+        let mut kbus = self.inner.lock()?;
+        f(&mut kbus)
+    }
+
+    /// Reads a series of bytes of the input process image starting at the given offset.
+    pub fn read_bytes(&self, offset: u32, data: &mut [u8]) -> Result<()> {
+        self.with(|kbus| kbus.reader()?.read_bytes(offset, data))
+    }
+
+    /// Reads a boolean value of the input process image from the specified bit offset.
+    pub fn read_bool(&self, bit_offset: u32) -> Result<bool> {
+        self.with(|kbus| {
+            let mut value = false;
+            kbus.reader()?.read_bool(bit_offset, &mut value)?;
+            Ok(value)
+        })
+    }
+
+    /// Writes a series of bytes to the output process image starting at the given offset.
+    pub fn write_bytes(&self, offset: u32, data: &mut [u8]) -> Result<()> {
+        self.with(|kbus| kbus.writer()?.write_bytes(offset, data))
+    }
+
+    /// Writes a boolean value to the output process image at the specified bit offset.
+    pub fn write_bool(&self, bit_offset: u32, value: bool) -> Result<()> {
+        self.with(|kbus| kbus.writer()?.write_bool(bit_offset, value))
+    }
+}
+
+/// Triggers bus cycles until the last [`SharedKBus`] handle is dropped or a cycle fails.
+fn io_thread<D: Device>(inner: Weak<Inner<D>>, cycle_time: Duration) {
+    let mut next_cycle = Instant::now();
+    while let Some(inner) = inner.upgrade() {
+        let result = inner
+            .kbus
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .trigger_bus_cycle();
+        if result.is_err() {
+            inner.failed.store(true, Ordering::Release);
+            return;
+        }
+        // Don't keep the device alive while sleeping
+        drop(inner);
+
+        next_cycle += cycle_time;
+        let now = Instant::now();
+        if next_cycle > now {
+            thread::sleep(next_cycle - now);
+        } else {
+            // Overrun, don't try to catch up with missed cycles
+            next_cycle = now;
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::AtomicU32;
+
+use super::*;
+
+const CYCLE_TIME: Duration = Duration::from_millis(1);
+
+/// A device counting its bus cycles, failing the cycle `fail_at` if set.
+#[derive(Default)]
+struct FakeDevice {
+    cycles: Arc<AtomicU32>,
+    stopped: Arc<AtomicBool>,
+    fail_at: Option<u32>,
+}
+
+impl Device for FakeDevice {
+    fn trigger_bus_cycle(&mut self) -> Result<()> {
+        let cycle = self.cycles.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.fail_at == Some(cycle) {
+            return Err(Error::DalError);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.stopped.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Waits up to a second for `condition`.
+fn wait_for(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !condition() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        thread::sleep(CYCLE_TIME);
+    }
+    true
+}
+
+#[test]
+fn test_bus_cycle_failed() {
+    let device = FakeDevice {
+        fail_at: Some(3),
+        ..FakeDevice::default()
+    };
+    let cycles = device.cycles.clone();
+    let inner = Inner::spawn(device, CYCLE_TIME).unwrap();
+
+    assert!(wait_for(|| inner.failed.load(Ordering::Acquire)));
+    assert!(matches!(inner.lock(), Err(Error::BusCycleFailed)));
+    // The I/O thread stopped after the failed cycle
+    thread::sleep(CYCLE_TIME * 10);
+    assert_eq!(cycles.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_drop() {
+    let device = FakeDevice::default();
+    let (cycles, stopped) = (device.cycles.clone(), device.stopped.clone());
+    let inner = Inner::spawn(device, CYCLE_TIME).unwrap();
+
+    assert!(wait_for(|| cycles.load(Ordering::SeqCst) >= 3));
+    assert!(inner.lock().is_ok());
+    assert!(!stopped.load(Ordering::SeqCst));
+
+    // Dropping the last handle stops the device and ends the I/O thread
+    drop(inner);
+    assert!(wait_for(|| stopped.load(Ordering::SeqCst)));
+    let count = cycles.load(Ordering::SeqCst);
+    thread::sleep(CYCLE_TIME * 10);
+    assert_eq!(cycles.load(Ordering::SeqCst), count);
+}
@@ -0,0 +1,111 @@
+//! Embedding the bridge in an application
+//!
+//! [`Bridge`] runs the K-Bus task of the bridge without MQTT, so an application
+//! linking the crate consumes the input changes as a stream and switches outputs
+//! directly, with the same cycle, filtering and output handling as the MQTT
+//! bridge.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, anyhow};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    task::JoinHandle,
+};
+use tokio_stream::{Stream, wrappers::UnboundedReceiverStream};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::Config,
+    kbus::{InputEvent, KBusCommand, KBusEvent, OUTPUT_SIZE, OutputWrite, kbus_task},
+};
+
+#[cfg(test)]
+mod tests;
+
+type Subscribers = Arc<Mutex<Vec<UnboundedSender<KBusEvent>>>>;
+
+/// The K-Bus task of the bridge, run by an embedding application.
+pub struct Bridge {
+    commands: UnboundedSender<KBusCommand>,
+    subscribers: Subscribers,
+    cancellation_token: CancellationToken,
+    kbus_task: JoinHandle<Result<(), anyhow::Error>>,
+}
+
+/// Sends the input changes to the subscribers until the K-Bus task ends.
+async fn forward_inputs(mut input_rx: UnboundedReceiver<InputEvent>, subscribers: Subscribers) {
+    while let Some(event) = input_rx.recv().await {
+        if let InputEvent::Channel(event) = event {
+            let mut subscribers = subscribers.lock().unwrap();
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+    // Ends the streams of the subscribers
+    subscribers.lock().unwrap().clear();
+}
+
+impl Bridge {
+    /// Starts the K-Bus task with `config`, the MQTT settings are unused.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn start(config: Config) -> Bridge {
+        let (input_tx, input_rx) = unbounded_channel();
+        let (commands, command_rx) = unbounded_channel();
+        let cancellation_token = CancellationToken::new();
+        let subscribers = Subscribers::default();
+        tokio::spawn(forward_inputs(input_rx, subscribers.clone()));
+        let kbus_task = tokio::spawn(kbus_task(
+            config,
+            input_tx,
+            command_rx,
+            cancellation_token.clone(),
+        ));
+        Bridge {
+            commands,
+            subscribers,
+            cancellation_token,
+            kbus_task,
+        }
+    }
+
+    /// Returns a stream of the changes of the monitored input channels from now on.
+    ///
+    /// Only changes are reported: the first K-Bus cycle reports the channels which
+    /// are on, a channel which is off is reported once it changes. A stream
+    /// subscribed after the first cycle doesn't get the current state. The stream
+    /// ends when the K-Bus task ends.
+    pub fn subscribe_inputs(&self) -> impl Stream<Item = KBusEvent> + use<> {
+        let (tx, rx) = unbounded_channel();
+        self.subscribers.lock().unwrap().push(tx);
+        UnboundedReceiverStream::new(rx)
+    }
+
+    /// Switches an output channel in the next K-Bus cycle.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the channel is beyond the output process image or the K-Bus task
+    /// ended.
+    pub fn write_output(&self, channel: u16, value: bool) -> Result<(), anyhow::Error> {
+        if usize::from(channel) >= OUTPUT_SIZE {
+            return Err(anyhow!(
+                "output channel {channel} out of range: maximum supported channel is {}",
+                OUTPUT_SIZE - 1
+            ));
+        }
+        self.commands
+            .send(KBusCommand::Output(OutputWrite::new(
+                KBusEvent { channel, value },
+                None,
+            )))
+            .map_err(|_| anyhow!("K-Bus task ended"))
+    }
+
+    /// Stops the K-Bus task and ends the input streams, returns its error if it
+    /// failed.
+    pub async fn shutdown(self) -> Result<(), anyhow::Error> {
+        self.cancellation_token.cancel();
+        self.kbus_task.await.context("failed to join K-Bus task")?
+    }
+}
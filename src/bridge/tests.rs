@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use kbus_mock::KBusHandle;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+
+use super::*;
+
+#[tokio::test]
+async fn test_bridge() {
+    let kbus = KBusHandle::register("libpackbus").unwrap();
+    let bridge = Bridge::start(Config::default());
+    let mut inputs = Box::pin(bridge.subscribe_inputs());
+
+    kbus.set_input_bit(5, true).unwrap();
+    let event = timeout(Duration::from_secs(5), async {
+        loop {
+            let event = inputs.next().await.unwrap();
+            if event.channel == 5 {
+                return event;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(event.value);
+
+    // A late subscriber only gets the changes from now on, not the state of channel 5
+    let mut late = Box::pin(bridge.subscribe_inputs());
+    kbus.set_input_bit(6, true).unwrap();
+    let event = timeout(Duration::from_secs(5), late.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((event.channel, event.value), (6, true));
+
+    bridge.write_output(3, true).unwrap();
+    timeout(Duration::from_secs(5), async {
+        while !kbus.get_output_bit(3).unwrap() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(bridge.write_output(OUTPUT_SIZE as u16, true).is_err());
+
+    bridge.shutdown().await.unwrap();
+    // The stream ends with the K-Bus task
+    let ended = timeout(Duration::from_secs(5), async {
+        while inputs.next().await.is_some() {}
+        while late.next().await.is_some() {}
+    })
+    .await;
+    assert!(ended.is_ok());
+}
@@ -0,0 +1,86 @@
+//! Embedded MQTT broker
+//!
+//! Standalone cells without broker infrastructure can run a minimal MQTT broker
+//! (rumqttd) inside the bridge. The bridge connects to it locally, HMIs on the
+//! machine network connect to the PFC directly. The broker runs on its own
+//! threads until the process exits, it keeps no messages across restarts.
+
+use std::{collections::HashMap, thread};
+
+use anyhow::Context;
+use rumqttd::{Broker, ConnectionSettings, RouterConfig, ServerSettings};
+use tracing::{error, info};
+
+use crate::config::{BrokerConfig, MqttConfig};
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of packets queued for a client.
+const MAX_OUTGOING_PACKETS: u64 = 200;
+
+/// Size and number of the segments of the router's commit log, bounding its memory.
+const MAX_SEGMENT_SIZE: usize = 1024 * 1024;
+const MAX_SEGMENT_COUNT: usize = 10;
+
+/// Time a client has to send CONNECT after opening the connection.
+const CONNECTION_TIMEOUT_MS: u16 = 60_000;
+
+/// Maximum size of the payload of published messages.
+const MAX_PAYLOAD_SIZE: usize = 256 * 1024;
+
+/// Maximum number of unacknowledged QoS 1 and 2 messages per client.
+const MAX_INFLIGHT: usize = 100;
+
+/// Returns the rumqttd configuration of an MQTT 3.1.1 broker.
+///
+/// With MQTT credentials configured, the broker only accepts clients with the
+/// credentials of the bridge.
+fn broker_config(config: &BrokerConfig, mqtt: &MqttConfig) -> rumqttd::Config {
+    let auth = match (&mqtt.username, &mqtt.password) {
+        (Some(username), Some(password)) => {
+            Some(HashMap::from([(username.clone(), password.clone())]))
+        }
+        _ => None,
+    };
+    let server = ServerSettings {
+        name: "v4".to_owned(),
+        listen: config.listen,
+        tls: None,
+        next_connection_delay_ms: 1,
+        connections: ConnectionSettings {
+            connection_timeout_ms: CONNECTION_TIMEOUT_MS,
+            max_payload_size: MAX_PAYLOAD_SIZE,
+            max_inflight_count: MAX_INFLIGHT,
+            auth,
+            external_auth: None,
+            dynamic_filters: true,
+        },
+    };
+    rumqttd::Config {
+        router: RouterConfig {
+            max_connections: config.max_connections,
+            max_outgoing_packet_count: MAX_OUTGOING_PACKETS,
+            max_segment_size: MAX_SEGMENT_SIZE,
+            max_segment_count: MAX_SEGMENT_COUNT,
+            ..RouterConfig::default()
+        },
+        v4: Some(HashMap::from([("1".to_owned(), server)])),
+        ..rumqttd::Config::default()
+    }
+}
+
+/// Starts the embedded broker on a separate thread.
+pub fn start(config: &BrokerConfig, mqtt: &MqttConfig) -> Result<(), anyhow::Error> {
+    let mut broker = Broker::new(broker_config(config, mqtt));
+    info!(listen = %config.listen, "starting embedded MQTT broker");
+    thread::Builder::new()
+        .name("broker".to_owned())
+        .spawn(move || {
+            if let Err(err) = broker.start() {
+                error!(error = %err, "embedded MQTT broker failed");
+            }
+        })
+        .context("failed to start embedded MQTT broker")?;
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn test_broker_config() {
+    let config = BrokerConfig {
+        listen: "0.0.0.0:1884".parse().unwrap(),
+        max_connections: 8,
+    };
+    let broker = broker_config(&config, &MqttConfig::default());
+    assert_eq!(broker.router.max_connections, 8);
+    let server = &broker.v4.as_ref().unwrap()["1"];
+    assert_eq!(server.listen, config.listen);
+    assert!(server.connections.auth.is_none());
+
+    // Clients need the credentials of the bridge
+    let mqtt = MqttConfig {
+        username: Some("bridge".to_owned()),
+        password: Some("secret".to_owned()),
+        ..MqttConfig::default()
+    };
+    let broker = broker_config(&config, &mqtt);
+    let auth = broker.v4.unwrap()["1"].connections.auth.clone().unwrap();
+    assert_eq!(auth["bridge"], "secret");
+}
@@ -0,0 +1,53 @@
+//! Compile-time information about the running binary
+//!
+//! Devices of a fleet can run different variants of the bridge (features, mock or
+//! real K-Bus, target), so the build info is published on the retained `buildinfo`
+//! topic and printed by `--version`.
+
+use serde_json::json;
+
+#[cfg(test)]
+mod tests;
+
+/// Version of the bridge.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Abbreviated hash of the built git commit, `unknown` outside of a git checkout.
+pub const GIT_HASH: &str = env!("BUILD_GIT_HASH");
+/// Version of the compiler.
+pub const RUSTC_VERSION: &str = env!("BUILD_RUSTC_VERSION");
+/// Target triple of the build.
+pub const TARGET: &str = env!("BUILD_TARGET");
+/// K-Bus implementation, the mock is used off-target or with the `mock-kbus` feature.
+pub const KBUS: &str = if cfg!(mock_kbus) { "mock" } else { "real" };
+
+/// Returns the enabled cargo features, sorted by name.
+pub fn features() -> Vec<&'static str> {
+    env!("BUILD_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect()
+}
+
+/// Returns the payload published on `buildinfo`.
+pub fn build_info() -> serde_json::Value {
+    json!({
+        "version": VERSION,
+        "git_hash": GIT_HASH,
+        "rustc": RUSTC_VERSION,
+        "target": TARGET,
+        "features": features(),
+        "kbus": KBUS,
+    })
+}
+
+/// Returns the text printed by `--version`.
+pub fn version_text() -> String {
+    format!(
+        "KBUS MQTT Bridge v{VERSION} ({GIT_HASH})\n\
+         target: {TARGET}\n\
+         rustc: {RUSTC_VERSION}\n\
+         features: {}\n\
+         kbus: {KBUS}",
+        features().join(", ")
+    )
+}
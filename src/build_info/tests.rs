@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn test_build_info() {
+    let info = build_info();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!info["git_hash"].as_str().unwrap().is_empty());
+    assert!(info["rustc"].as_str().unwrap().starts_with("rustc "));
+    assert!(!info["target"].as_str().unwrap().is_empty());
+    assert_eq!(info["kbus"], KBUS);
+
+    let features = features();
+    assert!(features.is_sorted());
+    assert_eq!(
+        features.contains(&"self-update"),
+        cfg!(feature = "self-update")
+    );
+
+    assert!(version_text().starts_with(&format!("KBUS MQTT Bridge v{VERSION} (")));
+}
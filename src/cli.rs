@@ -0,0 +1,342 @@
+//! One-shot K-Bus commands for commissioning
+//!
+//! The `read`, `write` and `scan` subcommands access the K-Bus directly, run a single
+//! bus cycle and exit, so the device state can be checked during commissioning
+//! without an MQTT client. With `--json`, the result is printed as a single JSON
+//! object, which shell scripts and health checks can parse. `init-config` prints a
+//! starter configuration naming every channel found by the scan, `bench` measures
+//! the read-back latency of an output wired back to an input. `cleanup-retained`
+//! is run with the configuration by the binary, it only accesses the broker.
+
+use std::fmt::Write;
+
+use anyhow::{Context, anyhow};
+use bitvec::prelude::*;
+use serde_json::json;
+
+use crate::kbus::{INPUT_SIZE, KBus, OUTPUT_SIZE};
+
+pub mod bench;
+#[cfg(test)]
+mod tests;
+
+use bench::BenchResult;
+
+/// A one-shot command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Access the K-Bus directly
+    KBus(KBusCommand),
+    /// Clear the retained topics of the device on the broker, run with the
+    /// configuration instead of the K-Bus
+    CleanupRetained,
+}
+
+/// A one-shot K-Bus command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KBusCommand {
+    /// Read all input channels, or just the given one
+    Read { channel: Option<u16> },
+    /// Set an output channel
+    Write { channel: u16, value: bool },
+    /// Report the process image sizes and active input channels
+    Scan,
+    /// Print a starter configuration with a channel map of the process images
+    InitConfig,
+    /// Toggle an output wired back to an input and measure the read-back latency
+    Bench {
+        output: u16,
+        input: u16,
+        iterations: u32,
+    },
+}
+
+/// Output of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Report {
+    Read {
+        channel: Option<u16>,
+        inputs: BitVec<u8>,
+    },
+    Write {
+        channel: u16,
+        value: bool,
+    },
+    Scan {
+        io_sizes: (u32, u32),
+        inputs: BitVec<u8>,
+    },
+    InitConfig {
+        io_sizes: (u32, u32),
+        inputs: BitVec<u8>,
+    },
+    Bench(BenchResult),
+}
+
+/// Parses a channel number in the range of the process image.
+fn parse_channel(arg: Option<&String>, size: usize) -> Result<u16, anyhow::Error> {
+    let arg = arg.context("missing channel")?;
+    let channel: u16 = arg
+        .parse()
+        .with_context(|| format!("invalid channel '{arg}'"))?;
+    if usize::from(channel) >= size {
+        return Err(anyhow!(
+            "channel {channel} out of range: maximum supported channel is {}",
+            size - 1
+        ));
+    }
+    Ok(channel)
+}
+
+/// Number of channels of a process image of `bytes`, at most `size`.
+fn image_channels(bytes: u32, size: usize) -> usize {
+    usize::try_from(bytes)
+        .unwrap_or(usize::MAX)
+        .saturating_mul(8)
+        .min(size)
+}
+
+/// Returns a commented starter configuration naming every channel of the process
+/// images of `io_sizes` bytes, marking the inputs active during the scan.
+///
+/// The DAL doesn't report the modules behind the process images, so channels are
+/// grouped in blocks of 8, e.g. input channel 11 is published on `input/di1/ch3`.
+pub fn starter_config(io_sizes: (u32, u32), inputs: &BitSlice<u8>) -> String {
+    let input_channels = image_channels(io_sizes.0, INPUT_SIZE);
+    let output_channels = image_channels(io_sizes.1, OUTPUT_SIZE);
+    let mut config = format!(
+        "# Starter configuration generated by `kbus_mqtt_bridge init-config`
+#
+# Process images: {} input and {} output bytes, {input_channels} input and
+# {output_channels} output channels supported by the bridge.
+# The K-Bus doesn't report the modules behind the process images, so channels are
+# grouped in blocks of 8 (`di0`, `do0`, ...) matching 8-channel modules. Rename
+# the groups and names after the connected modules and their functions, e.g.
+# group = \"hvac\" and name = \"fan_feedback\", and remove unused channels.
+
+device_name = \"kbus_mqtt_bridge\"
+
+[mqtt]
+broker_host = \"localhost\"
+broker_port = 1883
+",
+        io_sizes.0, io_sizes.1
+    );
+    for (direction, table, prefix, channels) in [
+        ("Input", "inputs", "di", input_channels),
+        ("Output", "outputs", "do", output_channels),
+    ] {
+        if channels == 0 {
+            continue;
+        }
+        let _ = write!(config, "\n# {direction} channels\n");
+        for channel in 0..channels {
+            let active = table == "inputs" && inputs.get(channel).is_some_and(|bit| *bit);
+            let _ = write!(
+                config,
+                "[[{table}.groups]]\nchannel = {channel}{}\ngroup = \"{prefix}{}\"\nname = \"ch{}\"\n",
+                if active {
+                    " # active during the scan"
+                } else {
+                    ""
+                },
+                channel / 8,
+                channel % 8
+            );
+        }
+    }
+    config
+}
+
+/// Parses the number of benchmark iterations, the default if not given.
+fn parse_iterations(arg: Option<&String>) -> Result<u32, anyhow::Error> {
+    let Some(arg) = arg else {
+        return Ok(bench::DEFAULT_ITERATIONS);
+    };
+    match arg.parse() {
+        Ok(iterations @ 1..=bench::MAX_ITERATIONS) => Ok(iterations),
+        _ => Err(anyhow!(
+            "invalid iterations '{arg}': must be between 1 and {}",
+            bench::MAX_ITERATIONS
+        )),
+    }
+}
+
+/// Parses a `true`/`false` value, also accepting `on`/`off` and `1`/`0`.
+fn parse_value(arg: Option<&String>) -> Result<bool, anyhow::Error> {
+    let arg = arg.context("missing value")?;
+    match arg.to_ascii_lowercase().as_str() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(anyhow!("invalid value '{arg}'")),
+    }
+}
+
+impl Command {
+    /// Parses the subcommand from the command line arguments (without the program name).
+    ///
+    /// Options (arguments starting with `-`) and their values are skipped. Returns
+    /// `None` if no subcommand is given, i.e. the bridge should run.
+    pub fn parse(args: &[String]) -> Result<Option<Command>, anyhow::Error> {
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-c" || arg == "--config" || arg == "-p" || arg == "--profile" {
+                args.next();
+            } else if !arg.starts_with('-') {
+                positional.push(arg);
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let Some(name) = positional.next() else {
+            return Ok(None);
+        };
+        let command = match name.as_str() {
+            "read" => KBusCommand::Read {
+                channel: positional
+                    .next()
+                    .map(|arg| parse_channel(Some(arg), INPUT_SIZE))
+                    .transpose()?,
+            },
+            "write" => KBusCommand::Write {
+                channel: parse_channel(positional.next(), OUTPUT_SIZE)?,
+                value: parse_value(positional.next())?,
+            },
+            "scan" => KBusCommand::Scan,
+            "init-config" => KBusCommand::InitConfig,
+            "cleanup-retained" => {
+                if let Some(arg) = positional.next() {
+                    return Err(anyhow!("unexpected argument '{arg}'"));
+                }
+                return Ok(Some(Command::CleanupRetained));
+            }
+            "bench" => KBusCommand::Bench {
+                output: parse_channel(positional.next(), OUTPUT_SIZE)?,
+                input: parse_channel(positional.next(), INPUT_SIZE)?,
+                iterations: parse_iterations(positional.next())?,
+            },
+            _ => return Err(anyhow!("unknown command '{name}'")),
+        };
+        if let Some(arg) = positional.next() {
+            return Err(anyhow!("unexpected argument '{arg}'"));
+        }
+        Ok(Some(Command::KBus(command)))
+    }
+}
+
+/// Triggers a bus cycle and reads all input channels.
+fn read_inputs(kbus: &mut KBus) -> Result<BitVec<u8>, anyhow::Error> {
+    kbus.trigger_bus_cycle()
+        .context("failed to trigger K-Bus cycle")?;
+    kbus.reader()
+        .context("failed to create K-Bus reader")?
+        .read_range(0, INPUT_SIZE)
+        .context("failed to read from K-Bus")
+}
+
+impl KBusCommand {
+    /// Runs the command on the K-Bus.
+    pub fn run(self) -> Result<Report, anyhow::Error> {
+        let mut kbus = KBus::new().context("failed to create K-Bus instance")?;
+        kbus.start().context("failed to start K-Bus instance")?;
+
+        Ok(match self {
+            KBusCommand::Read { channel } => Report::Read {
+                channel,
+                inputs: read_inputs(&mut kbus)?,
+            },
+            KBusCommand::Write { channel, value } => {
+                kbus.writer()
+                    .context("failed to create K-Bus writer")?
+                    .write_bool(u32::from(channel), value)
+                    .context("failed to write to K-Bus")?;
+                read_inputs(&mut kbus)?;
+                Report::Write { channel, value }
+            }
+            KBusCommand::Scan => Report::Scan {
+                inputs: read_inputs(&mut kbus)?,
+                io_sizes: kbus.io_sizes().context("failed to get K-Bus I/O sizes")?,
+            },
+            KBusCommand::InitConfig => Report::InitConfig {
+                inputs: read_inputs(&mut kbus)?,
+                io_sizes: kbus.io_sizes().context("failed to get K-Bus I/O sizes")?,
+            },
+            KBusCommand::Bench {
+                output,
+                input,
+                iterations,
+            } => Report::Bench(bench::run(&mut kbus, output, input, iterations)?),
+        })
+    }
+}
+
+impl Report {
+    /// Formats the report as a JSON object.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Report::Read {
+                channel: Some(channel),
+                inputs,
+            } => json!({
+                "channel": channel,
+                "value": inputs[usize::from(*channel)],
+            }),
+            Report::Read {
+                channel: None,
+                inputs,
+            } => json!({
+                "inputs": inputs.iter().by_vals().collect::<Vec<_>>(),
+            }),
+            Report::Write { channel, value } => json!({
+                "channel": channel,
+                "value": value,
+            }),
+            Report::Scan { io_sizes, inputs } => json!({
+                "io_sizes": { "input": io_sizes.0, "output": io_sizes.1 },
+                "channels": { "input": INPUT_SIZE, "output": OUTPUT_SIZE },
+                "active_inputs": inputs.iter_ones().collect::<Vec<_>>(),
+            }),
+            Report::InitConfig { io_sizes, inputs } => json!({
+                "config": starter_config(*io_sizes, inputs),
+            }),
+            Report::Bench(result) => json!(result),
+        }
+    }
+
+    /// Formats the report as human-readable text.
+    pub fn to_text(&self) -> String {
+        match self {
+            Report::Read {
+                channel: Some(channel),
+                inputs,
+            } => format!("input/{channel}: {}", inputs[usize::from(*channel)]),
+            Report::Read {
+                channel: None,
+                inputs,
+            } => inputs
+                .iter()
+                .by_vals()
+                .enumerate()
+                .map(|(channel, value)| format!("input/{channel}: {value}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Report::Write { channel, value } => format!("output/{channel}: {value}"),
+            Report::Scan { io_sizes, inputs } => {
+                let active: Vec<_> = inputs.iter_ones().map(|i| i.to_string()).collect();
+                format!(
+                    "I/O sizes: input {}, output {}\nChannels: input {INPUT_SIZE}, output {OUTPUT_SIZE}\nActive inputs: {}",
+                    io_sizes.0,
+                    io_sizes.1,
+                    if active.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        active.join(", ")
+                    }
+                )
+            }
+            Report::InitConfig { io_sizes, inputs } => starter_config(*io_sizes, inputs),
+            Report::Bench(result) => result.to_text(),
+        }
+    }
+}
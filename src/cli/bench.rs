@@ -0,0 +1,158 @@
+//! Loopback benchmark of the K-Bus
+//!
+//! An output channel wired back to an input channel (or the loopback of the mock
+//! K-Bus) is toggled repeatedly, every write is followed by bus cycles until the
+//! input reads the written value. The number of cycles and the time until then
+//! are the read-back latency, which validates the hardware and compares firmware
+//! versions on the same device.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, anyhow};
+use serde::Serialize;
+
+use crate::kbus::KBus;
+
+#[cfg(test)]
+mod tests;
+
+/// Default number of toggles of the output
+pub const DEFAULT_ITERATIONS: u32 = 100;
+/// Maximum number of toggles of the output
+pub const MAX_ITERATIONS: u32 = 100_000;
+/// Bus cycles the input may take to follow the output before the loopback is
+/// considered missing
+const MAX_LATENCY_CYCLES: u32 = 100;
+
+/// Minimum, mean and maximum of the measured values.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Summary {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    fn of(values: &[f64]) -> Summary {
+        Summary {
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: values.iter().sum::<f64>() / values.len().max(1) as f64,
+            max: values.iter().copied().fold(0.0, f64::max),
+        }
+    }
+}
+
+/// Result of a loopback benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchResult {
+    pub output: u16,
+    pub input: u16,
+    pub iterations: u32,
+    /// Bus cycles from the write until the input read the value
+    pub latency_cycles: Summary,
+    /// Milliseconds from the write until the input read the value
+    pub latency_ms: Summary,
+    /// Milliseconds of a single bus cycle
+    pub cycle_ms: Summary,
+}
+
+/// Bus cycles and time until the input follows a write of the output.
+struct Measurement {
+    cycles: u32,
+    latency: Duration,
+}
+
+/// Writes `value` to `output` and triggers bus cycles until `input` reads it,
+/// recording the duration of every cycle in `cycle_times`.
+fn follow(
+    kbus: &mut KBus,
+    (output, input): (u16, u16),
+    value: bool,
+    cycle_times: &mut Vec<f64>,
+) -> Result<Measurement, anyhow::Error> {
+    let start = Instant::now();
+    kbus.writer()
+        .context("failed to create K-Bus writer")?
+        .write_bool(u32::from(output), value)
+        .context("failed to write to K-Bus")?;
+    for cycles in 1..=MAX_LATENCY_CYCLES {
+        let cycle_start = Instant::now();
+        kbus.trigger_bus_cycle()
+            .context("failed to trigger K-Bus cycle")?;
+        cycle_times.push(cycle_start.elapsed().as_secs_f64() * 1000.0);
+
+        let mut read = false;
+        kbus.reader()
+            .context("failed to create K-Bus reader")?
+            .read_bool(u32::from(input), &mut read)
+            .context("failed to read from K-Bus")?;
+        if read == value {
+            return Ok(Measurement {
+                cycles,
+                latency: start.elapsed(),
+            });
+        }
+    }
+    Err(anyhow!(
+        "input {input} didn't follow output {output} within {MAX_LATENCY_CYCLES} bus cycles, check the loopback wiring"
+    ))
+}
+
+/// Toggles `output` `iterations` times and measures how fast `input` follows.
+///
+/// The output is switched off first, without measuring, and left off.
+pub fn run(
+    kbus: &mut KBus,
+    output: u16,
+    input: u16,
+    iterations: u32,
+) -> Result<BenchResult, anyhow::Error> {
+    let channels = (output, input);
+    let mut cycle_times = Vec::new();
+    follow(kbus, channels, false, &mut cycle_times)?;
+
+    let mut latency_cycles = Vec::with_capacity(iterations as usize);
+    let mut latency_ms = Vec::with_capacity(iterations as usize);
+    cycle_times.clear();
+    for iteration in 0..iterations {
+        let measurement = follow(kbus, channels, iteration % 2 == 0, &mut cycle_times)?;
+        latency_cycles.push(f64::from(measurement.cycles));
+        latency_ms.push(measurement.latency.as_secs_f64() * 1000.0);
+    }
+    if iterations % 2 == 1 {
+        follow(kbus, channels, false, &mut Vec::new())?;
+    }
+
+    Ok(BenchResult {
+        output,
+        input,
+        iterations,
+        latency_cycles: Summary::of(&latency_cycles),
+        latency_ms: Summary::of(&latency_ms),
+        cycle_ms: Summary::of(&cycle_times),
+    })
+}
+
+impl BenchResult {
+    /// Formats the result as human-readable text.
+    pub fn to_text(&self) -> String {
+        format!(
+            "Loopback output/{} -> input/{}, {} iterations\n\
+             Latency: min {:.0}, mean {:.2}, max {:.0} cycles\n\
+             Latency: min {:.3}, mean {:.3}, max {:.3} ms\n\
+             Cycle time: min {:.3}, mean {:.3}, max {:.3} ms",
+            self.output,
+            self.input,
+            self.iterations,
+            self.latency_cycles.min,
+            self.latency_cycles.mean,
+            self.latency_cycles.max,
+            self.latency_ms.min,
+            self.latency_ms.mean,
+            self.latency_ms.max,
+            self.cycle_ms.min,
+            self.cycle_ms.mean,
+            self.cycle_ms.max,
+        )
+    }
+}
@@ -0,0 +1,44 @@
+use kbus_mock::KBusHandle;
+
+use super::*;
+
+#[test]
+fn test_bench() {
+    let handle = KBusHandle::new();
+    handle.set_loopback(true);
+    let mut kbus = handle.kbus();
+
+    let result = run(&mut kbus, 3, 3, 5).unwrap();
+    assert_eq!(result.iterations, 5);
+    // The mock mirrors the outputs within the cycle they are written in
+    assert_eq!(
+        result.latency_cycles,
+        Summary {
+            min: 1.0,
+            mean: 1.0,
+            max: 1.0
+        }
+    );
+    assert!(result.latency_ms.min <= result.latency_ms.max);
+    assert!(!handle.get_output_bit(3).unwrap());
+    // Settling, 5 toggles and switching off again
+    assert_eq!(handle.cycles(), 7);
+    assert!(
+        result
+            .to_text()
+            .starts_with("Loopback output/3 -> input/3, 5 iterations\n")
+    );
+}
+
+#[test]
+fn test_bench_no_loopback() {
+    let handle = KBusHandle::new();
+    let mut kbus = handle.kbus();
+
+    // The input is off already, but never follows the output on
+    let err = run(&mut kbus, 3, 3, 5).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("check the loopback wiring"),
+        "{err:#}"
+    );
+}
@@ -0,0 +1,147 @@
+use super::*;
+use crate::config::Config;
+
+fn parse(args: &[&str]) -> Result<Option<Command>, anyhow::Error> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Command::parse(&args)
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse(&[]).unwrap(), None);
+    assert_eq!(parse(&["-c", "config.toml"]).unwrap(), None);
+    assert_eq!(
+        parse(&["read"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Read { channel: None }))
+    );
+    assert_eq!(
+        parse(&["--json", "read", "5"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Read { channel: Some(5) }))
+    );
+    assert_eq!(
+        parse(&["--config", "read", "write", "3", "on"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Write {
+            channel: 3,
+            value: true
+        }))
+    );
+    assert_eq!(
+        parse(&["scan", "--json"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Scan))
+    );
+    assert_eq!(
+        parse(&["init-config"]).unwrap(),
+        Some(Command::KBus(KBusCommand::InitConfig))
+    );
+    assert_eq!(
+        parse(&["-c", "config.toml", "cleanup-retained"]).unwrap(),
+        Some(Command::CleanupRetained)
+    );
+    assert_eq!(
+        parse(&["bench", "3", "5"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Bench {
+            output: 3,
+            input: 5,
+            iterations: bench::DEFAULT_ITERATIONS
+        }))
+    );
+    assert_eq!(
+        parse(&["bench", "3", "5", "1000"]).unwrap(),
+        Some(Command::KBus(KBusCommand::Bench {
+            output: 3,
+            input: 5,
+            iterations: 1000
+        }))
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    for args in [
+        &["dump"][..],
+        &["read", "90"],
+        &["read", "x"],
+        &["read", "1", "2"],
+        &["write", "3"],
+        &["write", "3", "maybe"],
+        &["write", "90", "true"],
+        &["scan", "all"],
+        &["init-config", "config.toml"],
+        &["cleanup-retained", "all"],
+        &["bench", "3"],
+        &["bench", "3", "90"],
+        &["bench", "3", "5", "0"],
+        &["bench", "3", "5", "1000000"],
+    ] {
+        assert!(parse(args).is_err(), "{args:?}");
+    }
+}
+
+#[test]
+fn test_report_format() {
+    let mut inputs = bitvec![u8, LocalBits; 0; INPUT_SIZE];
+    inputs.set(1, true);
+    inputs.set(5, true);
+
+    let report = Report::Read {
+        channel: Some(5),
+        inputs: inputs.clone(),
+    };
+    assert_eq!(report.to_json(), json!({"channel": 5, "value": true}));
+    assert_eq!(report.to_text(), "input/5: true");
+
+    let report = Report::Read {
+        channel: None,
+        inputs: inputs.clone(),
+    };
+    assert_eq!(report.to_json()["inputs"][1], true);
+    assert_eq!(report.to_json()["inputs"][2], false);
+    assert!(
+        report
+            .to_text()
+            .starts_with("input/0: false\ninput/1: true\n")
+    );
+
+    let report = Report::Scan {
+        io_sizes: (12, 4),
+        inputs,
+    };
+    assert_eq!(
+        report.to_json(),
+        json!({
+            "io_sizes": {"input": 12, "output": 4},
+            "channels": {"input": INPUT_SIZE, "output": OUTPUT_SIZE},
+            "active_inputs": [1, 5],
+        })
+    );
+    assert!(report.to_text().ends_with("Active inputs: 1, 5"));
+}
+
+#[test]
+fn test_starter_config() {
+    let mut inputs = bitvec![u8, LocalBits; 0; INPUT_SIZE];
+    inputs.set(11, true);
+
+    let text = starter_config((2, 1), &inputs);
+    let config: Config = toml::from_str(&text).unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.inputs.groups.len(), 16);
+    assert_eq!(config.outputs.groups.len(), 8);
+    assert_eq!(config.inputs.group(11).unwrap().path(), "di1/ch3");
+    assert_eq!(config.outputs.group(7).unwrap().path(), "do0/ch7");
+    assert!(text.contains("channel = 11 # active during the scan\n"));
+    assert!(!text.contains("channel = 10 #"));
+
+    // Sizes beyond the supported channels, as reported by some firmware
+    let config: Config = toml::from_str(&starter_config((12000, 12000), &inputs)).unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.inputs.groups.len(), INPUT_SIZE);
+    assert_eq!(config.outputs.groups.len(), OUTPUT_SIZE);
+
+    let report = Report::InitConfig {
+        io_sizes: (0, 1),
+        inputs,
+    };
+    assert_eq!(report.to_json()["config"], report.to_text());
+    assert!(!report.to_text().contains("[[inputs.groups]]"));
+}
@@ -1,17 +1,128 @@
 use std::{
+    collections::BTreeMap,
     env,
     fs::File,
     io::Read,
+    net::{Ipv4Addr, SocketAddr},
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::Context;
+use base64::prelude::*;
+use chrono::{NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    kbus::{INPUT_SIZE, MAX_SOE_CAPACITY, OUTPUT_SIZE},
+    rules::Expr,
+};
+
+mod include;
 
 #[cfg(test)]
 mod tests;
 
+/// Topic and payload layout used for publishing input events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadProfile {
+    /// One topic per channel (`input/<n>`) with `true`/`false` payloads
+    #[default]
+    Plain,
+    /// One topic per channel with JSON payloads carrying a timestamp and sequence number
+    Json,
+    /// WAGO Cloud style collection/telemetry JSON messages on the `telemetry` topic
+    WagoCloud,
+}
+
+/// Topic layout of the input and output channels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicProfile {
+    /// Channels on `input/<n>` and `output/<n>` under the topic prefix
+    #[default]
+    Bridge,
+    /// Channels in the Tasmota convention (`cmnd/<device_name>/POWER<i>`,
+    /// `stat/<device_name>/...` and `tele/<device_name>/...`)
+    Tasmota,
+}
+
+/// Source of the device identifier used in the topic prefix and the claim.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdentitySource {
+    /// MAC address of the first network interface
+    #[default]
+    Mac,
+    /// Hash of `/etc/machine-id`, stable on devices whose MAC changes (bonded or
+    /// bridged interfaces)
+    MachineId,
+}
+
+/// Format of the timestamps in published payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// RFC 3339 string in UTC, e.g. `2025-03-03T06:00:00.000000+00:00`
+    #[default]
+    Rfc3339Utc,
+    /// RFC 3339 string in the local time zone of the device
+    Rfc3339Local,
+    /// Number of milliseconds since the Unix epoch
+    EpochMillis,
+}
+
+/// Severity of an alarm, for sorting and coloring on an HMI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmSeverity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+/// An alarm raised while its condition holds, published on `alarms`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlarmConfig {
+    /// Name of the alarm, used in the acknowledgment topic `alarms/<name>/ack`
+    pub name: String,
+
+    /// Logical expression over monitored input channels, like a rule (e.g. `in3 && !in7`)
+    pub condition: String,
+
+    #[serde(default)]
+    pub severity: AlarmSeverity,
+
+    /// Text shown to the operator
+    #[serde(default)]
+    pub message: String,
+
+    /// Keep the alarm active after the condition cleared until it's acknowledged
+    #[serde(default)]
+    pub latching: bool,
+}
+
+/// Handling of retained incoming command messages.
+///
+/// Retained commands are delivered by the broker on every (re)subscription, so
+/// accepting them re-applies the last output state after a restart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetainedCommands {
+    /// Retained commands are applied like any other command
+    #[default]
+    Accept,
+    /// Retained commands are rejected
+    Ignore,
+    /// Retained commands are applied only if their payload timestamp is
+    /// not older than `retained_max_age`
+    Fresh,
+}
+
 /// Configuration for MQTT connection settings.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -38,231 +149,2283 @@ pub struct MqttConfig {
     /// Heartbeat interval duration (how often to send status updates, set to 0 to disable)
     #[serde(default = "default_heartbeat_interval", with = "humantime_serde")]
     pub heartbeat_interval: Duration,
+
+    /// Interval of the lightweight retained `ping` for liveness checks (0 to disable)
+    #[serde(default, with = "humantime_serde")]
+    pub ping_interval: Duration,
+
+    /// Interval of the system statistics on `telemetry/system` (0 to disable)
+    #[serde(default, with = "humantime_serde")]
+    pub telemetry_interval: Duration,
+
+    /// Topic and payload layout for input events
+    #[serde(default)]
+    pub payload_profile: PayloadProfile,
+
+    /// Topic layout of the input and output channels
+    #[serde(default)]
+    pub topic_profile: TopicProfile,
+
+    /// Format of the timestamps in published payloads
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+
+    /// QoS level (0, 1 or 2) requested for command topic subscriptions
+    #[serde(default = "default_subscribe_qos")]
+    pub subscribe_qos: u8,
+
+    /// Handling of retained output commands
+    #[serde(default)]
+    pub retained_commands: RetainedCommands,
+
+    /// Maximum age of retained output commands accepted with the `fresh` policy
+    #[serde(default = "default_retained_max_age", with = "humantime_serde")]
+    pub retained_max_age: Duration,
+
+    /// Maximum age of output commands carrying a payload timestamp (disabled if not set)
+    #[serde(default, with = "humantime_serde")]
+    pub command_max_age: Option<Duration>,
+
+    /// Maximum time spent on shutdown publishing the pending messages and waiting for
+    /// their acknowledgements (0 to disable)
+    #[serde(default = "default_shutdown_timeout", with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
+
+    /// Interval of rejected message statistics published on `security/rejections`
+    /// (set to 0 to disable)
+    #[serde(default = "default_rejections_interval", with = "humantime_serde")]
+    pub rejections_interval: Duration,
+
+    /// Time the broker has to complete the handshake of a QoS1/QoS2 publish before
+    /// it's reported as stalled on `diagnostics` (set to 0 to disable)
+    #[serde(default = "default_ack_timeout", with = "humantime_serde")]
+    pub ack_timeout: Duration,
+
+    /// Maximum accepted payload size of incoming messages in bytes (0 for unlimited)
+    #[serde(default)]
+    pub max_payload_size: usize,
+
+    /// Maximum number of incoming messages accepted per second (0 for unlimited)
+    #[serde(default)]
+    pub max_message_rate: u32,
+
+    /// Number of queued input events above which only the latest change of every
+    /// input channel is published (0 to publish every change)
+    #[serde(default)]
+    pub coalesce_threshold: usize,
+
+    /// Refresh interval of the claim of the device identity, only the instance holding
+    /// the claim drives outputs (set to 0 to disable)
+    #[serde(default, with = "humantime_serde")]
+    pub claim_interval: Duration,
+
+    /// Group of a shared subscription (`$share/<group>/...`) of the output and Modbus
+    /// command topics, so each command is handled by one bridge of the group only
+    #[serde(default)]
+    pub share_group: Option<String>,
+
+    /// Maximum time to wait on startup for a network interface and the broker (or
+    /// proxy) hostname to resolve, e.g. on boot (0 to disable)
+    #[serde(default = "default_startup_wait", with = "humantime_serde")]
+    pub startup_wait: Duration,
+
+    /// HTTP proxy used to reach the broker (direct connection if not set)
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+
+    /// Publish the internal state dumped on SIGUSR1 on `debug/state`
+    #[serde(default)]
+    pub publish_state_dump: bool,
+
+    /// Subscribe to `output/bit/<offset>` and `input/bit/<offset>`, writing and
+    /// reading single bits of the process images at raw bit offsets
+    #[serde(default)]
+    pub raw_bits: bool,
 }
 
-/// Main application configuration.
+/// HTTP proxy tunneling the broker connection with `CONNECT`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
-pub struct Config {
-    /// Name of the device to use in MQTT topics
-    #[serde(default = "default_device_name")]
-    pub device_name: String,
+pub struct ProxyConfig {
+    /// Proxy hostname or IP address
+    pub host: String,
 
-    /// MQTT connection configuration
-    pub mqtt: MqttConfig,
-}
+    /// Proxy port
+    pub port: u16,
 
-// Default values
+    /// Username for basic authentication (optional)
+    #[serde(default)]
+    pub username: Option<String>,
 
-const fn default_mqtt_port() -> u16 {
-    1883
+    /// Password for basic authentication (optional)
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
-const fn default_keepalive() -> Duration {
-    Duration::from_secs(300) // 5 minutes
+/// An inclusive range of channels, written as `"40-47"` or `"32"` for a single channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChannelRange {
+    pub first: u16,
+    pub last: u16,
 }
 
-const fn default_heartbeat_interval() -> Duration {
-    Duration::from_secs(60) // 1 minute
+impl ChannelRange {
+    pub fn contains(&self, channel: u16) -> bool {
+        (self.first..=self.last).contains(&channel)
+    }
 }
 
-fn default_device_name() -> String {
-    "kbus_mqtt_bridge".to_owned()
+impl TryFrom<String> for ChannelRange {
+    type Error = String;
+
+    fn try_from(range: String) -> Result<ChannelRange, String> {
+        let parse = |channel: &str| {
+            channel
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| format!("invalid channel range '{range}'"))
+        };
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(&range)?, parse(&range)?),
+        };
+        if first > last {
+            return Err(format!("channel range '{range}' is reversed"));
+        }
+        Ok(ChannelRange { first, last })
+    }
 }
 
-impl Default for MqttConfig {
-    fn default() -> MqttConfig {
-        MqttConfig {
-            broker_host: "localhost".to_string(),
-            broker_port: default_mqtt_port(),
-            username: None,
-            password: None,
-            keepalive: default_keepalive(),
-            heartbeat_interval: default_heartbeat_interval(),
+impl From<ChannelRange> for String {
+    fn from(range: ChannelRange) -> String {
+        if range.first == range.last {
+            range.first.to_string()
+        } else {
+            format!("{}-{}", range.first, range.last)
         }
     }
 }
 
-impl Default for Config {
-    fn default() -> Config {
-        Config {
-            device_name: default_device_name(),
-            mqtt: MqttConfig::default(),
+/// Configuration for K-Bus input channels.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputsConfig {
+    /// High-frequency input channels published with QoS0 via a lightweight path
+    /// (no per-message logging and statistics)
+    #[serde(default)]
+    pub fast: Vec<u16>,
+
+    /// Number of changes of the fast channels kept with their K-Bus cycle for the
+    /// sequence of events dump on `bridge/soe` (0 to disable)
+    #[serde(default = "default_soe_capacity")]
+    pub soe_capacity: usize,
+
+    /// Channel ranges checked for changes and published (all channels if empty)
+    #[serde(default)]
+    pub monitor: Vec<ChannelRange>,
+
+    /// Custom payloads of channel ranges, the first matching range applies
+    #[serde(default)]
+    pub payloads: Vec<ChannelPayload>,
+
+    /// Channels published under a functional group, e.g. `input/hvac/fan_feedback`
+    #[serde(default)]
+    pub groups: Vec<GroupedChannel>,
+
+    /// Channel ranges expected to change within a window, a channel without a
+    /// change is reported as dead on `diagnostics`
+    #[serde(default)]
+    pub activity: Vec<ChannelActivity>,
+}
+
+impl Default for InputsConfig {
+    fn default() -> InputsConfig {
+        InputsConfig {
+            fast: Vec::new(),
+            soe_capacity: default_soe_capacity(),
+            monitor: Vec::new(),
+            payloads: Vec::new(),
+            groups: Vec::new(),
+            activity: Vec::new(),
         }
     }
 }
 
-impl Config {
-    /// Load configuration from a TOML file.
-    ///
-    /// # Arguments
-    ///
-    /// * `path` - Path to the TOML configuration file
-    pub fn from_toml<P: AsRef<Path>>(path: P) -> Result<Config, anyhow::Error> {
-        let mut file = File::open(path.as_ref())
-            .with_context(|| format!("Failed to open config file: {}", path.as_ref().display()))?;
+impl InputsConfig {
+    /// Returns whether changes of the input channel are published.
+    pub fn is_monitored(&self, channel: u16) -> bool {
+        self.monitor.is_empty() || self.monitor.iter().any(|range| range.contains(channel))
+    }
 
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .with_context(|| format!("Failed to read config file: {}", path.as_ref().display()))?;
+    /// Returns the custom payload of the input channel, if configured.
+    pub fn payload(&self, channel: u16) -> Option<&ChannelPayload> {
+        find_payload(&self.payloads, channel)
+    }
 
-        let config: Config = toml::from_str(&contents)
-            .with_context(|| format!("Failed to parse TOML config: {}", path.as_ref().display()))?;
+    /// Returns the group of the input channel, if configured.
+    pub fn group(&self, channel: u16) -> Option<&GroupedChannel> {
+        find_group(&self.groups, channel)
+    }
 
-        Ok(config)
+    /// Returns the window the input channel is expected to change within, if
+    /// configured.
+    pub fn expect_activity_within(&self, channel: u16) -> Option<Duration> {
+        self.activity
+            .iter()
+            .find(|activity| activity.channels.contains(channel))
+            .map(|activity| activity.expect_activity_within)
     }
+}
 
-    /// Load configuration with the following precedence:
-    /// 1. Environment variables
-    /// 2. Config file (specified via command line argument)
-    /// 3. Config file (specified via KBUS_BRIDGE_CONFIG_FILE environment variable)
-    /// 4. Default values
-    ///
-    /// # Environment Variables
-    /// - `KBUS_BRIDGE_DEVICE_NAME`: Device name (default: "kbus_mqtt_bridge")
-    /// - `KBUS_BRIDGE_MQTT_HOST`: MQTT broker host
-    /// - `KBUS_BRIDGE_MQTT_PORT`: MQTT broker port (default: 1883)
-    /// - `KBUS_BRIDGE_MQTT_KEEPALIVE`: MQTT keepalive in seconds (default: 300)
-    /// - `KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL`: MQTT heartbeat interval in seconds (default: 60)
-    /// - `KBUS_BRIDGE_CONFIG_FILE`: Path to config file (used if command line path not provided)
-    ///
-    /// # Arguments
-    ///
-    /// * `config_path` - Optional path to a configuration file from command line
-    pub fn load(config_path: Option<PathBuf>) -> Result<Config, anyhow::Error> {
-        // Try to get config file path from environment if not provided via command line
-        let config_path = config_path.or_else(|| {
-            env::var("KBUS_BRIDGE_CONFIG_FILE")
-                .ok()
-                .as_ref()
-                .map(PathBuf::from)
-        });
+/// Input channels expected to change regularly, e.g. motion sensors or flow
+/// switches, so a broken sensor or wire is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelActivity {
+    /// Channels expected to change
+    pub channels: ChannelRange,
 
-        // Override with config file if provided
-        let mut config = if let Some(path) = config_path {
-            if path.exists() {
-                Config::from_toml(&path)?
-            } else {
-                return Err(anyhow::anyhow!("Config file not found: {}", path.display()));
-            }
-        } else {
-            Config::default()
-        };
+    /// Window every channel of the range must change within at least once
+    #[serde(with = "humantime_serde")]
+    pub expect_activity_within: Duration,
+}
 
-        // Override with environment variables if they exist
+/// Payload placeholder replaced with `payload_on` or `payload_off`
+pub const PAYLOAD_VALUE: &str = "{value}";
+/// Payload placeholder replaced with the channel number
+pub const PAYLOAD_CHANNEL: &str = "{channel}";
+/// Payload placeholder replaced with the current time, only for inputs
+pub const PAYLOAD_TIMESTAMP: &str = "{timestamp}";
 
-        if let Ok(device_name) = env::var("KBUS_BRIDGE_DEVICE_NAME") {
-            config.device_name = device_name;
-        }
+/// Custom payloads of a channel range, for devices and controllers expecting
+/// specific literals instead of `true`/`false`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelPayload {
+    /// Channels using the payloads
+    pub channels: ChannelRange,
 
-        if let Ok(broker_host) = env::var("KBUS_BRIDGE_MQTT_HOST") {
-            config.mqtt.broker_host = broker_host;
-        }
+    /// Payload of the value `true`
+    #[serde(default = "default_payload_on")]
+    pub payload_on: String,
 
-        if let Ok(username) = env::var("KBUS_BRIDGE_MQTT_USERNAME") {
-            config.mqtt.username = Some(username);
-        }
+    /// Payload of the value `false`
+    #[serde(default = "default_payload_off")]
+    pub payload_off: String,
 
-        if let Ok(password) = env::var("KBUS_BRIDGE_MQTT_PASSWORD") {
-            config.mqtt.password = Some(password);
-        }
+    /// Template the value payload is embedded in, e.g. `{"state": "{value}"}`,
+    /// with the placeholders `{value}`, `{channel}` and `{timestamp}`
+    #[serde(default)]
+    pub template: Option<String>,
+}
 
-        if let Ok(port_str) = env::var("KBUS_BRIDGE_MQTT_PORT") {
-            if let Ok(port) = port_str.parse::<u16>() {
-                config.mqtt.broker_port = port;
-            } else {
-                return Err(anyhow::anyhow!(
-                    "Invalid KBUS_BRIDGE_MQTT_PORT value: {}",
-                    port_str
-                ));
-            }
-        }
+fn find_payload(payloads: &[ChannelPayload], channel: u16) -> Option<&ChannelPayload> {
+    payloads
+        .iter()
+        .find(|payload| payload.channels.contains(channel))
+}
 
-        if let Ok(keepalive_str) = env::var("KBUS_BRIDGE_MQTT_KEEPALIVE") {
-            if let Ok(keepalive) = keepalive_str.parse::<u64>() {
-                config.mqtt.keepalive = Duration::from_secs(keepalive);
-            } else {
-                return Err(anyhow::anyhow!(
-                    "Invalid KBUS_BRIDGE_MQTT_KEEPALIVE value: {}",
-                    keepalive_str
-                ));
-            }
-        }
+/// A channel published under a functional group instead of its number, so
+/// subscribers can use wildcard subscriptions per area (e.g. `input/hvac/+`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupedChannel {
+    /// Channel number
+    pub channel: u16,
 
-        if let Ok(heartbeat_str) = env::var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL") {
-            if let Ok(heartbeat) = heartbeat_str.parse::<u64>() {
-                config.mqtt.heartbeat_interval = Duration::from_secs(heartbeat);
-            } else {
-                return Err(anyhow::anyhow!(
-                    "Invalid KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL value: {}",
-                    heartbeat_str
-                ));
-            }
-        }
+    /// Group level of the topic, e.g. `hvac`
+    pub group: String,
 
-        // Validate the config before returning
-        config.validate()?;
-        Ok(config)
-    }
+    /// Name level of the topic, e.g. `fan_feedback` (the channel number if not set)
+    #[serde(default)]
+    pub name: Option<String>,
+}
 
-    /// Validates the configuration values.
-    ///
-    /// Returns an error if any configuration value is invalid.
-    pub fn validate(&self) -> Result<(), anyhow::Error> {
-        // Validate device name (non-empty and no invalid characters)
-        if self.device_name.is_empty() {
-            return Err(anyhow::anyhow!("Device name cannot be empty"));
+impl GroupedChannel {
+    /// Returns the topic levels of the channel after `input/` or `output/`.
+    pub fn path(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{}/{name}", self.group),
+            None => format!("{}/{}", self.group, self.channel),
         }
+    }
+}
 
-        // Check for invalid characters in device name
-        // More efficient single-pass check
-        for c in self.device_name.chars() {
-            let error_msg = match c {
-                c if c.is_whitespace() => Some("Device name cannot contain whitespace"),
-                '/' => Some("Device name cannot contain '/' character (MQTT topic separator)"),
-                '+' => Some("Device name cannot contain '+' character (MQTT topic wildcard)"),
-                '#' => Some("Device name cannot contain '#' character (MQTT topic wildcard)"),
-                _ => None,
-            };
+fn find_group(groups: &[GroupedChannel], channel: u16) -> Option<&GroupedChannel> {
+    groups.iter().find(|group| group.channel == channel)
+}
 
-            if let Some(msg) = error_msg {
-                return Err(anyhow::anyhow!(msg));
-            }
-        }
+/// An output channel verified through an input channel mirroring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputVerify {
+    /// Output channel
+    pub output: u16,
 
-        // Validate MQTT broker host (non-empty)
-        if self.mqtt.broker_host.is_empty() {
-            return Err(anyhow::anyhow!("MQTT broker host cannot be empty"));
-        }
+    /// Input channel reading back the output (e.g. a feedback contact)
+    pub input: u16,
+}
 
-        // Validate port (though any u16 is valid, check specific port ranges)
-        if self.mqtt.broker_port == 0 {
-            return Err(anyhow::anyhow!("MQTT broker port cannot be 0"));
-        }
+/// Identity of the device.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityConfig {
+    /// Source of the device identifier
+    #[serde(default)]
+    pub source: IdentitySource,
+}
 
-        // Validate keepalive (shouldn't be too short or too long)
-        if self.mqtt.keepalive.as_secs() < 5 {
-            return Err(anyhow::anyhow!("MQTT keepalive must be at least 5 seconds"));
-        }
-        if self.mqtt.keepalive.as_secs() > 86400 {
-            return Err(anyhow::anyhow!(
-                "MQTT keepalive must be at most 24 hours (86400 seconds)"
-            ));
-        }
+/// Opening of the K-Bus.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KBusConfig {
+    /// Advisory lock file held while the K-Bus is open, so a second bridge started
+    /// by mistake fails fast instead of fighting over the DAL
+    #[serde(default)]
+    pub lock_file: Option<PathBuf>,
+
+    /// Time to retry opening the K-Bus while it's in use, e.g. by a previous
+    /// instance still shutting down (0 to fail immediately)
+    #[serde(default = "default_init_timeout", with = "humantime_serde")]
+    pub init_timeout: Duration,
+
+    /// Exit if the K-Bus can't be opened, otherwise the bridge runs without it and
+    /// retries opening it every `retry_interval`
+    #[serde(default = "default_kbus_required")]
+    pub required: bool,
+
+    /// Interval of retrying to open the K-Bus if it's not required
+    #[serde(default = "default_kbus_retry_interval", with = "humantime_serde")]
+    pub retry_interval: Duration,
+
+    /// Handling of configured channels beyond the process images reported by the
+    /// device at startup
+    #[serde(default)]
+    pub io_size_check: IoSizeCheck,
+
+    /// Order of the output writes, the bus cycle (`libpackbus_Push`) and the input
+    /// reads in every K-Bus cycle
+    #[serde(default)]
+    pub cycle_order: CycleOrder,
+}
+
+/// Order of the steps of a K-Bus cycle relative to the bus cycle (`libpackbus_Push`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CycleOrder {
+    /// Write outputs, push, read inputs: commands are actuated and their effect
+    /// read back by the same bus cycle
+    #[default]
+    WriteRead,
+    /// Read inputs, write outputs, push: inputs are those of the previous bus
+    /// cycle, commands are actuated right away
+    ReadWrite,
+    /// Push, read inputs, write outputs, as in WAGO's DAL examples: inputs are
+    /// fresh, commands are actuated by the next bus cycle
+    Wago,
+}
+
+impl CycleOrder {
+    /// Returns whether the staged outputs are written before the inputs are read.
+    pub fn writes_before_read(self) -> bool {
+        self == CycleOrder::WriteRead
+    }
+
+    /// Returns whether the bus cycle is triggered before the inputs are read.
+    pub fn pushes_before_read(self) -> bool {
+        self != CycleOrder::ReadWrite
+    }
+}
+
+/// Handling of configured channels beyond the process images of the device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoSizeCheck {
+    /// Sizes aren't checked
+    Off,
+    /// Channels beyond the process images are logged as a warning
+    #[default]
+    Warn,
+    /// Channels beyond the process images stop the K-Bus task
+    Error,
+}
+
+impl Default for KBusConfig {
+    fn default() -> KBusConfig {
+        KBusConfig {
+            lock_file: None,
+            init_timeout: default_init_timeout(),
+            required: default_kbus_required(),
+            retry_interval: default_kbus_retry_interval(),
+            io_size_check: IoSizeCheck::default(),
+            cycle_order: CycleOrder::default(),
+        }
+    }
+}
+
+/// Configuration for K-Bus output channels.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputsConfig {
+    /// Outputs verified after every write, failures are published on `verify_failed`
+    #[serde(default)]
+    pub verify: Vec<OutputVerify>,
+
+    /// Number of K-Bus cycles after a write until the read-back must match
+    #[serde(default = "default_verify_cycles")]
+    pub verify_cycles: u32,
+
+    /// Shadow mode, output commands are accepted and logged but not written
+    #[serde(default)]
+    pub shadow: bool,
+
+    /// Maximum age of output commands queued until the K-Bus is running,
+    /// zero rejects commands received before
+    #[serde(default = "default_startup_max_age", with = "humantime_serde")]
+    pub startup_max_age: Duration,
+
+    /// Custom payloads of commands and states of channel ranges, the first
+    /// matching range applies
+    #[serde(default)]
+    pub payloads: Vec<ChannelPayload>,
+
+    /// Channels switched and published under a functional group, e.g.
+    /// `output/lighting/hall`
+    #[serde(default)]
+    pub groups: Vec<GroupedChannel>,
+}
+
+impl OutputsConfig {
+    /// Returns the custom payload of the output channel, if configured.
+    pub fn payload(&self, channel: u16) -> Option<&ChannelPayload> {
+        find_payload(&self.payloads, channel)
+    }
+
+    /// Returns the group of the output channel, if configured.
+    pub fn group(&self, channel: u16) -> Option<&GroupedChannel> {
+        find_group(&self.groups, channel)
+    }
+}
+
+impl Default for OutputsConfig {
+    fn default() -> OutputsConfig {
+        OutputsConfig {
+            verify: Vec::new(),
+            verify_cycles: default_verify_cycles(),
+            shadow: false,
+            startup_max_age: default_startup_max_age(),
+            payloads: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+}
+
+/// Limits of heartbeat metrics raising alerts on the `alert` topic.
+///
+/// Limits are checked on every heartbeat, so alerts require a non-zero heartbeat interval.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// Limit of the used system memory in percent (disabled if not set)
+    #[serde(default)]
+    pub memory_usage: Option<f32>,
+
+    /// Limit of the global CPU usage in percent (disabled if not set)
+    #[serde(default)]
+    pub cpu_usage: Option<f32>,
+
+    /// Limit of input events waiting to be published (disabled if not set)
+    #[serde(default)]
+    pub queue_depth: Option<usize>,
+
+    /// Number of consecutive heartbeat intervals a limit must be exceeded to raise an alert
+    #[serde(default = "default_alert_intervals")]
+    pub intervals: u32,
+}
+
+/// Persistence of counters across restarts.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateConfig {
+    /// Path of the state file (persistence disabled if not set)
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+
+    /// Interval of periodic state file flushes (set to 0 to only flush on shutdown)
+    #[serde(default = "default_state_flush_interval", with = "humantime_serde")]
+    pub flush_interval: Duration,
+
+    /// Clear the retained topics under the topic prefix of the last run on startup,
+    /// if it changed (e.g. a new `device_name`)
+    #[serde(default)]
+    pub clear_previous_prefix: bool,
+}
+
+/// Restarting of auxiliary tasks after a failure.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SupervisorConfig {
+    /// Number of restarts of a failed task before the bridge shuts down
+    /// (0 to shut down on the first failure)
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+
+    /// Delay before a failed task is restarted
+    #[serde(default = "default_restart_delay", with = "humantime_serde")]
+    pub restart_delay: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> SupervisorConfig {
+        SupervisorConfig {
+            max_restarts: default_max_restarts(),
+            restart_delay: default_restart_delay(),
+        }
+    }
+}
+
+/// Configuration updates received over MQTT.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteConfig {
+    /// Accept new configurations on `bridge/config/set`
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Time the bridge must run with an updated configuration before it is kept,
+    /// the previous configuration is restored if the bridge fails earlier
+    #[serde(default = "default_grace_period", with = "humantime_serde")]
+    pub grace_period: Duration,
+}
+
+/// Signatures of write commands with a shared key (see [`crate::mqtt`]).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningConfig {
+    /// Shared key of the HMAC-SHA256 signatures
+    pub key: String,
+
+    /// Maximum age of a signed command, also the tolerated clock skew into the future
+    #[serde(default = "default_signing_max_age", with = "humantime_serde")]
+    pub max_age: Duration,
+}
+
+/// MQTT broker embedded in the bridge for cells without broker infrastructure.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrokerConfig {
+    /// Address the broker accepts clients on, e.g. HMIs on the machine network
+    #[serde(default = "default_broker_listen")]
+    pub listen: SocketAddr,
+
+    /// Maximum number of connected clients, including the bridge itself
+    #[serde(default = "default_broker_max_connections")]
+    pub max_connections: usize,
+}
+
+/// Local logging of input events to daily CSV files.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataLogConfig {
+    /// Directory of the files, e.g. on the SD card
+    pub directory: PathBuf,
+
+    /// Interval of writing the buffered rows to the file
+    #[serde(default = "default_datalog_flush_interval", with = "humantime_serde")]
+    pub flush_interval: Duration,
+
+    /// Number of daily files kept, older ones are deleted
+    #[serde(default = "default_datalog_max_files")]
+    pub max_files: usize,
+
+    /// Total size of the files in bytes, the oldest ones are deleted above it
+    /// (0 for no limit)
+    #[serde(default)]
+    pub max_total_size: u64,
+}
+
+/// Source of self-updates of the bridge binary.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelfUpdateConfig {
+    /// URL of the binary, its signature is downloaded from `<url>.sig`
+    pub url: String,
+
+    /// Base64 encoded Ed25519 public key verifying the signature
+    pub public_key: String,
+}
+
+/// A bridge whose state topics are republished by the aggregator.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregatorSource {
+    /// Name of the bridge in the merged namespace
+    pub name: String,
+
+    /// Topic prefix of the bridge (e.g. `<device_name>/<mac>`)
+    pub prefix: String,
+}
+
+/// Republishing of other bridges' state topics under `site/<area>/<name>/...`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregatorConfig {
+    /// Area of the merged namespace
+    pub area: String,
+
+    /// Bridges to aggregate
+    pub sources: Vec<AggregatorSource>,
+}
+
+/// Parity of the Modbus RTU serial line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    None,
+    Odd,
+    /// Default parity of Modbus RTU
+    #[default]
+    Even,
+}
+
+/// A range of Modbus data addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusRange {
+    /// First data address (0-based)
+    pub address: u16,
+
+    /// Number of coils, inputs or registers
+    pub count: u16,
+}
+
+impl ModbusRange {
+    /// Returns `true` if `address` is within the range.
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.address
+            && u32::from(address) < u32::from(self.address) + u32::from(self.count)
+    }
+}
+
+/// A Modbus RTU slave device and the data polled from it or written to it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusDeviceConfig {
+    /// Name of the device used in MQTT topics (`modbus/<name>/...`)
+    pub name: String,
+
+    /// Slave address of the device
+    pub slave: u8,
+
+    /// Discrete inputs published on `modbus/<name>/input/<address>`
+    #[serde(default)]
+    pub discrete_inputs: Option<ModbusRange>,
+
+    /// Input registers published on `modbus/<name>/register/<address>`
+    #[serde(default)]
+    pub input_registers: Option<ModbusRange>,
+
+    /// Coils written from `modbus/<name>/coil/<address>`
+    #[serde(default)]
+    pub coils: Option<ModbusRange>,
+
+    /// Holding registers written from `modbus/<name>/holding/<address>`
+    #[serde(default)]
+    pub holding_registers: Option<ModbusRange>,
+}
+
+/// Modbus RTU master on a serial port of the controller.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModbusConfig {
+    /// Serial port device (e.g. "/dev/ttyO0")
+    pub port: String,
+
+    /// Baud rate of the serial line
+    #[serde(default = "default_modbus_baud_rate")]
+    pub baud_rate: u32,
+
+    /// Parity of the serial line
+    #[serde(default)]
+    pub parity: Parity,
+
+    /// Number of stop bits (1 or 2)
+    #[serde(default = "default_modbus_stop_bits")]
+    pub stop_bits: u8,
+
+    /// Interval of polling the inputs and input registers of all devices
+    #[serde(default = "default_modbus_poll_interval", with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// Maximum time to wait for the response of a device
+    #[serde(default = "default_modbus_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+
+    /// Window of the min/max/mean aggregates of input registers (0 to disable)
+    #[serde(default, with = "humantime_serde")]
+    pub aggregate_window: Duration,
+
+    /// Slave devices on the serial line
+    pub devices: Vec<ModbusDeviceConfig>,
+}
+
+/// Script transforming incoming and outgoing MQTT messages.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformConfig {
+    /// Rhai script defining `incoming(topic, payload)` and/or `outgoing(topic, payload)`
+    pub script: PathBuf,
+
+    /// Additional topic filters subscribed for the `incoming` function (full topics)
+    #[serde(default)]
+    pub subscribe: Vec<String>,
+}
+
+/// Local schedule driving an output channel.
+///
+/// Exactly one of `at` (time of day) or `every` (interval) must be set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Output channel driven by the schedule
+    pub output: u16,
+
+    /// Value written to the output when the schedule fires
+    pub value: bool,
+
+    /// Local time of day at which the schedule fires (e.g. "06:00")
+    #[serde(default)]
+    pub at: Option<NaiveTime>,
+
+    /// Weekdays on which a time-of-day schedule fires (all days if empty)
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+
+    /// Interval at which the schedule fires
+    #[serde(default, with = "humantime_serde")]
+    pub every: Option<Duration>,
+}
+
+/// Main application configuration.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Name of the device to use in MQTT topics
+    #[serde(default = "default_device_name")]
+    pub device_name: String,
+
+    /// Include the MAC address in the topic prefix (`<device_name>/<mac>`),
+    /// otherwise the prefix is just `<device_name>`
+    #[serde(default = "default_topic_include_mac")]
+    pub topic_include_mac: bool,
+
+    /// Topic levels prepended to all topics, e.g. `acme/plant1/line3`, so brokers
+    /// shared by several sites can organize the devices hierarchically
+    #[serde(default)]
+    pub topic_root: Option<String>,
+
+    /// Source of the device identifier
+    #[serde(default)]
+    pub identity: IdentityConfig,
+
+    /// MQTT connection configuration
+    pub mqtt: MqttConfig,
+
+    /// K-Bus opening settings
+    #[serde(default)]
+    pub kbus: KBusConfig,
+
+    /// Input channels configuration
+    #[serde(default)]
+    pub inputs: InputsConfig,
+
+    /// Output channels settings
+    #[serde(default)]
+    pub outputs: OutputsConfig,
+
+    /// Derived signals: name mapped to a logical expression over input channels
+    #[serde(default)]
+    pub rules: BTreeMap<String, String>,
+
+    /// Local output schedules
+    #[serde(default)]
+    pub schedules: Vec<ScheduleConfig>,
+
+    /// Alarms on conditions of the input channels
+    #[serde(default)]
+    pub alarms: Vec<AlarmConfig>,
+
+    /// Alert limits of heartbeat metrics
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+
+    /// Persistence of counters across restarts
+    #[serde(default)]
+    pub state: StateConfig,
+
+    /// Restarting of failed auxiliary tasks
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+
+    /// Aggregator mode (disabled if not set)
+    #[serde(default)]
+    pub aggregator: Option<AggregatorConfig>,
+
+    /// Modbus RTU master (disabled if not set)
+    #[serde(default)]
+    pub modbus: Option<ModbusConfig>,
+
+    /// Transform script for MQTT messages (disabled if not set)
+    #[serde(default)]
+    pub transform: Option<TransformConfig>,
+
+    /// Configuration updates over MQTT
+    #[serde(default)]
+    pub remote_config: RemoteConfig,
+
+    /// Signed write commands, unsigned ones are rejected (accepted if not set)
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+
+    /// Self-update on `bridge/update` (disabled if not set)
+    #[serde(default)]
+    pub self_update: Option<SelfUpdateConfig>,
+
+    /// Embedded MQTT broker the bridge connects to (disabled if not set)
+    #[serde(default)]
+    pub broker: Option<BrokerConfig>,
+
+    /// Local data logging of input events (disabled if not set)
+    #[serde(default)]
+    pub datalog: Option<DataLogConfig>,
+
+    /// Path of the file the configuration was loaded from
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+
+    /// Profile of the file applied on top of its base configuration
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+// Default values
+
+const fn default_mqtt_port() -> u16 {
+    1883
+}
+
+const fn default_keepalive() -> Duration {
+    Duration::from_secs(300) // 5 minutes
+}
+
+const fn default_heartbeat_interval() -> Duration {
+    Duration::from_secs(60) // 1 minute
+}
+
+const fn default_subscribe_qos() -> u8 {
+    2
+}
+
+const fn default_retained_max_age() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+const fn default_rejections_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_ack_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+const fn default_alert_intervals() -> u32 {
+    3
+}
+
+const fn default_state_flush_interval() -> Duration {
+    Duration::from_secs(300) // 5 minutes
+}
+
+const fn default_max_restarts() -> u32 {
+    3
+}
+
+const fn default_restart_delay() -> Duration {
+    Duration::from_secs(5)
+}
+
+const fn default_grace_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_signing_max_age() -> Duration {
+    Duration::from_secs(30)
+}
+
+const fn default_verify_cycles() -> u32 {
+    1
+}
+
+const fn default_soe_capacity() -> usize {
+    1000
+}
+
+const fn default_startup_wait() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_payload_on() -> String {
+    "true".to_owned()
+}
+
+fn default_payload_off() -> String {
+    "false".to_owned()
+}
+
+const fn default_init_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+const fn default_kbus_required() -> bool {
+    true
+}
+
+const fn default_kbus_retry_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+const fn default_startup_max_age() -> Duration {
+    Duration::from_secs(5)
+}
+
+const fn default_broker_listen() -> SocketAddr {
+    SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 1883)
+}
+
+const fn default_broker_max_connections() -> usize {
+    32
+}
+
+const fn default_datalog_flush_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+const fn default_datalog_max_files() -> usize {
+    31 // a month
+}
+
+const fn default_modbus_baud_rate() -> u32 {
+    19200
+}
+
+const fn default_modbus_stop_bits() -> u8 {
+    1
+}
+
+const fn default_modbus_poll_interval() -> Duration {
+    Duration::from_millis(500)
+}
+
+const fn default_modbus_timeout() -> Duration {
+    Duration::from_millis(500)
+}
+
+const fn default_topic_include_mac() -> bool {
+    true
+}
+
+fn default_device_name() -> String {
+    "kbus_mqtt_bridge".to_owned()
+}
+
+impl Default for MqttConfig {
+    fn default() -> MqttConfig {
+        MqttConfig {
+            broker_host: "localhost".to_string(),
+            broker_port: default_mqtt_port(),
+            username: None,
+            password: None,
+            keepalive: default_keepalive(),
+            heartbeat_interval: default_heartbeat_interval(),
+            ping_interval: Duration::ZERO,
+            telemetry_interval: Duration::ZERO,
+            payload_profile: PayloadProfile::default(),
+            topic_profile: TopicProfile::default(),
+            timestamp_format: TimestampFormat::default(),
+            subscribe_qos: default_subscribe_qos(),
+            retained_commands: RetainedCommands::default(),
+            retained_max_age: default_retained_max_age(),
+            command_max_age: None,
+            shutdown_timeout: default_shutdown_timeout(),
+            rejections_interval: default_rejections_interval(),
+            ack_timeout: default_ack_timeout(),
+            max_payload_size: 0,
+            max_message_rate: 0,
+            coalesce_threshold: 0,
+            claim_interval: Duration::ZERO,
+            share_group: None,
+            startup_wait: default_startup_wait(),
+            proxy: None,
+            publish_state_dump: false,
+            raw_bits: false,
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> AlertsConfig {
+        AlertsConfig {
+            memory_usage: None,
+            cpu_usage: None,
+            queue_depth: None,
+            intervals: default_alert_intervals(),
+        }
+    }
+}
+
+impl Default for StateConfig {
+    fn default() -> StateConfig {
+        StateConfig {
+            file: None,
+            flush_interval: default_state_flush_interval(),
+            clear_previous_prefix: false,
+        }
+    }
+}
+
+impl Default for RemoteConfig {
+    fn default() -> RemoteConfig {
+        RemoteConfig {
+            enabled: false,
+            grace_period: default_grace_period(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            device_name: default_device_name(),
+            topic_include_mac: default_topic_include_mac(),
+            topic_root: None,
+            identity: IdentityConfig::default(),
+            mqtt: MqttConfig::default(),
+            inputs: InputsConfig::default(),
+            kbus: KBusConfig::default(),
+            outputs: OutputsConfig::default(),
+            rules: BTreeMap::new(),
+            schedules: Vec::new(),
+            alarms: Vec::new(),
+            alerts: AlertsConfig::default(),
+            state: StateConfig::default(),
+            supervisor: SupervisorConfig::default(),
+            aggregator: None,
+            modbus: None,
+            transform: None,
+            remote_config: RemoteConfig::default(),
+            signing: None,
+            self_update: None,
+            broker: None,
+            datalog: None,
+            file: None,
+            profile: None,
+        }
+    }
+}
+
+/// Replacement of secrets in the redacted configuration.
+const REDACTED: &str = "<redacted>";
+
+impl Config {
+    /// Returns a copy of the configuration with all passwords and keys replaced, safe
+    /// to publish.
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        if config.mqtt.password.is_some() {
+            config.mqtt.password = Some(REDACTED.to_owned());
+        }
+        if let Some(signing) = &mut config.signing {
+            signing.key = REDACTED.to_owned();
+        }
+        if let Some(password) = config
+            .mqtt
+            .proxy
+            .as_mut()
+            .and_then(|proxy| proxy.password.as_mut())
+        {
+            *password = REDACTED.to_owned();
+        }
+        config
+    }
+
+    /// Keeps the current passwords and keys where `self` has redacted ones, so a
+    /// configuration published on `config` can be edited and sent back as an update.
+    pub fn unredact(&mut self, current: &Config) {
+        if self.mqtt.password.as_deref() == Some(REDACTED) {
+            self.mqtt.password.clone_from(&current.mqtt.password);
+        }
+        if let Some(signing) = &mut self.signing {
+            if signing.key == REDACTED {
+                if let Some(current) = &current.signing {
+                    signing.key.clone_from(&current.key);
+                }
+            }
+        }
+        if let Some(proxy) = &mut self.mqtt.proxy {
+            if proxy.password.as_deref() == Some(REDACTED) {
+                proxy.password = current
+                    .mqtt
+                    .proxy
+                    .as_ref()
+                    .and_then(|proxy| proxy.password.clone());
+            }
+        }
+    }
+
+    /// Returns the path of the configuration file: `config_path` from the command line,
+    /// or the path from the `KBUS_BRIDGE_CONFIG_FILE` environment variable.
+    pub fn file_path(config_path: Option<PathBuf>) -> Option<PathBuf> {
+        config_path.or_else(|| {
+            env::var("KBUS_BRIDGE_CONFIG_FILE")
+                .ok()
+                .as_ref()
+                .map(PathBuf::from)
+        })
+    }
+
+    /// Returns the configuration profile: `profile` from the command line, or the
+    /// profile from the `KBUS_BRIDGE_PROFILE` environment variable.
+    pub fn profile_name(profile: Option<String>) -> Option<String> {
+        profile.or_else(|| env::var("KBUS_BRIDGE_PROFILE").ok())
+    }
+
+    /// Returns the prefix of all MQTT topics of the device with the given identifier
+    /// (see [`IdentitySource`]).
+    pub fn topic_prefix(&self, id: &str) -> String {
+        let device_name = &self.device_name;
+        if self.topic_include_mac {
+            self.rooted_topic(&format!("{device_name}/{id}"))
+        } else {
+            self.rooted_topic(device_name)
+        }
+    }
+
+    /// Returns `topic` below the `topic_root`, unchanged without it.
+    pub fn rooted_topic(&self, topic: &str) -> String {
+        match &self.topic_root {
+            Some(root) => format!("{root}/{topic}"),
+            None => topic.to_owned(),
+        }
+    }
+
+    /// Returns the merged namespace of the aggregator, `site/<area>` below the
+    /// `topic_root`.
+    pub fn aggregator_site(&self, aggregator: &AggregatorConfig) -> String {
+        self.rooted_topic(&format!("site/{}", aggregator.area))
+    }
+
+    /// Returns the host and port of the broker the bridge connects to.
+    ///
+    /// With the embedded broker, it's its listening address, or the loopback
+    /// address if it listens on all interfaces.
+    pub fn broker_address(&self) -> (String, u16) {
+        match &self.broker {
+            Some(broker) if broker.listen.ip().is_unspecified() => {
+                (Ipv4Addr::LOCALHOST.to_string(), broker.listen.port())
+            }
+            Some(broker) => (broker.listen.ip().to_string(), broker.listen.port()),
+            None => (self.mqtt.broker_host.clone(), self.mqtt.broker_port),
+        }
+    }
+
+    /// Parses a TOML configuration, applying the given profile.
+    ///
+    /// The `[profile.<name>]` tables override the base configuration, nested tables
+    /// are merged and all other values replaced. A profile is required to exist if
+    /// the configuration defines profiles, otherwise it's ignored (e.g. after a
+    /// remote configuration update wrote the effective configuration).
+    ///
+    /// Included files are only resolved when loading a file (see [`Config::from_toml`]).
+    pub fn from_toml_str(contents: &str, profile: Option<&str>) -> Result<Config, anyhow::Error> {
+        let table: toml::Table = toml::from_str(contents)?;
+        if table.contains_key("include") {
+            return Err(anyhow::anyhow!(
+                "`include` is only supported in configuration files"
+            ));
+        }
+        Config::from_table(table, profile)
+    }
+
+    /// Applies the profile to a parsed configuration, see [`Config::from_toml_str`].
+    fn from_table(mut table: toml::Table, profile: Option<&str>) -> Result<Config, anyhow::Error> {
+        let profiles = match table.remove("profile") {
+            Some(toml::Value::Table(profiles)) => Some(profiles),
+            Some(_) => return Err(anyhow::anyhow!("`profile` must be a table of profiles")),
+            None => None,
+        };
+
+        match (profile, profiles) {
+            (Some(name), Some(mut profiles)) => match profiles.remove(name) {
+                Some(toml::Value::Table(overrides)) => merge_tables(&mut table, overrides),
+                Some(_) => return Err(anyhow::anyhow!("Profile `{name}` must be a table")),
+                None => return Err(anyhow::anyhow!("Profile `{name}` not found")),
+            },
+            (Some(name), None) => warn!(profile = name, "no profiles configured, profile ignored"),
+            (None, _) => {}
+        }
+
+        let config = Config::deserialize(table)?;
+        Ok(Config {
+            profile: profile.map(str::to_owned),
+            ..config
+        })
+    }
+
+    /// Load configuration from a TOML file.
+    ///
+    /// The files listed in `include` are merged into it before the profile is applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the TOML configuration file
+    /// * `profile` - Profile applied on top of the base configuration
+    pub fn from_toml<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> Result<Config, anyhow::Error> {
+        let mut file = File::open(path.as_ref())
+            .with_context(|| format!("Failed to open config file: {}", path.as_ref().display()))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .with_context(|| format!("Failed to read config file: {}", path.as_ref().display()))?;
+
+        let mut table: toml::Table = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse TOML config: {}", path.as_ref().display()))?;
+        let dir = path.as_ref().parent().unwrap_or(Path::new(""));
+        include::resolve(&mut table, dir)?;
+        let config = Config::from_table(table, profile)
+            .with_context(|| format!("Failed to parse TOML config: {}", path.as_ref().display()))?;
+
+        Ok(config)
+    }
+
+    /// Load configuration with the following precedence:
+    /// 1. Environment variables
+    /// 2. Config file (specified via command line argument)
+    /// 3. Config file (specified via KBUS_BRIDGE_CONFIG_FILE environment variable)
+    /// 4. Default values
+    ///
+    /// # Environment Variables
+    /// - `KBUS_BRIDGE_DEVICE_NAME`: Device name (default: "kbus_mqtt_bridge")
+    /// - `KBUS_BRIDGE_TOPIC_INCLUDE_MAC`: Include the MAC address in topics (default: true)
+    /// - `KBUS_BRIDGE_TOPIC_ROOT`: Topic levels prepended to all topics
+    /// - `KBUS_BRIDGE_MQTT_HOST`: MQTT broker host
+    /// - `KBUS_BRIDGE_MQTT_PORT`: MQTT broker port (default: 1883)
+    /// - `KBUS_BRIDGE_MQTT_KEEPALIVE`: MQTT keepalive in seconds (default: 300)
+    /// - `KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL`: MQTT heartbeat interval in seconds (default: 60)
+    /// - `KBUS_BRIDGE_MQTT_PING_INTERVAL`: MQTT liveness ping interval in seconds (default: 0)
+    /// - `KBUS_BRIDGE_MQTT_TELEMETRY_INTERVAL`: MQTT system telemetry interval in seconds (default: 0)
+    /// - `KBUS_BRIDGE_MQTT_SUBSCRIBE_QOS`: QoS of command subscriptions (default: 2)
+    /// - `KBUS_BRIDGE_CONFIG_FILE`: Path to config file (used if command line path not provided)
+    /// - `KBUS_BRIDGE_PROFILE`: Profile of the config file (used if command line profile not provided)
+    ///
+    /// # Arguments
+    ///
+    /// * `config_path` - Optional path to a configuration file from command line
+    /// * `profile` - Optional profile of the configuration file from command line
+    pub fn load(
+        config_path: Option<PathBuf>,
+        profile: Option<String>,
+    ) -> Result<Config, anyhow::Error> {
+        // Try to get config file path from environment if not provided via command line
+        let config_path = Config::file_path(config_path);
+        let profile = Config::profile_name(profile);
+
+        // Override with config file if provided
+        let mut config = if let Some(path) = config_path {
+            if path.exists() {
+                Config {
+                    file: Some(path.clone()),
+                    ..Config::from_toml(&path, profile.as_deref())?
+                }
+            } else {
+                return Err(anyhow::anyhow!("Config file not found: {}", path.display()));
+            }
+        } else if let Some(profile) = profile {
+            return Err(anyhow::anyhow!(
+                "Profile `{profile}` selected without a config file"
+            ));
+        } else {
+            Config::default()
+        };
+
+        // Override with environment variables if they exist
+
+        if let Ok(device_name) = env::var("KBUS_BRIDGE_DEVICE_NAME") {
+            config.device_name = device_name;
+        }
+
+        if let Ok(topic_root) = env::var("KBUS_BRIDGE_TOPIC_ROOT") {
+            config.topic_root = Some(topic_root);
+        }
+
+        if let Ok(include_mac_str) = env::var("KBUS_BRIDGE_TOPIC_INCLUDE_MAC") {
+            if let Ok(include_mac) = include_mac_str.parse::<bool>() {
+                config.topic_include_mac = include_mac;
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_TOPIC_INCLUDE_MAC value: {}",
+                    include_mac_str
+                ));
+            }
+        }
+
+        if let Ok(broker_host) = env::var("KBUS_BRIDGE_MQTT_HOST") {
+            config.mqtt.broker_host = broker_host;
+        }
+
+        if let Ok(username) = env::var("KBUS_BRIDGE_MQTT_USERNAME") {
+            config.mqtt.username = Some(username);
+        }
+
+        if let Ok(password) = env::var("KBUS_BRIDGE_MQTT_PASSWORD") {
+            config.mqtt.password = Some(password);
+        }
+
+        if let Ok(port_str) = env::var("KBUS_BRIDGE_MQTT_PORT") {
+            if let Ok(port) = port_str.parse::<u16>() {
+                config.mqtt.broker_port = port;
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_PORT value: {}",
+                    port_str
+                ));
+            }
+        }
+
+        if let Ok(keepalive_str) = env::var("KBUS_BRIDGE_MQTT_KEEPALIVE") {
+            if let Ok(keepalive) = keepalive_str.parse::<u64>() {
+                config.mqtt.keepalive = Duration::from_secs(keepalive);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_KEEPALIVE value: {}",
+                    keepalive_str
+                ));
+            }
+        }
+
+        if let Ok(heartbeat_str) = env::var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL") {
+            if let Ok(heartbeat) = heartbeat_str.parse::<u64>() {
+                config.mqtt.heartbeat_interval = Duration::from_secs(heartbeat);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL value: {}",
+                    heartbeat_str
+                ));
+            }
+        }
+
+        if let Ok(ping_str) = env::var("KBUS_BRIDGE_MQTT_PING_INTERVAL") {
+            if let Ok(ping) = ping_str.parse::<u64>() {
+                config.mqtt.ping_interval = Duration::from_secs(ping);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_PING_INTERVAL value: {}",
+                    ping_str
+                ));
+            }
+        }
+
+        if let Ok(telemetry_str) = env::var("KBUS_BRIDGE_MQTT_TELEMETRY_INTERVAL") {
+            if let Ok(telemetry) = telemetry_str.parse::<u64>() {
+                config.mqtt.telemetry_interval = Duration::from_secs(telemetry);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_TELEMETRY_INTERVAL value: {}",
+                    telemetry_str
+                ));
+            }
+        }
+
+        if let Ok(qos_str) = env::var("KBUS_BRIDGE_MQTT_SUBSCRIBE_QOS") {
+            if let Ok(qos) = qos_str.parse::<u8>() {
+                config.mqtt.subscribe_qos = qos;
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Invalid KBUS_BRIDGE_MQTT_SUBSCRIBE_QOS value: {}",
+                    qos_str
+                ));
+            }
+        }
+
+        // Validate the config before returning
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validates the configuration values.
+    ///
+    /// Returns an error if any configuration value is invalid.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        // Validate device name (non-empty and no invalid characters)
+        if self.device_name.is_empty() {
+            return Err(anyhow::anyhow!("Device name cannot be empty"));
+        }
+
+        validate_topic_level("Device name", &self.device_name)?;
+
+        // Validate topic root (non-empty levels without wildcards)
+        if let Some(root) = &self.topic_root {
+            if root.split('/').any(str::is_empty) {
+                return Err(anyhow::anyhow!(
+                    "Topic root cannot be empty, start or end with '/' or contain empty levels"
+                ));
+            }
+            for level in root.split('/') {
+                validate_topic_level("Topic root", level)?;
+            }
+        }
+
+        // Validate MQTT broker host (non-empty)
+        if self.mqtt.broker_host.is_empty() {
+            return Err(anyhow::anyhow!("MQTT broker host cannot be empty"));
+        }
+
+        // Validate port (though any u16 is valid, check specific port ranges)
+        if self.mqtt.broker_port == 0 {
+            return Err(anyhow::anyhow!("MQTT broker port cannot be 0"));
+        }
+
+        // Validate proxy (credentials are only used as a pair)
+        if let Some(proxy) = &self.mqtt.proxy {
+            if proxy.host.is_empty() {
+                return Err(anyhow::anyhow!("Proxy host cannot be empty"));
+            }
+            if proxy.port == 0 {
+                return Err(anyhow::anyhow!("Proxy port cannot be 0"));
+            }
+            if proxy.username.is_some() != proxy.password.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Proxy username and password must be set together"
+                ));
+            }
+        }
+
+        // Validate keepalive (shouldn't be too short or too long)
+        if self.mqtt.keepalive.as_secs() < 5 {
+            return Err(anyhow::anyhow!("MQTT keepalive must be at least 5 seconds"));
+        }
+        if self.mqtt.keepalive.as_secs() > 86400 {
+            return Err(anyhow::anyhow!(
+                "MQTT keepalive must be at most 24 hours (86400 seconds)"
+            ));
+        }
 
         // Validate heartbeat interval (shouldn't be too long, 0 means disabled)
         // A value of 0 means "don't send heartbeat"
         if !self.mqtt.heartbeat_interval.is_zero() && self.mqtt.heartbeat_interval.as_secs() < 1 {
             return Err(anyhow::anyhow!(
-                "Heartbeat interval must be at least 1 second or 0 to disable"
+                "Heartbeat interval must be at least 1 second or 0 to disable"
+            ));
+        }
+        if self.mqtt.heartbeat_interval.as_secs() > 3600 {
+            return Err(anyhow::anyhow!(
+                "Heartbeat interval must be at most 1 hour (3600 seconds)"
+            ));
+        }
+
+        // Validate ping interval (0 means disabled)
+        if !self.mqtt.ping_interval.is_zero()
+            && (self.mqtt.ping_interval.as_secs() < 1 || self.mqtt.ping_interval.as_secs() > 3600)
+        {
+            return Err(anyhow::anyhow!(
+                "Ping interval must be 0 (disabled) or between 1 second and 1 hour"
+            ));
+        }
+
+        // Validate telemetry interval (0 means disabled)
+        if !self.mqtt.telemetry_interval.is_zero()
+            && (self.mqtt.telemetry_interval.as_secs() < 1
+                || self.mqtt.telemetry_interval.as_secs() > 86400)
+        {
+            return Err(anyhow::anyhow!(
+                "Telemetry interval must be 0 (disabled) or between 1 second and 24 hours"
+            ));
+        }
+
+        // Validate subscribe QoS (MQTT defines levels 0-2 only)
+        if self.mqtt.subscribe_qos > 2 {
+            return Err(anyhow::anyhow!(
+                "MQTT subscribe QoS must be 0, 1 or 2, got {}",
+                self.mqtt.subscribe_qos
+            ));
+        }
+
+        // Validate retained command max age (a zero window would reject everything)
+        if self.mqtt.retained_commands == RetainedCommands::Fresh
+            && self.mqtt.retained_max_age.as_secs() < 1
+        {
+            return Err(anyhow::anyhow!(
+                "Retained command max age must be at least 1 second"
+            ));
+        }
+
+        // Validate command max age
+        if self
+            .mqtt
+            .command_max_age
+            .is_some_and(|max_age| max_age.as_secs() < 1)
+        {
+            return Err(anyhow::anyhow!("Command max age must be at least 1 second"));
+        }
+
+        // Validate rejections interval (0 means disabled)
+        if !self.mqtt.rejections_interval.is_zero() && self.mqtt.rejections_interval.as_secs() < 1 {
+            return Err(anyhow::anyhow!(
+                "Rejections interval must be at least 1 second or 0 to disable"
             ));
         }
-        if self.mqtt.heartbeat_interval.as_secs() > 3600 {
+        if self.mqtt.rejections_interval.as_secs() > 86400 {
             return Err(anyhow::anyhow!(
-                "Heartbeat interval must be at most 1 hour (3600 seconds)"
+                "Rejections interval must be at most 24 hours (86400 seconds)"
+            ));
+        }
+
+        // Validate claim interval (0 means disabled)
+        if !self.mqtt.claim_interval.is_zero() && self.mqtt.claim_interval.as_secs() < 1 {
+            return Err(anyhow::anyhow!(
+                "Claim interval must be at least 1 second or 0 to disable"
+            ));
+        }
+        if self.mqtt.claim_interval.as_secs() > 3600 {
+            return Err(anyhow::anyhow!(
+                "Claim interval must be at most 1 hour (3600 seconds)"
+            ));
+        }
+
+        // Validate share group (a single topic level, commands must reach the claim holder)
+        if let Some(group) = &self.mqtt.share_group {
+            if group.is_empty() || group.contains(['/', '+', '#']) {
+                return Err(anyhow::anyhow!(
+                    "Share group must be non-empty and must not contain '/', '+' or '#'"
+                ));
+            }
+            if !self.mqtt.claim_interval.is_zero() {
+                return Err(anyhow::anyhow!(
+                    "Share group can't be used with a claim interval: commands delivered to a standby instance would be lost"
+                ));
+            }
+        }
+
+        // Validate startup wait (the service manager must notice a bridge never starting)
+        if self.mqtt.startup_wait > Duration::from_secs(600) {
+            return Err(anyhow::anyhow!(
+                "Startup wait must be at most 10 minutes (600 seconds)"
+            ));
+        }
+
+        // Validate shutdown timeout (must not delay shutdown indefinitely)
+        if self.mqtt.shutdown_timeout.as_secs() > 60 {
+            return Err(anyhow::anyhow!(
+                "Shutdown timeout must be at most 60 seconds"
+            ));
+        }
+
+        // Validate alert limits (percentages, at least one interval, heartbeat enabled)
+        for (what, limit) in [
+            ("Memory usage", self.alerts.memory_usage),
+            ("CPU usage", self.alerts.cpu_usage),
+        ] {
+            if limit.is_some_and(|limit| !(limit > 0.0 && limit <= 100.0)) {
+                return Err(anyhow::anyhow!(
+                    "{what} alert limit must be greater than 0 and at most 100 percent"
+                ));
+            }
+        }
+        if !(1..=100).contains(&self.alerts.intervals) {
+            return Err(anyhow::anyhow!("Alert intervals must be between 1 and 100"));
+        }
+        let alerts_enabled = self.alerts.memory_usage.is_some()
+            || self.alerts.cpu_usage.is_some()
+            || self.alerts.queue_depth.is_some();
+        if alerts_enabled && self.mqtt.heartbeat_interval.is_zero() {
+            return Err(anyhow::anyhow!(
+                "Alert limits require a non-zero heartbeat interval"
+            ));
+        }
+
+        // Validate state flush interval (0 means only on shutdown)
+        if !self.state.flush_interval.is_zero() && self.state.flush_interval.as_secs() < 1 {
+            return Err(anyhow::anyhow!(
+                "State flush interval must be at least 1 second or 0 to disable"
+            ));
+        }
+        if self.state.flush_interval.as_secs() > 86400 {
+            return Err(anyhow::anyhow!(
+                "State flush interval must be at most 24 hours (86400 seconds)"
+            ));
+        }
+        if self.state.clear_previous_prefix && self.state.file.is_none() {
+            return Err(anyhow::anyhow!(
+                "Clearing the previous topic prefix requires a state file"
+            ));
+        }
+
+        // Validate remote configuration grace period
+        if self.remote_config.grace_period.as_secs() < 10
+            || self.remote_config.grace_period.as_secs() > 3600
+        {
+            return Err(anyhow::anyhow!(
+                "Remote configuration grace period must be between 10 seconds and 1 hour"
+            ));
+        }
+
+        // Validate command signing (a guessable key or a long replay window defeat it)
+        if let Some(signing) = &self.signing {
+            if signing.key.len() < 16 {
+                return Err(anyhow::anyhow!(
+                    "Signing key must have at least 16 characters"
+                ));
+            }
+            if !(Duration::from_secs(1)..=Duration::from_secs(3600)).contains(&signing.max_age) {
+                return Err(anyhow::anyhow!(
+                    "Signing max age must be between 1 second and 1 hour"
+                ));
+            }
+        }
+
+        // Validate self-update source (supported by the build, HTTP URL, Ed25519 key)
+        if let Some(self_update) = &self.self_update {
+            if !cfg!(feature = "self-update") {
+                return Err(anyhow::anyhow!(
+                    "Self-update is not supported by this build (`self-update` feature)"
+                ));
+            }
+            if !self_update.url.starts_with("http://") && !self_update.url.starts_with("https://") {
+                return Err(anyhow::anyhow!("Self-update URL must be an HTTP(S) URL"));
+            }
+            if BASE64_STANDARD
+                .decode(&self_update.public_key)
+                .map_or(true, |key| key.len() != 32)
+            {
+                return Err(anyhow::anyhow!(
+                    "Self-update public key must be a base64 encoded Ed25519 key"
+                ));
+            }
+        }
+
+        // Validate embedded broker (supported by the build, reachable without proxy)
+        if let Some(broker) = &self.broker {
+            if !cfg!(feature = "embedded-broker") {
+                return Err(anyhow::anyhow!(
+                    "Embedded broker is not supported by this build (`embedded-broker` feature)"
+                ));
+            }
+            if broker.listen.port() == 0 {
+                return Err(anyhow::anyhow!("Embedded broker port cannot be 0"));
+            }
+            if !(2..=1000).contains(&broker.max_connections) {
+                return Err(anyhow::anyhow!(
+                    "Embedded broker max connections must be between 2 and 1000"
+                ));
+            }
+            if self.mqtt.proxy.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Embedded broker can't be used with an MQTT proxy"
+                ));
+            }
+        }
+
+        // Validate data log (a directory, at least the file of the current day kept)
+        if let Some(datalog) = &self.datalog {
+            if datalog.directory.as_os_str().is_empty() {
+                return Err(anyhow::anyhow!("Data log directory cannot be empty"));
+            }
+            if datalog.flush_interval.is_zero() {
+                return Err(anyhow::anyhow!("Data log flush interval cannot be 0"));
+            }
+            if datalog.max_files == 0 {
+                return Err(anyhow::anyhow!("Data log max files must be at least 1"));
+            }
+        }
+
+        // Validate aggregator (valid names, sources not overlapping the merged namespace)
+        if let Some(aggregator) = &self.aggregator {
+            if aggregator.area.is_empty() {
+                return Err(anyhow::anyhow!("Aggregator area cannot be empty"));
+            }
+            validate_topic_level("Aggregator area", &aggregator.area)?;
+            if aggregator.sources.is_empty() {
+                return Err(anyhow::anyhow!("Aggregator needs at least one source"));
+            }
+
+            let site = self.aggregator_site(aggregator);
+            for (index, source) in aggregator.sources.iter().enumerate() {
+                if source.name.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: name cannot be empty"
+                    ));
+                }
+                validate_topic_level("Aggregator source name", &source.name)?;
+                if aggregator.sources[..index]
+                    .iter()
+                    .any(|other| other.name == source.name)
+                {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: duplicate name '{}'",
+                        source.name
+                    ));
+                }
+
+                let prefix = &source.prefix;
+                if prefix.is_empty() || prefix.starts_with('/') || prefix.ends_with('/') {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: prefix cannot be empty or start or end with '/'"
+                    ));
+                }
+                if prefix.contains(['+', '#']) {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: prefix cannot contain MQTT topic wildcards"
+                    ));
+                }
+                // Republished messages must not be aggregated again
+                if is_topic_prefix(prefix, &site) || is_topic_prefix(&site, prefix) {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: prefix '{prefix}' overlaps the merged namespace '{site}'"
+                    ));
+                }
+                if let Some(other) = aggregator.sources[..index].iter().find(|other| {
+                    is_topic_prefix(&other.prefix, prefix) || is_topic_prefix(prefix, &other.prefix)
+                }) {
+                    return Err(anyhow::anyhow!(
+                        "Aggregator source #{index}: prefix '{prefix}' overlaps the prefix of '{}'",
+                        other.name
+                    ));
+                }
+            }
+        }
+
+        // Validate Modbus master (serial line, unique device names, addressable ranges)
+        if let Some(modbus) = &self.modbus {
+            validate_modbus(modbus)?;
+        }
+
+        // Validate transform script (path and additional subscriptions)
+        if let Some(transform) = &self.transform {
+            if transform.script.as_os_str().is_empty() {
+                return Err(anyhow::anyhow!("Transform script path cannot be empty"));
+            }
+            for filter in &transform.subscribe {
+                if !is_topic_filter(filter) {
+                    return Err(anyhow::anyhow!(
+                        "Transform subscription '{filter}' is not a valid topic filter"
+                    ));
+                }
+            }
+        }
+
+        // Validate fast input channels (must exist in the input process image)
+        if let Some(channel) = self
+            .inputs
+            .fast
+            .iter()
+            .find(|&&channel| usize::from(channel) >= INPUT_SIZE)
+        {
+            return Err(anyhow::anyhow!(
+                "Fast input channel {channel} out of range: maximum supported channel is {}",
+                INPUT_SIZE - 1
+            ));
+        }
+
+        if self.inputs.soe_capacity > MAX_SOE_CAPACITY {
+            return Err(anyhow::anyhow!(
+                "Sequence of events capacity {} too large: maximum is {MAX_SOE_CAPACITY}",
+                self.inputs.soe_capacity
+            ));
+        }
+
+        // Validate monitored input ranges (must exist in the input process image)
+        if let Some(range) = self
+            .inputs
+            .monitor
+            .iter()
+            .find(|range| usize::from(range.last) >= INPUT_SIZE)
+        {
+            return Err(anyhow::anyhow!(
+                "Monitored input range {}-{} out of range: maximum supported channel is {}",
+                range.first,
+                range.last,
+                INPUT_SIZE - 1
+            ));
+        }
+        if let Some(channel) = self
+            .inputs
+            .fast
+            .iter()
+            .find(|&&channel| !self.inputs.is_monitored(channel))
+        {
+            return Err(anyhow::anyhow!(
+                "Fast input channel {channel} is not in a monitored range"
+            ));
+        }
+
+        // Validate custom payloads (existing channels, distinguishable values,
+        // output commands must be parsable)
+        let payloads = self
+            .inputs
+            .payloads
+            .iter()
+            .map(|payload| ("input", INPUT_SIZE, payload))
+            .chain(
+                self.outputs
+                    .payloads
+                    .iter()
+                    .map(|payload| ("output", OUTPUT_SIZE, payload)),
+            );
+        for (kind, size, payload) in payloads {
+            let range = String::from(payload.channels);
+            if usize::from(payload.channels.last) >= size {
+                return Err(anyhow::anyhow!(
+                    "Payload {kind} range {range} out of range: maximum supported channel is {}",
+                    size - 1
+                ));
+            }
+            if payload.payload_on.is_empty() || payload.payload_off.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Payloads of {kind} range {range} cannot be empty"
+                ));
+            }
+            if payload.payload_on == payload.payload_off {
+                return Err(anyhow::anyhow!(
+                    "Payloads of {kind} range {range} must differ for on and off"
+                ));
+            }
+            if let Some(template) = &payload.template {
+                if template.matches(PAYLOAD_VALUE).count() != 1 {
+                    return Err(anyhow::anyhow!(
+                        "Payload template of {kind} range {range} must contain {PAYLOAD_VALUE} once"
+                    ));
+                }
+                if kind == "output" && template.contains(PAYLOAD_TIMESTAMP) {
+                    return Err(anyhow::anyhow!(
+                        "Payload template of output range {range} cannot contain {PAYLOAD_TIMESTAMP}"
+                    ));
+                }
+            }
+        }
+        if self.mqtt.payload_profile == PayloadProfile::WagoCloud
+            && !(self.inputs.payloads.is_empty() && self.outputs.payloads.is_empty())
+        {
+            return Err(anyhow::anyhow!(
+                "Custom payloads are not supported with the wago_cloud payload profile"
+            ));
+        }
+
+        // Validate Tasmota topics (fixed payloads, channel topics outside of the prefix)
+        if self.mqtt.topic_profile == TopicProfile::Tasmota {
+            if !(self.inputs.payloads.is_empty() && self.outputs.payloads.is_empty()) {
+                return Err(anyhow::anyhow!(
+                    "Custom payloads are not supported with the tasmota topic profile"
+                ));
+            }
+            if self.aggregator.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Aggregator mode is not supported with the tasmota topic profile"
+                ));
+            }
+            if !(self.inputs.groups.is_empty() && self.outputs.groups.is_empty()) {
+                return Err(anyhow::anyhow!(
+                    "Channel groups are not supported with the tasmota topic profile"
+                ));
+            }
+        }
+
+        // Validate dead channel detection (monitored channels, a window of at least
+        // a second)
+        for activity in &self.inputs.activity {
+            let range = String::from(activity.channels);
+            if usize::from(activity.channels.last) >= INPUT_SIZE {
+                return Err(anyhow::anyhow!(
+                    "Activity input range {range} out of range: maximum supported channel is {}",
+                    INPUT_SIZE - 1
+                ));
+            }
+            if let Some(channel) = (activity.channels.first..=activity.channels.last)
+                .find(|&channel| !self.inputs.is_monitored(channel))
+            {
+                return Err(anyhow::anyhow!(
+                    "Activity input range {range}: channel {channel} is not monitored"
+                ));
+            }
+            if activity.expect_activity_within < Duration::from_secs(1) {
+                return Err(anyhow::anyhow!(
+                    "Activity window of input range {range} must be at least 1 second"
+                ));
+            }
+        }
+
+        // Validate channel groups (existing channels, valid and unique topics not
+        // colliding with the numbered and raw bit topics)
+        for (direction, size, groups) in [
+            ("input", INPUT_SIZE, &self.inputs.groups),
+            ("output", OUTPUT_SIZE, &self.outputs.groups),
+        ] {
+            for (index, grouped) in groups.iter().enumerate() {
+                let channel = grouped.channel;
+                if usize::from(channel) >= size {
+                    return Err(anyhow::anyhow!(
+                        "Grouped {direction} channel {channel} out of range: maximum supported channel is {}",
+                        size - 1
+                    ));
+                }
+                if grouped.group.is_empty()
+                    || grouped.group == "bit"
+                    || grouped.group.bytes().all(|b| b.is_ascii_digit())
+                {
+                    return Err(anyhow::anyhow!(
+                        "Group '{}' of {direction} channel {channel} must not be empty, 'bit' or a number",
+                        grouped.group
+                    ));
+                }
+                validate_topic_level(
+                    &format!("Group of {direction} channel {channel}"),
+                    &grouped.group,
+                )?;
+                if let Some(name) = &grouped.name {
+                    if name.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "Name of {direction} channel {channel} must not be empty"
+                        ));
+                    }
+                    validate_topic_level(&format!("Name of {direction} channel {channel}"), name)?;
+                }
+                if groups[..index].iter().any(|other| other.channel == channel) {
+                    return Err(anyhow::anyhow!(
+                        "Grouped {direction} channel {channel} is configured more than once"
+                    ));
+                }
+                if groups[..index]
+                    .iter()
+                    .any(|other| other.path() == grouped.path())
+                {
+                    return Err(anyhow::anyhow!(
+                        "Topic {direction}/{} is used by more than one channel",
+                        grouped.path()
+                    ));
+                }
+            }
+        }
+
+        // Validate output verification (existing channels, each output once)
+        for (index, verify) in self.outputs.verify.iter().enumerate() {
+            if usize::from(verify.output) >= OUTPUT_SIZE {
+                return Err(anyhow::anyhow!(
+                    "Verified output channel {} out of range: maximum supported channel is {}",
+                    verify.output,
+                    OUTPUT_SIZE - 1
+                ));
+            }
+            if usize::from(verify.input) >= INPUT_SIZE {
+                return Err(anyhow::anyhow!(
+                    "Read-back input channel {} out of range: maximum supported channel is {}",
+                    verify.input,
+                    INPUT_SIZE - 1
+                ));
+            }
+            if self.outputs.verify[..index]
+                .iter()
+                .any(|other| other.output == verify.output)
+            {
+                return Err(anyhow::anyhow!(
+                    "Output channel {} is verified more than once",
+                    verify.output
+                ));
+            }
+        }
+        if !(1..=100).contains(&self.outputs.verify_cycles) {
+            return Err(anyhow::anyhow!(
+                "Output verify cycles must be between 1 and 100"
+            ));
+        }
+        if self.outputs.startup_max_age > Duration::from_secs(60) {
+            return Err(anyhow::anyhow!(
+                "Output startup max age must not exceed 1 minute"
+            ));
+        }
+
+        // Validate K-Bus opening (a required K-Bus that can't be opened must exit,
+        // an optional one must not be retried in a busy loop)
+        if self.kbus.init_timeout > Duration::from_secs(300) {
+            return Err(anyhow::anyhow!(
+                "K-Bus init timeout must not exceed 5 minutes"
+            ));
+        }
+        if !(Duration::from_secs(1)..=Duration::from_secs(3600)).contains(&self.kbus.retry_interval)
+        {
+            return Err(anyhow::anyhow!(
+                "K-Bus retry interval must be between 1 second and 1 hour"
             ));
         }
 
+        // Validate task restarts (a failing task must not keep the bridge degraded forever)
+        if self.supervisor.max_restarts > 100 {
+            return Err(anyhow::anyhow!(
+                "Supervisor max restarts must not exceed 100"
+            ));
+        }
+        if self.supervisor.restart_delay > Duration::from_secs(300) {
+            return Err(anyhow::anyhow!(
+                "Supervisor restart delay must not exceed 5 minutes"
+            ));
+        }
+
+        // Validate derived signals (usable as topic level, valid expression)
+        for (name, source) in &self.rules {
+            if name.is_empty() {
+                return Err(anyhow::anyhow!("Rule name cannot be empty"));
+            }
+            validate_topic_level("Rule name", name)?;
+
+            let expr = Expr::parse(source).with_context(|| format!("Invalid rule '{name}'"))?;
+            if let Some(channel) = expr
+                .max_channel()
+                .filter(|&channel| usize::from(channel) >= INPUT_SIZE)
+            {
+                return Err(anyhow::anyhow!(
+                    "Rule '{name}' references input channel {channel} out of range: maximum supported channel is {}",
+                    INPUT_SIZE - 1
+                ));
+            }
+        }
+
+        // Validate alarms (unique names usable as topic level, valid condition over
+        // monitored channels, whose changes are the only ones the alarms see)
+        for (index, alarm) in self.alarms.iter().enumerate() {
+            if alarm.name.is_empty() {
+                return Err(anyhow::anyhow!("Alarm #{index}: name cannot be empty"));
+            }
+            validate_topic_level("Alarm name", &alarm.name)?;
+            if self.alarms[..index]
+                .iter()
+                .any(|other| other.name == alarm.name)
+            {
+                return Err(anyhow::anyhow!("Duplicate alarm '{}'", alarm.name));
+            }
+
+            let expr = Expr::parse(&alarm.condition)
+                .with_context(|| format!("Invalid condition of alarm '{}'", alarm.name))?;
+            if let Some(channel) = expr
+                .max_channel()
+                .filter(|&channel| usize::from(channel) >= INPUT_SIZE)
+            {
+                return Err(anyhow::anyhow!(
+                    "Alarm '{}' references input channel {channel} out of range: maximum supported channel is {}",
+                    alarm.name,
+                    INPUT_SIZE - 1
+                ));
+            }
+            let mut unmonitored = None;
+            expr.for_each_input(&mut |channel| {
+                if !self.inputs.is_monitored(channel) {
+                    unmonitored.get_or_insert(channel);
+                }
+            });
+            if let Some(channel) = unmonitored {
+                return Err(anyhow::anyhow!(
+                    "Alarm '{}' references input channel {channel}, which is not monitored",
+                    alarm.name
+                ));
+            }
+        }
+
+        // Validate schedules (existing output, exactly one trigger)
+        for (index, schedule) in self.schedules.iter().enumerate() {
+            if usize::from(schedule.output) >= OUTPUT_SIZE {
+                return Err(anyhow::anyhow!(
+                    "Schedule #{index}: output channel {} out of range: maximum supported channel is {}",
+                    schedule.output,
+                    OUTPUT_SIZE - 1
+                ));
+            }
+            match (schedule.at, schedule.every) {
+                (Some(_), None) => {}
+                (None, Some(every)) => {
+                    if every.as_secs() < 1 {
+                        return Err(anyhow::anyhow!(
+                            "Schedule #{index}: interval must be at least 1 second"
+                        ));
+                    }
+                    if !schedule.days.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "Schedule #{index}: days can only be used with 'at'"
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Schedule #{index}: exactly one of 'at' or 'every' must be set"
+                    ));
+                }
+            }
+        }
+
         Ok(())
     }
 }
+
+/// Maximum number of coils or discrete inputs read in one Modbus request.
+const MODBUS_MAX_BITS: u16 = 2000;
+/// Maximum number of registers read in one Modbus request.
+const MODBUS_MAX_REGISTERS: u16 = 125;
+
+fn validate_modbus(modbus: &ModbusConfig) -> Result<(), anyhow::Error> {
+    if modbus.port.is_empty() {
+        return Err(anyhow::anyhow!("Modbus serial port cannot be empty"));
+    }
+    if modbus.baud_rate == 0 {
+        return Err(anyhow::anyhow!("Modbus baud rate cannot be 0"));
+    }
+    if !matches!(modbus.stop_bits, 1 | 2) {
+        return Err(anyhow::anyhow!("Modbus stop bits must be 1 or 2"));
+    }
+    if modbus.poll_interval < Duration::from_millis(10) || modbus.poll_interval.as_secs() > 3600 {
+        return Err(anyhow::anyhow!(
+            "Modbus poll interval must be between 10 milliseconds and 1 hour"
+        ));
+    }
+    if modbus.timeout < Duration::from_millis(10) || modbus.timeout.as_secs() > 10 {
+        return Err(anyhow::anyhow!(
+            "Modbus timeout must be between 10 milliseconds and 10 seconds"
+        ));
+    }
+    if !modbus.aggregate_window.is_zero()
+        && (modbus.aggregate_window < modbus.poll_interval
+            || modbus.aggregate_window.as_secs() > 86400)
+    {
+        return Err(anyhow::anyhow!(
+            "Modbus aggregate window must be 0 (disabled) or between the poll interval and 24 hours"
+        ));
+    }
+    if modbus.devices.is_empty() {
+        return Err(anyhow::anyhow!("Modbus needs at least one device"));
+    }
+
+    for (index, device) in modbus.devices.iter().enumerate() {
+        if device.name.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Modbus device #{index}: name cannot be empty"
+            ));
+        }
+        validate_topic_level("Modbus device name", &device.name)?;
+        if modbus.devices[..index]
+            .iter()
+            .any(|other| other.name == device.name)
+        {
+            return Err(anyhow::anyhow!(
+                "Modbus device #{index}: duplicate name '{}'",
+                device.name
+            ));
+        }
+        if !(1..=247).contains(&device.slave) {
+            return Err(anyhow::anyhow!(
+                "Modbus device '{}': slave address must be between 1 and 247",
+                device.name
+            ));
+        }
+
+        let ranges = [
+            ("discrete inputs", device.discrete_inputs, MODBUS_MAX_BITS),
+            (
+                "input registers",
+                device.input_registers,
+                MODBUS_MAX_REGISTERS,
+            ),
+            ("coils", device.coils, MODBUS_MAX_BITS),
+            (
+                "holding registers",
+                device.holding_registers,
+                MODBUS_MAX_REGISTERS,
+            ),
+        ];
+        if ranges.iter().all(|(_, range, _)| range.is_none()) {
+            return Err(anyhow::anyhow!(
+                "Modbus device '{}': no inputs, registers or coils configured",
+                device.name
+            ));
+        }
+        for (what, range, max_count) in ranges {
+            let Some(range) = range else {
+                continue;
+            };
+            if range.count == 0 || range.count > max_count {
+                return Err(anyhow::anyhow!(
+                    "Modbus device '{}': number of {what} must be between 1 and {max_count}",
+                    device.name
+                ));
+            }
+            if u32::from(range.address) + u32::from(range.count) > 0x10000 {
+                return Err(anyhow::anyhow!(
+                    "Modbus device '{}': {what} exceed the address space",
+                    device.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `filter` is a valid MQTT topic filter: not empty, `+` only
+/// as a whole level and `#` only as the whole last level.
+fn is_topic_filter(filter: &str) -> bool {
+    let levels: Vec<&str> = filter.split('/').collect();
+    !filter.is_empty()
+        && levels.iter().enumerate().all(|(i, level)| {
+            !level.contains(['+', '#']) || *level == "+" || (*level == "#" && i == levels.len() - 1)
+        })
+}
+
+/// Returns `true` if `topic` equals `prefix` or is below it in the topic hierarchy.
+fn is_topic_prefix(prefix: &str, topic: &str) -> bool {
+    topic
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Merges `overrides` into `base`, recursing into tables present in both.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_tables(base, overrides);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Checks that `value` can be used as a single MQTT topic level.
+fn validate_topic_level(what: &str, value: &str) -> Result<(), anyhow::Error> {
+    // More efficient single-pass check
+    for c in value.chars() {
+        let error_msg = match c {
+            c if c.is_whitespace() => Some("cannot contain whitespace"),
+            '/' => Some("cannot contain '/' character (MQTT topic separator)"),
+            '+' => Some("cannot contain '+' character (MQTT topic wildcard)"),
+            '#' => Some("cannot contain '#' character (MQTT topic wildcard)"),
+            _ => None,
+        };
+
+        if let Some(msg) = error_msg {
+            return Err(anyhow::anyhow!("{what} {msg}"));
+        }
+    }
+    Ok(())
+}
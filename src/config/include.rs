@@ -0,0 +1,151 @@
+//! Configuration include files
+//!
+//! Large channel maps can be split into files per cabinet section, or generated by
+//! tooling, and listed in `include`. The included files are merged into the main
+//! file in the order of the patterns, files matching a pattern in the order of
+//! their names, so the result doesn't depend on the directory listing. Tables are
+//! merged and arrays appended, any other value may only be defined once: a value
+//! defined twice is reported as a conflict instead of silently overriding the other.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+#[cfg(test)]
+mod tests;
+
+/// Merges the files listed in the `include` key of `table` into it.
+///
+/// Patterns are relative to `dir`, the directory of the configuration file. `*` and
+/// `?` match in the file name, a pattern without them names a file that must exist.
+/// Included files can't include further files.
+pub(super) fn resolve(table: &mut toml::Table, dir: &Path) -> Result<(), anyhow::Error> {
+    let Some(include) = table.remove("include") else {
+        return Ok(());
+    };
+    let patterns: Vec<String> = include
+        .try_into()
+        .context("`include` must be a list of file patterns")?;
+
+    let mut included: Vec<PathBuf> = Vec::new();
+    for pattern in &patterns {
+        for path in expand(dir, pattern)? {
+            // A file matched by several patterns is included once
+            if included.contains(&path) {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read included file: {}", path.display()))?;
+            let other: toml::Table = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse included file: {}", path.display()))?;
+            if other.contains_key("include") {
+                return Err(anyhow::anyhow!(
+                    "Included file {} can't include further files",
+                    path.display()
+                ));
+            }
+            merge(table, other, "")
+                .with_context(|| format!("Failed to include file: {}", path.display()))?;
+            included.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the files matching `pattern`, sorted by name.
+fn expand(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let path = dir.join(pattern);
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Invalid include pattern: {pattern}"))?;
+    let parent = path.parent().unwrap_or(dir);
+    if parent.to_string_lossy().contains(['*', '?']) {
+        return Err(anyhow::anyhow!(
+            "Include pattern {pattern} can only match file names"
+        ));
+    }
+    if !name.contains(['*', '?']) {
+        if !path.is_file() {
+            return Err(anyhow::anyhow!(
+                "Included file not found: {}",
+                path.display()
+            ));
+        }
+        return Ok(vec![path]);
+    }
+
+    let entries = fs::read_dir(parent)
+        .with_context(|| format!("Failed to read include directory: {}", parent.display()))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry
+            .with_context(|| format!("Failed to read include directory: {}", parent.display()))?;
+        let matched = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|file_name| matches(name, file_name));
+        if matched && entry.path().is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns whether `name` matches `pattern`, `*` matching any number of characters
+/// and `?` a single one.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last `*` in the pattern and the name, to backtrack to
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some('?') => (p, n) = (p + 1, n + 1),
+            Some(&c) if c == name[n] => (p, n) = (p + 1, n + 1),
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    (p, n) = (star_p, star_n + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Merges `other` into `base`, recursing into tables and appending arrays.
+///
+/// `key` is the dotted key of `base` for the error of a value defined in both.
+fn merge(base: &mut toml::Table, other: toml::Table, key: &str) -> Result<(), anyhow::Error> {
+    for (name, value) in other {
+        let path = if key.is_empty() {
+            name.clone()
+        } else {
+            format!("{key}.{name}")
+        };
+        match (base.get_mut(&name), value) {
+            (None, value) => {
+                base.insert(name, value);
+            }
+            (Some(toml::Value::Table(base)), toml::Value::Table(other)) => {
+                merge(base, other, &path)?;
+            }
+            (Some(toml::Value::Array(base)), toml::Value::Array(other)) => {
+                base.extend(other);
+            }
+            (Some(_), _) => return Err(anyhow::anyhow!("`{path}` is already defined")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::fs;
+
+use tempfile::tempdir;
+
+use super::*;
+
+#[test]
+fn test_matches() {
+    assert!(matches("*.toml", "cabinet1.toml"));
+    assert!(matches("*.toml", ".toml"));
+    assert!(matches("cabinet?.toml", "cabinet1.toml"));
+    assert!(matches("*net*.toml", "cabinet1.toml"));
+    assert!(matches("*", "anything"));
+    assert!(!matches("cabinet?.toml", "cabinet10.toml"));
+    assert!(!matches("*.toml", "cabinet1.toml.bak"));
+    assert!(!matches("*.toml", "cabinet1.yaml"));
+}
+
+#[test]
+fn test_merge() {
+    let mut base: toml::Table = toml::from_str(
+        r#"
+        device_name = "line1"
+        [inputs]
+        monitor = ["0-3"]
+        [[inputs.groups]]
+        channel = 0
+        group = "pumps"
+        "#,
+    )
+    .unwrap();
+    let other: toml::Table = toml::from_str(
+        r#"
+        [inputs]
+        debounce = "20ms"
+        monitor = ["8-11"]
+        [[inputs.groups]]
+        channel = 8
+        group = "valves"
+        "#,
+    )
+    .unwrap();
+    merge(&mut base, other, "").unwrap();
+    assert_eq!(base["device_name"].as_str(), Some("line1"));
+    assert_eq!(base["inputs"]["debounce"].as_str(), Some("20ms"));
+    // Arrays are appended in the order of the files
+    assert_eq!(base["inputs"]["monitor"].as_array().unwrap().len(), 2);
+    let groups = base["inputs"]["groups"].as_array().unwrap();
+    assert_eq!(groups[1]["group"].as_str(), Some("valves"));
+
+    // Values defined twice are conflicts, reported with their key
+    let other: toml::Table = toml::from_str("[inputs]\ndebounce = \"50ms\"").unwrap();
+    let err = merge(&mut base, other, "").unwrap_err();
+    assert_eq!(err.to_string(), "`inputs.debounce` is already defined");
+    let other: toml::Table = toml::from_str("inputs = 1").unwrap();
+    assert!(merge(&mut base, other, "").is_err());
+}
+
+#[test]
+fn test_resolve() {
+    let dir = tempdir().unwrap();
+    let channels = dir.path().join("channels.d");
+    fs::create_dir(&channels).unwrap();
+    fs::write(channels.join("b.toml"), "[[inputs.groups]]\nchannel = 1").unwrap();
+    fs::write(channels.join("a.toml"), "[[inputs.groups]]\nchannel = 0").unwrap();
+    fs::write(channels.join("notes.txt"), "not included").unwrap();
+    fs::write(dir.path().join("mqtt.toml"), "[mqtt]\nbroker_port = 8883").unwrap();
+
+    let mut table: toml::Table = toml::from_str(
+        r#"
+        include = ["mqtt.toml", "channels.d/*.toml", "channels.d/a.toml"]
+        [mqtt]
+        broker_host = "mqtt.example.com"
+        "#,
+    )
+    .unwrap();
+    resolve(&mut table, dir.path()).unwrap();
+    assert!(!table.contains_key("include"));
+    assert_eq!(table["mqtt"]["broker_port"].as_integer(), Some(8883));
+    // Files matching a pattern are sorted by name, a file is included once
+    let channels: Vec<_> = table["inputs"]["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|group| group["channel"].as_integer().unwrap())
+        .collect();
+    assert_eq!(channels, [0, 1]);
+
+    // Without `include`, the table is unchanged
+    let mut table: toml::Table = toml::from_str("device_name = \"line1\"").unwrap();
+    resolve(&mut table, dir.path()).unwrap();
+    assert_eq!(table.len(), 1);
+
+    let invalid = [
+        // Not a list
+        "include = \"mqtt.toml\"",
+        // Missing file
+        "include = [\"missing.toml\"]",
+        // Wildcard in a directory
+        "include = [\"*/a.toml\"]",
+        // Conflicting value
+        "include = [\"mqtt.toml\"]\n[mqtt]\nbroker_port = 1883",
+    ];
+    for contents in invalid {
+        let mut table: toml::Table = toml::from_str(contents).unwrap();
+        assert!(resolve(&mut table, dir.path()).is_err(), "{contents}");
+    }
+
+    // Included files can't include further files
+    fs::write(dir.path().join("nested.toml"), "include = [\"mqtt.toml\"]").unwrap();
+    let mut table: toml::Table = toml::from_str("include = [\"nested.toml\"]").unwrap();
+    assert!(resolve(&mut table, dir.path()).is_err());
+}
@@ -23,6 +23,7 @@ fn test_default_config() {
     assert_eq!(config.mqtt.password, None);
     assert_eq!(config.mqtt.keepalive, Duration::from_secs(300));
     assert_eq!(config.mqtt.heartbeat_interval, Duration::from_secs(60));
+    assert_eq!(config.mqtt.subscribe_qos, 2);
 }
 
 #[test]
@@ -44,7 +45,7 @@ fn test_from_toml() {
 
     fs::write(&config_path, toml_content).unwrap();
 
-    let config = Config::from_toml(config_path).unwrap();
+    let config = Config::from_toml(config_path, None).unwrap();
     assert_eq!(config.device_name, "test_device");
     assert_eq!(config.mqtt.broker_host, "test.mosquitto.org");
     assert_eq!(config.mqtt.broker_port, 8883);
@@ -54,6 +55,58 @@ fn test_from_toml() {
     assert_eq!(config.mqtt.heartbeat_interval, Duration::from_secs(30));
 }
 
+#[test]
+fn test_profiles() {
+    let toml_content = r#"
+        device_name = "pfc200"
+
+        [mqtt]
+        broker_host = "mqtt.example.com"
+        heartbeat_interval = "30s"
+
+        [profile.lab.mqtt]
+        broker_host = "lab.example.com"
+
+        [profile.production]
+        device_name = "line1"
+        "#;
+
+    // Without a profile, the base configuration is used
+    let config = Config::from_toml_str(toml_content, None).unwrap();
+    assert_eq!(config.device_name, "pfc200");
+    assert_eq!(config.mqtt.broker_host, "mqtt.example.com");
+    assert_eq!(config.profile, None);
+
+    // Nested tables are merged with the base configuration
+    let config = Config::from_toml_str(toml_content, Some("lab")).unwrap();
+    assert_eq!(config.device_name, "pfc200");
+    assert_eq!(config.mqtt.broker_host, "lab.example.com");
+    assert_eq!(config.mqtt.heartbeat_interval, Duration::from_secs(30));
+    assert_eq!(config.profile.as_deref(), Some("lab"));
+
+    let config = Config::from_toml_str(toml_content, Some("production")).unwrap();
+    assert_eq!(config.device_name, "line1");
+    assert_eq!(config.mqtt.broker_host, "mqtt.example.com");
+
+    assert!(Config::from_toml_str(toml_content, Some("staging")).is_err());
+    assert!(Config::from_toml_str("profile = 1", None).is_err());
+    // Unknown fields in profiles are rejected like in the base configuration
+    assert!(
+        Config::from_toml_str(&format!("{toml_content}\nunknown = 1"), Some("production")).is_err()
+    );
+
+    // A file without profiles is used as is
+    let config = Config::from_toml_str("[mqtt]\nbroker_host = \"a\"", Some("lab")).unwrap();
+    assert_eq!(config.mqtt.broker_host, "a");
+
+    let dir = tempdir().unwrap();
+    let config_path = dir.path().join("config.toml");
+    fs::write(&config_path, toml_content).unwrap();
+    let config = Config::load(Some(config_path), Some("production".to_owned())).unwrap();
+    assert_eq!(config.device_name, "line1");
+    assert!(Config::load(None, Some("production".to_owned())).is_err());
+}
+
 #[test]
 fn test_env_variables() {
     // Setup
@@ -64,9 +117,11 @@ fn test_env_variables() {
     set_env_var("KBUS_BRIDGE_MQTT_PASSWORD", "env_password");
     set_env_var("KBUS_BRIDGE_MQTT_KEEPALIVE", "150");
     set_env_var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL", "45");
+    set_env_var("KBUS_BRIDGE_TOPIC_INCLUDE_MAC", "false");
 
-    let config = Config::load(None).unwrap();
+    let config = Config::load(None, None).unwrap();
     assert_eq!(config.device_name, "env_device");
+    assert!(!config.topic_include_mac);
     assert_eq!(config.mqtt.broker_host, "env.mqtt.com");
     assert_eq!(config.mqtt.broker_port, 2345);
     assert_eq!(config.mqtt.username, Some("env_user".to_string()));
@@ -82,6 +137,7 @@ fn test_env_variables() {
     remove_env_var("KBUS_BRIDGE_MQTT_PASSWORD");
     remove_env_var("KBUS_BRIDGE_MQTT_KEEPALIVE");
     remove_env_var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL");
+    remove_env_var("KBUS_BRIDGE_TOPIC_INCLUDE_MAC");
 }
 
 #[test]
@@ -123,7 +179,7 @@ fn test_load_precedence() {
     set_env_var("KBUS_BRIDGE_MQTT_PORT", "2345");
 
     // Test 1: CLI arg takes precedence over env file
-    let config = Config::load(Some(config_path.clone())).unwrap();
+    let config = Config::load(Some(config_path.clone()), None).unwrap();
 
     // Environment variables should override file config
     assert_eq!(config.device_name, "file_device"); // From CLI config file, not env file
@@ -138,7 +194,7 @@ fn test_load_precedence() {
     set_env_var("KBUS_BRIDGE_MQTT_KEEPALIVE", "45");
     set_env_var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL", "75");
 
-    let config2 = Config::load(None).unwrap();
+    let config2 = Config::load(None, None).unwrap();
     assert_eq!(config2.device_name, "env_file_device"); // From env file
     assert_eq!(config2.mqtt.broker_host, "env_file.mqtt.org"); // From env file
     assert_eq!(config2.mqtt.broker_port, 7777); // From env file
@@ -154,17 +210,17 @@ fn test_load_precedence() {
 #[test]
 fn test_invalid_env_values() {
     set_env_var("KBUS_BRIDGE_MQTT_PORT", "not_a_number");
-    let result = Config::load(None);
+    let result = Config::load(None, None);
     assert!(result.is_err());
 
     remove_env_var("KBUS_BRIDGE_MQTT_PORT");
     set_env_var("KBUS_BRIDGE_MQTT_KEEPALIVE", "invalid");
-    let result = Config::load(None);
+    let result = Config::load(None, None);
     assert!(result.is_err());
 
     remove_env_var("KBUS_BRIDGE_MQTT_KEEPALIVE");
     set_env_var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL", "invalid");
-    let result = Config::load(None);
+    let result = Config::load(None, None);
     assert!(result.is_err());
 
     remove_env_var("KBUS_BRIDGE_MQTT_HEARTBEAT_INTERVAL");
@@ -181,7 +237,9 @@ fn test_valid_config_validation() {
             password: None,
             keepalive: Duration::from_secs(300),
             heartbeat_interval: Duration::from_secs(60),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
 
     let result = config.validate();
@@ -194,6 +252,7 @@ fn test_invalid_device_name() {
     let config = Config {
         device_name: "".to_string(),
         mqtt: MqttConfig::default(),
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -202,6 +261,7 @@ fn test_invalid_device_name() {
     let config = Config {
         device_name: "test device".to_string(),
         mqtt: MqttConfig::default(),
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -210,6 +270,7 @@ fn test_invalid_device_name() {
     let config = Config {
         device_name: "test/device".to_string(),
         mqtt: MqttConfig::default(),
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -218,6 +279,7 @@ fn test_invalid_device_name() {
     let config = Config {
         device_name: "test+device".to_string(),
         mqtt: MqttConfig::default(),
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -226,6 +288,7 @@ fn test_invalid_device_name() {
     let config = Config {
         device_name: "test#device".to_string(),
         mqtt: MqttConfig::default(),
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -242,7 +305,9 @@ fn test_invalid_mqtt_host() {
             password: None,
             keepalive: Duration::from_secs(300),
             heartbeat_interval: Duration::from_secs(60),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -259,7 +324,9 @@ fn test_invalid_mqtt_port() {
             password: None,
             keepalive: Duration::from_secs(300),
             heartbeat_interval: Duration::from_secs(60),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -277,7 +344,9 @@ fn test_invalid_keepalive() {
             password: None,
             keepalive: Duration::from_secs(3),
             heartbeat_interval: Duration::from_secs(60),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -292,7 +361,9 @@ fn test_invalid_keepalive() {
             password: None,
             keepalive: Duration::from_secs(100000),
             heartbeat_interval: Duration::from_secs(60),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -310,7 +381,9 @@ fn test_invalid_heartbeat_interval() {
             password: None,
             keepalive: Duration::from_secs(300),
             heartbeat_interval: Duration::from_millis(500),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
@@ -325,8 +398,1529 @@ fn test_invalid_heartbeat_interval() {
             password: None,
             keepalive: Duration::from_secs(300),
             heartbeat_interval: Duration::from_secs(4000),
+            ..MqttConfig::default()
         },
+        ..Config::default()
     };
     let result = config.validate();
     assert!(result.is_err());
 }
+
+#[test]
+fn test_proxy() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [mqtt.proxy]
+        host = "proxy.example.com"
+        port = 3128
+        username = "bridge"
+        password = "secret"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    let proxy = config.mqtt.proxy.as_ref().unwrap();
+    assert_eq!(proxy.host, "proxy.example.com");
+    assert_eq!(proxy.port, 3128);
+    assert!(config.validate().is_ok());
+
+    // Username without password
+    let mut config = config;
+    config.mqtt.proxy.as_mut().unwrap().password = None;
+    assert!(config.validate().is_err());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            proxy: Some(ProxyConfig {
+                host: String::new(),
+                port: 3128,
+                username: None,
+                password: None,
+            }),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_redacted() {
+    let config = Config {
+        mqtt: MqttConfig {
+            username: Some("bridge".to_owned()),
+            password: Some("secret".to_owned()),
+            proxy: Some(ProxyConfig {
+                host: "proxy.example.com".to_owned(),
+                port: 3128,
+                username: Some("proxy_user".to_owned()),
+                password: Some("proxy_secret".to_owned()),
+            }),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let json = serde_json::to_string(&config.redacted()).unwrap();
+    assert!(!json.contains("secret"), "{json}");
+    assert!(json.contains("\"username\":\"bridge\""), "{json}");
+    assert!(json.contains("proxy_user"), "{json}");
+    assert_eq!(config.mqtt.password.as_deref(), Some("secret"));
+
+    // Unset passwords stay unset
+    let redacted = Config::default().redacted();
+    assert_eq!(redacted.mqtt.password, None);
+}
+
+#[test]
+fn test_remote_config() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [remote_config]
+        enabled = true
+        grace_period = "2m"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert!(config.remote_config.enabled);
+    assert_eq!(config.remote_config.grace_period, Duration::from_secs(120));
+    assert!(config.validate().is_ok());
+
+    let config = Config {
+        remote_config: RemoteConfig {
+            enabled: true,
+            grace_period: Duration::from_secs(5),
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_unredact() {
+    let current = Config {
+        mqtt: MqttConfig {
+            password: Some("secret".to_owned()),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let mut update = current.redacted();
+    update.unredact(&current);
+    assert_eq!(update.mqtt.password.as_deref(), Some("secret"));
+
+    // A new password is kept
+    let mut update = current.redacted();
+    update.mqtt.password = Some("changed".to_owned());
+    update.unredact(&current);
+    assert_eq!(update.mqtt.password.as_deref(), Some("changed"));
+}
+
+#[test]
+fn test_signing() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [signing]
+        key = "0123456789abcdef"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let signing = config.signing.as_ref().unwrap();
+    assert_eq!(signing.max_age, Duration::from_secs(30));
+
+    // The key is never published
+    let mut update = config.redacted();
+    assert_eq!(update.signing.as_ref().unwrap().key, "<redacted>");
+    update.unredact(&config);
+    assert_eq!(update.signing.unwrap().key, "0123456789abcdef");
+
+    for (key, max_age, valid) in [
+        ("0123456789abcdef", Duration::from_secs(3600), true),
+        ("0123456789abcde", Duration::from_secs(30), false),
+        ("0123456789abcdef", Duration::ZERO, false),
+        ("0123456789abcdef", Duration::from_secs(3601), false),
+    ] {
+        let config = Config {
+            signing: Some(SigningConfig {
+                key: key.to_owned(),
+                max_age,
+            }),
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{key} {max_age:?}");
+    }
+}
+
+#[test]
+fn test_ping_interval() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+        ping_interval = "10s"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.mqtt.ping_interval, Duration::from_secs(10));
+    assert!(config.validate().is_ok());
+
+    // Disabled by default
+    assert!(Config::default().mqtt.ping_interval.is_zero());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            ping_interval: Duration::from_millis(500),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_telemetry_interval() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+        telemetry_interval = "5m"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.mqtt.telemetry_interval, Duration::from_secs(300));
+    assert!(config.validate().is_ok());
+
+    // Disabled by default
+    assert!(Config::default().mqtt.telemetry_interval.is_zero());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            telemetry_interval: Duration::from_secs(2 * 86400),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_fast_input_channels() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [inputs]
+        fast = [3, 17]
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.inputs.fast, vec![3, 17]);
+    assert!(config.validate().is_ok());
+    assert_eq!(config.inputs.soe_capacity, 1000);
+
+    // Sequence of events too large for a single message
+    let mut large = config.clone();
+    large.inputs.soe_capacity = MAX_SOE_CAPACITY + 1;
+    assert!(large.validate().is_err());
+
+    // Fast channel beyond the input process image
+    let config = Config {
+        inputs: InputsConfig {
+            fast: vec![1000],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_monitored_input_ranges() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [inputs]
+        monitor = ["0-15", "32", "40-47"]
+        fast = [3]
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(
+        config.inputs.monitor,
+        [
+            ChannelRange { first: 0, last: 15 },
+            ChannelRange {
+                first: 32,
+                last: 32
+            },
+            ChannelRange {
+                first: 40,
+                last: 47
+            },
+        ]
+    );
+    assert!(config.validate().is_ok());
+    assert!(config.inputs.is_monitored(32));
+    assert!(!config.inputs.is_monitored(16));
+
+    for range in ["15-0", "a-3", "", "3-"] {
+        let toml_content = format!(
+            r#"
+            [mqtt]
+            broker_host = "mqtt.example.com"
+
+            [inputs]
+            monitor = ["{range}"]
+            "#
+        );
+        assert!(toml::from_str::<Config>(&toml_content).is_err(), "{range}");
+    }
+
+    // Range beyond the input process image
+    let config = Config {
+        inputs: InputsConfig {
+            monitor: vec![ChannelRange {
+                first: 0,
+                last: 1000,
+            }],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+
+    // Fast channel which isn't monitored
+    let config = Config {
+        inputs: InputsConfig {
+            fast: vec![20],
+            monitor: vec![ChannelRange { first: 0, last: 15 }],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_rules() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [rules]
+        alarm = "in3 && !in7"
+        any_door_open = "in10 || in11"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.rules.len(), 2);
+    assert_eq!(config.rules["alarm"], "in3 && !in7");
+    assert!(config.validate().is_ok());
+
+    // Invalid expression
+    let config = Config {
+        rules: BTreeMap::from([("alarm".to_string(), "in3 &&".to_string())]),
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+
+    // Input channel out of range
+    let config = Config {
+        rules: BTreeMap::from([("alarm".to_string(), "in3 && in1000".to_string())]),
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+
+    // Name not usable as a topic level
+    let config = Config {
+        rules: BTreeMap::from([("door/alarm".to_string(), "in3".to_string())]),
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_schedules() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [[schedules]]
+        output = 4
+        value = true
+        at = "06:00"
+        days = ["mon", "tue", "wed", "thu", "fri"]
+
+        [[schedules]]
+        output = 4
+        value = false
+        at = "20:00"
+
+        [[schedules]]
+        output = 5
+        value = true
+        every = "15m"
+        "#;
+
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.schedules.len(), 3);
+    assert_eq!(config.schedules[0].at, NaiveTime::from_hms_opt(6, 0, 0));
+    assert_eq!(config.schedules[0].days.len(), 5);
+    assert_eq!(config.schedules[2].every, Some(Duration::from_secs(900)));
+    assert!(config.validate().is_ok());
+
+    // Neither 'at' nor 'every'
+    let mut invalid = config.clone();
+    invalid.schedules[0].at = None;
+    assert!(invalid.validate().is_err());
+
+    // Both 'at' and 'every'
+    let mut invalid = config.clone();
+    invalid.schedules[0].every = Some(Duration::from_secs(60));
+    assert!(invalid.validate().is_err());
+
+    // Output channel out of range
+    let mut invalid = config.clone();
+    invalid.schedules[1].output = 1000;
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn test_invalid_subscribe_qos() {
+    let config = Config {
+        mqtt: MqttConfig {
+            subscribe_qos: 3,
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            subscribe_qos: 1,
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn test_retained_commands() {
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "localhost"
+        retained_commands = "fresh"
+        retained_max_age = "5m"
+        "#;
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.mqtt.retained_commands, RetainedCommands::Fresh);
+    assert_eq!(config.mqtt.retained_max_age, Duration::from_secs(300));
+    assert!(config.validate().is_ok());
+
+    assert_eq!(
+        Config::default().mqtt.retained_commands,
+        RetainedCommands::Accept
+    );
+
+    let config = Config {
+        mqtt: MqttConfig {
+            retained_commands: RetainedCommands::Fresh,
+            retained_max_age: Duration::ZERO,
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_command_max_age() {
+    assert_eq!(Config::default().mqtt.command_max_age, None);
+
+    let toml_content = r#"
+        [mqtt]
+        broker_host = "localhost"
+        command_max_age = "30s"
+        "#;
+    let config: Config = toml::from_str(toml_content).unwrap();
+    assert_eq!(config.mqtt.command_max_age, Some(Duration::from_secs(30)));
+    assert!(config.validate().is_ok());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            command_max_age: Some(Duration::from_millis(500)),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_shutdown_timeout() {
+    assert_eq!(
+        Config::default().mqtt.shutdown_timeout,
+        Duration::from_secs(5)
+    );
+
+    let config = Config {
+        mqtt: MqttConfig {
+            shutdown_timeout: Duration::ZERO,
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+
+    let config = Config {
+        mqtt: MqttConfig {
+            shutdown_timeout: Duration::from_secs(61),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_rejections_interval() {
+    assert_eq!(
+        Config::default().mqtt.rejections_interval,
+        Duration::from_secs(60)
+    );
+
+    for (interval, valid) in [
+        (Duration::ZERO, true),
+        (Duration::from_millis(500), false),
+        (Duration::from_secs(3600), true),
+        (Duration::from_secs(86401), false),
+    ] {
+        let config = Config {
+            mqtt: MqttConfig {
+                rejections_interval: interval,
+                ..MqttConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{interval:?}");
+    }
+}
+
+#[test]
+fn test_topic_prefix() {
+    let mut config = Config {
+        device_name: "pfc200".to_owned(),
+        ..Config::default()
+    };
+    assert!(config.topic_include_mac);
+    assert_eq!(
+        config.topic_prefix("00:30:de:00:00:01"),
+        "pfc200/00:30:de:00:00:01"
+    );
+
+    config.topic_include_mac = false;
+    assert_eq!(config.topic_prefix("00:30:de:00:00:01"), "pfc200");
+
+    config.topic_root = Some("acme/plant1/line3".to_owned());
+    assert!(config.validate().is_ok());
+    assert_eq!(
+        config.topic_prefix("00:30:de:00:00:01"),
+        "acme/plant1/line3/pfc200"
+    );
+    assert_eq!(
+        config.aggregator_site(&AggregatorConfig {
+            area: "hall1".to_owned(),
+            sources: Vec::new(),
+        }),
+        "acme/plant1/line3/site/hall1"
+    );
+
+    for root in [
+        "",
+        "/acme",
+        "acme/",
+        "acme//plant1",
+        "acme/+",
+        "acme/#",
+        "acme plant",
+    ] {
+        config.topic_root = Some(root.to_owned());
+        assert!(config.validate().is_err(), "{root}");
+    }
+}
+
+#[test]
+fn test_identity() {
+    assert_eq!(Config::default().identity.source, IdentitySource::Mac);
+
+    let config: Config = toml::from_str(
+        r#"
+        [identity]
+        source = "machine-id"
+
+        [mqtt]
+        broker_host = "localhost"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.identity.source, IdentitySource::MachineId);
+
+    let result: Result<Config, _> = toml::from_str(
+        r#"
+        [identity]
+        source = "serial"
+
+        [mqtt]
+        broker_host = "localhost"
+        "#,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_claim_interval() {
+    assert!(Config::default().mqtt.claim_interval.is_zero());
+
+    for (interval, valid) in [
+        (Duration::ZERO, true),
+        (Duration::from_millis(500), false),
+        (Duration::from_secs(10), true),
+        (Duration::from_secs(3601), false),
+    ] {
+        let config = Config {
+            mqtt: MqttConfig {
+                claim_interval: interval,
+                ..MqttConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{interval:?}");
+    }
+}
+
+#[test]
+fn test_share_group() {
+    assert_eq!(Config::default().mqtt.share_group, None);
+
+    for (group, claim_interval, valid) in [
+        ("bridges", Duration::ZERO, true),
+        ("", Duration::ZERO, false),
+        ("a/b", Duration::ZERO, false),
+        ("a+", Duration::ZERO, false),
+        ("bridges", Duration::from_secs(10), false),
+    ] {
+        let config = Config {
+            mqtt: MqttConfig {
+                share_group: Some(group.to_owned()),
+                claim_interval,
+                ..MqttConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{group:?}");
+    }
+}
+
+#[test]
+fn test_alerts() {
+    let config = Config::default();
+    assert_eq!(config.alerts.memory_usage, None);
+    assert_eq!(config.alerts.intervals, 3);
+
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [alerts]
+        memory_usage = 90.0
+        queue_depth = 1000
+        intervals = 5
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.alerts.memory_usage, Some(90.0));
+    assert_eq!(config.alerts.cpu_usage, None);
+    assert_eq!(config.alerts.queue_depth, Some(1000));
+    assert_eq!(config.alerts.intervals, 5);
+    assert!(config.validate().is_ok());
+
+    for (alerts, valid) in [
+        (
+            AlertsConfig {
+                cpu_usage: Some(0.0),
+                ..AlertsConfig::default()
+            },
+            false,
+        ),
+        (
+            AlertsConfig {
+                cpu_usage: Some(100.5),
+                ..AlertsConfig::default()
+            },
+            false,
+        ),
+        (
+            AlertsConfig {
+                memory_usage: Some(f32::NAN),
+                ..AlertsConfig::default()
+            },
+            false,
+        ),
+        (
+            AlertsConfig {
+                intervals: 0,
+                ..AlertsConfig::default()
+            },
+            false,
+        ),
+    ] {
+        let config = Config {
+            alerts: alerts.clone(),
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{alerts:?}");
+    }
+
+    // Limits are checked on heartbeats
+    let config = Config {
+        mqtt: MqttConfig {
+            heartbeat_interval: Duration::ZERO,
+            ..MqttConfig::default()
+        },
+        alerts: AlertsConfig {
+            queue_depth: Some(100),
+            ..AlertsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_state() {
+    let config = Config::default();
+    assert_eq!(config.state.file, None);
+    assert_eq!(config.state.flush_interval, Duration::from_secs(300));
+
+    for (interval, valid) in [
+        (Duration::ZERO, true),
+        (Duration::from_millis(500), false),
+        (Duration::from_secs(60), true),
+        (Duration::from_secs(86401), false),
+    ] {
+        let config = Config {
+            state: StateConfig {
+                file: Some(PathBuf::from("/var/lib/kbus_mqtt_bridge/state.json")),
+                flush_interval: interval,
+                ..StateConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{interval:?}");
+    }
+
+    // Clearing the previous topic prefix needs the prefix recorded in the state file
+    let config = Config {
+        state: StateConfig {
+            clear_previous_prefix: true,
+            ..StateConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_payloads() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [[inputs.payloads]]
+        channels = "0-7"
+        payload_on = "ON"
+        payload_off = "OFF"
+
+        [[outputs.payloads]]
+        channels = "4"
+        template = '{"state": {value}}'
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.inputs.payload(7).unwrap().payload_on, "ON");
+    assert_eq!(config.inputs.payload(8), None);
+    let payload = config.outputs.payload(4).unwrap();
+    assert_eq!(payload.payload_on, "true");
+    assert_eq!(payload.payload_off, "false");
+
+    let payload = |channels: &str, on: &str, template: Option<&str>| ChannelPayload {
+        channels: ChannelRange::try_from(channels.to_owned()).unwrap(),
+        payload_on: on.to_owned(),
+        payload_off: "OFF".to_owned(),
+        template: template.map(str::to_owned),
+    };
+    let invalid = [
+        // Out of range, indistinguishable or empty payloads
+        payload("0-9999", "ON", None),
+        payload("0", "OFF", None),
+        payload("0", "", None),
+        // Template without or with a repeated value
+        payload("0", "ON", Some("state")),
+        payload("0", "ON", Some("{value}{value}")),
+    ];
+    for payload in invalid {
+        let config = Config {
+            outputs: OutputsConfig {
+                payloads: vec![payload.clone()],
+                ..OutputsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{payload:?}");
+    }
+
+    // Timestamps can't be parsed from output commands
+    let template = Some(r#"{"state": "{value}", "time": "{timestamp}"}"#);
+    let config = Config {
+        inputs: InputsConfig {
+            payloads: vec![payload("0", "ON", template)],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+    let config = Config {
+        outputs: OutputsConfig {
+            payloads: vec![payload("0", "ON", template)],
+            ..OutputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+
+    // Custom payloads need per-channel topics
+    let mut config = Config {
+        inputs: InputsConfig {
+            payloads: vec![payload("0", "ON", None)],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    config.mqtt.payload_profile = PayloadProfile::WagoCloud;
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_topic_profile() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+        topic_profile = "tasmota"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.mqtt.topic_profile, TopicProfile::Tasmota);
+    assert_eq!(Config::default().mqtt.topic_profile, TopicProfile::Bridge);
+
+    // Tasmota payloads are fixed
+    let mut invalid = config.clone();
+    invalid.outputs.payloads = vec![ChannelPayload {
+        channels: ChannelRange { first: 0, last: 0 },
+        payload_on: "1".to_owned(),
+        payload_off: "0".to_owned(),
+        template: None,
+    }];
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn test_supervisor() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [supervisor]
+        max_restarts = 0
+        restart_delay = "1s"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.supervisor.max_restarts, 0);
+    assert_eq!(config.supervisor.restart_delay, Duration::from_secs(1));
+    assert_eq!(Config::default().supervisor.max_restarts, 3);
+
+    for (max_restarts, restart_delay, valid) in [
+        (100, Duration::ZERO, true),
+        (101, Duration::ZERO, false),
+        (3, Duration::from_secs(301), false),
+    ] {
+        let config = Config {
+            supervisor: SupervisorConfig {
+                max_restarts,
+                restart_delay,
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{max_restarts}");
+    }
+}
+
+#[test]
+fn test_kbus_not_required() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [kbus]
+        required = false
+        retry_interval = "1m"
+        cycle_order = "wago"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert!(!config.kbus.required);
+    assert_eq!(config.kbus.cycle_order, CycleOrder::Wago);
+    assert_eq!(Config::default().kbus.cycle_order, CycleOrder::WriteRead);
+    assert_eq!(config.kbus.retry_interval, Duration::from_secs(60));
+    assert!(Config::default().kbus.required);
+
+    for (retry_interval, valid) in [
+        (Duration::from_secs(1), true),
+        (Duration::from_millis(999), false),
+        (Duration::from_secs(3601), false),
+    ] {
+        let config = Config {
+            kbus: KBusConfig {
+                retry_interval,
+                ..KBusConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(config.validate().is_ok(), valid, "{retry_interval:?}");
+    }
+}
+
+#[test]
+fn test_aggregator() {
+    assert!(Config::default().aggregator.is_none());
+
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [aggregator]
+        area = "hall1"
+
+        [[aggregator.sources]]
+        name = "coupler1"
+        prefix = "pfc200/00:30:de:00:00:01"
+
+        [[aggregator.sources]]
+        name = "coupler2"
+        prefix = "pfc200/00:30:de:00:00:02"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+
+    let source = |name: &str, prefix: &str| AggregatorSource {
+        name: name.to_owned(),
+        prefix: prefix.to_owned(),
+    };
+    for (area, sources) in [
+        ("", vec![source("a", "pfc/1")]),
+        ("hall/1", vec![source("a", "pfc/1")]),
+        ("hall1", vec![]),
+        ("hall1", vec![source("", "pfc/1")]),
+        ("hall1", vec![source("a b", "pfc/1")]),
+        ("hall1", vec![source("a", "pfc/1"), source("a", "pfc/2")]),
+        ("hall1", vec![source("a", "")]),
+        ("hall1", vec![source("a", "pfc/1/")]),
+        ("hall1", vec![source("a", "pfc/+")]),
+        ("hall1", vec![source("a", "site")]),
+        ("hall1", vec![source("a", "site/hall1/b")]),
+        ("hall1", vec![source("a", "pfc"), source("b", "pfc/2")]),
+    ] {
+        let config = Config {
+            aggregator: Some(AggregatorConfig {
+                area: area.to_owned(),
+                sources: sources.clone(),
+            }),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{area} {sources:?}");
+    }
+
+    // The merged namespace is below the topic root
+    let mut config = Config {
+        topic_root: Some("acme".to_owned()),
+        aggregator: Some(AggregatorConfig {
+            area: "hall1".to_owned(),
+            sources: vec![source("a", "site/hall1/a")],
+        }),
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+    config.aggregator.as_mut().unwrap().sources = vec![source("a", "acme/site/hall1/a")];
+    assert!(config.validate().is_err());
+
+    // Other areas and levels only sharing a string prefix are fine
+    let config = Config {
+        aggregator: Some(AggregatorConfig {
+            area: "hall1".to_owned(),
+            sources: vec![source("a", "site/hall2/a"), source("b", "site/hall2/ab")],
+        }),
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn test_modbus() {
+    assert!(Config::default().modbus.is_none());
+
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [modbus]
+        port = "/dev/ttyO0"
+        parity = "none"
+
+        [[modbus.devices]]
+        name = "meter"
+        slave = 1
+        input_registers = { address = 0, count = 10 }
+
+        [[modbus.devices]]
+        name = "relays"
+        slave = 2
+        discrete_inputs = { address = 0, count = 8 }
+        coils = { address = 16, count = 8 }
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let modbus = config.modbus.unwrap();
+    assert_eq!(modbus.baud_rate, 19200);
+    assert_eq!(modbus.parity, Parity::None);
+    assert_eq!(modbus.stop_bits, 1);
+    assert_eq!(modbus.poll_interval, Duration::from_millis(500));
+    assert!(modbus.aggregate_window.is_zero());
+    assert_eq!(modbus.devices[1].coils.unwrap().count, 8);
+
+    let range = |address, count| Some(ModbusRange { address, count });
+    let device = ModbusDeviceConfig {
+        name: "meter".to_owned(),
+        slave: 1,
+        discrete_inputs: None,
+        input_registers: range(0, 10),
+        coils: None,
+        holding_registers: None,
+    };
+    let valid = ModbusConfig {
+        devices: vec![device.clone()],
+        ..modbus
+    };
+    for modbus in [
+        ModbusConfig {
+            port: String::new(),
+            ..valid.clone()
+        },
+        ModbusConfig {
+            baud_rate: 0,
+            ..valid.clone()
+        },
+        ModbusConfig {
+            stop_bits: 3,
+            ..valid.clone()
+        },
+        ModbusConfig {
+            poll_interval: Duration::from_millis(5),
+            ..valid.clone()
+        },
+        ModbusConfig {
+            timeout: Duration::from_secs(11),
+            ..valid.clone()
+        },
+        ModbusConfig {
+            aggregate_window: Duration::from_millis(100),
+            ..valid.clone()
+        },
+        ModbusConfig {
+            aggregate_window: Duration::from_secs(86401),
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![device.clone(), device.clone()],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![ModbusDeviceConfig {
+                name: "meter/1".to_owned(),
+                ..device.clone()
+            }],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![ModbusDeviceConfig {
+                slave: 0,
+                ..device.clone()
+            }],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![ModbusDeviceConfig {
+                input_registers: None,
+                ..device.clone()
+            }],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![ModbusDeviceConfig {
+                input_registers: range(0, 126),
+                ..device.clone()
+            }],
+            ..valid.clone()
+        },
+        ModbusConfig {
+            devices: vec![ModbusDeviceConfig {
+                coils: range(65530, 8),
+                ..device.clone()
+            }],
+            ..valid.clone()
+        },
+    ] {
+        let config = Config {
+            modbus: Some(modbus.clone()),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{modbus:?}");
+    }
+}
+
+#[test]
+fn test_transform() {
+    assert!(Config::default().transform.is_none());
+
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [transform]
+        script = "/etc/kbus_mqtt_bridge/transform.rhai"
+        subscribe = ["legacy/+/set", "legacy/all/#"]
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+
+    for (script, subscribe) in [
+        ("", vec![]),
+        ("transform.rhai", vec![""]),
+        ("transform.rhai", vec!["legacy/relay+"]),
+        ("transform.rhai", vec!["legacy/#/set"]),
+    ] {
+        let config = Config {
+            transform: Some(TransformConfig {
+                script: script.into(),
+                subscribe: subscribe.iter().map(|s| s.to_string()).collect(),
+            }),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{script} {subscribe:?}");
+    }
+}
+
+#[test]
+fn test_outputs_verify() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [outputs]
+        verify = [{ output = 4, input = 12 }, { output = 5, input = 13 }]
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(
+        config.outputs.verify[1],
+        OutputVerify {
+            output: 5,
+            input: 13
+        }
+    );
+    assert_eq!(config.outputs.verify_cycles, 1);
+
+    let verify = |output, input| OutputVerify { output, input };
+    for (verify, verify_cycles) in [
+        (vec![verify(90, 0)], 1),
+        (vec![verify(0, 90)], 1),
+        (vec![verify(4, 12), verify(4, 13)], 1),
+        (vec![], 0),
+        (vec![], 101),
+    ] {
+        let config = Config {
+            outputs: OutputsConfig {
+                verify: verify.clone(),
+                verify_cycles,
+                ..OutputsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{verify:?} {verify_cycles}");
+    }
+}
+
+#[test]
+fn test_channel_groups() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [[inputs.groups]]
+        channel = 12
+        group = "hvac"
+        name = "fan_feedback"
+
+        [[outputs.groups]]
+        channel = 3
+        group = "lighting"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.inputs.group(12).unwrap().path(), "hvac/fan_feedback");
+    assert_eq!(config.outputs.group(3).unwrap().path(), "lighting/3");
+    assert!(config.inputs.group(3).is_none());
+
+    let grouped = |channel, group: &str, name: Option<&str>| GroupedChannel {
+        channel,
+        group: group.to_owned(),
+        name: name.map(str::to_owned),
+    };
+    for groups in [
+        vec![grouped(90, "hvac", None)],
+        vec![grouped(1, "", None)],
+        vec![grouped(1, "bit", None)],
+        vec![grouped(1, "12", None)],
+        vec![grouped(1, "hvac/fan", None)],
+        vec![grouped(1, "hvac", Some("fan+"))],
+        vec![grouped(1, "hvac", Some(""))],
+        vec![grouped(1, "hvac", None), grouped(1, "lighting", None)],
+        vec![
+            grouped(1, "hvac", Some("fan")),
+            grouped(2, "hvac", Some("fan")),
+        ],
+        vec![grouped(1, "hvac", Some("2")), grouped(2, "hvac", None)],
+    ] {
+        let config = Config {
+            outputs: OutputsConfig {
+                groups: groups.clone(),
+                ..OutputsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{groups:?}");
+    }
+
+    let mut config = config;
+    config.mqtt.topic_profile = TopicProfile::Tasmota;
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_inputs_activity() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [[inputs.activity]]
+        channels = "4-7"
+        expect_activity_within = "24h"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(
+        config.inputs.expect_activity_within(5),
+        Some(Duration::from_secs(86400))
+    );
+    assert_eq!(config.inputs.expect_activity_within(8), None);
+
+    for (monitor, first, last, expect_activity_within) in [
+        (vec![], 85, 90, Duration::from_secs(60)),
+        (
+            vec![ChannelRange { first: 0, last: 5 }],
+            4,
+            7,
+            Duration::from_secs(60),
+        ),
+        (vec![], 4, 7, Duration::from_millis(500)),
+    ] {
+        let config = Config {
+            inputs: InputsConfig {
+                monitor,
+                activity: vec![ChannelActivity {
+                    channels: ChannelRange { first, last },
+                    expect_activity_within,
+                }],
+                ..InputsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(config.validate().is_err(), "{first}-{last}");
+    }
+}
+
+#[test]
+fn test_outputs_startup_max_age() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [outputs]
+        startup_max_age = "0s"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.outputs.startup_max_age, Duration::ZERO);
+    assert_eq!(
+        Config::default().outputs.startup_max_age,
+        Duration::from_secs(5)
+    );
+
+    let config = Config {
+        outputs: OutputsConfig {
+            startup_max_age: Duration::from_secs(61),
+            ..OutputsConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_broker() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [broker]
+        "#,
+    )
+    .unwrap();
+    // Only builds with the `embedded-broker` feature accept the section
+    assert_eq!(config.validate().is_ok(), cfg!(feature = "embedded-broker"));
+    let broker = config.broker.clone().unwrap();
+    assert_eq!(broker.listen, "0.0.0.0:1883".parse().unwrap());
+    assert_eq!(broker.max_connections, 32);
+    // The bridge connects to the embedded broker instead of `broker_host`
+    assert_eq!(config.broker_address(), ("127.0.0.1".to_owned(), 1883));
+
+    let config = Config {
+        broker: Some(BrokerConfig {
+            listen: "192.168.1.10:1884".parse().unwrap(),
+            max_connections: 1,
+        }),
+        ..config
+    };
+    assert_eq!(config.broker_address(), ("192.168.1.10".to_owned(), 1884));
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_datalog() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "mqtt.example.com"
+
+        [datalog]
+        directory = "/media/sd/datalog"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let datalog = config.datalog.clone().unwrap();
+    assert_eq!(datalog.flush_interval, Duration::from_secs(10));
+    assert_eq!(datalog.max_files, 31);
+    assert_eq!(datalog.max_total_size, 0);
+
+    let invalid = [
+        DataLogConfig {
+            directory: PathBuf::new(),
+            ..datalog.clone()
+        },
+        DataLogConfig {
+            flush_interval: Duration::ZERO,
+            ..datalog.clone()
+        },
+        DataLogConfig {
+            max_files: 0,
+            ..datalog.clone()
+        },
+    ];
+    for datalog in invalid {
+        let config = Config {
+            datalog: Some(datalog),
+            ..config.clone()
+        };
+        assert!(config.validate().is_err());
+    }
+}
+
+#[test]
+fn test_alarms() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [inputs]
+        monitor = ["0-7"]
+
+        [[alarms]]
+        name = "overtemp"
+        condition = "in3 && !in7"
+        severity = "critical"
+        message = "Motor overtemperature"
+        latching = true
+
+        [[alarms]]
+        name = "door"
+        condition = "in1"
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.alarms[0].severity, AlarmSeverity::Critical);
+    assert!(config.alarms[0].latching);
+    assert_eq!(config.alarms[1].severity, AlarmSeverity::Warning);
+    assert!(!config.alarms[1].latching);
+
+    for (name, condition) in [
+        // Empty, duplicate and invalid topic level names
+        ("", "in1"),
+        ("overtemp", "in1"),
+        ("door/front", "in1"),
+        // Invalid condition
+        ("pressure", "in1 &&"),
+        // Unmonitored channel
+        ("pressure", "in8"),
+    ] {
+        let mut config = config.clone();
+        config.alarms[1].name = name.to_owned();
+        config.alarms[1].condition = condition.to_owned();
+        assert!(config.validate().is_err(), "{name}: {condition}");
+    }
+}
+
+#[test]
+fn test_self_update() {
+    let config: Config = toml::from_str(
+        r#"
+        [mqtt]
+        broker_host = "localhost"
+
+        [self_update]
+        url = "https://updates.example.com/kbus_mqtt_bridge"
+        public_key = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="
+        "#,
+    )
+    .unwrap();
+    // Only builds with the `self-update` feature accept the section
+    assert_eq!(config.validate().is_ok(), cfg!(feature = "self-update"));
+
+    for (url, public_key) in [
+        (
+            "ftp://updates.example.com/kbus_mqtt_bridge",
+            "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+        ),
+        ("https://updates.example.com/kbus_mqtt_bridge", "not base64"),
+        ("https://updates.example.com/kbus_mqtt_bridge", "c2hvcnQ="),
+    ] {
+        let config = Config {
+            self_update: Some(SelfUpdateConfig {
+                url: url.to_owned(),
+                public_key: public_key.to_owned(),
+            }),
+            ..config.clone()
+        };
+        assert!(config.validate().is_err(), "{url} {public_key}");
+    }
+}
+
+#[test]
+fn test_include() {
+    let dir = tempdir().unwrap();
+    let config_path = dir.path().join("config.toml");
+    fs::create_dir(dir.path().join("channels.d")).unwrap();
+    fs::write(
+        dir.path().join("channels.d/cabinet1.toml"),
+        r#"
+        [[inputs.groups]]
+        channel = 0
+        group = "pumps"
+        name = "p1"
+
+        [profile.lab]
+        device_name = "line1-lab"
+        "#,
+    )
+    .unwrap();
+    fs::write(
+        &config_path,
+        r#"
+        include = ["channels.d/*.toml"]
+        device_name = "line1"
+
+        [mqtt]
+        broker_host = "mqtt.example.com"
+        "#,
+    )
+    .unwrap();
+
+    let config = Config::from_toml(&config_path, None).unwrap();
+    assert_eq!(config.device_name, "line1");
+    assert_eq!(config.inputs.groups.len(), 1);
+    assert_eq!(config.inputs.groups[0].path(), "pumps/p1");
+    // Profiles of included files are applied like the ones of the main file
+    let config = Config::from_toml(&config_path, Some("lab")).unwrap();
+    assert_eq!(config.device_name, "line1-lab");
+
+    // Includes are resolved relative to a file only
+    assert!(Config::from_toml_str("include = [\"channels.d/*.toml\"]", None).is_err());
+}
@@ -0,0 +1,223 @@
+//! Local data logging
+//!
+//! Plants without a historian can still keep their data: input events pass through
+//! the data log on their way to the MQTT task and are appended to a CSV file per
+//! day (UTC) in the configured directory, e.g. on the SD card. Rows are buffered
+//! and written every `flush_interval` to spare the card, the oldest files are
+//! deleted above `max_files` or `max_total_size`. A failing card never holds back
+//! the events, write errors are only logged.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    time::{MissedTickBehavior, interval},
+};
+use tracing::{info, instrument};
+
+use crate::{config::DataLogConfig, kbus::InputEvent, throttle::warn_throttled};
+
+#[cfg(test)]
+mod tests;
+
+/// First line of every file
+const HEADER: &str = "timestamp,source,value";
+
+/// Returns the name of the file of `date`, names sort by date.
+fn file_name(date: NaiveDate) -> String {
+    format!("inputs-{date}.csv")
+}
+
+/// Returns the source (topic relative to the prefix) and value logged for an event,
+/// `None` for events which aren't samples.
+fn row(event: &InputEvent) -> Option<(String, String)> {
+    match event {
+        InputEvent::Channel(event) => {
+            Some((format!("input/{}", event.channel), event.value.to_string()))
+        }
+        InputEvent::Derived(event) => {
+            Some((format!("derived/{}", event.name), event.value.to_string()))
+        }
+        InputEvent::Modbus(event) => Some((
+            format!("modbus/{}/{}/{}", event.device, event.kind(), event.address),
+            event.value.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Daily CSV files of input events.
+#[derive(Debug)]
+pub struct DataLog {
+    config: DataLogConfig,
+    /// Date and writer of the open file
+    file: Option<(NaiveDate, BufWriter<File>)>,
+}
+
+impl DataLog {
+    pub fn new(config: DataLogConfig) -> DataLog {
+        DataLog { config, file: None }
+    }
+
+    /// Appends a row for `event` at `now`, switching to the file of a new day.
+    pub fn append(&mut self, event: &InputEvent, now: DateTime<Utc>) -> Result<(), anyhow::Error> {
+        let Some((source, value)) = row(event) else {
+            return Ok(());
+        };
+        let date = now.date_naive();
+        let writer = match &mut self.file {
+            Some((file_date, writer)) if *file_date == date => writer,
+            _ => self.open(date)?,
+        };
+        let result = writeln!(
+            writer,
+            "{},{},{}",
+            now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            csv_field(&source),
+            csv_field(&value)
+        );
+        // The file is opened again with the next row, e.g. after the card was replaced
+        if result.is_err() {
+            self.file = None;
+        }
+        result.context("Failed to write data log")
+    }
+
+    /// Writes the buffered rows to the file.
+    pub fn flush(&mut self) -> Result<(), anyhow::Error> {
+        let Some((_, writer)) = &mut self.file else {
+            return Ok(());
+        };
+        let result = writer.flush();
+        if result.is_err() {
+            self.file = None;
+        }
+        result.context("Failed to flush data log")
+    }
+
+    /// Opens the file of `date` for appending and deletes the files above the limits.
+    fn open(&mut self, date: NaiveDate) -> Result<&mut BufWriter<File>, anyhow::Error> {
+        // Rows of the previous day are written before its file is closed
+        if let Some((_, mut writer)) = self.file.take() {
+            writer.flush().context("Failed to flush data log")?;
+        }
+
+        let directory = &self.config.directory;
+        fs::create_dir_all(directory).with_context(|| {
+            format!(
+                "Failed to create data log directory: {}",
+                directory.display()
+            )
+        })?;
+        let path = directory.join(file_name(date));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open data log: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        if writer.get_ref().metadata()?.len() == 0 {
+            writeln!(writer, "{HEADER}")?;
+        }
+        info!(path = %path.display(), "data log opened");
+
+        self.delete_old_files(date)?;
+        Ok(&mut self.file.insert((date, writer)).1)
+    }
+
+    /// Deletes the oldest files above `max_files` or `max_total_size`, never the
+    /// file of `date`.
+    fn delete_old_files(&self, date: NaiveDate) -> Result<(), anyhow::Error> {
+        let directory = &self.config.directory;
+        let current = file_name(date);
+        let mut files: Vec<(PathBuf, u64)> = Vec::new();
+        for entry in fs::read_dir(directory).with_context(|| {
+            format!("Failed to read data log directory: {}", directory.display())
+        })? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.starts_with("inputs-") && name.ends_with(".csv") && name != current {
+                files.push((entry.path(), entry.metadata()?.len()));
+            }
+        }
+        files.sort();
+
+        let mut total: u64 = files.iter().map(|(_, size)| size).sum();
+        // The file of the current day counts towards the limits
+        let mut count = files.len() + 1;
+        for (path, size) in files {
+            let over_size = self.config.max_total_size > 0 && total > self.config.max_total_size;
+            if count <= self.config.max_files && !over_size {
+                break;
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete data log: {}", path.display()))?;
+            info!(path = %path.display(), "old data log deleted");
+            total -= size;
+            count -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Entry point task function for logging the input events.
+///
+/// Forwards every event to the MQTT task, the task ends once all senders of
+/// `input_rx` are dropped, so no event is lost on shutdown.
+///
+/// # Arguments
+///
+/// * `config` - Directory and limits of the files
+/// * `input_rx` - Channel receiving the input events
+/// * `forward_tx` - Channel forwarding the events to the MQTT task
+#[instrument(name = "datalog", skip_all, err)]
+pub async fn datalog_task(
+    config: DataLogConfig,
+    mut input_rx: UnboundedReceiver<InputEvent>,
+    forward_tx: UnboundedSender<InputEvent>,
+) -> Result<(), anyhow::Error> {
+    info!(directory = %config.directory.display(), "starting data log task");
+    let mut flush_interval = interval(config.flush_interval);
+    flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut datalog = DataLog::new(config);
+
+    loop {
+        tokio::select! {
+            event = input_rx.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                if let Err(err) = datalog.append(&event, Utc::now()) {
+                    warn_throttled!("datalog", error = format!("{err:#}"), "failed to log input event");
+                }
+                // The MQTT task is gone on shutdown, the events are still logged
+                let _ = forward_tx.send(event);
+            }
+            _ = flush_interval.tick() => {
+                if let Err(err) = datalog.flush() {
+                    warn_throttled!("datalog", error = format!("{err:#}"), "failed to flush data log");
+                }
+            }
+        }
+    }
+
+    datalog.flush()
+}
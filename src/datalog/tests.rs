@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use chrono::TimeZone;
+use tempfile::tempdir;
+use tokio::sync::mpsc::unbounded_channel;
+
+use super::*;
+use crate::{
+    kbus::{DerivedEvent, KBusEvent},
+    modbus::{ModbusEvent, ModbusValue},
+};
+
+fn config(directory: PathBuf) -> DataLogConfig {
+    DataLogConfig {
+        directory,
+        flush_interval: Duration::from_secs(10),
+        max_files: 31,
+        max_total_size: 0,
+    }
+}
+
+fn channel(channel: u16, value: bool) -> InputEvent {
+    InputEvent::Channel(KBusEvent { channel, value })
+}
+
+fn time(day: u32, hour: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap()
+}
+
+#[test]
+fn test_row() {
+    assert_eq!(
+        row(&channel(5, true)),
+        Some(("input/5".to_owned(), "true".to_owned()))
+    );
+    let event = InputEvent::Derived(DerivedEvent {
+        name: "alarm".into(),
+        value: false,
+    });
+    assert_eq!(
+        row(&event),
+        Some(("derived/alarm".to_owned(), "false".to_owned()))
+    );
+    let event = InputEvent::Modbus(ModbusEvent {
+        device: "meter".to_owned(),
+        address: 3,
+        value: ModbusValue::Register(230),
+    });
+    assert_eq!(
+        row(&event),
+        Some(("modbus/meter/register/3".to_owned(), "230".to_owned()))
+    );
+    // Events which aren't samples are not logged
+    assert_eq!(row(&InputEvent::KBusAvailable(true)), None);
+
+    assert_eq!(csv_field("input/5"), "input/5");
+    assert_eq!(csv_field("a,b"), "\"a,b\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+}
+
+#[test]
+fn test_append() {
+    let dir = tempdir().unwrap();
+    let mut datalog = DataLog::new(config(dir.path().join("log")));
+
+    datalog.append(&channel(1, true), time(3, 6)).unwrap();
+    datalog
+        .append(&InputEvent::KBusAvailable(true), time(3, 6))
+        .unwrap();
+    datalog.append(&channel(1, false), time(3, 7)).unwrap();
+    // Rows are buffered until flushed
+    let path = dir.path().join("log/inputs-2025-03-03.csv");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    datalog.flush().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "timestamp,source,value\n\
+         2025-03-03T06:00:00.000Z,input/1,true\n\
+         2025-03-03T07:00:00.000Z,input/1,false\n"
+    );
+
+    // A new day starts a new file, the previous one is flushed
+    datalog.append(&channel(2, true), time(4, 0)).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    datalog.flush().unwrap();
+    let path = dir.path().join("log/inputs-2025-03-04.csv");
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+    // An existing file is appended to without a second header
+    let mut datalog = DataLog::new(config(dir.path().join("log")));
+    datalog.append(&channel(3, true), time(4, 1)).unwrap();
+    datalog.flush().unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 3);
+    assert_eq!(contents.matches(HEADER).count(), 1);
+}
+
+#[test]
+fn test_retention() {
+    let dir = tempdir().unwrap();
+    let directory = dir.path().to_path_buf();
+    fs::write(directory.join("inputs-2025-03-01.csv"), "a".repeat(100)).unwrap();
+    fs::write(directory.join("inputs-2025-03-02.csv"), "a".repeat(100)).unwrap();
+    fs::write(directory.join("notes.txt"), "a".repeat(1000)).unwrap();
+
+    // The file of the current day counts towards the number of files
+    let mut datalog = DataLog::new(DataLogConfig {
+        max_files: 2,
+        ..config(directory.clone())
+    });
+    datalog.append(&channel(1, true), time(3, 0)).unwrap();
+    assert!(!directory.join("inputs-2025-03-01.csv").exists());
+    assert!(directory.join("inputs-2025-03-02.csv").exists());
+    assert!(directory.join("notes.txt").exists());
+    datalog.flush().unwrap();
+
+    // The oldest files are deleted above the total size, never the current one
+    fs::write(directory.join("inputs-2025-03-01.csv"), "a".repeat(100)).unwrap();
+    let mut datalog = DataLog::new(DataLogConfig {
+        max_total_size: 150,
+        ..config(directory.clone())
+    });
+    datalog.append(&channel(1, true), time(4, 0)).unwrap();
+    assert!(!directory.join("inputs-2025-03-01.csv").exists());
+    assert!(!directory.join("inputs-2025-03-02.csv").exists());
+    assert!(directory.join("inputs-2025-03-03.csv").exists());
+    assert!(directory.join("inputs-2025-03-04.csv").exists());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_datalog_task() {
+    let dir = tempdir().unwrap();
+    let (input_tx, input_rx) = unbounded_channel();
+    let (forward_tx, mut forward_rx) = unbounded_channel();
+    let task = tokio::spawn(datalog_task(
+        config(dir.path().to_path_buf()),
+        input_rx,
+        forward_tx,
+    ));
+
+    input_tx.send(channel(4, true)).unwrap();
+    input_tx.send(InputEvent::KBusAvailable(true)).unwrap();
+    // Every event is forwarded, logged or not
+    assert!(matches!(
+        forward_rx.recv().await,
+        Some(InputEvent::Channel(KBusEvent { channel: 4, .. }))
+    ));
+    assert!(matches!(
+        forward_rx.recv().await,
+        Some(InputEvent::KBusAvailable(true))
+    ));
+
+    // The rows are flushed when all senders are gone
+    drop(input_tx);
+    task.await.unwrap().unwrap();
+    assert!(forward_rx.recv().await.is_none());
+    let name = file_name(Utc::now().date_naive());
+    let contents = fs::read_to_string(dir.path().join(name)).unwrap();
+    assert!(contents.contains(",input/4,true\n"));
+}
@@ -0,0 +1,73 @@
+//! Dump of the internal state on SIGUSR1
+//!
+//! Without a debugger on the device, `kill -USR1` makes the bridge log a snapshot
+//! of its internal state: the process image, queue depths, which tasks are still
+//! running and a summary of the configuration. With `mqtt.publish_state_dump` it's
+//! also published on `debug/state`.
+
+use serde_json::json;
+
+use crate::{
+    config::Config,
+    kbus::ProcessImage,
+    mqtt::{self, MqttStats},
+    timestamp,
+    utils::hex_dump,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Returns the configuration settings worth knowing when debugging, without secrets.
+pub fn config_summary(config: &Config) -> serde_json::Value {
+    json!({
+        "device_name": config.device_name,
+        "profile": config.profile,
+        "broker": format!("{}:{}", config.mqtt.broker_host, config.mqtt.broker_port),
+        "payload_profile": config.mqtt.payload_profile,
+        "shadow": config.outputs.shadow,
+        "rules": config.rules.len(),
+        "schedules": config.schedules.len(),
+        "modbus_devices": config.modbus.as_ref().map_or(0, |modbus| modbus.devices.len()),
+        "aggregator": config.aggregator.is_some(),
+        "transform": config.transform.is_some(),
+        "datalog": config.datalog.is_some(),
+        "remote_config": config.remote_config.enabled,
+    })
+}
+
+/// Builds the state dump.
+///
+/// `image` is `None` if the K-Bus task didn't respond, `tasks` lists the name of
+/// every started task and whether it's still running.
+pub fn state_dump(
+    image: Option<&ProcessImage>,
+    tasks: &[(&'static str, bool)],
+    config_summary: &serde_json::Value,
+) -> serde_json::Value {
+    let stats = MqttStats::current();
+    json!({
+        "timestamp": timestamp::now(),
+        "process_image": image.map(|image| json!({
+            "inputs": hex_dump(&image.inputs),
+            "outputs": hex_dump(&image.outputs),
+        })),
+        "queues": {
+            "input_events": mqtt::input_queue_depth(),
+        },
+        "tasks": tasks
+            .iter()
+            .map(|&(task, running)| {
+                let state = if running { "running" } else { "finished" };
+                (task.to_owned(), json!(state))
+            })
+            .collect::<serde_json::Map<_, _>>(),
+        "mqtt_stats": {
+            "sent": stats.sent,
+            "received": stats.received,
+            "rejected": stats.rejected,
+            "dropped": stats.dropped,
+        },
+        "config": config_summary,
+    })
+}
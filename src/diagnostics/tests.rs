@@ -0,0 +1,28 @@
+use super::*;
+
+#[test]
+fn test_state_dump() {
+    let config = Config::default();
+    let image = ProcessImage {
+        inputs: vec![0x01, 0x80],
+        outputs: vec![0x00],
+    };
+    let dump = state_dump(
+        Some(&image),
+        &[("kbus", true), ("mqtt", false)],
+        &config_summary(&config),
+    );
+    assert_eq!(
+        dump["process_image"]["inputs"],
+        json!(hex_dump(&image.inputs))
+    );
+    assert_eq!(dump["tasks"]["kbus"], "running");
+    assert_eq!(dump["tasks"]["mqtt"], "finished");
+    assert!(dump["queues"]["input_events"].is_u64());
+    assert_eq!(dump["config"]["device_name"], config.device_name);
+    assert_eq!(dump["config"]["rules"], 0);
+
+    // The K-Bus task didn't respond
+    let dump = state_dump(None, &[], &config_summary(&config));
+    assert!(dump["process_image"].is_null());
+}
@@ -0,0 +1,75 @@
+//! Device identity
+//!
+//! The device identifier is part of the topic prefix (`<device_name>/<id>`) and of
+//! the claim of the device identity. By default it's the MAC address of the first
+//! network interface. On devices with bonded or bridged interfaces the MAC can
+//! change, `/etc/machine-id` is stable instead. It's hashed with an application
+//! specific key, so the published identifier doesn't reveal the machine ID.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use pnet::datalink;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::IdentitySource;
+
+#[cfg(test)]
+mod tests;
+
+const MACHINE_ID_PATH: &str = "/etc/machine-id";
+
+/// Key of the machine ID hash, so other applications derive other identifiers
+const MACHINE_ID_KEY: &[u8] = b"kbus_mqtt_bridge";
+
+/// Number of hex digits of the hashed machine ID, as many as a MAC address has
+const MACHINE_ID_DIGITS: usize = 12;
+
+/// Identifier of the device and where it's derived from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Identity {
+    pub source: IdentitySource,
+    pub id: String,
+}
+
+impl Identity {
+    /// Derives the identity of this device from `source`.
+    pub fn from_source(source: IdentitySource) -> Result<Identity, anyhow::Error> {
+        let id = match source {
+            IdentitySource::Mac => datalink::interfaces()
+                .first()
+                .context("No network interface found")?
+                .mac
+                .context("No MAC address found")?
+                .to_string(),
+            IdentitySource::MachineId => machine_id(Path::new(MACHINE_ID_PATH))?,
+        };
+        Ok(Identity { source, id })
+    }
+}
+
+/// Reads the machine ID from `path` and returns its hash.
+fn machine_id(path: &Path) -> Result<String, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read machine ID from {}", path.display()))?;
+    let machine_id = contents.trim();
+    // Empty or "uninitialized" until the first boot completed
+    if machine_id.len() != 32 || !machine_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!(
+            "invalid machine ID in {}: expected 32 hex digits",
+            path.display()
+        ));
+    }
+    Ok(hash_machine_id(machine_id))
+}
+
+fn hash_machine_id(machine_id: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(MACHINE_ID_KEY)
+        .chain_update(machine_id.to_ascii_lowercase())
+        .finalize();
+    let mut id: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    id.truncate(MACHINE_ID_DIGITS);
+    id
+}
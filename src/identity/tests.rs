@@ -0,0 +1,25 @@
+use std::io::Write;
+
+use super::*;
+
+#[test]
+fn test_machine_id() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(file, "3d1219c7c4c5404aaa1f6d2a48adfda4").unwrap();
+    let id = machine_id(file.path()).unwrap();
+    assert_eq!(id.len(), MACHINE_ID_DIGITS);
+    assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+    // Stable, independent of the case
+    assert_eq!(id, hash_machine_id("3D1219C7C4C5404AAA1F6D2A48ADFDA4"));
+    assert_ne!(id, hash_machine_id("3d1219c7c4c5404aaa1f6d2a48adfda5"));
+}
+
+#[test]
+fn test_machine_id_invalid() {
+    for contents in ["", "uninitialized\n", "3d1219c7c4c5404a"] {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "{contents}").unwrap();
+        assert!(machine_id(file.path()).is_err(), "{contents:?}");
+    }
+    assert!(machine_id(Path::new("/nonexistent/machine-id")).is_err());
+}
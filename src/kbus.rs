@@ -4,37 +4,91 @@
 //! It handles bidirectional communication with digital I/O modules connected to the controller,
 //! providing a thread-safe way to read from and write to digital channels.
 
-use std::time::Duration;
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    io,
+    ops::Range,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use anyhow::Context;
 use bitvec::prelude::*;
-#[cfg(feature = "real-kbus")]
-use kbus::KBus;
-#[cfg(feature = "mock-kbus")]
-use kbus_mock::KBus;
+use chrono::{DateTime, Utc};
+#[cfg(not(mock_kbus))]
+pub(crate) use kbus::{Error as KBusError, KBus};
+#[cfg(mock_kbus)]
+pub(crate) use kbus_mock::{Error as KBusError, KBus};
 use serde::{Deserialize, Serialize};
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
-    time::{MissedTickBehavior, interval},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    time::{Instant, MissedTickBehavior, interval, sleep},
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, info_span, instrument, warn};
+use tracing::{debug, error, info, info_span, instrument, trace, warn};
+
+use crate::{
+    config::{Config, InputsConfig, IoSizeCheck, KBusConfig, OutputsConfig},
+    modbus::{ModbusAggregate, ModbusEvent},
+    rules::Rule,
+    shutdown::{self, ShutdownReason},
+    supervisor,
+    throttle::warn_throttled,
+};
 
+mod activity;
+pub mod errors;
+mod soe;
 #[cfg(test)]
 mod tests;
+pub mod timing;
+mod verify;
+
+use activity::ActivityMonitor;
+pub use activity::DeadChannel;
+use errors::{DAL_ERRORS, Operation};
+use soe::SoeRecorder;
+pub use soe::{MAX_SOE_CAPACITY, SoeDump, SoeEntry};
+use verify::OutputVerifier;
+pub use verify::VerifyFailed;
 
 /// Maximum number of digital input channels to monitor
-const INPUT_SIZE: usize = 90;
+pub const INPUT_SIZE: usize = 90;
 /// Maximum number of digital output channels
-const OUTPUT_SIZE: usize = 90;
+pub const OUTPUT_SIZE: usize = 90;
+/// Maximum number of bytes returned by a single process image read request
+pub const MAX_READ_LENGTH: usize = 1024;
 /// Duration between K-Bus cycles
 const KBUS_CYCLE: Duration = Duration::from_millis(10);
+/// First and maximum delay between attempts to open a K-Bus in use
+const INIT_RETRY_DELAY: Duration = Duration::from_millis(100);
+const INIT_RETRY_MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Set once the K-Bus is started, until the K-Bus task ends
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the K-Bus is started and output commands are processed.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Last correlation id generated for an output command without one
+static COMMAND_ID: AtomicU64 = AtomicU64::new(0);
 
 /// Represents a digital I/O event on the KBUS system.
 ///
 /// This structure is used to communicate events between the KBUS hardware
 /// and the application, representing both input and output signals.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KBusEvent {
     /// The channel number (0-based) on which the event occurred.
     pub channel: u16,
@@ -42,21 +96,280 @@ pub struct KBusEvent {
     pub value: bool,
 }
 
+/// An output command, traced across tasks by its correlation id.
+#[derive(Debug)]
+pub struct OutputWrite {
+    /// The output channel and its new value.
+    pub event: KBusEvent,
+    /// Correlation id of the command, recorded in the `command` spans of the MQTT
+    /// and K-Bus tasks and published with the resulting output state.
+    pub id: String,
+}
+
+impl OutputWrite {
+    /// Creates a write of `event`, generating a correlation id if `id` is `None`.
+    pub fn new(event: KBusEvent, id: Option<String>) -> OutputWrite {
+        let id = id.unwrap_or_else(|| (COMMAND_ID.fetch_add(1, Ordering::Relaxed) + 1).to_string());
+        OutputWrite { event, id }
+    }
+}
+
+/// Events produced by the K-Bus task for the application.
+#[derive(Debug)]
+pub enum InputEvent {
+    /// An input channel changed its state.
+    Channel(KBusEvent),
+    /// A derived signal (see [`crate::rules`]) changed its state.
+    Derived(DerivedEvent),
+    /// An output channel was written, whatever the source of the command.
+    Output(OutputWrite),
+    /// An output command was accepted in shadow mode, logged but not written.
+    Shadowed(OutputWrite),
+    /// An input or register of a Modbus device (see [`crate::modbus`]) changed its value.
+    Modbus(ModbusEvent),
+    /// The aggregate window of a Modbus input register ended.
+    ModbusAggregate(ModbusAggregate),
+    /// A written output wasn't read back with the commanded value.
+    VerifyFailed(VerifyFailed),
+    /// An input channel didn't change within its expected window or changed again.
+    DeadChannel(DeadChannel),
+    /// The internal state dumped on SIGUSR1 (see [`crate::diagnostics`]).
+    StateDump(serde_json::Value),
+    /// The K-Bus was opened or couldn't be opened and the bridge runs without it.
+    KBusAvailable(bool),
+}
+
+/// Represents a change of a derived signal computed from input channels.
+#[derive(Debug)]
+pub struct DerivedEvent {
+    /// Name of the rule defining the signal, shared with the rule so sending
+    /// doesn't allocate in the K-Bus cycle.
+    pub name: Arc<str>,
+    /// The new boolean state of the signal.
+    pub value: bool,
+}
+
+/// Snapshot of the K-Bus process image as seen by the bridge.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessImage {
+    /// Input process image, read in full for the snapshot.
+    pub inputs: Vec<u8>,
+    /// Output process image as last written by the bridge.
+    pub outputs: Vec<u8>,
+}
+
+/// Commands sent from the application to the K-Bus task.
+#[derive(Debug)]
+pub enum KBusCommand {
+    /// Set the output channel to the given value.
+    Output(OutputWrite),
+    /// Request a snapshot of the current process image.
+    Dump(oneshot::Sender<ProcessImage>),
+    /// Request the recorded sequence of events of the fast input channels.
+    Soe(oneshot::Sender<SoeDump>),
+    /// Read an arbitrary region of the input process image.
+    Read {
+        /// Byte offset of the region.
+        offset: u32,
+        /// Number of bytes to read (at most [`MAX_READ_LENGTH`]).
+        length: usize,
+        /// Channel for sending back the region or the read error.
+        reply: oneshot::Sender<Result<Vec<u8>, anyhow::Error>>,
+    },
+    /// Write a single bit of the output process image, regardless of the channels.
+    WriteBit {
+        /// Bit offset in the output process image.
+        offset: u32,
+        value: bool,
+        /// Channel for sending back the write error, if any.
+        reply: oneshot::Sender<Result<(), anyhow::Error>>,
+    },
+    /// Read a single bit of the input process image, regardless of the channels.
+    ReadBit {
+        /// Bit offset in the input process image.
+        offset: u32,
+        /// Channel for sending back the bit or the read error.
+        reply: oneshot::Sender<Result<bool, anyhow::Error>>,
+    },
+    /// Enable or disable writing outputs, e.g. when another instance holds the claim
+    /// of the device identity.
+    OutputsEnabled(bool),
+    /// Enable or disable the shadow mode, in which output commands are acknowledged
+    /// but not written.
+    Shadow(bool),
+}
+
+/// A write to the output process image waiting for the next K-Bus cycle.
+#[derive(Debug)]
+enum StagedWrite {
+    /// Output channel write, published as [`InputEvent::Output`] once written
+    Output(OutputWrite),
+    /// Raw bit write, its result is sent back once written
+    Bit {
+        offset: u32,
+        value: bool,
+        reply: oneshot::Sender<Result<(), anyhow::Error>>,
+    },
+}
+
+/// Returns the mask of input channels whose changes are published.
+fn monitor_mask(config: &InputsConfig) -> BitVec<u8, LocalBits> {
+    (0..INPUT_SIZE)
+        .map(|channel| config.is_monitored(channel as u16))
+        .collect()
+}
+
+/// Returns the byte ranges of the input process image read in every cycle.
+///
+/// Only bytes holding monitored channels or channels used by rules and output
+/// verification are read, adjacent bytes are merged into a single range.
+fn read_ranges(
+    monitored: &BitSlice<u8>,
+    rules: &[Rule],
+    outputs: &OutputsConfig,
+) -> Vec<Range<usize>> {
+    let mut used = monitored.to_bitvec();
+    let mut set = |channel: u16| {
+        if let Some(mut bit) = used.get_mut(usize::from(channel)) {
+            *bit = true;
+        }
+    };
+    for rule in rules {
+        rule.expr().for_each_input(&mut set);
+    }
+    for verify in &outputs.verify {
+        set(verify.input);
+    }
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (index, _) in used
+        .as_raw_slice()
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte != 0)
+    {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+/// Returns the configured input and output channels beyond the process images of
+/// `input_bytes` and `output_bytes` reported by the device.
+///
+/// All inputs are monitored by default, so only explicitly monitored inputs count.
+fn channels_beyond_image(
+    config: &Config,
+    rules: &[Rule],
+    (input_bytes, output_bytes): (u32, u32),
+) -> (BTreeSet<u16>, BTreeSet<u16>) {
+    let mut inputs = BTreeSet::new();
+    let mut check_input = |channel: u16| {
+        if u64::from(channel) >= u64::from(input_bytes) * 8 {
+            inputs.insert(channel);
+        }
+    };
+    for range in &config.inputs.monitor {
+        (range.first..=range.last).for_each(&mut check_input);
+    }
+    for rule in rules {
+        rule.expr().for_each_input(&mut check_input);
+    }
+    for verify in &config.outputs.verify {
+        check_input(verify.input);
+    }
+
+    let outputs = config
+        .outputs
+        .verify
+        .iter()
+        .map(|verify| verify.output)
+        .chain(config.schedules.iter().map(|schedule| schedule.output))
+        .filter(|&channel| u64::from(channel) >= u64::from(output_bytes) * 8)
+        .collect();
+    (inputs, outputs)
+}
+
+/// Checks the configured channels against the process image sizes reported by the
+/// device, a wrong channel would otherwise silently read or write unrelated data.
+///
+/// Fails with `kbus.io_size_check = "error"` if any channel is beyond the images,
+/// missing or failing size queries only skip the check.
+fn check_io_sizes(
+    kbus: &mut KBus,
+    config: &Config,
+    rules: &[Rule],
+    available: bool,
+) -> Result<(), anyhow::Error> {
+    if config.kbus.io_size_check == IoSizeCheck::Off {
+        return Ok(());
+    }
+    if !available {
+        warn!("DAL function GetIoSizes missing, channels not checked against the process images");
+        return Ok(());
+    }
+    let (input_bytes, output_bytes) = match kbus.io_sizes() {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            warn!(error = %err, "failed to get I/O sizes, channels not checked against the process images");
+            return Ok(());
+        }
+    };
+
+    let (inputs, outputs) = channels_beyond_image(config, rules, (input_bytes, output_bytes));
+    if inputs.is_empty() && outputs.is_empty() {
+        debug!(
+            input_bytes,
+            output_bytes, "configured channels within the process images"
+        );
+        return Ok(());
+    }
+    let message = format!(
+        "configured channels beyond the process images (input {input_bytes} bytes, output {output_bytes} bytes): inputs {inputs:?}, outputs {outputs:?}"
+    );
+    match config.kbus.io_size_check {
+        IoSizeCheck::Error => Err(anyhow::anyhow!(message)),
+        _ => {
+            warn!("{message}");
+            Ok(())
+        }
+    }
+}
+
+/// Runs the K-Bus cycle and processes commands until cancelled.
+///
+/// Cycles are timed by the tokio clock only, so tests can run the loop with paused
+/// time (`#[tokio::test(start_paused = true)]`) and advance it cycle by cycle.
 pub async fn kbus_loop(
-    input_tx: UnboundedSender<KBusEvent>,
-    mut kbus_output_rx: UnboundedReceiver<KBusEvent>,
+    mut kbus: KBus,
+    config: Config,
+    input_tx: UnboundedSender<InputEvent>,
+    mut kbus_command_rx: UnboundedReceiver<KBusCommand>,
     cancellation_token: CancellationToken,
 ) -> Result<(), anyhow::Error> {
     info!("starting K-Bus task");
 
+    // Unusual firmware may lack DAL functions, outputs fall back to bit writes
+    let capabilities = kbus.capabilities();
+    if !capabilities.read_bytes {
+        return Err(KBusError::MissingDalFunction("ReadBytes"))
+            .context("K-Bus cycle can't read the input process image");
+    }
+    if !capabilities.write_bool {
+        warn!(
+            ?capabilities,
+            "DAL function WriteBool missing, writing outputs as bits"
+        );
+    }
+
     let mut interval = interval(KBUS_CYCLE);
     interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-    // Initialize KBUS communication
-    let mut kbus = KBus::new().context("failed to create K-Bus instance")?;
-
     // Set application state to "Running" to drive kbus by yourself.
     kbus.start().context("failed ot start K-Bus instanece")?;
+    RUNNING.store(true, Ordering::Relaxed);
 
     // Double buffer setup for change detection
     // Using two bit vectors to detect changes between KBUS cycles
@@ -67,34 +380,96 @@ pub async fn kbus_loop(
 
     // Index of the current buffer (toggles between 0 and 1)
     let mut current_buffer = 0;
+    // Changed bits of the current cycle, reused to keep allocations out of the cycle
+    let mut diff_bits = bitvec![u8, LocalBits; 0; INPUT_SIZE];
+
+    // Derived signals, evaluated every cycle the inputs changed
+    let mut rules = config
+        .rules
+        .iter()
+        .map(|(name, source)| Rule::new(name, source))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_io_sizes(&mut kbus, &config, &rules, capabilities.io_sizes)?;
+    let mut first_cycle = true;
+
+    // Shadow copy of the output process image, updated on every successful write
+    let mut outputs = bitvec![u8, LocalBits; 0; OUTPUT_SIZE];
+    // Writes received since the last cycle, committed together in the next one, so
+    // a command switching several outputs never straddles two bus cycles
+    let mut staged: Vec<StagedWrite> = Vec::new();
+    // Order of writing outputs, triggering the bus cycle and reading inputs
+    let cycle_order = config.kbus.cycle_order;
+    // Time of the last bus cycle, the inputs read are those of this cycle
+    let mut push_time = Utc::now();
+    // With claims enabled, outputs are only written once this instance holds the claim
+    let mut outputs_enabled = config.mqtt.claim_interval.is_zero();
+    // In shadow mode, output commands are logged and acknowledged but not written
+    let mut shadow = config.outputs.shadow;
+    // Written outputs waiting for their read-back check
+    let mut verifier = OutputVerifier::new(&config.outputs, OUTPUT_SIZE);
+    // Inputs expected to change within a window, reported as dead otherwise
+    let mut activity = ActivityMonitor::new(&config.inputs, INPUT_SIZE, Instant::now());
+    // Changes of the fast inputs with their cycle, for the sequence of events
+    let mut soe = SoeRecorder::new(&config.inputs, INPUT_SIZE);
+    // Channels whose changes are published, the others are skipped in change detection
+    let monitored = monitor_mask(&config.inputs);
+    // Bytes of the input process image read every cycle, unread bytes stay 0
+    let ranges = read_ranges(&monitored, &rules, &config.outputs);
 
     // Main processing loop - runs until cancellation is requested
     loop {
         tokio::select! {
             // Wait for next cycle (100 Hz frequency)
-            _ = interval.tick() => {
-                // Trigger a hardware bus cycle - reads inputs and writes outputs
-                kbus.trigger_bus_cycle()
-                    .context("failed to trigger K-Bus cycle")?;
+            scheduled = interval.tick() => {
+                timing::CYCLE_DELAY.record(scheduled.elapsed());
+                if cycle_order.writes_before_read() && !staged.is_empty() {
+                    let _out_span = info_span!("out").entered();
+                    commit_writes(
+                        &mut kbus,
+                        capabilities.write_bool,
+                        &mut staged,
+                        &mut outputs,
+                        &mut verifier,
+                        &input_tx,
+                    )?;
+                }
+                if cycle_order.pushes_before_read() {
+                    push_time = push(&mut kbus)?;
+                }
 
-                let _in_span = info_span!("in").entered();
+                let in_span = info_span!("in").entered();
 
                 // Get the current and previous buffer indices using XOR toggle pattern
                 let current = current_buffer;
                 let old = current ^ 1; // XOR with 1 toggles between 0 and 1
                 current_buffer = old; // Swap for next iteration
 
-                // Read the current state of all input channels into the current buffer
-                let mut reader = kbus.reader().context("failed to create K-Bus reader")?;
-                reader
-                    .read_bytes(0, buffers[current].as_raw_mut_slice())
-                    .context("failed to read from K-Bus")?;
+                // Read the used regions of the input process image into the current
+                // buffer, all in one read sequence ended before outputs are written
+                {
+                    let mut reader = DAL_ERRORS
+                        .check(Operation::Read, kbus.reader())
+                        .context("failed to create K-Bus reader")?;
+                    for range in &ranges {
+                        let bits = range.start * 8..(range.end * 8).min(INPUT_SIZE);
+                        let data = timing::READ
+                            .time(|| reader.read_range(bits.start as u32, bits.len()));
+                        let data = DAL_ERRORS
+                            .check(Operation::Read, data)
+                            .context("failed to read from K-Bus")?;
+                        buffers[current][bits].copy_from_bitslice(&data);
+                    }
+                }
 
                 // Compare current and previous buffer to detect changes
-                // Create a temporary bitvec to hold the differences
-                let mut diff_bits = buffers[current].clone();
+                diff_bits.copy_from_bitslice(&buffers[current]);
                 // XOR with old buffer to find differences (1 means bit changed)
                 diff_bits ^= &buffers[old];
+                // Derived signals may depend on unmonitored channels
+                let changed = diff_bits.any();
+                diff_bits &= &monitored;
+                // The inputs are those of the last bus cycle
+                soe.on_cycle(push_time, &diff_bits, &buffers[current]);
 
                 // Iterate through set bits in the diff_bits (only process changed bits)
                 for i in diff_bits.iter_ones() {
@@ -103,33 +478,150 @@ pub async fn kbus_loop(
                         channel: i as u16,
                         value: buffers[current][i],
                     };
-                    info!(?event);
+                    // Logged with topic and payload when published
+                    trace!(?event);
+                    input_tx
+                        .send(InputEvent::Channel(event))
+                        .context("K-Bus input processing channel closed")?;
+                    if let Some(alive) = activity.on_change(i as u16, scheduled) {
+                        input_tx
+                            .send(InputEvent::DeadChannel(alive))
+                            .context("K-Bus input processing channel closed")?;
+                    }
+                }
+                for dead in activity.on_cycle(scheduled) {
+                    input_tx
+                        .send(InputEvent::DeadChannel(dead))
+                        .context("K-Bus input processing channel closed")?;
+                }
+
+                for failed in verifier.on_cycle(&buffers[current]) {
                     input_tx
-                        .send(event)
+                        .send(InputEvent::VerifyFailed(failed))
                         .context("K-Bus input processing channel closed")?;
                 }
+
+                // Evaluate derived signals on the consistent image of this cycle
+                if first_cycle || changed {
+                    first_cycle = false;
+                    for rule in &mut rules {
+                        if let Some(value) = rule.update(&buffers[current]) {
+                            let event = DerivedEvent {
+                                name: rule.name().clone(),
+                                value,
+                            };
+                            trace!(?event);
+                            input_tx
+                                .send(InputEvent::Derived(event))
+                                .context("K-Bus input processing channel closed")?;
+                        }
+                    }
+                }
+                drop(in_span);
+
+                if !cycle_order.writes_before_read() && !staged.is_empty() {
+                    let _out_span = info_span!("out").entered();
+                    commit_writes(
+                        &mut kbus,
+                        capabilities.write_bool,
+                        &mut staged,
+                        &mut outputs,
+                        &mut verifier,
+                        &input_tx,
+                    )?;
+                }
+                if !cycle_order.pushes_before_read() {
+                    push_time = push(&mut kbus)?;
+                }
             },
-            event = kbus_output_rx.recv() => {
+            command = kbus_command_rx.recv() => {
                 let _out_span = info_span!("out").entered();
 
-                let Some(event) = event else {
-                    error!("K-Bus output channel closed");
+                let Some(command) = command else {
+                    error!("K-Bus command channel closed");
                     break;
                 };
 
-                info!(?event);
-
-                if usize::from(event.channel) < OUTPUT_SIZE {
-                    let mut writer = kbus.writer().context("failed to create K-Bus writer")?;
-                    writer
-                        .write_bool(event.channel as u32, event.value)
-                        .context("failed to write to K-Bus")?;
-                } else {
-                    warn!(
-                        "Ignoring output event for invalid channel {}: maximum supported channel is {}",
-                        event.channel,
-                        OUTPUT_SIZE - 1
-                    );
+                match command {
+                    KBusCommand::Output(write) => {
+                        let _command_span = info_span!("command", id = write.id).entered();
+                        let event = &write.event;
+                        info!(?event);
+
+                        if !outputs_enabled {
+                            warn_throttled!(
+                                "outputs_disabled",
+                                ?event,
+                                "Ignoring output event, outputs are disabled"
+                            );
+                        } else if shadow && usize::from(event.channel) < OUTPUT_SIZE {
+                            info!(?event, "shadow mode, output not written");
+                            // Acknowledged like a write, the output image isn't changed
+                            input_tx
+                                .send(InputEvent::Shadowed(write))
+                                .context("K-Bus input processing channel closed")?;
+                        } else if usize::from(event.channel) < OUTPUT_SIZE {
+                            staged.push(StagedWrite::Output(write));
+                        } else {
+                            warn_throttled!(
+                                "invalid_channel",
+                                "Ignoring output event for invalid channel {}: maximum supported channel is {}",
+                                event.channel,
+                                OUTPUT_SIZE - 1
+                            );
+                        }
+                    }
+                    KBusCommand::Dump(reply) => {
+                        // The cycle only reads the used bytes, the others would
+                        // be dumped as zeros. A failed read drops the request.
+                        match read_inputs(&mut kbus) {
+                            Ok(inputs) => {
+                                let image = ProcessImage {
+                                    inputs,
+                                    outputs: outputs.as_raw_slice().to_vec(),
+                                };
+                                // The requester may have given up waiting, nothing to do then
+                                let _ = reply.send(image);
+                            }
+                            Err(err) => warn!(
+                                error = format!("{err:#}"),
+                                "failed to read the input process image for a dump"
+                            ),
+                        }
+                    }
+                    KBusCommand::Soe(reply) => {
+                        let _ = reply.send(soe.dump());
+                    }
+                    KBusCommand::Read { offset, length, reply } => {
+                        info!(offset, length, "process image read requested");
+                        // A failed read is reported to the requester and must not
+                        // stop the K-Bus loop
+                        let result = read_region(&mut kbus, offset, length);
+                        let _ = reply.send(result);
+                    }
+                    KBusCommand::WriteBit { offset, value, reply } => {
+                        info!(offset, value, "raw output bit write requested");
+                        if !outputs_enabled {
+                            let _ = reply.send(Err(anyhow::anyhow!("outputs are disabled")));
+                        } else if shadow {
+                            info!(offset, value, "shadow mode, output bit not written");
+                            let _ = reply.send(Ok(()));
+                        } else {
+                            staged.push(StagedWrite::Bit { offset, value, reply });
+                        }
+                    }
+                    KBusCommand::ReadBit { offset, reply } => {
+                        info!(offset, "raw input bit read requested");
+                        let _ = reply.send(read_bit(&mut kbus, offset));
+                    }
+                    KBusCommand::OutputsEnabled(enabled) => {
+                        info!(enabled, "outputs enabled changed");
+                        outputs_enabled = enabled;
+                    }
+                    KBusCommand::Shadow(enabled) => {
+                        info!(enabled, "shadow mode changed");
+                        shadow = enabled;
+                    }
                 }
             }
             _ = cancellation_token.cancelled() => break,
@@ -138,24 +630,268 @@ pub async fn kbus_loop(
     Ok(())
 }
 
+/// Triggers a bus cycle, which writes the outputs and reads the inputs, returns
+/// its time.
+fn push(kbus: &mut KBus) -> Result<DateTime<Utc>, anyhow::Error> {
+    let result = timing::BUS_CYCLE.time(|| kbus.trigger_bus_cycle());
+    DAL_ERRORS
+        .check(Operation::Cycle, result)
+        .context("failed to trigger K-Bus cycle")?;
+    Ok(Utc::now())
+}
+
+/// Writes the staged writes in one write sequence, in the order received.
+///
+/// Written output channels are published, a failed raw bit write is reported to
+/// its requester and doesn't stop the K-Bus loop.
+fn commit_writes(
+    kbus: &mut KBus,
+    write_bool: bool,
+    staged: &mut Vec<StagedWrite>,
+    outputs: &mut BitVec<u8, LocalBits>,
+    verifier: &mut OutputVerifier,
+    input_tx: &UnboundedSender<InputEvent>,
+) -> Result<(), anyhow::Error> {
+    let mut writer = DAL_ERRORS
+        .check(Operation::Write, kbus.writer())
+        .context("failed to create K-Bus writer")?;
+    for write in staged.drain(..) {
+        match write {
+            StagedWrite::Output(write) => {
+                let event = &write.event;
+                let result = timing::WRITE.time(|| {
+                    if write_bool {
+                        writer.write_bool(event.channel as u32, event.value)
+                    } else {
+                        writer.write_bit(event.channel as u32, &mut u8::from(event.value))
+                    }
+                });
+                DAL_ERRORS
+                    .check(Operation::Write, result)
+                    .context("failed to write to K-Bus")?;
+                outputs.set(usize::from(event.channel), event.value);
+                verifier.on_write(event.channel, event.value);
+                input_tx
+                    .send(InputEvent::Output(write))
+                    .context("K-Bus input processing channel closed")?;
+            }
+            StagedWrite::Bit {
+                offset,
+                value,
+                reply,
+            } => {
+                let result = timing::WRITE.time(|| writer.write_bit(offset, &mut u8::from(value)));
+                let result = DAL_ERRORS
+                    .check(Operation::Write, result)
+                    .context("failed to write to K-Bus");
+                // Bits of the output channels are kept in the dump
+                if result.is_ok() && (offset as usize) < OUTPUT_SIZE {
+                    outputs.set(offset as usize, value);
+                }
+                let _ = reply.send(result);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the whole input process image.
+fn read_inputs(kbus: &mut KBus) -> Result<Vec<u8>, anyhow::Error> {
+    let mut reader = DAL_ERRORS
+        .check(Operation::Read, kbus.reader())
+        .context("failed to create K-Bus reader")?;
+    let result = timing::READ.time(|| reader.read_range(0, INPUT_SIZE));
+    let data = DAL_ERRORS
+        .check(Operation::Read, result)
+        .context("failed to read from K-Bus")?;
+    let mut inputs = bitvec![u8, LocalBits; 0; INPUT_SIZE];
+    inputs.copy_from_bitslice(&data);
+    Ok(inputs.into_vec())
+}
+
+/// Reads `length` bytes of the input process image starting at byte `offset`.
+fn read_region(kbus: &mut KBus, offset: u32, length: usize) -> Result<Vec<u8>, anyhow::Error> {
+    if length == 0 || length > MAX_READ_LENGTH {
+        return Err(anyhow::anyhow!(
+            "invalid read length {length}: must be between 1 and {MAX_READ_LENGTH}"
+        ));
+    }
+
+    let mut data = vec![0; length];
+    let mut reader = DAL_ERRORS
+        .check(Operation::Read, kbus.reader())
+        .context("failed to create K-Bus reader")?;
+    let result = timing::READ.time(|| reader.read_bytes(offset, &mut data));
+    DAL_ERRORS
+        .check(Operation::Read, result)
+        .context("failed to read from K-Bus")?;
+    Ok(data)
+}
+
+/// Reads the bit at `offset` of the input process image.
+fn read_bit(kbus: &mut KBus, offset: u32) -> Result<bool, anyhow::Error> {
+    let mut data = 0;
+    let mut reader = DAL_ERRORS
+        .check(Operation::Read, kbus.reader())
+        .context("failed to create K-Bus reader")?;
+    let result = timing::READ.time(|| reader.read_bit(offset, &mut data));
+    DAL_ERRORS
+        .check(Operation::Read, result)
+        .context("failed to read from K-Bus")?;
+    Ok(data & 1 != 0)
+}
+
+/// Takes the advisory lock on `path`, held until the returned file is dropped.
+///
+/// Fails with [`KBusError::AlreadyInitialized`] if another process holds the lock.
+fn lock(path: &Path) -> Result<File, anyhow::Error> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open lock file {}", path.display()))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            return Err(
+                anyhow::Error::new(KBusError::AlreadyInitialized).context(format!(
+                    "lock file {} held by another process",
+                    path.display()
+                )),
+            );
+        }
+        return Err(err).with_context(|| format!("failed to lock {}", path.display()));
+    }
+    Ok(file)
+}
+
+/// Returns whether opening the K-Bus failed because it's in use.
+fn is_in_use(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<KBusError>(),
+            Some(KBusError::AlreadyInitialized)
+        )
+    })
+}
+
+/// Opens the K-Bus, holding the lock file if configured.
+///
+/// While the K-Bus is in use, e.g. by a previous instance still shutting down,
+/// opening is retried with exponential backoff for `init_timeout`.
+async fn open(
+    config: &KBusConfig,
+    cancellation_token: &CancellationToken,
+) -> Result<(KBus, Option<File>), anyhow::Error> {
+    let deadline = Instant::now() + config.init_timeout;
+    let mut delay = INIT_RETRY_DELAY;
+    loop {
+        let result = config
+            .lock_file
+            .as_deref()
+            .map(lock)
+            .transpose()
+            .and_then(|lock| {
+                let kbus = KBus::new().context("failed to create K-Bus instance")?;
+                Ok((kbus, lock))
+            });
+        match result {
+            Err(err) if is_in_use(&err) && Instant::now() + delay <= deadline => {
+                warn!(error = format!("{err:#}"), ?delay, "K-Bus in use, retrying");
+                tokio::select! {
+                    _ = sleep(delay) => {}
+                    _ = cancellation_token.cancelled() => return Err(err),
+                }
+                delay = (delay * 2).min(INIT_RETRY_MAX_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Retries opening a K-Bus which isn't required every `retry_interval`.
+///
+/// Returns `None` if cancelled before the K-Bus was opened.
+async fn wait_for_kbus(
+    config: &KBusConfig,
+    err: anyhow::Error,
+    input_tx: &UnboundedSender<InputEvent>,
+    cancellation_token: &CancellationToken,
+) -> Option<(KBus, Option<File>)> {
+    warn!(
+        error = format!("{err:#}"),
+        retry_interval = ?config.retry_interval,
+        "K-Bus unavailable, running without it"
+    );
+    let _ = input_tx.send(InputEvent::KBusAvailable(false));
+    loop {
+        tokio::select! {
+            _ = sleep(config.retry_interval) => {}
+            _ = cancellation_token.cancelled() => return None,
+        }
+        match open(config, cancellation_token).await {
+            Ok(opened) => {
+                info!("K-Bus available");
+                return Some(opened);
+            }
+            Err(err) => debug!(error = format!("{err:#}"), "K-Bus still unavailable"),
+        }
+    }
+}
+
 /// Entry point task function for KBUS communication.
 ///
 /// This wrapper function provides instrumentation and error handling around the main
-/// KBUS implementation. It calls the `kbus_loop` function which handles the core
-/// KBUS operations, and manages error reporting and cancellation.
+/// KBUS implementation. It opens the K-Bus device and calls the `kbus_loop` function
+/// which handles the core KBUS operations, and manages error reporting and cancellation.
 ///
 /// # Arguments
 ///
+/// * `config` - Application configuration
 /// * `input_tx` - Channel for sending input events detected on the KBUS to the application
-/// * `kbus_output_rx` - Channel for receiving output events from the application to write to KBUS
+/// * `kbus_command_rx` - Channel for receiving commands (output writes, process image dumps)
+///   from the application
 /// * `cancellation_token` - Token to signal when this task should terminate
 #[instrument(name = "kbus", skip_all)]
 pub async fn kbus_task(
-    input_tx: UnboundedSender<KBusEvent>,
-    kbus_output_rx: UnboundedReceiver<KBusEvent>,
+    config: Config,
+    input_tx: UnboundedSender<InputEvent>,
+    kbus_command_rx: UnboundedReceiver<KBusCommand>,
     cancellation_token: CancellationToken,
 ) -> Result<(), anyhow::Error> {
-    let result = kbus_loop(input_tx, kbus_output_rx, cancellation_token.clone()).await;
+    supervisor::started("kbus");
+    // Initialize KBUS communication, the lock is released when the task ends
+    let opened = match open(&config.kbus, &cancellation_token).await {
+        Err(err) if !config.kbus.required => {
+            Ok(wait_for_kbus(&config.kbus, err, &input_tx, &cancellation_token).await)
+        }
+        result => result.map(Some),
+    };
+    let result = match opened {
+        Ok(Some((kbus, _lock))) => {
+            let _ = input_tx.send(InputEvent::KBusAvailable(true));
+            let result = kbus_loop(
+                kbus,
+                config,
+                input_tx,
+                kbus_command_rx,
+                cancellation_token.clone(),
+            )
+            .await;
+            if result.is_err() {
+                shutdown::initiate(ShutdownReason::KBus);
+            }
+            result
+        }
+        Ok(None) => Ok(()),
+        Err(err) => {
+            shutdown::initiate(ShutdownReason::KBusInit);
+            Err(err)
+        }
+    };
+    RUNNING.store(false, Ordering::Relaxed);
+    supervisor::finished("kbus", &result);
 
     cancellation_token.cancel();
 
@@ -0,0 +1,94 @@
+//! Detection of dead input channels
+//!
+//! Some inputs are expected to change regularly, e.g. motion sensors, flow switches
+//! or pulse counters. If such a channel doesn't change within its configured window,
+//! the sensor or its wire is likely broken, which a plain input topic can't tell
+//! from a quiet channel. The channel is reported once as dead and again as alive
+//! on its next change.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::InputsConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// An input channel that became dead or changed again after being dead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeadChannel {
+    /// Input channel
+    pub channel: u16,
+    /// Whether the channel didn't change within the window, `false` once it changed
+    pub dead: bool,
+    /// Window the channel is expected to change within
+    #[serde(with = "humantime_serde")]
+    pub expect_activity_within: Duration,
+}
+
+#[derive(Debug)]
+struct Watched {
+    window: Duration,
+    last_change: Instant,
+    dead: bool,
+}
+
+/// Tracks the last change of the input channels expected to change.
+#[derive(Debug)]
+pub struct ActivityMonitor {
+    /// Watched state by input channel
+    channels: Vec<Option<Watched>>,
+}
+
+impl ActivityMonitor {
+    /// Starts the windows of all configured channels at `now`.
+    pub fn new(config: &InputsConfig, input_channels: usize, now: Instant) -> ActivityMonitor {
+        let channels = (0..input_channels)
+            .map(|channel| {
+                let window = config.expect_activity_within(channel as u16)?;
+                Some(Watched {
+                    window,
+                    last_change: now,
+                    dead: false,
+                })
+            })
+            .collect();
+        ActivityMonitor { channels }
+    }
+
+    /// Restarts the window of a changed channel, reporting it alive if it was dead.
+    pub fn on_change(&mut self, channel: u16, now: Instant) -> Option<DeadChannel> {
+        let watched = self.channels.get_mut(usize::from(channel))?.as_mut()?;
+        watched.last_change = now;
+        if !watched.dead {
+            return None;
+        }
+        watched.dead = false;
+        Some(DeadChannel {
+            channel,
+            dead: false,
+            expect_activity_within: watched.window,
+        })
+    }
+
+    /// Returns the channels whose window ended at `now` without a change.
+    pub fn on_cycle(&mut self, now: Instant) -> Vec<DeadChannel> {
+        let mut dead = Vec::new();
+        for (channel, watched) in self.channels.iter_mut().enumerate() {
+            let Some(watched) = watched else {
+                continue;
+            };
+            if !watched.dead && now.duration_since(watched.last_change) >= watched.window {
+                watched.dead = true;
+                dead.push(DeadChannel {
+                    channel: channel as u16,
+                    dead: true,
+                    expect_activity_within: watched.window,
+                });
+            }
+        }
+        dead
+    }
+}
@@ -0,0 +1,56 @@
+use super::*;
+use crate::config::{ChannelActivity, ChannelRange};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn test_dead_channel() {
+    let config = InputsConfig {
+        activity: vec![ChannelActivity {
+            channels: ChannelRange { first: 4, last: 5 },
+            expect_activity_within: HOUR,
+        }],
+        ..InputsConfig::default()
+    };
+    let start = Instant::now();
+    let mut monitor = ActivityMonitor::new(&config, 90, start);
+
+    monitor.on_change(4, start + HOUR / 2);
+    assert!(monitor.on_cycle(start + HOUR / 2).is_empty());
+
+    // Channel 5 never changed, channel 4 changed within the window
+    let dead = monitor.on_cycle(start + HOUR);
+    assert_eq!(
+        dead,
+        [DeadChannel {
+            channel: 5,
+            dead: true,
+            expect_activity_within: HOUR,
+        }]
+    );
+    // Reported once
+    let later = start + HOUR * 5 / 4;
+    assert!(monitor.on_cycle(later).is_empty());
+
+    // Unwatched channels are ignored
+    assert_eq!(monitor.on_change(6, later), None);
+    assert_eq!(monitor.on_change(4, later), None);
+
+    let alive = monitor.on_change(5, later);
+    assert_eq!(
+        alive,
+        Some(DeadChannel {
+            channel: 5,
+            dead: false,
+            expect_activity_within: HOUR,
+        })
+    );
+    // The window restarts with the change
+    let window_end = later + HOUR;
+    assert!(
+        monitor
+            .on_cycle(window_end - Duration::from_secs(1))
+            .is_empty()
+    );
+    assert_eq!(monitor.on_cycle(window_end).len(), 2);
+}
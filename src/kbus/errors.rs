@@ -0,0 +1,124 @@
+//! Counters of failed DAL calls
+//!
+//! A failed bus cycle, read or write of the K-Bus cycle ends the K-Bus task, but
+//! on-demand reads and raw bit writes fail without it, and a module going bad often
+//! shows single failures first. The failures are counted since the start of the
+//! bridge and the last one is kept with its time, reported in the heartbeat and on
+//! `diagnostics` when new failures occurred, so trending errors are visible before
+//! they become fatal.
+
+use std::sync::{
+    Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use super::KBusError;
+use crate::timestamp;
+
+#[cfg(test)]
+mod tests;
+
+/// Kind of a failed DAL call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// `trigger_bus_cycle`
+    Cycle,
+    /// Reads of the input process image, including starting them
+    Read,
+    /// Writes of the output process image, including starting them
+    Write,
+}
+
+impl Operation {
+    const fn name(self) -> &'static str {
+        match self {
+            Operation::Cycle => "bus_cycle",
+            Operation::Read => "read",
+            Operation::Write => "write",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LastError {
+    operation: Operation,
+    error: String,
+    time: DateTime<Utc>,
+}
+
+/// Failed DAL calls by operation and the last error, updated without allocating
+/// unless a call failed.
+#[derive(Debug)]
+pub struct ErrorCounters {
+    failed: [AtomicU64; 3],
+    last: Mutex<Option<LastError>>,
+}
+
+impl ErrorCounters {
+    pub const fn new() -> ErrorCounters {
+        ErrorCounters {
+            failed: [const { AtomicU64::new(0) }; 3],
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Counts a failed call and keeps its error as the last one.
+    pub fn record(&self, operation: Operation, error: &KBusError) {
+        self.failed[operation as usize].fetch_add(1, Ordering::Relaxed);
+        *self.last.lock().unwrap() = Some(LastError {
+            operation,
+            error: error.to_string(),
+            time: Utc::now(),
+        });
+    }
+
+    /// Passes the `result` of a call through, counting it if it failed.
+    pub fn check<T>(
+        &self,
+        operation: Operation,
+        result: Result<T, KBusError>,
+    ) -> Result<T, KBusError> {
+        if let Err(err) = &result {
+            self.record(operation, err);
+        }
+        result
+    }
+
+    /// Returns the number of failed calls of all operations.
+    pub fn total(&self) -> u64 {
+        self.failed
+            .iter()
+            .map(|failed| failed.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns the counters and the last error, `null` without failures.
+    pub fn report(&self) -> serde_json::Value {
+        let failed = |operation: Operation| self.failed[operation as usize].load(Ordering::Relaxed);
+        let last_error = self.last.lock().unwrap().as_ref().map(|last| {
+            json!({
+                "operation": last.operation.name(),
+                "error": last.error,
+                "timestamp": timestamp::at(last.time),
+            })
+        });
+        json!({
+            "failed_cycles": failed(Operation::Cycle),
+            "failed_reads": failed(Operation::Read),
+            "failed_writes": failed(Operation::Write),
+            "last_error": last_error,
+        })
+    }
+}
+
+impl Default for ErrorCounters {
+    fn default() -> ErrorCounters {
+        ErrorCounters::new()
+    }
+}
+
+/// Failed DAL calls of the K-Bus task
+pub static DAL_ERRORS: ErrorCounters = ErrorCounters::new();
@@ -0,0 +1,38 @@
+use super::*;
+
+#[test]
+fn test_error_counters() {
+    let errors = ErrorCounters::new();
+    let report = errors.report();
+    assert_eq!(report["failed_cycles"], 0);
+    assert_eq!(report["last_error"], serde_json::Value::Null);
+    assert_eq!(errors.total(), 0);
+
+    assert_eq!(errors.check(Operation::Read, Ok(42)).unwrap(), 42);
+    assert_eq!(errors.total(), 0);
+
+    assert!(
+        errors
+            .check::<()>(
+                Operation::Cycle,
+                Err(KBusError::OperationFailed("timeout".into()))
+            )
+            .is_err()
+    );
+    errors.record(Operation::Write, &KBusError::Unimplemented);
+    errors.record(
+        Operation::Write,
+        &KBusError::OperationFailed("timeout".into()),
+    );
+    let report = errors.report();
+    assert_eq!(report["failed_cycles"], 1);
+    assert_eq!(report["failed_reads"], 0);
+    assert_eq!(report["failed_writes"], 2);
+    assert_eq!(report["last_error"]["operation"], "write");
+    assert_eq!(
+        report["last_error"]["error"],
+        KBusError::OperationFailed("timeout".into()).to_string()
+    );
+    assert!(report["last_error"]["timestamp"].is_string());
+    assert_eq!(errors.total(), 3);
+}
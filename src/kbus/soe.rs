@@ -0,0 +1,104 @@
+//! Sequence of events capture
+//!
+//! Changes of the fast input channels are published over the network, whose jitter
+//! blurs the order of changes only a few milliseconds apart, e.g. the trip of a
+//! breaker and its auxiliary contacts. The K-Bus cycle records these changes with
+//! the number and time of the cycle which read them into a ring buffer, dumped on
+//! request for a post-trip analysis. Changes within the same cycle happened within
+//! one cycle time and are recorded in channel order.
+
+use std::collections::VecDeque;
+
+use bitvec::prelude::*;
+use chrono::{DateTime, Utc};
+
+use crate::config::InputsConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of recorded changes, the dump is published as a single message
+pub const MAX_SOE_CAPACITY: usize = 10_000;
+
+/// A change of a fast input channel as read by the K-Bus cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoeEntry {
+    /// Number of the K-Bus cycle since the start
+    pub cycle: u64,
+    /// Time of the K-Bus cycle
+    pub time: DateTime<Utc>,
+    pub channel: u16,
+    pub value: bool,
+}
+
+/// Recorded changes, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct SoeDump {
+    pub entries: Vec<SoeEntry>,
+    /// Number of changes dropped from the full buffer since the start
+    pub overwritten: u64,
+}
+
+/// Ring buffer of the changes of the fast input channels.
+#[derive(Debug)]
+pub struct SoeRecorder {
+    /// Recorded channels, none if disabled
+    channels: BitVec<u8, LocalBits>,
+    entries: VecDeque<SoeEntry>,
+    capacity: usize,
+    cycle: u64,
+    overwritten: u64,
+}
+
+impl SoeRecorder {
+    /// Creates an empty buffer recording the fast channels, allocated once so the
+    /// K-Bus cycle doesn't allocate.
+    pub fn new(config: &InputsConfig, input_channels: usize) -> SoeRecorder {
+        let mut channels = bitvec![u8, LocalBits; 0; input_channels];
+        if config.soe_capacity > 0 {
+            for &channel in &config.fast {
+                if let Some(mut bit) = channels.get_mut(usize::from(channel)) {
+                    *bit = true;
+                }
+            }
+        }
+        SoeRecorder {
+            channels,
+            entries: VecDeque::with_capacity(config.soe_capacity),
+            capacity: config.soe_capacity,
+            cycle: 0,
+            overwritten: 0,
+        }
+    }
+
+    /// Records the changed fast channels of a K-Bus cycle run at `time`.
+    ///
+    /// `changed` are the channels changed in the cycle, `inputs` the input image it
+    /// read.
+    pub fn on_cycle(&mut self, time: DateTime<Utc>, changed: &BitSlice<u8>, inputs: &BitSlice<u8>) {
+        self.cycle += 1;
+        for channel in changed.iter_ones() {
+            if !self.channels.get(channel).is_some_and(|recorded| *recorded) {
+                continue;
+            }
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+                self.overwritten += 1;
+            }
+            self.entries.push_back(SoeEntry {
+                cycle: self.cycle,
+                time,
+                channel: channel as u16,
+                value: inputs[channel],
+            });
+        }
+    }
+
+    /// Returns a copy of the recorded changes, the buffer keeps them.
+    pub fn dump(&self) -> SoeDump {
+        SoeDump {
+            entries: self.entries.iter().copied().collect(),
+            overwritten: self.overwritten,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::TimeDelta;
+
+use super::*;
+
+fn bits(channels: &[usize]) -> BitVec<u8, LocalBits> {
+    let mut bits = bitvec![u8, LocalBits; 0; 16];
+    for &channel in channels {
+        bits.set(channel, true);
+    }
+    bits
+}
+
+fn recorder(capacity: usize) -> SoeRecorder {
+    let config = InputsConfig {
+        fast: vec![2, 3, 5],
+        soe_capacity: capacity,
+        ..InputsConfig::default()
+    };
+    SoeRecorder::new(&config, 16)
+}
+
+#[test]
+fn test_record() {
+    let mut soe = recorder(10);
+    let start = Utc::now();
+
+    // Changes of other channels aren't recorded
+    soe.on_cycle(start, &bits(&[1]), &bits(&[1]));
+    // Changes within a cycle in channel order
+    let trip = start + TimeDelta::milliseconds(10);
+    soe.on_cycle(trip, &bits(&[3, 5]), &bits(&[3]));
+    soe.on_cycle(trip + TimeDelta::milliseconds(10), &bits(&[]), &bits(&[3]));
+    let later = trip + TimeDelta::milliseconds(20);
+    soe.on_cycle(later, &bits(&[2]), &bits(&[2, 3]));
+
+    let dump = soe.dump();
+    assert_eq!(
+        dump.entries,
+        [
+            SoeEntry {
+                cycle: 2,
+                time: trip,
+                channel: 3,
+                value: true,
+            },
+            SoeEntry {
+                cycle: 2,
+                time: trip,
+                channel: 5,
+                value: false,
+            },
+            SoeEntry {
+                cycle: 4,
+                time: later,
+                channel: 2,
+                value: true,
+            },
+        ]
+    );
+    assert_eq!(dump.overwritten, 0);
+    // The dump doesn't clear the buffer
+    assert_eq!(soe.dump().entries.len(), 3);
+}
+
+#[test]
+fn test_ring_buffer() {
+    let mut soe = recorder(2);
+    let now = Utc::now();
+    for cycle in 0..5 {
+        soe.on_cycle(now, &bits(&[2]), &bits(&[]));
+        assert!(soe.entries.len() <= 2, "cycle {cycle}");
+    }
+    let dump = soe.dump();
+    let cycles: Vec<_> = dump.entries.iter().map(|entry| entry.cycle).collect();
+    assert_eq!(cycles, [4, 5]);
+    assert_eq!(dump.overwritten, 3);
+}
+
+#[test]
+fn test_disabled() {
+    let mut soe = recorder(0);
+    soe.on_cycle(Utc::now(), &bits(&[2, 3]), &bits(&[2, 3]));
+    assert!(soe.dump().entries.is_empty());
+    assert_eq!(soe.dump().overwritten, 0);
+}
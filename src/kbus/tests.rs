@@ -1,33 +1,42 @@
+use kbus_mock::{InputStep, KBusHandle, Terminal};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio_util::sync::CancellationToken;
 
 use super::*;
+use crate::config::{
+    ChannelActivity, ChannelRange, CycleOrder, InputsConfig, IoSizeCheck, KBusConfig, OutputVerify,
+    OutputsConfig,
+};
 
-#[tokio::test]
+#[tokio::test(start_paused = true)]
 async fn test_kbus_event_processing() {
-    tracing_subscriber::fmt::init();
-
     // Setup channels for testing
     let (input_tx, mut input_rx) = unbounded_channel();
     let (output_tx, output_rx) = unbounded_channel();
     let cancellation_token = CancellationToken::new();
 
-    // Reset mock state before test
-    kbus_mock::reset_state();
+    // Every test gets its own mock device
+    let kbus = KBusHandle::new();
 
     // Set an initial input bit in the mock
-    kbus_mock::set_input_bit(5, true).unwrap();
+    kbus.set_input_bit(5, true).unwrap();
 
-    // Start the KBUS task in the background
-    let task_handle = tokio::spawn(kbus_task(input_tx, output_rx, cancellation_token.clone()));
+    // Start the KBUS loop in the background
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        Config::default(),
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
 
-    // Wait a bit to let the task initialize and read inputs
+    // Let the task initialize and read inputs, the paused clock advances instantly
     tokio::time::sleep(tokio::time::Duration::from_millis(15)).await;
 
     // We should receive an event for bit 5 which was set to true
-    if let Some(event) = input_rx.recv().await {
+    if let Some(InputEvent::Channel(event)) = input_rx.recv().await {
         assert_eq!(event.channel, 5);
-        assert_eq!(event.value, true);
+        assert!(event.value);
     } else {
         panic!("Expected to receive an event");
     }
@@ -37,15 +46,799 @@ async fn test_kbus_event_processing() {
         channel: 10,
         value: true,
     };
-    output_tx.send(output_event).unwrap();
+    output_tx
+        .send(KBusCommand::Output(OutputWrite::new(output_event, None)))
+        .unwrap();
 
     // Wait for the event to be processed
     tokio::time::sleep(tokio::time::Duration::from_millis(15)).await;
 
     // Check if the output was set correctly in the mock
-    assert_eq!(kbus_mock::get_output_bit(10).unwrap(), true);
+    assert!(kbus.get_output_bit(10).unwrap());
 
     // Cleanup
     cancellation_token.cancel();
     let _ = task_handle.await;
 }
+
+#[tokio::test(start_paused = true)]
+async fn test_output_verification() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    // Loopback mirrors every output on the input with the same channel
+    let kbus = KBusHandle::new();
+    kbus.set_loopback(true);
+
+    let config = Config {
+        outputs: OutputsConfig {
+            verify: vec![
+                OutputVerify {
+                    output: 10,
+                    input: 10,
+                },
+                // Read back from the wrong channel, never matches
+                OutputVerify {
+                    output: 20,
+                    input: 30,
+                },
+            ],
+            verify_cycles: 2,
+            ..OutputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+
+    for channel in [10, 20] {
+        output_tx
+            .send(KBusCommand::Output(OutputWrite::new(
+                KBusEvent {
+                    channel,
+                    value: true,
+                },
+                None,
+            )))
+            .unwrap();
+    }
+
+    let failed = tokio::time::timeout(Duration::from_secs(1), async {
+        while let Some(event) = input_rx.recv().await {
+            if let InputEvent::VerifyFailed(failed) = event {
+                return Some(failed);
+            }
+        }
+        None
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        failed,
+        VerifyFailed {
+            output: 20,
+            input: 30,
+            expected: true,
+        }
+    );
+
+    // No more failures, output 10 was read back
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    while let Ok(event) = input_rx.try_recv() {
+        assert!(!matches!(event, InputEvent::VerifyFailed(_)), "{event:?}");
+    }
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_shadow_mode() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    let config = Config {
+        outputs: OutputsConfig {
+            shadow: true,
+            ..OutputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+
+    let output = |channel| {
+        KBusCommand::Output(OutputWrite::new(
+            KBusEvent {
+                channel,
+                value: true,
+            },
+            None,
+        ))
+    };
+    output_tx.send(output(10)).unwrap();
+    let (reply, reply_rx) = oneshot::channel();
+    output_tx
+        .send(KBusCommand::WriteBit {
+            offset: 12,
+            value: true,
+            reply,
+        })
+        .unwrap();
+    // Raw bit writes are acknowledged like channel writes
+    reply_rx.await.unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(15)).await;
+    assert!(!kbus.get_output_bit(10).unwrap());
+    assert!(!kbus.get_output_bit(12).unwrap());
+
+    // Actuation enabled, e.g. by `bridge/shadow`
+    output_tx.send(KBusCommand::Shadow(false)).unwrap();
+    output_tx.send(output(11)).unwrap();
+    tokio::time::sleep(Duration::from_millis(15)).await;
+    assert!(kbus.get_output_bit(11).unwrap());
+    assert!(!kbus.get_output_bit(10).unwrap());
+
+    // The shadowed output is acknowledged, only the written output is mirrored
+    let (mut shadowed, mut written) = (Vec::new(), Vec::new());
+    while let Ok(event) = input_rx.try_recv() {
+        match event {
+            InputEvent::Shadowed(write) => shadowed.push(write.event.channel),
+            InputEvent::Output(write) => written.push(write.event.channel),
+            _ => {}
+        }
+    }
+    assert_eq!(shadowed, [10]);
+    assert_eq!(written, [11]);
+
+    let (reply, reply_rx) = oneshot::channel();
+    output_tx.send(KBusCommand::Dump(reply)).unwrap();
+    let image = reply_rx.await.unwrap();
+    assert_eq!(image.outputs[1], 0b0000_1000);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_staged_writes() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        Config::default(),
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+    // The first cycle runs immediately
+    tokio::task::yield_now().await;
+
+    for channel in [10, 11] {
+        output_tx
+            .send(KBusCommand::Output(OutputWrite::new(
+                KBusEvent {
+                    channel,
+                    value: true,
+                },
+                None,
+            )))
+            .unwrap();
+    }
+    let (reply_tx, mut reply_rx) = oneshot::channel();
+    output_tx
+        .send(KBusCommand::WriteBit {
+            offset: 12,
+            value: true,
+            reply: reply_tx,
+        })
+        .unwrap();
+
+    // Staged until the next cycle
+    tokio::time::advance(KBUS_CYCLE / 2).await;
+    tokio::task::yield_now().await;
+    for channel in [10, 11, 12] {
+        assert!(!kbus.get_output_bit(channel).unwrap());
+    }
+    assert!(reply_rx.try_recv().is_err());
+    assert!(input_rx.try_recv().is_err());
+
+    // All written together right before the cycle
+    tokio::time::advance(KBUS_CYCLE / 2).await;
+    tokio::task::yield_now().await;
+    for channel in [10, 11, 12] {
+        assert!(kbus.get_output_bit(channel).unwrap());
+    }
+    assert!(reply_rx.await.unwrap().is_ok());
+    let mut written = Vec::new();
+    while let Ok(event) = input_rx.try_recv() {
+        if let InputEvent::Output(write) = event {
+            written.push(write.event.channel);
+        }
+    }
+    assert_eq!(written, [10, 11]);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+/// Returns the number of K-Bus cycles until the input channel is read with `value`.
+async fn cycles_until(
+    input_rx: &mut UnboundedReceiver<InputEvent>,
+    channel: u16,
+    value: bool,
+) -> u32 {
+    for cycles in 0..10 {
+        while let Ok(event) = input_rx.try_recv() {
+            if let InputEvent::Channel(event) = event {
+                if (event.channel, event.value) == (channel, value) {
+                    return cycles;
+                }
+            }
+        }
+        tokio::time::advance(KBUS_CYCLE).await;
+        tokio::task::yield_now().await;
+    }
+    panic!("input {channel} not read as {value}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cycle_order() {
+    // Cycles until an input set by the first bus cycle is read, and until a written
+    // output is read back
+    for (cycle_order, input_cycles, loopback_cycles) in [
+        (CycleOrder::WriteRead, 0, 1),
+        (CycleOrder::ReadWrite, 1, 2),
+        (CycleOrder::Wago, 0, 2),
+    ] {
+        let (input_tx, mut input_rx) = unbounded_channel();
+        let (output_tx, output_rx) = unbounded_channel();
+        let cancellation_token = CancellationToken::new();
+
+        let kbus = KBusHandle::new();
+        kbus.play([InputStep::new(0, 5, true)]);
+        let config = Config {
+            kbus: KBusConfig {
+                cycle_order,
+                ..KBusConfig::default()
+            },
+            ..Config::default()
+        };
+        let task_handle = tokio::spawn(kbus_loop(
+            kbus.kbus(),
+            config,
+            input_tx,
+            output_rx,
+            cancellation_token.clone(),
+        ));
+        // The first cycle runs immediately
+        tokio::task::yield_now().await;
+        assert_eq!(
+            cycles_until(&mut input_rx, 5, true).await,
+            input_cycles,
+            "{cycle_order:?}"
+        );
+
+        kbus.set_loopback(true);
+        output_tx
+            .send(KBusCommand::Output(OutputWrite::new(
+                KBusEvent {
+                    channel: 3,
+                    value: true,
+                },
+                None,
+            )))
+            .unwrap();
+        // Staged before the next cycle
+        tokio::task::yield_now().await;
+        assert_eq!(
+            cycles_until(&mut input_rx, 3, true).await,
+            loopback_cycles,
+            "{cycle_order:?}"
+        );
+
+        cancellation_token.cancel();
+        let _ = task_handle.await;
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_raw_bits() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    kbus.set_input_bit(42, true).unwrap();
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        Config::default(),
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+
+    let (reply, reply_rx) = oneshot::channel();
+    output_tx
+        .send(KBusCommand::WriteBit {
+            offset: 17,
+            value: true,
+            reply,
+        })
+        .unwrap();
+    reply_rx.await.unwrap().unwrap();
+    assert!(kbus.get_output_bit(17).unwrap());
+
+    let read = |offset| {
+        let (reply, reply_rx) = oneshot::channel();
+        output_tx
+            .send(KBusCommand::ReadBit { offset, reply })
+            .unwrap();
+        reply_rx
+    };
+    assert!(read(42).await.unwrap().unwrap());
+    assert!(!read(43).await.unwrap().unwrap());
+    // Out of the process image, reported without stopping the K-Bus loop
+    assert!(read(1000).await.unwrap().is_err());
+    assert!(read(42).await.unwrap().unwrap());
+
+    // Raw writes aren't mirrored as output channels
+    while let Ok(event) = input_rx.try_recv() {
+        assert!(!matches!(event, InputEvent::Output(_)));
+    }
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[test]
+fn test_read_region() {
+    let handle = KBusHandle::new();
+    handle.set_input_bit(9, true).unwrap();
+    let mut kbus = handle.kbus();
+    assert_eq!(read_region(&mut kbus, 1, 2).unwrap(), [0x02, 0x00]);
+
+    let err = read_region(&mut kbus, 0, 0).unwrap_err();
+    assert!(err.to_string().contains("invalid read length 0"), "{err:#}");
+    let err = read_region(&mut kbus, 0, MAX_READ_LENGTH + 1).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&format!("invalid read length {}", MAX_READ_LENGTH + 1)),
+        "{err:#}"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cycle_timing() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (_output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        Config::default(),
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+    // The first cycle runs immediately
+    tokio::task::yield_now().await;
+
+    // A change is only seen by the next cycle
+    kbus.set_input_bit(6, true).unwrap();
+    tokio::time::advance(KBUS_CYCLE / 2).await;
+    let timeout = Duration::from_millis(1);
+    assert!(
+        tokio::time::timeout(timeout, input_rx.recv())
+            .await
+            .is_err()
+    );
+
+    tokio::time::advance(KBUS_CYCLE / 2).await;
+    let event = tokio::time::timeout(timeout, input_rx.recv())
+        .await
+        .unwrap();
+    match event {
+        Some(InputEvent::Channel(event)) => assert_eq!((event.channel, event.value), (6, true)),
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_soe() {
+    let (input_tx, _input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    let config = Config {
+        inputs: InputsConfig {
+            fast: vec![6, 7],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+    tokio::task::yield_now().await;
+
+    // Both changes are seen by the same cycle, the other channel isn't fast
+    kbus.set_input_bit(7, true).unwrap();
+    kbus.set_input_bit(6, true).unwrap();
+    kbus.set_input_bit(8, true).unwrap();
+    tokio::time::advance(KBUS_CYCLE).await;
+    tokio::task::yield_now().await;
+    kbus.set_input_bit(7, false).unwrap();
+    tokio::time::advance(KBUS_CYCLE).await;
+    tokio::task::yield_now().await;
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    output_tx.send(KBusCommand::Soe(reply_tx)).unwrap();
+    let dump = reply_rx.await.unwrap();
+    let events: Vec<_> = dump
+        .entries
+        .iter()
+        .map(|entry| (entry.cycle, entry.channel, entry.value))
+        .collect();
+    assert_eq!(events, [(2, 6, true), (2, 7, true), (3, 7, false)]);
+    assert_eq!(dump.entries[0].time, dump.entries[1].time);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[test]
+fn test_monitor_mask() {
+    let mask = monitor_mask(&InputsConfig::default());
+    assert_eq!(mask.len(), INPUT_SIZE);
+    assert!(mask.all());
+
+    let config = InputsConfig {
+        monitor: vec![
+            ChannelRange { first: 0, last: 15 },
+            ChannelRange {
+                first: 32,
+                last: 32,
+            },
+        ],
+        ..InputsConfig::default()
+    };
+    let mask = monitor_mask(&config);
+    assert_eq!(mask.count_ones(), 17);
+    assert!(mask[15] && mask[32]);
+    assert!(!mask[16] && !mask[33]);
+}
+
+#[test]
+fn test_read_ranges() {
+    let all = monitor_mask(&InputsConfig::default());
+    assert_eq!(
+        read_ranges(&all, &[], &OutputsConfig::default()),
+        vec![0..INPUT_SIZE.div_ceil(8)]
+    );
+
+    let monitored = monitor_mask(&InputsConfig {
+        monitor: vec![ChannelRange { first: 0, last: 15 }],
+        ..InputsConfig::default()
+    });
+    let rules = [Rule::new("alarm", "in40 && !in3").unwrap()];
+    let outputs = OutputsConfig {
+        verify: vec![OutputVerify {
+            output: 0,
+            input: 60,
+        }],
+        ..OutputsConfig::default()
+    };
+    assert_eq!(
+        read_ranges(&monitored, &rules, &outputs),
+        [0..2, 5..6, 7..8]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_unmonitored_inputs() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (_output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    kbus.set_input_bit(5, true).unwrap();
+    kbus.set_input_bit(20, true).unwrap();
+
+    let config = Config {
+        inputs: InputsConfig {
+            monitor: vec![ChannelRange { first: 0, last: 7 }],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut channels = Vec::new();
+    while let Ok(event) = input_rx.try_recv() {
+        if let InputEvent::Channel(event) = event {
+            channels.push(event.channel);
+        }
+    }
+    assert_eq!(channels, [5]);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_dump_unmonitored_inputs() {
+    let (input_tx, _input_rx) = unbounded_channel();
+    let (output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    kbus.set_input_bit(5, true).unwrap();
+    kbus.set_input_bit(20, true).unwrap();
+
+    let config = Config {
+        inputs: InputsConfig {
+            monitor: vec![ChannelRange { first: 0, last: 7 }],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Bytes not read by the cycle are dumped with their state
+    let (reply_tx, reply_rx) = oneshot::channel();
+    output_tx.send(KBusCommand::Dump(reply_tx)).unwrap();
+    let image = reply_rx.await.unwrap();
+    assert_eq!(image.inputs.len(), INPUT_SIZE.div_ceil(8));
+    assert_eq!(image.inputs[..3], [0x20, 0x00, 0x10]);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[test]
+fn test_channels_beyond_image() {
+    let config = Config {
+        inputs: InputsConfig {
+            monitor: vec![ChannelRange { first: 6, last: 9 }],
+            ..InputsConfig::default()
+        },
+        outputs: OutputsConfig {
+            verify: vec![OutputVerify {
+                output: 20,
+                input: 3,
+            }],
+            ..OutputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let rules = [Rule::new("alarm", "in40 && !in3").unwrap()];
+    let (inputs, outputs) = channels_beyond_image(&config, &rules, (1, 3));
+    assert_eq!(Vec::from_iter(inputs), [8, 9, 40]);
+    assert!(outputs.is_empty());
+
+    let (inputs, outputs) = channels_beyond_image(&config, &rules, (6, 2));
+    assert!(inputs.is_empty());
+    assert_eq!(Vec::from_iter(outputs), [20]);
+
+    // All inputs are monitored by default, which doesn't count
+    let (inputs, outputs) = channels_beyond_image(&Config::default(), &[], (0, 0));
+    assert!(inputs.is_empty() && outputs.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_io_size_check() {
+    let run = |kbus: &KBusHandle, io_size_check| {
+        let (input_tx, input_rx) = unbounded_channel();
+        let (output_tx, output_rx) = unbounded_channel();
+        let cancellation_token = CancellationToken::new();
+        let config = Config {
+            kbus: KBusConfig {
+                io_size_check,
+                ..KBusConfig::default()
+            },
+            rules: [("alarm".to_string(), "in40".to_string())].into(),
+            ..Config::default()
+        };
+        let task_handle = tokio::spawn(kbus_loop(
+            kbus.kbus(),
+            config,
+            input_tx,
+            output_rx,
+            cancellation_token.clone(),
+        ));
+        (task_handle, cancellation_token, input_rx, output_tx)
+    };
+
+    let small_rack = KBusHandle::with_terminals([
+        Terminal::digital_inputs("750-430", 8),
+        Terminal::digital_outputs("750-530", 8),
+    ]);
+    let (task_handle, ..) = run(&small_rack, IoSizeCheck::Error);
+    let err = task_handle.await.unwrap().unwrap_err();
+    assert!(err.to_string().contains("inputs {40}"), "{err:#}");
+
+    let large_rack = KBusHandle::with_terminals(
+        std::iter::repeat_n(Terminal::digital_inputs("750-430", 8), 16)
+            .chain([Terminal::digital_outputs("750-530", 8)]),
+    );
+    let (task_handle, cancellation_token, _input_rx, _output_tx) =
+        run(&large_rack, IoSizeCheck::Error);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!task_handle.is_finished());
+    cancellation_token.cancel();
+    task_handle.await.unwrap().unwrap();
+
+    let misreported = KBusHandle::new();
+    misreported.set_io_sizes(2, 2);
+    let (task_handle, cancellation_token, _input_rx, _output_tx) =
+        run(&misreported, IoSizeCheck::Warn);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!task_handle.is_finished());
+    cancellation_token.cancel();
+    task_handle.await.unwrap().unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_dead_channel() {
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let (_output_tx, output_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    let kbus = KBusHandle::new();
+    let config = Config {
+        inputs: InputsConfig {
+            activity: vec![ChannelActivity {
+                channels: ChannelRange { first: 5, last: 5 },
+                expect_activity_within: Duration::from_secs(1),
+            }],
+            ..InputsConfig::default()
+        },
+        ..Config::default()
+    };
+    let task_handle = tokio::spawn(kbus_loop(
+        kbus.kbus(),
+        config,
+        input_tx,
+        output_rx,
+        cancellation_token.clone(),
+    ));
+    let mut dead_channels = || {
+        let mut dead_channels = Vec::new();
+        while let Ok(event) = input_rx.try_recv() {
+            if let InputEvent::DeadChannel(dead) = event {
+                dead_channels.push((dead.channel, dead.dead));
+            }
+        }
+        dead_channels
+    };
+
+    tokio::time::sleep(Duration::from_millis(900)).await;
+    assert!(dead_channels().is_empty());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(dead_channels(), [(5, true)]);
+
+    kbus.set_input_bit(5, true).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(dead_channels(), [(5, false)]);
+
+    cancellation_token.cancel();
+    let _ = task_handle.await;
+}
+
+#[test]
+fn test_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("kbus.lock");
+
+    let file = lock(&path).unwrap();
+    let err = lock(&path).unwrap_err();
+    assert!(is_in_use(&err), "{err:#}");
+
+    drop(file);
+    lock(&path).unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_open_retry() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = KBusConfig {
+        lock_file: Some(dir.path().join("kbus.lock")),
+        init_timeout: Duration::from_secs(1),
+        ..KBusConfig::default()
+    };
+    let cancellation_token = CancellationToken::new();
+
+    // Still in use after the timeout
+    let held = lock(config.lock_file.as_ref().unwrap()).unwrap();
+    let err = open(&config, &cancellation_token).await.err().unwrap();
+    assert!(is_in_use(&err), "{err:#}");
+
+    // Released within the timeout by a previous instance shutting down
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drop(held);
+    });
+    let (_kbus, lock) = open(&config, &cancellation_token).await.unwrap();
+    assert!(lock.is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_wait_for_kbus() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = KBusConfig {
+        lock_file: Some(dir.path().join("kbus.lock")),
+        init_timeout: Duration::ZERO,
+        required: false,
+        retry_interval: Duration::from_secs(30),
+        io_size_check: IoSizeCheck::Off,
+        cycle_order: CycleOrder::default(),
+    };
+    let (input_tx, mut input_rx) = unbounded_channel();
+    let cancellation_token = CancellationToken::new();
+
+    // Unavailable is reported right away, retried until the lock is released
+    let held = lock(config.lock_file.as_ref().unwrap()).unwrap();
+    let err = open(&config, &cancellation_token).await.err().unwrap();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        drop(held);
+    });
+    let (_kbus, lock) = wait_for_kbus(&config, err, &input_tx, &cancellation_token)
+        .await
+        .unwrap();
+    assert!(lock.is_some());
+    assert!(matches!(
+        input_rx.try_recv(),
+        Ok(InputEvent::KBusAvailable(false))
+    ));
+    assert!(input_rx.try_recv().is_err());
+
+    // Cancelled while waiting
+    cancellation_token.cancel();
+    let err = anyhow::anyhow!("unavailable");
+    assert!(
+        wait_for_kbus(&config, err, &input_tx, &cancellation_token)
+            .await
+            .is_none()
+    );
+}
@@ -0,0 +1,107 @@
+//! Durations of DAL calls
+//!
+//! A missed K-Bus cycle is either caused by the DAL taking long (e.g. a module
+//! answering slowly) or by the task being scheduled late. The durations of the
+//! bus cycle triggers, process image reads and writes and the delay of the cycles
+//! after their scheduled time are collected in histograms since the start of the
+//! bridge and reported in the heartbeat, next to the runtime metrics, to tell
+//! them apart.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde_json::{Map, json};
+
+#[cfg(test)]
+mod tests;
+
+/// Upper bounds of the histogram buckets in microseconds, a last bucket takes
+/// the longer calls
+const BUCKETS_US: [u64; 8] = [50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Histogram of call durations, updated without locking in the K-Bus cycle.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS_US.len() + 1],
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Histogram {
+    pub const fn new() -> Histogram {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS_US.len() + 1],
+            count: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    /// Records the duration of a call.
+    pub fn record(&self, duration: Duration) {
+        let us = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKETS_US
+            .iter()
+            .position(|&bound| us <= bound)
+            .unwrap_or(BUCKETS_US.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Calls `f` and records its duration.
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(start.elapsed());
+        result
+    }
+
+    /// Returns the number of calls, their mean and maximum duration and the number
+    /// of calls per bucket, by upper bound in microseconds (`inf` for the last one).
+    pub fn report(&self) -> serde_json::Value {
+        let count = self.count.load(Ordering::Relaxed);
+        let buckets: Map<_, _> = BUCKETS_US
+            .iter()
+            .map(u64::to_string)
+            .chain(["inf".to_owned()])
+            .zip(&self.buckets)
+            .map(|(bound, bucket)| (bound, json!(bucket.load(Ordering::Relaxed))))
+            .collect();
+        json!({
+            "count": count,
+            "mean_us": self.sum_us.load(Ordering::Relaxed).checked_div(count).unwrap_or(0),
+            "max_us": self.max_us.load(Ordering::Relaxed),
+            "buckets": buckets,
+        })
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new()
+    }
+}
+
+/// Delay of the K-Bus cycles after their scheduled time (scheduler jitter)
+pub static CYCLE_DELAY: Histogram = Histogram::new();
+/// `trigger_bus_cycle` calls
+pub static BUS_CYCLE: Histogram = Histogram::new();
+/// Reads of the input process image
+pub static READ: Histogram = Histogram::new();
+/// Writes of the output process image
+pub static WRITE: Histogram = Histogram::new();
+
+/// Returns the DAL call durations reported in the heartbeat.
+pub fn report() -> serde_json::Value {
+    json!({
+        "cycle_delay": CYCLE_DELAY.report(),
+        "trigger_bus_cycle": BUS_CYCLE.report(),
+        "read": READ.report(),
+        "write": WRITE.report(),
+    })
+}
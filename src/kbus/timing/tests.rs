@@ -0,0 +1,30 @@
+use super::*;
+
+#[test]
+fn test_histogram() {
+    let histogram = Histogram::new();
+    let report = histogram.report();
+    assert_eq!(report["count"], 0);
+    assert_eq!(report["mean_us"], 0);
+
+    for us in [10, 50, 51, 3000, 20000] {
+        histogram.record(Duration::from_micros(us));
+    }
+    let report = histogram.report();
+    assert_eq!(report["count"], 5);
+    assert_eq!(report["mean_us"], 4622);
+    assert_eq!(report["max_us"], 20000);
+    // Upper bounds are inclusive
+    assert_eq!(report["buckets"]["50"], 2);
+    assert_eq!(report["buckets"]["100"], 1);
+    assert_eq!(report["buckets"]["250"], 0);
+    assert_eq!(report["buckets"]["5000"], 1);
+    assert_eq!(report["buckets"]["inf"], 1);
+}
+
+#[test]
+fn test_time() {
+    let histogram = Histogram::new();
+    assert_eq!(histogram.time(|| 42), 42);
+    assert_eq!(histogram.report()["count"], 1);
+}
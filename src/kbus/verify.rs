@@ -0,0 +1,94 @@
+//! Read-back verification of written outputs
+//!
+//! The DAL only reads the input process image, so outputs are verified through
+//! input channels mirroring them, e.g. feedback contacts of relays or diagnostic
+//! bits of output modules. A configured number of bus cycles after an output is
+//! written, its read-back channel is compared to the commanded value, which
+//! reveals values the hardware didn't take (e.g. because of a module error).
+
+use bitvec::prelude::*;
+use serde::Serialize;
+
+use crate::config::OutputsConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// An output whose read-back channel didn't match the commanded value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyFailed {
+    /// Output channel
+    pub output: u16,
+    /// Input channel mirroring the output
+    pub input: u16,
+    /// Value written to the output
+    pub expected: bool,
+}
+
+#[derive(Debug)]
+struct Pending {
+    output: u16,
+    input: u16,
+    expected: bool,
+    cycles_left: u32,
+}
+
+/// Tracks written outputs until their read-back is checked.
+#[derive(Debug)]
+pub struct OutputVerifier {
+    /// Read-back input channel by output channel
+    inputs: Vec<Option<u16>>,
+    cycles: u32,
+    pending: Vec<Pending>,
+}
+
+impl OutputVerifier {
+    pub fn new(config: &OutputsConfig, output_channels: usize) -> OutputVerifier {
+        let mut inputs = vec![None; output_channels];
+        for verify in &config.verify {
+            inputs[usize::from(verify.output)] = Some(verify.input);
+        }
+        OutputVerifier {
+            inputs,
+            cycles: config.verify_cycles,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Schedules the verification of a written output, if it has a read-back channel.
+    ///
+    /// A pending verification of the same output is replaced, only the last
+    /// written value is checked.
+    pub fn on_write(&mut self, output: u16, value: bool) {
+        let Some(&Some(input)) = self.inputs.get(usize::from(output)) else {
+            return;
+        };
+        self.pending.retain(|pending| pending.output != output);
+        self.pending.push(Pending {
+            output,
+            input,
+            expected: value,
+            cycles_left: self.cycles,
+        });
+    }
+
+    /// Counts a bus cycle and checks the outputs due in it against the read inputs.
+    pub fn on_cycle(&mut self, inputs: &BitSlice<u8>) -> Vec<VerifyFailed> {
+        let mut failed = Vec::new();
+        self.pending.retain_mut(|pending| {
+            pending.cycles_left -= 1;
+            if pending.cycles_left > 0 {
+                return true;
+            }
+            if inputs.get(usize::from(pending.input)).as_deref() != Some(&pending.expected) {
+                failed.push(VerifyFailed {
+                    output: pending.output,
+                    input: pending.input,
+                    expected: pending.expected,
+                });
+            }
+            false
+        });
+        failed
+    }
+}
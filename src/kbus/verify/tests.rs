@@ -0,0 +1,63 @@
+use super::*;
+use crate::config::OutputVerify;
+
+fn verifier(verify_cycles: u32) -> OutputVerifier {
+    OutputVerifier::new(
+        &OutputsConfig {
+            verify: vec![OutputVerify {
+                output: 4,
+                input: 12,
+            }],
+            verify_cycles,
+            ..OutputsConfig::default()
+        },
+        90,
+    )
+}
+
+#[test]
+fn test_verify() {
+    let mut verifier = verifier(1);
+    let mut inputs = bitvec![u8, LocalBits; 0; 90];
+
+    // Outputs without read-back channel are not verified
+    verifier.on_write(5, true);
+    assert!(verifier.on_cycle(&inputs).is_empty());
+
+    inputs.set(12, true);
+    verifier.on_write(4, true);
+    assert!(verifier.on_cycle(&inputs).is_empty());
+
+    verifier.on_write(4, false);
+    assert_eq!(
+        verifier.on_cycle(&inputs),
+        vec![VerifyFailed {
+            output: 4,
+            input: 12,
+            expected: false,
+        }]
+    );
+    // Checked only once
+    assert!(verifier.on_cycle(&inputs).is_empty());
+}
+
+#[test]
+fn test_verify_cycles() {
+    let mut verifier = verifier(3);
+    let mut inputs = bitvec![u8, LocalBits; 0; 90];
+
+    verifier.on_write(4, true);
+    assert!(verifier.on_cycle(&inputs).is_empty());
+    // The value is read back before the check is due
+    inputs.set(12, true);
+    assert!(verifier.on_cycle(&inputs).is_empty());
+    assert!(verifier.on_cycle(&inputs).is_empty());
+
+    // A new write restarts the verification with the last value
+    verifier.on_write(4, true);
+    verifier.on_cycle(&inputs);
+    verifier.on_write(4, false);
+    assert!(verifier.on_cycle(&inputs).is_empty());
+    assert!(verifier.on_cycle(&inputs).is_empty());
+    assert_eq!(verifier.on_cycle(&inputs).len(), 1);
+}
@@ -1,4 +1,26 @@
+pub mod bridge;
+#[cfg(feature = "embedded-broker")]
+pub mod broker;
+pub mod build_info;
+pub mod cli;
 pub mod config;
+pub mod datalog;
+pub mod diagnostics;
+pub mod identity;
 pub mod kbus;
+pub mod metrics;
+pub mod modbus;
 pub mod mqtt;
+pub mod network;
+pub mod report;
+pub mod rules;
+pub mod schedule;
+#[cfg(feature = "self-update")]
+pub mod self_update;
+pub mod shutdown;
+pub mod state;
+pub mod supervisor;
+pub mod throttle;
+pub mod timestamp;
+pub mod update;
 pub mod utils;
@@ -1,115 +1,434 @@
-use std::{env, error::Error, path::PathBuf, time::Duration};
+use std::{env, path::PathBuf, process, process::ExitCode, time::Duration};
 
 use anyhow::Context;
+#[cfg(feature = "embedded-broker")]
+use kbus_mqtt_bridge::broker;
 use kbus_mqtt_bridge::{
-    config::Config,
-    kbus::kbus_task,
-    mqtt::mqtt_client_task,
+    build_info,
+    cli::{self, Command},
+    config::{Config, TopicProfile},
+    datalog::datalog_task,
+    diagnostics,
+    identity::Identity,
+    kbus::{self, InputEvent, KBusCommand, kbus_task},
+    metrics::metrics_task,
+    modbus::modbus_task,
+    mqtt::{CommandQueues, cleanup_retained, mqtt_client_task, mqtt_options, publish_last_error},
+    network,
+    report::ErrorReport,
+    schedule::schedule_task,
+    shutdown::{self, ShutdownReason},
+    state::{self, State, state_task},
+    timestamp, update,
     utils::{KBUS_MAINPRIO, SchedPolicy, configure_scheduler},
 };
-use pnet::datalink;
-use rumqttc::{LastWill, MqttOptions, QoS};
-use tokio::signal;
+use rumqttc::{LastWill, QoS};
+use tokio::{
+    signal,
+    sync::{mpsc::UnboundedSender, oneshot},
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
 fn print_help() {
     println!("KBUS MQTT Bridge");
-    println!("Usage: kbus_mqtt_bridge [OPTIONS]");
+    println!("Usage: kbus_mqtt_bridge [OPTIONS] [COMMAND]");
+    println!();
+    println!("Commands (one-shot K-Bus access, the bridge must not be running):");
+    println!("  read [CHANNEL]         Print all input channels or the given one");
+    println!("  write CHANNEL VALUE    Set an output channel (true/false, on/off, 1/0)");
+    println!("  scan                   Print the process image sizes and active inputs");
+    println!("  init-config            Print a starter configuration with a channel map");
+    println!("  bench OUTPUT INPUT [N] Toggle an output wired to an input N times (default 100)");
+    println!("                         and print the read-back latency and cycle times");
+    println!("  cleanup-retained       Clear all retained topics of the device on the broker,");
+    println!("                         e.g. when decommissioning it (uses the configuration)");
     println!();
     println!("Options:");
     println!("  -c, --config <FILE>  Path to TOML configuration file");
+    println!("  -p, --profile <NAME> Profile of the configuration file to apply");
+    println!("      --json           Print the command result as JSON");
     println!("  -h, --help           Print this help message");
     println!("  -v, --version        Print version information");
     println!();
     println!("Configuration can also be provided via environment variables:");
     println!("  KBUS_BRIDGE_CONFIG_FILE     Path to configuration file (alternative to --config)");
+    println!("  KBUS_BRIDGE_PROFILE         Configuration profile (alternative to --profile)");
     println!("  KBUS_BRIDGE_DEVICE_NAME     Device name used in MQTT topics");
+    println!("  KBUS_BRIDGE_TOPIC_INCLUDE_MAC  Include the MAC address in MQTT topics");
+    println!("  KBUS_BRIDGE_TOPIC_ROOT      Topic levels prepended to all MQTT topics");
     println!("  KBUS_BRIDGE_MQTT_HOST       MQTT broker hostname or IP address");
     println!("  KBUS_BRIDGE_MQTT_PORT       MQTT broker port");
     println!("  KBUS_BRIDGE_MQTT_USERNAME   MQTT username for authentication");
     println!("  KBUS_BRIDGE_MQTT_PASSWORD   MQTT password for authentication");
     println!("  KBUS_BRIDGE_MQTT_KEEPALIVE  MQTT keepalive duration in seconds");
+    println!("  KBUS_BRIDGE_MQTT_PING_INTERVAL  Liveness ping interval in seconds, 0 disables it");
+    println!(
+        "  KBUS_BRIDGE_MQTT_TELEMETRY_INTERVAL  System telemetry interval in seconds, 0 disables it"
+    );
+    println!("  KBUS_BRIDGE_MQTT_SUBSCRIBE_QOS  QoS level of command subscriptions");
+    println!();
+    println!("Signals:");
+    println!("  SIGTERM, SIGINT  Shut down gracefully");
+    println!("  SIGUSR1          Log the internal state (see mqtt.publish_state_dump)");
+}
+
+/// Initializes logging, with the tokio-console instrumentation layer if enabled.
+fn init_tracing() {
+    #[cfg(feature = "tokio-console")]
+    {
+        use tracing_subscriber::{EnvFilter, fmt, prelude::*};
+
+        tracing_subscriber::registry()
+            .with(console_subscriber::spawn())
+            .with(fmt::layer().with_filter(EnvFilter::from_default_env()))
+            .init();
+    }
+
+    #[cfg(not(feature = "tokio-console"))]
+    tracing_subscriber::fmt::init();
+}
+
+/// Logs the internal state and publishes it if `input_tx` is given.
+async fn dump_state(
+    kbus_commands: &UnboundedSender<KBusCommand>,
+    tasks: &[(&'static str, bool)],
+    config_summary: &serde_json::Value,
+    input_tx: Option<&UnboundedSender<InputEvent>>,
+) {
+    // The K-Bus task may have failed or be stuck, don't wait for it too long
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let image = if kbus::is_running() && kbus_commands.send(KBusCommand::Dump(reply_tx)).is_ok() {
+        tokio::time::timeout(Duration::from_secs(1), reply_rx)
+            .await
+            .ok()
+            .and_then(Result::ok)
+    } else {
+        None
+    };
+    let dump = diagnostics::state_dump(image.as_ref(), tasks, config_summary);
+    info!(state = %dump, "state dump");
+    if let Some(input_tx) = input_tx {
+        if input_tx.send(InputEvent::StateDump(dump)).is_err() {
+            error!("failed to publish state dump, input event queue closed");
+        }
+    }
+}
+
+/// Exits the process on a second signal while the tasks are shut down.
+///
+/// Spawned once the shutdown started, so a hanging task, e.g. waiting for MQTT
+/// acknowledgements or the DAL to exit, doesn't block the service manager.
+fn force_exit_on_signal(mut terminate: signal::unix::Signal) {
+    tokio::spawn(async move {
+        let signal = tokio::select! {
+            res = signal::ctrl_c() => {
+                if res.is_err() {
+                    // Without a Ctrl+C handler, only wait for SIGTERM
+                    terminate.recv().await;
+                    "SIGTERM"
+                } else {
+                    "Ctrl+C"
+                }
+            },
+            _ = terminate.recv() => "SIGTERM",
+        };
+        let exit_code = ShutdownReason::Forced.exit_code();
+        error!(
+            signal,
+            exit_code, "Received second signal while shutting down, forcing exit"
+        );
+        process::exit(exit_code.into());
+    });
+}
+
+/// Clears the retained topics of the device on the broker, for `cleanup-retained`.
+///
+/// Connects with its own client id, so a bridge still running isn't disconnected.
+async fn cleanup(config: &Config, json: bool) -> ExitCode {
+    let result = async {
+        let identity = Identity::from_source(config.identity.source)?;
+        let topic_prefix = config.topic_prefix(&identity.id);
+        let mqtt_options = mqtt_options(config, format!("{}-cleanup", config.device_name));
+        cleanup_retained(mqtt_options, config, &topic_prefix).await
+    };
+    match result.await {
+        Ok(topics) if json => println!("{}", serde_json::json!({ "cleared": topics })),
+        Ok(topics) => {
+            for topic in &topics {
+                println!("{topic}");
+            }
+            println!("Cleared {} retained topics", topics.len());
+        }
+        Err(err) if json => {
+            println!("{}", serde_json::json!({ "error": format!("{err:#}") }));
+            return ExitCode::FAILURE;
+        }
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
 }
 
 async fn app(config: Config) -> Result<(), anyhow::Error> {
     let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
         .context("failed to setup SIGTERM handler")?;
+    let mut user_defined1 = signal::unix::signal(signal::unix::SignalKind::user_defined1())
+        .context("failed to setup SIGUSR1 handler")?;
 
     let cancellation_token = CancellationToken::new();
 
-    let mac = datalink::interfaces()
-        .first()
-        .context("No network interface found")?
-        .mac
-        .context("No MAC address found")?;
+    // The interface providing the MAC address may not be up yet on boot
+    tokio::select! {
+        _ = network::wait(&config) => {}
+        _ = terminate.recv() => {
+            info!("Received SIGTERM while waiting for the network, shutting down...");
+            shutdown::initiate(ShutdownReason::Signal);
+            return Ok(());
+        }
+    }
 
-    let device_name = config.device_name.clone();
-    let topic_prefix = format!("{device_name}/{mac}");
+    let identity = Identity::from_source(config.identity.source)?;
+    let topic_prefix = config.topic_prefix(&identity.id);
 
-    let mut mqtt_options = MqttOptions::new(
-        config.device_name,
-        config.mqtt.broker_host,
-        config.mqtt.broker_port,
-    );
-    mqtt_options.set_keep_alive(config.mqtt.keepalive);
-    mqtt_options.set_last_will(LastWill {
-        topic: format!("{topic_prefix}/status"),
-        message: "offline".into(),
-        qos: QoS::ExactlyOnce,
-        retain: true,
-    });
+    #[cfg(feature = "embedded-broker")]
+    if let Some(broker) = &config.broker {
+        broker::start(broker, &config.mqtt)?;
+    }
+
+    let mut mqtt_options = mqtt_options(&config, config.device_name.clone());
+    // A connection has a single last will, Tasmota consumers watch the LWT topic
+    let last_will = match config.mqtt.topic_profile {
+        TopicProfile::Bridge => LastWill {
+            topic: format!("{topic_prefix}/status"),
+            message: "offline".into(),
+            qos: QoS::ExactlyOnce,
+            retain: true,
+        },
+        TopicProfile::Tasmota => LastWill {
+            topic: config.rooted_topic(&format!("tele/{}/LWT", config.device_name)),
+            message: "Offline".into(),
+            qos: QoS::ExactlyOnce,
+            retain: true,
+        },
+    };
+    mqtt_options.set_last_will(last_will);
 
-    if let (Some(username), Some(password)) = (&config.mqtt.username, &config.mqtt.password) {
-        mqtt_options.set_credentials(username, password);
+    timestamp::init(config.mqtt.timestamp_format);
+
+    // A configuration update on trial is kept once the bridge ran for the grace period
+    if let Some(path) = config.file.clone().filter(|path| update::is_on_trial(path)) {
+        let grace_period = config.remote_config.grace_period;
+        let cancellation_token = cancellation_token.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep(grace_period) => {
+                    if let Err(err) = update::confirm(&path) {
+                        error!(error = format!("{err:#}"), "failed to confirm configuration update");
+                    }
+                }
+                _ = cancellation_token.cancelled() => {}
+            }
+        });
+    }
+
+    if let Some(path) = &config.state.file {
+        state::restore(path)?;
     }
 
     let (input_tx, input_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (kbus_output_tx, kbus_output_rx) = tokio::sync::mpsc::unbounded_channel();
+    let state_dump_tx = config.mqtt.publish_state_dump.then(|| input_tx.clone());
+    let config_summary = diagnostics::config_summary(&config);
+    let (kbus_command_tx, kbus_command_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let (modbus_command_tx, modbus_task_handle) = match config.modbus.clone() {
+        Some(modbus_config) => {
+            let (modbus_command_tx, modbus_command_rx) = tokio::sync::mpsc::unbounded_channel();
+            let handle = tokio::spawn(modbus_task(
+                modbus_config,
+                input_tx.clone(),
+                modbus_command_rx,
+                cancellation_token.clone(),
+            ));
+            (Some(modbus_command_tx), Some(handle))
+        }
+        None => (None, None),
+    };
+
+    // Input events pass through the data log on their way to the MQTT task
+    let (input_rx, datalog_task_handle) = match config.datalog.clone() {
+        Some(datalog_config) => {
+            let (forward_tx, forward_rx) = tokio::sync::mpsc::unbounded_channel();
+            let handle = tokio::spawn(datalog_task(datalog_config, input_rx, forward_tx));
+            (forward_rx, Some(handle))
+        }
+        None => (input_rx, None),
+    };
 
     let kbus_task_handle = tokio::task::spawn(kbus_task(
+        config.clone(),
         input_tx,
-        kbus_output_rx,
+        kbus_command_rx,
         cancellation_token.clone(),
     ));
 
+    let schedule_task_handle = (!config.schedules.is_empty()).then(|| {
+        tokio::spawn(schedule_task(
+            config.schedules.clone(),
+            kbus_command_tx.clone(),
+            config.supervisor.clone(),
+            cancellation_token.clone(),
+        ))
+    });
+
+    let state_task_handle = config.state.file.clone().map(|path| {
+        tokio::spawn(state_task(
+            path,
+            config.state.flush_interval,
+            config.supervisor.clone(),
+            cancellation_token.clone(),
+        ))
+    });
+
+    // System metrics are only sampled for the heartbeat
+    let heartbeat_interval = config.mqtt.heartbeat_interval;
+    let metrics_task_handle = (!heartbeat_interval.is_zero()).then(|| {
+        tokio::spawn(metrics_task(
+            heartbeat_interval,
+            config.supervisor.clone(),
+            cancellation_token.clone(),
+        ))
+    });
+
+    let state_file = config.state.file.clone();
+    let shutdown_timeout = config.mqtt.shutdown_timeout;
     let mqtt_task_handle = tokio::spawn(mqtt_client_task(
         topic_prefix.clone(),
+        identity,
         mqtt_options.clone(),
+        config,
         input_rx,
-        kbus_output_tx.clone(),
-        Duration::from_secs(60),
+        CommandQueues {
+            kbus: kbus_command_tx.clone(),
+            modbus: modbus_command_tx,
+        },
         cancellation_token.clone(),
     ));
 
-    tokio::select! {
-        res = signal::ctrl_c() => {
-            info!("Received Ctrl+C, shutting down...");
-            res.context("Unable to listen for shutdown signal")?;
-            cancellation_token.cancel();
-        },
-        _ = terminate.recv() => {
-            info!("Received SIGTERM, shutting down...");
-            cancellation_token.cancel();
-        },
-        _ = cancellation_token.cancelled() => {}
+    loop {
+        tokio::select! {
+            res = signal::ctrl_c() => {
+                info!("Received Ctrl+C, shutting down...");
+                res.context("Unable to listen for shutdown signal")?;
+                shutdown::initiate(ShutdownReason::Signal);
+                cancellation_token.cancel();
+                break;
+            },
+            _ = terminate.recv() => {
+                info!("Received SIGTERM, shutting down...");
+                shutdown::initiate(ShutdownReason::Signal);
+                cancellation_token.cancel();
+                break;
+            },
+            _ = user_defined1.recv() => {
+                let tasks = [
+                    ("kbus", Some(&kbus_task_handle)),
+                    ("mqtt", Some(&mqtt_task_handle)),
+                    ("schedule", schedule_task_handle.as_ref()),
+                    ("modbus", modbus_task_handle.as_ref()),
+                    ("state", state_task_handle.as_ref()),
+                    ("metrics", metrics_task_handle.as_ref()),
+                    ("datalog", datalog_task_handle.as_ref()),
+                ];
+                let tasks: Vec<_> = tasks
+                    .into_iter()
+                    .filter_map(|(task, handle)| handle.map(|handle| (task, !handle.is_finished())))
+                    .collect();
+                dump_state(&kbus_command_tx, &tasks, &config_summary, state_dump_tx.as_ref()).await;
+            },
+            _ = cancellation_token.cancelled() => break,
+        }
     }
+    drop(state_dump_tx);
+    force_exit_on_signal(terminate);
 
-    kbus_task_handle
-        .await
-        .context("failed to join K-Bus task")?
-        .context("K-Bus task failed")?;
+    // Join all tasks, the first failure is reported on `last_error`
+    let tasks = [
+        ("kbus", "K-Bus", Some(kbus_task_handle)),
+        ("mqtt", "MQTT", Some(mqtt_task_handle)),
+        ("schedule", "schedule", schedule_task_handle),
+        ("modbus", "Modbus", modbus_task_handle),
+        ("state", "state", state_task_handle),
+        ("metrics", "system metrics", metrics_task_handle),
+        ("datalog", "data log", datalog_task_handle),
+    ];
+    let mut failure = None;
+    for (task, description, handle) in tasks {
+        let Some(handle) = handle else {
+            continue;
+        };
+        let result = handle
+            .await
+            .with_context(|| format!("failed to join {description} task"))
+            .and_then(|result| result.with_context(|| format!("{description} task failed")));
+        match (result, &failure) {
+            (Ok(()), _) => {}
+            (Err(err), None) => failure = Some((task, err)),
+            (Err(err), Some(_)) => error!(task, error = format!("{err:#}"), "task failed"),
+        }
+    }
+
+    // Flush the counters after all tasks finished updating them
+    if let Some(path) = state_file {
+        State::current().save(path)?;
+    }
 
-    mqtt_task_handle
-        .await
-        .context("failed to join MQTT task")?
-        .context("MQTT task failed")?;
+    if let Some((task, err)) = failure {
+        let report = ErrorReport::new(task, &err);
+        if let Err(err) =
+            publish_last_error(mqtt_options, &topic_prefix, &report, shutdown_timeout).await
+        {
+            error!(error = format!("{err:#}"), "failed to publish last error");
+        }
+        return Err(err);
+    }
 
     Ok(())
 }
 
+/// Runs a one-shot command, prints its result (or the parse error) and exits.
+fn run_command(command: Result<cli::KBusCommand, anyhow::Error>, json: bool) -> ! {
+    match command.and_then(cli::KBusCommand::run) {
+        Ok(report) if json => println!("{}", report.to_json()),
+        Ok(report) => println!("{}", report.to_text()),
+        Err(err) if json => {
+            println!("{}", serde_json::json!({ "error": format!("{err:#}") }));
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            process::exit(1);
+        }
+    }
+    process::exit(0)
+}
+
+/// Rolls back a configuration update on trial, the bridge failed with it.
+fn rollback_on_trial(config_path: Option<PathBuf>) {
+    if let Some(path) = config_path.filter(|path| update::is_on_trial(path)) {
+        if let Err(err) = update::rollback(&path) {
+            error!(
+                error = format!("{err:#}"),
+                "failed to roll back configuration update"
+            );
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
     if env::var("RUST_LOG").is_err() {
         let rust_log = "info,kbus_mqtt_bridge=info";
         // SAFETY: set_var is called during app initialization when no other
@@ -118,36 +437,80 @@ async fn main() -> Result<(), Box<dyn Error>> {
         unsafe { env::set_var("RUST_LOG", rust_log) };
     }
 
-    tracing_subscriber::fmt::init();
+    init_tracing();
 
     let args: Vec<String> = env::args().collect();
 
     if args.iter().any(|arg| arg == "-h" || arg == "--help") {
         print_help();
-        return Ok(());
+        return ExitCode::SUCCESS;
     }
 
     if args.iter().any(|arg| arg == "-v" || arg == "--version") {
-        println!("KBUS MQTT Bridge v{}", env!("CARGO_PKG_VERSION"));
-        return Ok(());
+        println!("{}", build_info::version_text());
+        return ExitCode::SUCCESS;
     }
 
+    let json = args.iter().any(|arg| arg == "--json");
+    let cleanup_retained = match Command::parse(&args[1..]) {
+        Ok(Some(Command::KBus(command))) => run_command(Ok(command), json),
+        Err(err) => run_command(Err(err), json),
+        // Clearing the retained topics needs the configuration of the broker
+        Ok(Some(Command::CleanupRetained)) => true,
+        Ok(None) => false,
+    };
+
     let config_path = args
         .iter()
         .position(|arg| arg == "-c" || arg == "--config")
         .and_then(|index| args.get(index + 1))
         .map(PathBuf::from);
 
-    let config = Config::load(config_path)?;
+    // A configuration update on trial which can't be loaded is rolled back, so the
+    // bridge starts with the previous configuration next time
+    let config_path = Config::file_path(config_path);
+    let profile = args
+        .iter()
+        .position(|arg| arg == "-p" || arg == "--profile")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+    let config = match Config::load(config_path.clone(), profile) {
+        Ok(config) => config,
+        Err(err) => {
+            error!(error = format!("{err:#}"), "invalid configuration");
+            rollback_on_trial(config_path);
+            return ExitCode::from(ShutdownReason::Config.exit_code());
+        }
+    };
     info!(?config);
 
-    // switch to RT Priority
-    configure_scheduler(SchedPolicy::Fifo, KBUS_MAINPRIO)
-        .context("failed to set scheduler priority")?;
+    if cleanup_retained {
+        return cleanup(&config, json).await;
+    }
 
-    if let Err(err) = app(config).await {
-        error!(error = format!("{err:#}"));
+    // switch to RT Priority
+    if let Err(err) = configure_scheduler(SchedPolicy::Fifo, KBUS_MAINPRIO) {
+        error!(
+            error = format!("{err:#}"),
+            "failed to set scheduler priority"
+        );
+        return ExitCode::from(ShutdownReason::Task.exit_code());
     }
 
-    Ok(())
+    let config_file = config.file.clone();
+    let reason = match app(config).await {
+        Ok(()) => shutdown::reason().unwrap_or(ShutdownReason::Signal),
+        Err(err) => {
+            error!(error = format!("{err:#}"));
+            // The bridge failed within the grace period of a configuration update
+            rollback_on_trial(config_file);
+            // A failure while shutting down on a signal is still a failure
+            shutdown::reason()
+                .filter(|reason| reason.is_failure())
+                .unwrap_or(ShutdownReason::Task)
+        }
+    };
+    info!(?reason, exit_code = reason.exit_code(), "shut down");
+
+    ExitCode::from(reason.exit_code())
 }
@@ -0,0 +1,122 @@
+//! System metrics collection
+//!
+//! The CPU and memory usage published on the heartbeat are sampled by a separate
+//! task into a [`watch`] channel. Refreshing reads `/proc` and runs on the blocking
+//! thread pool, so consumers like the heartbeat only copy the latest snapshot and
+//! never block the async runtime.
+
+use std::{sync::LazyLock, time::Duration};
+
+use anyhow::Context;
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use tokio::{
+    sync::watch,
+    task::spawn_blocking,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument};
+
+use crate::{
+    config::SupervisorConfig,
+    shutdown::{self, ShutdownReason},
+    supervisor,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Latest sample, all zero until the metrics task sampled the system
+static METRICS: LazyLock<watch::Sender<SystemMetrics>> =
+    LazyLock::new(|| watch::Sender::new(SystemMetrics::default()));
+
+/// Returns a receiver of the latest system metrics.
+pub fn subscribe() -> watch::Receiver<SystemMetrics> {
+    METRICS.subscribe()
+}
+
+/// Latest system usage sampled by the metrics task.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemMetrics {
+    /// Used memory in percent of the total memory
+    pub memory_usage: f32,
+    /// Global CPU usage in percent since the previous sample
+    pub cpu_usage: f32,
+}
+
+fn refresh_kind() -> RefreshKind {
+    RefreshKind::nothing()
+        .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+        .with_memory(MemoryRefreshKind::nothing().with_ram())
+}
+
+/// Refreshes `system` and returns the usage since the previous refresh.
+fn sample(system: &mut System) -> SystemMetrics {
+    system.refresh_specifics(refresh_kind());
+
+    let total_memory = system.total_memory() as f32;
+    let used_memory = system.used_memory() as f32;
+    let memory_usage = if total_memory > 0.0 {
+        (used_memory / total_memory) * 100.0
+    } else {
+        0.0
+    };
+
+    SystemMetrics {
+        memory_usage,
+        cpu_usage: system.global_cpu_usage(),
+    }
+}
+
+async fn metrics_loop(
+    sample_interval: Duration,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let mut system = System::new_with_specifics(refresh_kind());
+    let mut timer = interval(sample_interval);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {
+                let (refreshed, sampled) = spawn_blocking(move || {
+                    let sampled = sample(&mut system);
+                    (system, sampled)
+                })
+                .await
+                .context("failed to join system metrics sampling")?;
+                system = refreshed;
+                // Stored even without receivers, for consumers subscribing later
+                METRICS.send_replace(sampled);
+            }
+            _ = cancellation_token.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Entry point task function for sampling the system metrics.
+///
+/// # Arguments
+///
+/// * `sample_interval` - Interval between samples, usually the heartbeat interval
+/// * `supervisor_config` - Restarting of the task after a failure
+/// * `cancellation_token` - Token to signal when this task should terminate
+#[instrument(name = "metrics", skip_all, err)]
+pub async fn metrics_task(
+    sample_interval: Duration,
+    supervisor_config: SupervisorConfig,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    info!(?sample_interval, "starting system metrics task");
+    let result = supervisor::supervise("metrics", &supervisor_config, &cancellation_token, || {
+        metrics_loop(sample_interval, cancellation_token.clone())
+    })
+    .await;
+
+    if result.is_err() {
+        shutdown::initiate(ShutdownReason::Task);
+    }
+    cancellation_token.cancel();
+
+    result
+}
@@ -0,0 +1,28 @@
+use super::*;
+
+#[test]
+fn test_sample() {
+    let mut system = System::new_with_specifics(refresh_kind());
+    let metrics = sample(&mut system);
+    assert!((0.0..=100.0).contains(&metrics.memory_usage), "{metrics:?}");
+    assert!(metrics.memory_usage > 0.0, "{metrics:?}");
+    assert!((0.0..=100.0).contains(&metrics.cpu_usage), "{metrics:?}");
+}
+
+#[tokio::test]
+async fn test_metrics_task() {
+    let mut metrics_rx = subscribe();
+    let cancellation_token = CancellationToken::new();
+    let task_handle = tokio::spawn(metrics_task(
+        Duration::from_secs(1),
+        SupervisorConfig::default(),
+        cancellation_token.clone(),
+    ));
+
+    // The first sample is taken right away
+    metrics_rx.changed().await.unwrap();
+    assert!(metrics_rx.borrow().memory_usage > 0.0);
+
+    cancellation_token.cancel();
+    task_handle.await.unwrap().unwrap();
+}
@@ -0,0 +1,368 @@
+//! Modbus RTU master extending the I/O over the serial port of the PFC
+//!
+//! Discrete inputs and input registers of the configured slave devices are polled
+//! periodically and changes are published next to the K-Bus channels, coils and
+//! holding registers are written on commands received over MQTT. A device that
+//! doesn't respond is reported once and polled again in the next interval.
+//! Optionally, aggregates of the input registers are published once per window
+//! (see [`aggregate`]).
+
+use std::{fmt, future::Future, time::Duration};
+
+use anyhow::{Context as _, anyhow};
+use serde::Serialize;
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    time::{self, Instant, MissedTickBehavior, interval, interval_at},
+};
+use tokio_modbus::{
+    client::{Context, rtu},
+    prelude::*,
+};
+use tokio_serial::{SerialStream, StopBits};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    config::{ModbusConfig, ModbusDeviceConfig, Parity},
+    kbus::InputEvent,
+    shutdown::{self, ShutdownReason},
+    supervisor,
+    throttle::warn_throttled,
+};
+
+#[cfg(test)]
+mod tests;
+
+pub mod aggregate;
+
+use aggregate::{Aggregate, RegisterAggregator};
+
+/// Value of a Modbus coil, discrete input or register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ModbusValue {
+    /// Coil or discrete input
+    Bit(bool),
+    /// Input or holding register
+    Register(u16),
+}
+
+impl fmt::Display for ModbusValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModbusValue::Bit(value) => value.fmt(f),
+            ModbusValue::Register(value) => value.fmt(f),
+        }
+    }
+}
+
+/// A discrete input or input register of a Modbus device changed its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModbusEvent {
+    /// Name of the device
+    pub device: String,
+    /// Data address of the input or register
+    pub address: u16,
+    pub value: ModbusValue,
+}
+
+impl ModbusEvent {
+    /// Topic level of the value kind, `input` or `register`.
+    pub const fn kind(&self) -> &'static str {
+        match self.value {
+            ModbusValue::Bit(_) => "input",
+            ModbusValue::Register(_) => "register",
+        }
+    }
+}
+
+/// Aggregate of an input register of a Modbus device over the aggregate window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModbusAggregate {
+    /// Name of the device
+    pub device: String,
+    /// Data address of the register
+    pub address: u16,
+    pub aggregate: Aggregate,
+}
+
+/// Write of a coil ([`ModbusValue::Bit`]) or holding register ([`ModbusValue::Register`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusCommand {
+    /// Index of the device in the configuration
+    pub device: usize,
+    pub address: u16,
+    pub value: ModbusValue,
+}
+
+/// Last values read from a device, used to publish only changes.
+#[derive(Debug)]
+struct DeviceState {
+    config: ModbusDeviceConfig,
+    inputs: Option<Vec<bool>>,
+    registers: Option<Vec<u16>>,
+    /// Input registers polled in the current aggregate window
+    aggregator: RegisterAggregator,
+    /// Whether the last poll of the device failed
+    failed: bool,
+}
+
+impl DeviceState {
+    fn new(config: ModbusDeviceConfig) -> DeviceState {
+        DeviceState {
+            config,
+            inputs: None,
+            registers: None,
+            aggregator: RegisterAggregator::default(),
+            failed: false,
+        }
+    }
+
+    /// Stores the discrete inputs read from the device and returns the changed ones.
+    ///
+    /// All inputs are reported after the first read.
+    fn update_inputs(&mut self, first: u16, inputs: Vec<bool>) -> Vec<ModbusEvent> {
+        let events = changes(first, self.inputs.as_deref(), &inputs)
+            .map(|(address, value)| (address, ModbusValue::Bit(value)));
+        let events = self.events(events);
+        self.inputs = Some(inputs);
+        events
+    }
+
+    /// Stores the input registers read from the device and returns the changed ones.
+    fn update_registers(&mut self, first: u16, registers: Vec<u16>) -> Vec<ModbusEvent> {
+        self.aggregator.sample(first, &registers);
+        let events = changes(first, self.registers.as_deref(), &registers)
+            .map(|(address, value)| (address, ModbusValue::Register(value)));
+        let events = self.events(events);
+        self.registers = Some(registers);
+        events
+    }
+
+    /// Returns the aggregates of the input registers and starts a new window.
+    fn take_aggregates(&mut self) -> Vec<ModbusAggregate> {
+        self.aggregator
+            .take()
+            .into_iter()
+            .map(|(address, aggregate)| ModbusAggregate {
+                device: self.config.name.clone(),
+                address,
+                aggregate,
+            })
+            .collect()
+    }
+
+    fn events(&self, changes: impl Iterator<Item = (u16, ModbusValue)>) -> Vec<ModbusEvent> {
+        changes
+            .map(|(address, value)| ModbusEvent {
+                device: self.config.name.clone(),
+                address,
+                value,
+            })
+            .collect()
+    }
+}
+
+/// Returns the addresses and values in `current` that differ from `previous`.
+fn changes<'a, T: Copy + PartialEq>(
+    first: u16,
+    previous: Option<&'a [T]>,
+    current: &'a [T],
+) -> impl Iterator<Item = (u16, T)> + 'a {
+    current
+        .iter()
+        .enumerate()
+        .filter(move |(i, value)| previous.and_then(|previous| previous.get(*i)) != Some(value))
+        .map(move |(i, value)| (first + i as u16, *value))
+}
+
+/// Waits for a Modbus request, failing on timeouts, transport errors and exceptions.
+async fn request<T>(
+    timeout: Duration,
+    request: impl Future<Output = tokio_modbus::Result<T>>,
+) -> Result<T, anyhow::Error> {
+    Ok(time::timeout(timeout, request)
+        .await
+        .map_err(|_| anyhow!("no response"))???)
+}
+
+/// Reads the inputs and registers of a device and returns the changed values.
+async fn poll_device(
+    ctx: &mut Context,
+    device: &mut DeviceState,
+    timeout: Duration,
+) -> Result<Vec<ModbusEvent>, anyhow::Error> {
+    ctx.set_slave(Slave(device.config.slave));
+
+    let mut events = Vec::new();
+    if let Some(range) = device.config.discrete_inputs {
+        let inputs = request(
+            timeout,
+            ctx.read_discrete_inputs(range.address, range.count),
+        )
+        .await
+        .context("failed to read discrete inputs")?;
+        events.extend(device.update_inputs(range.address, inputs));
+    }
+    if let Some(range) = device.config.input_registers {
+        let registers = request(
+            timeout,
+            ctx.read_input_registers(range.address, range.count),
+        )
+        .await
+        .context("failed to read input registers")?;
+        events.extend(device.update_registers(range.address, registers));
+    }
+    Ok(events)
+}
+
+async fn write(
+    ctx: &mut Context,
+    device: &ModbusDeviceConfig,
+    command: ModbusCommand,
+    timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    ctx.set_slave(Slave(device.slave));
+    match command.value {
+        ModbusValue::Bit(value) => request(timeout, ctx.write_single_coil(command.address, value))
+            .await
+            .context("failed to write coil"),
+        ModbusValue::Register(value) => {
+            request(timeout, ctx.write_single_register(command.address, value))
+                .await
+                .context("failed to write holding register")
+        }
+    }
+}
+
+fn open(config: &ModbusConfig) -> Result<Context, anyhow::Error> {
+    let parity = match config.parity {
+        Parity::None => tokio_serial::Parity::None,
+        Parity::Odd => tokio_serial::Parity::Odd,
+        Parity::Even => tokio_serial::Parity::Even,
+    };
+    let stop_bits = if config.stop_bits == 2 {
+        StopBits::Two
+    } else {
+        StopBits::One
+    };
+    let builder = tokio_serial::new(&config.port, config.baud_rate)
+        .parity(parity)
+        .stop_bits(stop_bits);
+    let port = SerialStream::open(&builder)
+        .with_context(|| format!("failed to open serial port {}", config.port))?;
+    Ok(rtu::attach(port))
+}
+
+/// Waits for the next tick of an optional timer, never completes if there's no timer.
+async fn tick(timer: &mut Option<time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn modbus_loop(
+    config: ModbusConfig,
+    input_tx: UnboundedSender<InputEvent>,
+    mut commands: UnboundedReceiver<ModbusCommand>,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let mut ctx = open(&config)?;
+    info!(
+        port = config.port,
+        devices = config.devices.len(),
+        "Starting Modbus task"
+    );
+
+    let mut devices: Vec<_> = config.devices.into_iter().map(DeviceState::new).collect();
+    let mut poll_timer = interval(config.poll_interval);
+    poll_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let window = config.aggregate_window;
+    let mut aggregate_timer =
+        (!window.is_zero()).then(|| interval_at(Instant::now() + window, window));
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+            _ = poll_timer.tick() => {
+                for device in &mut devices {
+                    let name = device.config.name.clone();
+                    match poll_device(&mut ctx, device, config.timeout).await {
+                        Ok(events) => {
+                            if device.failed {
+                                info!(device = name, "Modbus device responding again");
+                                device.failed = false;
+                            }
+                            for event in events {
+                                input_tx
+                                    .send(InputEvent::Modbus(event))
+                                    .context("input event queue closed")?;
+                            }
+                        }
+                        Err(err) => {
+                            if !device.failed {
+                                warn!(device = name, error = format!("{err:#}"), "Modbus device failed");
+                                device.failed = true;
+                            }
+                        }
+                    }
+                }
+            }
+            _ = tick(&mut aggregate_timer) => {
+                for device in &mut devices {
+                    for aggregate in device.take_aggregates() {
+                        input_tx
+                            .send(InputEvent::ModbusAggregate(aggregate))
+                            .context("input event queue closed")?;
+                    }
+                }
+            }
+            Some(command) = commands.recv() => {
+                let device = &devices[command.device].config;
+                if let Err(err) = write(&mut ctx, device, command, config.timeout).await {
+                    warn_throttled!(
+                        &format!("modbus_write/{}", device.name),
+                        device = device.name,
+                        ?command,
+                        error = format!("{err:#}"),
+                        "Modbus write failed"
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Polls the configured devices and publishes their changes as input events, and
+/// executes coil and holding register writes, until cancelled.
+///
+/// # Arguments
+///
+/// * `config` - Validated Modbus configuration
+/// * `input_tx` - Channel for sending input events to the MQTT task
+/// * `commands` - Channel for receiving write commands from the MQTT task
+/// * `cancellation_token` - Token to signal when this task should terminate
+#[instrument(name = "modbus", skip_all, err)]
+pub async fn modbus_task(
+    config: ModbusConfig,
+    input_tx: UnboundedSender<InputEvent>,
+    commands: UnboundedReceiver<ModbusCommand>,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    supervisor::started("modbus");
+    let result = modbus_loop(config, input_tx, commands, cancellation_token.clone()).await;
+    supervisor::finished("modbus", &result);
+
+    if result.is_err() {
+        shutdown::initiate(ShutdownReason::Task);
+    }
+    cancellation_token.cancel();
+
+    result
+}
@@ -0,0 +1,77 @@
+//! Aggregation of input registers over a window
+//!
+//! Historians which don't need every change can use the min, max and mean of each
+//! input register over `aggregate_window` instead. They are computed from every
+//! polled value, not only the published changes, and published once per window.
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+/// Min, max and mean of an input register over one window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Aggregate {
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    /// Number of polled values
+    pub samples: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    min: u16,
+    max: u16,
+    sum: u64,
+    samples: u64,
+}
+
+/// Polled values of the input registers of a device in the current window.
+#[derive(Debug, Default)]
+pub struct RegisterAggregator {
+    first: u16,
+    registers: Vec<Option<Accumulator>>,
+}
+
+impl RegisterAggregator {
+    /// Adds the input registers read starting at address `first`.
+    pub fn sample(&mut self, first: u16, registers: &[u16]) {
+        self.first = first;
+        self.registers.resize(registers.len(), None);
+        for (accumulator, &value) in self.registers.iter_mut().zip(registers) {
+            let accumulator = accumulator.get_or_insert(Accumulator {
+                min: value,
+                max: value,
+                sum: 0,
+                samples: 0,
+            });
+            accumulator.min = accumulator.min.min(value);
+            accumulator.max = accumulator.max.max(value);
+            accumulator.sum += u64::from(value);
+            accumulator.samples += 1;
+        }
+    }
+
+    /// Returns the aggregates of the current window by address and starts a new one.
+    ///
+    /// Registers without any value in the window, e.g. because the device didn't
+    /// respond, are skipped.
+    pub fn take(&mut self) -> Vec<(u16, Aggregate)> {
+        let first = self.first;
+        self.registers
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, accumulator)| {
+                let accumulator = accumulator.take()?;
+                let aggregate = Aggregate {
+                    min: accumulator.min,
+                    max: accumulator.max,
+                    mean: accumulator.sum as f64 / accumulator.samples as f64,
+                    samples: accumulator.samples,
+                };
+                Some((first + i as u16, aggregate))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,41 @@
+use super::*;
+
+#[test]
+fn test_aggregate() {
+    let mut aggregator = RegisterAggregator::default();
+    assert!(aggregator.take().is_empty());
+
+    aggregator.sample(10, &[230, 50]);
+    aggregator.sample(10, &[232, 50]);
+    aggregator.sample(10, &[225, 51]);
+    assert_eq!(
+        aggregator.take(),
+        vec![
+            (
+                10,
+                Aggregate {
+                    min: 225,
+                    max: 232,
+                    mean: 229.0,
+                    samples: 3,
+                }
+            ),
+            (
+                11,
+                Aggregate {
+                    min: 50,
+                    max: 51,
+                    mean: 151.0 / 3.0,
+                    samples: 3,
+                }
+            ),
+        ]
+    );
+
+    // A new window starts, without values until the next poll
+    assert!(aggregator.take().is_empty());
+    aggregator.sample(10, &[240, 49]);
+    let aggregates = aggregator.take();
+    assert_eq!(aggregates[0].1.min, 240);
+    assert_eq!(aggregates[0].1.samples, 1);
+}
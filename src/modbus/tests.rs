@@ -0,0 +1,60 @@
+use super::*;
+
+fn device() -> DeviceState {
+    DeviceState::new(ModbusDeviceConfig {
+        name: "meter".to_owned(),
+        slave: 1,
+        discrete_inputs: None,
+        input_registers: None,
+        coils: None,
+        holding_registers: None,
+    })
+}
+
+#[test]
+fn test_update_inputs() {
+    let mut device = device();
+
+    // All inputs are reported after the first read
+    let events = device.update_inputs(10, vec![false, true]);
+    assert_eq!(
+        events,
+        vec![
+            ModbusEvent {
+                device: "meter".to_owned(),
+                address: 10,
+                value: ModbusValue::Bit(false),
+            },
+            ModbusEvent {
+                device: "meter".to_owned(),
+                address: 11,
+                value: ModbusValue::Bit(true),
+            },
+        ]
+    );
+
+    assert!(device.update_inputs(10, vec![false, true]).is_empty());
+
+    let events = device.update_inputs(10, vec![true, true]);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].address, 10);
+    assert_eq!(events[0].kind(), "input");
+}
+
+#[test]
+fn test_update_registers() {
+    let mut device = device();
+    assert_eq!(device.update_registers(0, vec![230, 50]).len(), 2);
+
+    let events = device.update_registers(0, vec![231, 50]);
+    assert_eq!(
+        events,
+        vec![ModbusEvent {
+            device: "meter".to_owned(),
+            address: 0,
+            value: ModbusValue::Register(231),
+        }]
+    );
+    assert_eq!(events[0].kind(), "register");
+    assert_eq!(events[0].value.to_string(), "231");
+}
@@ -1,238 +1,309 @@
+//! MQTT side of the bridge
+//!
+//! [`mqtt_client_task`] connects to the broker and runs the tasks of the connection:
+//! the event loop in `client`, the publish pipeline in `publisher` and the
+//! heartbeat with the counters of `stats`. Topics of received messages are
+//! resolved by the `router`.
+
 use std::{
-    str::from_utf8,
-    sync::{
-        LazyLock, Mutex,
-        atomic::{AtomicU64, Ordering},
-    },
-    time::{Duration, Instant},
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
 };
 
-use anyhow::{Context, anyhow};
-use chrono::Utc;
-use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, Publish, QoS};
+use anyhow::Context;
+use rumqttc::{AsyncClient, MqttOptions, Proxy, ProxyAuth, ProxyType, QoS, SubscribeFilter};
 use serde_json::json;
-use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    sync::mpsc::{UnboundedReceiver, unbounded_channel},
     time::interval,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{info, instrument, trace, warn};
-
-use crate::kbus::KBusEvent;
-
-static SYSTEM: LazyLock<Mutex<System>> = LazyLock::new(|| {
-    let refresh_kind = RefreshKind::nothing()
-        .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
-        .with_memory(MemoryRefreshKind::nothing().with_ram());
-
-    let sys = System::new_with_specifics(refresh_kind);
-
-    Mutex::new(sys)
-});
-static APP_START_TIME: LazyLock<Instant> = LazyLock::new(Instant::now);
-static MQTT_MESSAGES_SENT: AtomicU64 = AtomicU64::new(0);
-static MQTT_MESSAGES_RECEIVED: AtomicU64 = AtomicU64::new(0);
-static MQTT_MESSAGES_PROCESSED: AtomicU64 = AtomicU64::new(0);
-static MQTT_MESSAGES_REJECTED: AtomicU64 = AtomicU64::new(0);
-
-fn heartbeat() -> serde_json::Value {
-    let app_uptime = APP_START_TIME.elapsed().as_secs();
-
-    let mqtt_sent = MQTT_MESSAGES_SENT.load(Ordering::Relaxed);
-    let mqtt_received = MQTT_MESSAGES_RECEIVED.load(Ordering::Relaxed);
-    let mqtt_processed = MQTT_MESSAGES_PROCESSED.load(Ordering::Relaxed);
-    let mqtt_rejected = MQTT_MESSAGES_REJECTED.load(Ordering::Relaxed);
+use tracing::{debug, info, instrument, warn};
 
-    let mut system = SYSTEM.lock().unwrap();
-
-    system.refresh_specifics(
-        RefreshKind::nothing()
-            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
-            .with_memory(MemoryRefreshKind::nothing().with_ram()),
-    );
+use crate::{
+    build_info,
+    config::{
+        AlertsConfig, Config, GroupedChannel, IdentitySource, SelfUpdateConfig, TopicProfile,
+    },
+    identity::Identity,
+    kbus::{InputEvent, errors::DAL_ERRORS},
+    metrics,
+    shutdown::{self, ShutdownReason},
+    state, supervisor, timestamp,
+};
 
-    let total_memory = system.total_memory() as f32;
-    let used_memory = system.used_memory() as f32;
-    let memory_percentage = if total_memory > 0.0 {
-        (used_memory / total_memory) * 100.0
-    } else {
-        0.0
-    };
+#[cfg(feature = "self-update")]
+use crate::self_update;
+
+mod aggregator;
+mod alarms;
+mod alerts;
+mod channel_stats;
+mod claim;
+mod cleanup;
+mod client;
+mod coalesce;
+mod handshake;
+mod payloads;
+mod publisher;
+mod rejections;
+mod router;
+mod signature;
+mod startup;
+mod stats;
+mod tasmota;
+mod transform;
+
+use aggregator::{Aggregator, Forward};
+use alarms::Annunciator;
+use alerts::AlertMonitor;
+use tasmota::Tasmota;
+use transform::Transform;
+
+pub use cleanup::cleanup_retained;
+pub use client::{CommandQueues, publish_last_error};
+use client::{MqttEventLoop, modbus_subscriptions, mqtt_event_loop, share_prefix};
+use publisher::{InputFormat, MqttPublisher, mqtt_publish_loop, publish_on_shutdown};
+use stats::{APP_START_TIME, MQTT_MESSAGES_SENT, heartbeat, sample_usage, telemetry};
+pub use stats::{MqttStats, input_queue_depth};
+
+#[cfg(test)]
+mod tests;
+
+/// Returns the options of a connection to the broker with `client_id`, without a
+/// last will.
+pub fn mqtt_options(config: &Config, client_id: String) -> MqttOptions {
+    let (broker_host, broker_port) = config.broker_address();
+    let mut mqtt_options = MqttOptions::new(client_id, broker_host, broker_port);
+    mqtt_options.set_keep_alive(config.mqtt.keepalive);
+
+    if let (Some(username), Some(password)) = (&config.mqtt.username, &config.mqtt.password) {
+        mqtt_options.set_credentials(username, password);
+    }
 
-    json!({
-        "timestamp": Utc::now().to_rfc3339(),
-        "app_uptime": app_uptime,
-        "system_uptime": System::uptime(),
-        "cpu_usage": system.global_cpu_usage(),
-        "memory_usage": memory_percentage,
-        "mqtt_stats": {
-            "sent": mqtt_sent,
-            "received": mqtt_received,
-            "processed": mqtt_processed,
-            "rejected": mqtt_rejected,
-            "total": mqtt_received + mqtt_sent
-        },
-    })
+    if let Some(proxy) = &config.mqtt.proxy {
+        let auth = match (&proxy.username, &proxy.password) {
+            (Some(username), Some(password)) => ProxyAuth::Basic {
+                username: username.clone(),
+                password: password.clone(),
+            },
+            _ => ProxyAuth::None,
+        };
+        mqtt_options.set_proxy(Proxy {
+            ty: ProxyType::Http,
+            auth,
+            addr: proxy.host.clone(),
+            port: proxy.port,
+        });
+    }
+    mqtt_options
 }
 
-const fn decode_value(payload: &[u8]) -> Option<bool> {
-    match payload {
-        b"true" | b"on" | b"ON" | b"\x01" => Some(true),
-        b"false" | b"off" | b"OFF" | b"\x00" => Some(false),
-        _ => None,
+/// Static device information, published retained on startup.
+///
+/// Grouped channels are listed with their topic levels by channel number, so
+/// consumers can map the numbered and grouped topics.
+fn metadata(config: &Config, identity: &Identity) -> serde_json::Value {
+    let mut metadata = json!({
+        "device_name": config.device_name,
+        "id": identity.id,
+        "identity_source": identity.source,
+        "version": build_info::VERSION,
+    });
+    if identity.source == IdentitySource::Mac {
+        metadata["mac"] = json!(identity.id);
+    }
+    let groups = |groups: &[GroupedChannel]| {
+        groups
+            .iter()
+            .map(|grouped| (grouped.channel.to_string(), json!(grouped.path())))
+            .collect::<serde_json::Map<_, _>>()
+    };
+    if !(config.inputs.groups.is_empty() && config.outputs.groups.is_empty()) {
+        metadata["groups"] = json!({
+            "input": groups(&config.inputs.groups),
+            "output": groups(&config.outputs.groups),
+        });
     }
+    metadata
 }
 
-enum DecodedTopic {
-    KBusOutput { channel: u16 },
+/// Returns the Tasmota topics of the device, if enabled.
+fn tasmota_topics(config: &Config) -> Option<Tasmota> {
+    (config.mqtt.topic_profile == TopicProfile::Tasmota)
+        .then(|| Tasmota::new(&config.device_name).with_root(config.topic_root.as_deref()))
 }
 
-struct MqttEventLoop {
-    event_loop: EventLoop,
-    topic_prefix: String,
-    kbus_output: UnboundedSender<KBusEvent>,
-}
+/// Republishes messages of other bridges in the merged namespace of the aggregator.
+///
+/// Messages are published in order of arrival from a single loop, so the latest
+/// state of every topic wins.
+#[instrument(name = "aggregator", skip_all, err)]
+async fn mqtt_aggregator_loop(
+    mqtt_publisher: &MqttPublisher,
+    forwards: Option<&mut UnboundedReceiver<Forward>>,
+) -> Result<(), anyhow::Error> {
+    let Some(forwards) = forwards else {
+        return std::future::pending().await;
+    };
 
-impl MqttEventLoop {
-    fn new(
-        event_loop: EventLoop,
-        topic_prefix: String,
-        kbus_output: UnboundedSender<KBusEvent>,
-    ) -> MqttEventLoop {
-        MqttEventLoop {
-            event_loop,
-            topic_prefix,
-            kbus_output,
-        }
+    while let Some(forward) = forwards.recv().await {
+        debug!(topic = forward.topic, retain = forward.retain, "forwarding");
+        mqtt_publisher
+            .client
+            .publish(
+                forward.topic,
+                QoS::AtLeastOnce,
+                forward.retain,
+                forward.payload,
+            )
+            .await?;
+        MQTT_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
     }
+    Ok(())
+}
 
-    fn decode_topic(&self, topic: &str) -> Option<DecodedTopic> {
-        let topic = topic.strip_prefix(&self.topic_prefix)?;
-        if let Some(maybe_channel) = topic.strip_prefix("/output/") {
-            let channel = maybe_channel.parse().ok()?;
-            Some(DecodedTopic::KBusOutput { channel })
-        } else {
-            None
-        }
-    }
+/// Installs the self-updates requested on `bridge/update`.
+///
+/// The result is published on `update/result`. Returns after a successful update,
+/// which shuts down the bridge, so it's restarted with the new binary.
+#[cfg(feature = "self-update")]
+#[instrument(name = "update", skip_all, err)]
+async fn mqtt_self_update_loop(
+    mqtt_publisher: &MqttPublisher,
+    config: Option<&SelfUpdateConfig>,
+    requests: &mut UnboundedReceiver<()>,
+) -> Result<(), anyhow::Error> {
+    let Some(config) = config else {
+        return std::future::pending().await;
+    };
 
-    fn on_mqtt_message(&mut self, topic: &str, payload: &[u8]) -> Result<(), anyhow::Error> {
-        match self.decode_topic(topic) {
-            Some(DecodedTopic::KBusOutput { channel }) => {
-                if let Some(value) = decode_value(payload) {
-                    if let Ok(payload) = from_utf8(payload) {
-                        info!(topic, payload);
-                    } else {
-                        info!(topic, ?payload);
-                    }
-                    let event = KBusEvent { channel, value };
-                    self.kbus_output
-                        .send(event)
-                        .context("K-Bus output queue closed")?;
-                    Ok(())
-                } else {
-                    Err(anyhow!("invalid payload"))
-                }
-            }
-            None => {
-                // This should never happen, but even if it does,
-                // we can safely ignore it
-                Err(anyhow!("unknown topic"))
+    while requests.recv().await.is_some() {
+        let result = self_update::update(config.clone()).await;
+        let payload = match &result {
+            Ok(()) => json!({ "status": "installed", "timestamp": timestamp::now() }),
+            Err(err) => json!({
+                "status": "failed",
+                "error": format!("{err:#}"),
+                "timestamp": timestamp::now(),
+            }),
+        };
+        mqtt_publisher
+            .publish(
+                "update/result",
+                QoS::AtLeastOnce,
+                false,
+                payload.to_string(),
+            )
+            .await?;
+        match result {
+            Ok(()) => {
+                info!("self-update installed, restarting");
+                shutdown::initiate(ShutdownReason::Restart);
+                return Ok(());
             }
+            Err(err) => warn!(error = format!("{err:#}"), "self-update failed"),
         }
     }
+    Ok(())
+}
 
-    async fn poll(&mut self) -> Result<Event, anyhow::Error> {
-        self.event_loop
-            .poll()
-            .await
-            .context("failed to poll MQTT event loop")
-    }
+#[cfg(not(feature = "self-update"))]
+async fn mqtt_self_update_loop(
+    _mqtt_publisher: &MqttPublisher,
+    _config: Option<&SelfUpdateConfig>,
+    _requests: &mut UnboundedReceiver<()>,
+) -> Result<(), anyhow::Error> {
+    std::future::pending().await
 }
 
-#[instrument(name = "sub", skip_all, err)]
-async fn mqtt_event_loop(event_loop: &mut MqttEventLoop) -> Result<(), anyhow::Error> {
-    loop {
-        let notification = event_loop.poll().await?;
-        trace!(?notification);
-        match notification {
-            Event::Incoming(Packet::Publish(Publish { topic, payload, .. })) => {
-                MQTT_MESSAGES_RECEIVED.fetch_add(1, Ordering::Relaxed);
-
-                if let Err(err) = event_loop.on_mqtt_message(&topic, &payload) {
-                    if let Ok(payload) = from_utf8(&payload) {
-                        warn!(message_rejected = format!("{err:#}"), topic, payload);
-                    } else {
-                        warn!(message_rejected = format!("{err:#}"), topic, ?payload);
-                    }
-                    MQTT_MESSAGES_REJECTED.fetch_add(1, Ordering::Relaxed);
-                } else {
-                    MQTT_MESSAGES_PROCESSED.fetch_add(1, Ordering::Relaxed);
-                }
-            }
-            Event::Incoming(_) | Event::Outgoing(_) => {}
-        }
+/// Clears the retained topics under a previous topic prefix and of previous Tasmota
+/// topics, recorded in the state file.
+///
+/// All retained topics the broker has under them are cleared, like with
+/// `cleanup-retained`, on a connection of its own without a last will. Topics of
+/// the current prefix are kept.
+async fn clear_previous_topics(
+    config: &Config,
+    topic_prefix: &str,
+    tasmota_filters: &[String],
+) -> Result<(), anyhow::Error> {
+    let mut filters: Vec<String> = state::previous_topic_prefix(topic_prefix)
+        .map(|previous| format!("{previous}/#"))
+        .into_iter()
+        .collect();
+    filters.extend(state::previous_tasmota_filters(tasmota_filters));
+    if filters.is_empty() {
+        return Ok(());
+    }
+    if !config.state.clear_previous_prefix {
+        info!(?filters, "topic prefix changed, retained topics kept");
+        return Ok(());
     }
-}
 
-struct MqttPublisher {
-    client: AsyncClient,
-    topic_prefix: String,
+    info!(
+        ?filters,
+        "clearing retained topics of the previous topic prefix"
+    );
+    let mqtt_options = mqtt_options(config, format!("{}-cleanup", config.device_name));
+    let topics = cleanup::clear_retained(mqtt_options, &filters, Some(topic_prefix)).await?;
+    info!(
+        cleared = topics.len(),
+        "retained topics of the previous topic prefix cleared"
+    );
+    Ok(())
 }
 
-impl MqttPublisher {
-    fn new(client: AsyncClient, topic_prefix: String) -> MqttPublisher {
-        MqttPublisher {
-            client,
-            topic_prefix,
-        }
+/// Publishes the lightweight retained `ping` with the current timestamp, so
+/// liveness checks don't need to parse the heartbeat.
+async fn mqtt_ping_loop(
+    mqtt_publisher: &MqttPublisher,
+    ping_interval: Duration,
+) -> Result<(), anyhow::Error> {
+    if ping_interval.is_zero() {
+        return std::future::pending().await;
     }
 
-    async fn publish(
-        &self,
-        topic: &str,
-        qos: QoS,
-        retain: bool,
-        payload: String,
-    ) -> Result<(), anyhow::Error> {
-        let topic_prefix = &self.topic_prefix;
-        let topic = if topic_prefix.is_empty() {
-            topic.to_owned()
-        } else {
-            format!("{topic_prefix}/{topic}")
-        };
-
-        info!(topic, payload);
-        self.client.publish(topic, qos, retain, payload).await?;
-
-        MQTT_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
-
-        Ok(())
+    info!("Ping enabled with interval {:?}", ping_interval);
+    let mut ping_timer = interval(ping_interval);
+    loop {
+        ping_timer.tick().await;
+        mqtt_publisher
+            .publish_quiet("ping", QoS::AtLeastOnce, true, timestamp::now_text())
+            .await?;
     }
 }
 
-#[instrument(name = "pub", skip_all, err)]
-async fn mqtt_publish_loop(
+/// Publishes the system statistics on `telemetry/system`, less often than the
+/// liveness ping and without the MQTT counters of the heartbeat.
+async fn mqtt_telemetry_loop(
     mqtt_publisher: &MqttPublisher,
-    mut input_events: UnboundedReceiver<KBusEvent>,
+    telemetry_interval: Duration,
 ) -> Result<(), anyhow::Error> {
-    info!("Starting MQTT publish task");
-
-    while let Some(event) = input_events.recv().await {
-        let topic = format!("input/{}", event.channel);
-        let payload = event.value.to_string();
-        mqtt_publisher
-            .publish(&topic, QoS::AtLeastOnce, false, payload)
-            .await?;
+    if telemetry_interval.is_zero() {
+        return std::future::pending().await;
     }
 
-    Ok(())
+    info!("Telemetry enabled with interval {:?}", telemetry_interval);
+    let mut telemetry_timer = interval(telemetry_interval);
+    let system_metrics = metrics::subscribe();
+    loop {
+        telemetry_timer.tick().await;
+        let usage = sample_usage(&system_metrics.borrow());
+        mqtt_publisher.publish_background(
+            "telemetry/system",
+            QoS::AtLeastOnce,
+            false,
+            telemetry(&usage).to_string(),
+        )?;
+    }
 }
 
 async fn mqtt_heartbeat_loop(
     mqtt_publisher: &MqttPublisher,
     heartbeat_interval: Duration,
+    alerts_config: &AlertsConfig,
+    tasmota: Option<&Tasmota>,
 ) -> Result<(), anyhow::Error> {
     // Only create heartbeat timer if interval is not zero
     if heartbeat_interval.is_zero() {
@@ -242,80 +313,297 @@ async fn mqtt_heartbeat_loop(
 
     info!("Heartbeat enabled with interval {:?}", heartbeat_interval);
     let mut heartbeat_timer = interval(heartbeat_interval);
+    let system_metrics = metrics::subscribe();
+    let mut alerts = AlertMonitor::new(alerts_config);
+    if !alerts.is_empty() {
+        info!(?alerts_config, "Heartbeat alerts enabled");
+    }
+    let mut kbus_errors = DAL_ERRORS.total();
 
     loop {
         heartbeat_timer.tick().await;
-        mqtt_publisher
-            .publish(
-                "heartbeat",
+        let usage = sample_usage(&system_metrics.borrow());
+        mqtt_publisher.publish_background(
+            "heartbeat",
+            QoS::AtLeastOnce,
+            false,
+            heartbeat(&usage).to_string(),
+        )?;
+        if let Some(tasmota) = tasmota {
+            mqtt_publisher.publish_background_to(
+                tasmota.state_topic(),
                 QoS::AtLeastOnce,
                 false,
-                heartbeat().to_string(),
-            )
-            .await?;
+                tasmota::state(APP_START_TIME.elapsed()).to_string(),
+            )?;
+        }
+
+        for alert in alerts.update(&usage) {
+            warn!(?alert, "heartbeat alert");
+            let mut payload = json!(alert);
+            payload["timestamp"] = timestamp::now();
+            mqtt_publisher.publish_background(
+                "alert",
+                QoS::AtLeastOnce,
+                false,
+                payload.to_string(),
+            )?;
+        }
+
+        // Failed DAL calls since the last heartbeat
+        let total = DAL_ERRORS.total();
+        if total > kbus_errors {
+            warn!(failed = total - kbus_errors, "K-Bus calls failed");
+            kbus_errors = total;
+            let mut payload = DAL_ERRORS.report();
+            payload["diagnostic"] = json!("kbus_errors");
+            payload["timestamp"] = timestamp::now();
+            mqtt_publisher.publish_background(
+                "diagnostics",
+                QoS::AtMostOnce,
+                false,
+                payload.to_string(),
+            )?;
+        }
     }
 }
 
 pub async fn mqtt_client_task_impl(
     topic_prefix: String,
+    identity: Identity,
     mqtt_options: MqttOptions,
-    input_events: UnboundedReceiver<KBusEvent>,
-    kbus_output: UnboundedSender<KBusEvent>,
-    heartbeat_interval: Duration,
+    config: Config,
+    mut input_events: UnboundedReceiver<InputEvent>,
+    commands: CommandQueues,
     cancellation_token: CancellationToken,
 ) -> Result<(), anyhow::Error> {
+    let subscribe_qos = rumqttc::qos(config.mqtt.subscribe_qos).context("invalid subscribe QoS")?;
+    let claim_topic = (!config.mqtt.claim_interval.is_zero()).then_some("claim");
+    if config.remote_config.enabled && config.file.is_none() {
+        warn!("remote configuration updates need a configuration file, disabled");
+    }
+    let config_topic =
+        (config.remote_config.enabled && config.file.is_some()).then_some("bridge/config/set");
+    let update_topic = config.self_update.is_some().then_some("bridge/update");
+    let aggregator = config
+        .aggregator
+        .as_ref()
+        .map(|aggregator| Aggregator::new(aggregator, &config.aggregator_site(aggregator)));
+    let share = share_prefix(config.mqtt.share_group.as_deref());
+    // Outputs are switched on the Tasmota command topics instead, if enabled
+    let tasmota = tasmota_topics(&config);
+    let output_topic = tasmota.is_none().then(|| "output/+".to_owned());
+    // Grouped outputs are switched on `output/<group>/<name>`, one filter per group
+    let mut output_groups: Vec<_> = config
+        .outputs
+        .groups
+        .iter()
+        .map(|grouped| format!("output/{}/+", grouped.group))
+        .collect();
+    output_groups.sort();
+    output_groups.dedup();
+    let raw_bits_topics = config
+        .mqtt
+        .raw_bits
+        .then(|| ["output/bit/+".to_owned(), "input/bit/+".to_owned()]);
+    let subscriptions: Vec<_> = output_topic
+        .into_iter()
+        .chain(output_groups)
+        .chain(raw_bits_topics.into_iter().flatten())
+        .chain(config.modbus.iter().flat_map(modbus_subscriptions))
+        .map(|topic| format!("{share}{topic_prefix}/{topic}"))
+        .chain(
+            tasmota
+                .iter()
+                .map(|tasmota| format!("{share}{}", tasmota.command_filter())),
+        )
+        .chain(
+            [
+                "bridge/dump",
+                "bridge/read",
+                "bridge/soe",
+                "bridge/stats",
+                "bridge/ping",
+                "bridge/shadow",
+            ]
+            .into_iter()
+            .chain(claim_topic)
+            .chain(config_topic)
+            .chain(update_topic)
+            .chain((!config.alarms.is_empty()).then_some("alarms/+/ack"))
+            .map(|topic| format!("{topic_prefix}/{topic}")),
+        )
+        .chain(aggregator.iter().flat_map(Aggregator::subscriptions))
+        .chain(
+            config
+                .transform
+                .iter()
+                .flat_map(|transform| transform.subscribe.clone()),
+        )
+        .map(|topic| SubscribeFilter::new(topic, subscribe_qos))
+        .collect();
+    let (forward_tx, forward_rx) = unbounded_channel();
+    let mut forward_rx = aggregator.is_some().then_some(forward_rx);
+    let (update_tx, mut update_rx) = unbounded_channel();
+
     let (client, event_loop) = AsyncClient::new(mqtt_options.clone(), 10);
-    client
-        .subscribe(format!("{topic_prefix}/output/+"), QoS::ExactlyOnce)
-        .await?;
 
-    let mut mqtt_subscriber =
-        MqttEventLoop::new(event_loop, topic_prefix.clone(), kbus_output.clone());
-    let mqtt_publisher = MqttPublisher::new(client, topic_prefix.clone());
+    let transform = config
+        .transform
+        .as_ref()
+        .map(|transform| Transform::load(&transform.script).map(Arc::new))
+        .transpose()?;
+    let (background_tx, mut background_rx) = unbounded_channel();
+    let mut mqtt_publisher = MqttPublisher::new(
+        client,
+        topic_prefix.clone(),
+        transform.clone(),
+        background_tx,
+    );
+    if !config.alarms.is_empty() {
+        mqtt_publisher = mqtt_publisher.with_alarms(Annunciator::new(&config.alarms)?);
+    }
+    let mut mqtt_subscriber = MqttEventLoop::new(
+        event_loop,
+        topic_prefix.clone(),
+        commands,
+        mqtt_publisher.clone(),
+        &config,
+        &identity.id,
+        aggregator.map(|aggregator| (aggregator, forward_tx)),
+    );
+    mqtt_subscriber.update_requests = update_topic.map(|_| update_tx);
+    mqtt_subscriber.subscribe(subscriptions)?;
 
     mqtt_publisher
         .publish("status", QoS::ExactlyOnce, true, "online".to_owned())
         .await?;
+    if let Some(tasmota) = &tasmota {
+        mqtt_publisher
+            .publish_to(
+                tasmota.lwt_topic(),
+                QoS::ExactlyOnce,
+                true,
+                "Online".to_owned(),
+            )
+            .await?;
+    }
+    mqtt_publisher
+        .publish(
+            "metadata",
+            QoS::AtLeastOnce,
+            true,
+            metadata(&config, &identity).to_string(),
+        )
+        .await?;
+    mqtt_publisher
+        .publish(
+            "buildinfo",
+            QoS::AtLeastOnce,
+            true,
+            build_info::build_info().to_string(),
+        )
+        .await?;
+    mqtt_publisher
+        .publish(
+            "config",
+            QoS::AtLeastOnce,
+            true,
+            serde_json::to_string(&config.redacted())?,
+        )
+        .await?;
+    // Alarms raised before the restart are cleared until their condition holds again
+    if let Some(summary) = mqtt_publisher.alarm_summary() {
+        mqtt_publisher
+            .publish("alarms/active", QoS::AtLeastOnce, true, summary.to_string())
+            .await?;
+    }
+    let tasmota_filters: Vec<String> = tasmota.iter().flat_map(Tasmota::topic_filters).collect();
+    // Kept in the state file to retry on the next start if clearing fails
+    match clear_previous_topics(&config, &topic_prefix, &tasmota_filters).await {
+        Ok(()) => state::set_topic_prefix(&topic_prefix, &tasmota_filters),
+        Err(err) => warn!(
+            error = format!("{err:#}"),
+            "failed to clear retained topics of the previous topic prefix"
+        ),
+    }
 
+    let input_format = InputFormat::new(&config, tasmota.as_ref());
+    supervisor::started("mqtt_event_loop");
+    supervisor::started("mqtt_publish_loop");
     tokio::select! {
         res = mqtt_event_loop(&mut mqtt_subscriber) => {
+            supervisor::finished("mqtt_event_loop", &res);
             res.context("MQTT event loop failed")?
         },
-        res = mqtt_publish_loop(&mqtt_publisher, input_events) => {
+        res = mqtt_publish_loop(
+            &mqtt_publisher,
+            &input_format,
+            config.mqtt.coalesce_threshold,
+            &mut input_events,
+            &mut background_rx,
+        ) => {
+            supervisor::finished("mqtt_publish_loop", &res);
             res.context("MQTT publish loop failed")?
         },
-        res = mqtt_heartbeat_loop(&mqtt_publisher, heartbeat_interval) => {
+        res = mqtt_aggregator_loop(&mqtt_publisher, forward_rx.as_mut()) => {
+            res.context("MQTT aggregator loop failed")?
+        },
+        res = mqtt_self_update_loop(&mqtt_publisher, config.self_update.as_ref(), &mut update_rx) => {
+            res.context("MQTT self-update loop failed")?
+        },
+        res = mqtt_ping_loop(&mqtt_publisher, config.mqtt.ping_interval) => {
+            res.context("MQTT ping loop failed")?
+        },
+        res = mqtt_telemetry_loop(&mqtt_publisher, config.mqtt.telemetry_interval) => {
+            res.context("MQTT telemetry loop failed")?
+        },
+        res = mqtt_heartbeat_loop(
+            &mqtt_publisher,
+            config.mqtt.heartbeat_interval,
+            &config.alerts,
+            tasmota.as_ref(),
+        ) => {
             res.context("MQTT heartbeat loop failed")?
         },
         _ = cancellation_token.cancelled() => {},
     }
 
-    mqtt_publisher
-        .publish("status", QoS::ExactlyOnce, true, "offline".to_owned())
-        .await?;
-
-    Ok(())
+    let shutdown_publish = publish_on_shutdown(
+        &mqtt_publisher,
+        &input_format,
+        &mut input_events,
+        mqtt_subscriber.claim.is_some() && mqtt_subscriber.outputs_enabled,
+    );
+    mqtt_subscriber
+        .drain(shutdown_publish, config.mqtt.shutdown_timeout)
+        .await
 }
 
 #[instrument(name = "mqtt", skip_all, err)]
 pub async fn mqtt_client_task(
     topic_prefix: String,
+    identity: Identity,
     mqtt_options: MqttOptions,
-    input_events: UnboundedReceiver<KBusEvent>,
-    kbus_output: UnboundedSender<KBusEvent>,
-    heartbeat_interval: Duration,
+    config: Config,
+    input_events: UnboundedReceiver<InputEvent>,
+    commands: CommandQueues,
     cancellation_token: CancellationToken,
 ) -> Result<(), anyhow::Error> {
     let result = mqtt_client_task_impl(
         topic_prefix,
+        identity,
         mqtt_options,
+        config,
         input_events,
-        kbus_output,
-        heartbeat_interval,
+        commands,
         cancellation_token.clone(),
     )
     .await;
 
+    if result.is_err() {
+        shutdown::initiate(ShutdownReason::Mqtt);
+    }
     cancellation_token.cancel();
 
     result
@@ -0,0 +1,111 @@
+//! Aggregation of other bridges' topics
+//!
+//! In aggregator mode, the bridge subscribes to the state topics of other bridges
+//! and republishes them in a merged namespace `site/<area>/<name>/...` (below the
+//! `topic_root`), so a PFC can act as a local concentrator for several couplers.
+//! Command topics are not forwarded.
+
+use crate::config::AggregatorConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// State topics of a bridge republished by the aggregator, relative to its prefix.
+const FORWARDED_TOPICS: &[&str] = &[
+    "status",
+    "kbus/status",
+    "metadata",
+    "buildinfo",
+    "config",
+    "heartbeat",
+    "ping",
+    "alert",
+    "input/+",
+    "input/+/+",
+    "derived/+",
+    "output/+/state",
+    "output/+/+/state",
+    "output/+/shadow",
+    "output/+/+/shadow",
+    "telemetry",
+    "telemetry/system",
+    "dump",
+    "read",
+    "security/rejections",
+    "last_error",
+    "verify_failed",
+    "diagnostics",
+];
+
+/// Topics a bridge publishes retained. The broker only sets the retain flag on
+/// messages delivered on subscription, so live updates are republished retained too.
+const RETAINED_TOPICS: &[&str] = &[
+    "status",
+    "kbus/status",
+    "metadata",
+    "buildinfo",
+    "config",
+    "ping",
+    "last_error",
+];
+
+/// A message to republish in the merged namespace.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Forward {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+#[derive(Debug)]
+struct Source {
+    /// Topic prefix of the source bridge followed by `/`
+    prefix: String,
+    /// Topic prefix in the merged namespace
+    target: String,
+}
+
+/// Maps topics of the source bridges to the merged namespace.
+#[derive(Debug)]
+pub struct Aggregator {
+    sources: Vec<Source>,
+}
+
+impl Aggregator {
+    /// Creates the aggregator republishing under the merged namespace `site`, see
+    /// [`crate::config::Config::aggregator_site`].
+    pub fn new(config: &AggregatorConfig, site: &str) -> Aggregator {
+        let sources = config
+            .sources
+            .iter()
+            .map(|source| Source {
+                prefix: format!("{}/", source.prefix),
+                target: format!("{site}/{}", source.name),
+            })
+            .collect();
+        Aggregator { sources }
+    }
+
+    /// Returns the topic filters of the source bridges' state topics.
+    pub fn subscriptions(&self) -> impl Iterator<Item = String> + '_ {
+        self.sources.iter().flat_map(|source| {
+            FORWARDED_TOPICS
+                .iter()
+                .map(move |topic| format!("{}{topic}", source.prefix))
+        })
+    }
+
+    /// Returns the message to republish, or `None` if `topic` doesn't belong to a source.
+    pub fn forward(&self, topic: &str, payload: &[u8], retain: bool) -> Option<Forward> {
+        self.sources.iter().find_map(|source| {
+            let suffix = topic.strip_prefix(&source.prefix)?;
+            Some(Forward {
+                topic: format!("{}/{suffix}", source.target),
+                payload: payload.to_vec(),
+                retain: retain
+                    || RETAINED_TOPICS.contains(&suffix)
+                    || (suffix.starts_with("output/") && suffix.ends_with("/state")),
+            })
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use super::*;
+use crate::config::AggregatorSource;
+
+fn aggregator() -> Aggregator {
+    Aggregator::new(
+        &AggregatorConfig {
+            area: "hall1".to_owned(),
+            sources: vec![
+                AggregatorSource {
+                    name: "coupler1".to_owned(),
+                    prefix: "line1/pfc200".to_owned(),
+                },
+                AggregatorSource {
+                    name: "coupler2".to_owned(),
+                    prefix: "pfc200".to_owned(),
+                },
+            ],
+        },
+        "site/hall1",
+    )
+}
+
+#[test]
+fn test_subscriptions() {
+    let subscriptions: Vec<_> = aggregator().subscriptions().collect();
+    assert_eq!(subscriptions.len(), 2 * FORWARDED_TOPICS.len());
+    assert!(subscriptions.contains(&"line1/pfc200/input/+".to_owned()));
+    assert!(subscriptions.contains(&"pfc200/status".to_owned()));
+    assert!(subscriptions.contains(&"pfc200/output/+/state".to_owned()));
+    // Output commands are not forwarded
+    assert!(!subscriptions.contains(&"pfc200/output/+".to_owned()));
+}
+
+#[test]
+fn test_forward() {
+    let aggregator = aggregator();
+
+    assert_eq!(
+        aggregator.forward("line1/pfc200/input/5", b"true", false),
+        Some(Forward {
+            topic: "site/hall1/coupler1/input/5".to_owned(),
+            payload: b"true".to_vec(),
+            retain: false,
+        })
+    );
+    assert_eq!(
+        aggregator
+            .forward("pfc200/input/5", b"true", false)
+            .unwrap()
+            .topic,
+        "site/hall1/coupler2/input/5"
+    );
+    // Prefixes only match whole topic levels
+    assert_eq!(aggregator.forward("pfc2000/input/5", b"true", false), None);
+    assert_eq!(aggregator.forward("other/input/5", b"true", false), None);
+}
+
+#[test]
+fn test_forward_retained() {
+    let aggregator = aggregator();
+
+    // Retained on subscription
+    assert!(
+        aggregator
+            .forward("pfc200/heartbeat", b"{}", true)
+            .unwrap()
+            .retain
+    );
+    // Live updates of retained topics
+    assert!(
+        aggregator
+            .forward("pfc200/status", b"offline", false)
+            .unwrap()
+            .retain
+    );
+    assert!(
+        aggregator
+            .forward("pfc200/output/3/state", b"true", false)
+            .unwrap()
+            .retain
+    );
+    assert!(
+        !aggregator
+            .forward("pfc200/heartbeat", b"{}", false)
+            .unwrap()
+            .retain
+    );
+}
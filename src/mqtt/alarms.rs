@@ -0,0 +1,217 @@
+//! Alarm annunciation
+//!
+//! A lightweight annunciator for cells without a SCADA: every configured alarm is
+//! raised while its condition over the input channels holds and published on
+//! `alarms`, operators acknowledge it on `alarms/<name>/ack`. A latching alarm
+//! stays active after its condition cleared until it's acknowledged, so short
+//! faults aren't missed. The active alarms are summarized retained on
+//! `alarms/active`, so an HMI shows them right after connecting.
+//!
+//! Conditions are evaluated on the changes of the monitored input channels as they
+//! are published, the alarms of a redundant instance are tracked independently.
+
+use anyhow::Context;
+use bitvec::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    config::{AlarmConfig, AlarmSeverity},
+    kbus::INPUT_SIZE,
+    rules::Expr,
+    timestamp,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// State of an alarm as published on `alarms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmState {
+    /// Raised and not acknowledged yet
+    Active,
+    /// Acknowledged, the condition still holds
+    Acknowledged,
+    /// Back to normal
+    Cleared,
+}
+
+/// Transition of an alarm, published on `alarms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AlarmEvent {
+    pub alarm: String,
+    pub state: AlarmState,
+    /// Whether the condition holds, `false` for a latched alarm waiting for its
+    /// acknowledgment
+    pub condition: bool,
+    pub severity: AlarmSeverity,
+    pub message: String,
+}
+
+impl AlarmEvent {
+    /// Returns the payload published on `alarms`.
+    pub fn payload(&self) -> serde_json::Value {
+        let mut payload = json!(self);
+        payload["timestamp"] = timestamp::now();
+        payload
+    }
+
+    /// Logs the transition, raised alarms as warnings.
+    pub fn log(&self) {
+        let AlarmEvent {
+            alarm,
+            state,
+            severity,
+            message,
+            ..
+        } = self;
+        if *state == AlarmState::Active {
+            warn!(alarm, ?severity, message, "alarm raised");
+        } else {
+            info!(alarm, ?state, "alarm changed");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Alarm {
+    config: AlarmConfig,
+    condition: Expr,
+    /// Whether the condition holds
+    holds: bool,
+    /// Whether the alarm is raised, longer than the condition if latching
+    active: bool,
+    acknowledged: bool,
+    /// Time the alarm was raised
+    since: Option<serde_json::Value>,
+}
+
+impl Alarm {
+    fn state(&self) -> AlarmState {
+        match (self.active, self.acknowledged) {
+            (false, _) => AlarmState::Cleared,
+            (true, false) => AlarmState::Active,
+            (true, true) => AlarmState::Acknowledged,
+        }
+    }
+
+    fn event(&self) -> AlarmEvent {
+        AlarmEvent {
+            alarm: self.config.name.clone(),
+            state: self.state(),
+            condition: self.holds,
+            severity: self.config.severity,
+            message: self.config.message.clone(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.active = false;
+        self.acknowledged = false;
+        self.since = None;
+    }
+}
+
+/// Tracks the configured alarms over the input channel changes.
+#[derive(Debug)]
+pub struct Annunciator {
+    alarms: Vec<Alarm>,
+    /// Input channels as seen in the published changes
+    inputs: BitVec<u8>,
+}
+
+impl Annunciator {
+    /// Creates the configured alarms, all cleared.
+    pub fn new(config: &[AlarmConfig]) -> Result<Annunciator, anyhow::Error> {
+        let alarms = config
+            .iter()
+            .map(|config| {
+                let condition = Expr::parse(&config.condition)
+                    .with_context(|| format!("invalid condition of alarm '{}'", config.name))?;
+                Ok(Alarm {
+                    config: config.clone(),
+                    condition,
+                    holds: false,
+                    active: false,
+                    acknowledged: false,
+                    since: None,
+                })
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Annunciator {
+            alarms,
+            inputs: bitvec![u8, Lsb0; 0; INPUT_SIZE],
+        })
+    }
+
+    /// Updates an input channel, returns the alarms changed by it.
+    pub fn on_input(&mut self, channel: u16, value: bool) -> Vec<AlarmEvent> {
+        let Some(mut bit) = self.inputs.get_mut(usize::from(channel)) else {
+            return Vec::new();
+        };
+        *bit = value;
+        drop(bit);
+
+        let mut events = Vec::new();
+        for alarm in &mut self.alarms {
+            let holds = alarm.condition.eval(&self.inputs);
+            if holds == alarm.holds {
+                continue;
+            }
+            alarm.holds = holds;
+            if holds {
+                // A latched alarm waiting for its acknowledgment is raised already
+                if !alarm.active {
+                    alarm.active = true;
+                    alarm.since = Some(timestamp::now());
+                }
+            } else if !alarm.config.latching || alarm.acknowledged {
+                alarm.clear();
+            }
+            events.push(alarm.event());
+        }
+        events
+    }
+
+    /// Acknowledges the alarm at `index`, a latched alarm whose condition cleared
+    /// is cleared by it.
+    ///
+    /// Returns `None` if the alarm isn't waiting for an acknowledgment.
+    pub fn acknowledge(&mut self, index: usize) -> Option<AlarmEvent> {
+        let alarm = self.alarms.get_mut(index)?;
+        if !alarm.active || alarm.acknowledged {
+            return None;
+        }
+        alarm.acknowledged = true;
+        if !alarm.holds {
+            alarm.clear();
+        }
+        Some(alarm.event())
+    }
+
+    /// Returns the summary of the active alarms published on `alarms/active`.
+    pub fn summary(&self) -> serde_json::Value {
+        let active: Vec<_> = self
+            .alarms
+            .iter()
+            .filter(|alarm| alarm.active)
+            .map(|alarm| {
+                let mut entry = json!(alarm.event());
+                entry["since"] = json!(alarm.since);
+                entry
+            })
+            .collect();
+        let unacknowledged = self
+            .alarms
+            .iter()
+            .filter(|alarm| alarm.state() == AlarmState::Active)
+            .count();
+        json!({
+            "timestamp": timestamp::now(),
+            "unacknowledged": unacknowledged,
+            "alarms": active,
+        })
+    }
+}
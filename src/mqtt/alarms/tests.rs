@@ -0,0 +1,121 @@
+use super::*;
+
+fn alarm(name: &str, condition: &str, latching: bool) -> AlarmConfig {
+    AlarmConfig {
+        name: name.to_owned(),
+        condition: condition.to_owned(),
+        severity: AlarmSeverity::Critical,
+        message: format!("{name} tripped"),
+        latching,
+    }
+}
+
+fn states(events: &[AlarmEvent]) -> Vec<(&str, AlarmState)> {
+    events
+        .iter()
+        .map(|event| (event.alarm.as_str(), event.state))
+        .collect()
+}
+
+#[test]
+fn test_raise_and_clear() {
+    let mut annunciator = Annunciator::new(&[alarm("overtemp", "in3 && !in7", false)]).unwrap();
+
+    assert_eq!(
+        states(&annunciator.on_input(3, true)),
+        [("overtemp", AlarmState::Active)]
+    );
+    // Unrelated and unchanged inputs don't publish anything
+    assert!(annunciator.on_input(5, true).is_empty());
+    assert!(annunciator.on_input(3, true).is_empty());
+
+    let acknowledged = annunciator.acknowledge(0).unwrap();
+    assert_eq!(acknowledged.state, AlarmState::Acknowledged);
+    assert!(acknowledged.condition);
+    // Acknowledged once
+    assert_eq!(annunciator.acknowledge(0), None);
+
+    assert_eq!(
+        states(&annunciator.on_input(7, true)),
+        [("overtemp", AlarmState::Cleared)]
+    );
+    // A cleared alarm isn't waiting for an acknowledgment
+    assert_eq!(annunciator.acknowledge(0), None);
+
+    // Raised again without the previous acknowledgment
+    annunciator.on_input(7, false);
+    assert_eq!(annunciator.summary()["unacknowledged"], 1);
+}
+
+#[test]
+fn test_latching() {
+    let mut annunciator =
+        Annunciator::new(&[alarm("estop", "in1", true), alarm("door", "in2", false)]).unwrap();
+
+    annunciator.on_input(1, true);
+    let events = annunciator.on_input(1, false);
+    assert_eq!(states(&events), [("estop", AlarmState::Active)]);
+    assert!(!events[0].condition);
+
+    // The acknowledgment clears the latched alarm
+    let cleared = annunciator.acknowledge(0).unwrap();
+    assert_eq!(cleared.state, AlarmState::Cleared);
+
+    // Acknowledged while the condition holds, cleared with it
+    annunciator.on_input(1, true);
+    annunciator.acknowledge(0).unwrap();
+    assert_eq!(
+        states(&annunciator.on_input(1, false)),
+        [("estop", AlarmState::Cleared)]
+    );
+
+    // Unknown alarm
+    assert_eq!(annunciator.acknowledge(2), None);
+}
+
+#[test]
+fn test_summary() {
+    let mut annunciator = Annunciator::new(&[
+        alarm("estop", "in1", true),
+        alarm("door", "in2", false),
+        alarm("pressure", "in3", false),
+    ])
+    .unwrap();
+    let summary = annunciator.summary();
+    assert_eq!(summary["unacknowledged"], 0);
+    assert_eq!(summary["alarms"], json!([]));
+
+    annunciator.on_input(1, true);
+    annunciator.on_input(2, true);
+    annunciator.acknowledge(1).unwrap();
+
+    let summary = annunciator.summary();
+    assert_eq!(summary["unacknowledged"], 1);
+    let alarms = summary["alarms"].as_array().unwrap();
+    assert_eq!(alarms.len(), 2);
+    assert_eq!(alarms[0]["alarm"], "estop");
+    assert_eq!(alarms[0]["state"], "active");
+    assert_eq!(alarms[0]["severity"], "critical");
+    assert_eq!(alarms[0]["message"], "estop tripped");
+    assert!(!alarms[0]["since"].is_null());
+    assert_eq!(alarms[1]["alarm"], "door");
+    assert_eq!(alarms[1]["state"], "acknowledged");
+}
+
+#[test]
+fn test_event_payload() {
+    let event = AlarmEvent {
+        alarm: "estop".to_owned(),
+        state: AlarmState::Active,
+        condition: true,
+        severity: AlarmSeverity::Warning,
+        message: "Emergency stop".to_owned(),
+    };
+    let payload = event.payload();
+    assert_eq!(payload["alarm"], "estop");
+    assert_eq!(payload["state"], "active");
+    assert_eq!(payload["condition"], true);
+    assert_eq!(payload["severity"], "warning");
+    assert_eq!(payload["message"], "Emergency stop");
+    assert!(payload.get("timestamp").is_some());
+}
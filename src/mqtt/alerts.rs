@@ -0,0 +1,142 @@
+//! Threshold alerts on heartbeat metrics
+//!
+//! Every heartbeat sample is checked against the configured limits. An alert is
+//! raised once a limit is exceeded for the configured number of consecutive
+//! intervals, and cleared on the first interval back within the limit, giving
+//! early warning e.g. before the device runs out of memory.
+
+use serde::Serialize;
+
+use crate::config::AlertsConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// Metric of the heartbeat an alert limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Used system memory in percent
+    MemoryUsage,
+    /// Global CPU usage in percent
+    CpuUsage,
+    /// Number of input events waiting to be published
+    QueueDepth,
+}
+
+/// Values of the alert metrics sampled on a heartbeat.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub memory_usage: f32,
+    pub cpu_usage: f32,
+    pub queue_depth: usize,
+}
+
+impl Sample {
+    fn get(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::MemoryUsage => self.memory_usage,
+            Metric::CpuUsage => self.cpu_usage,
+            Metric::QueueDepth => self.queue_depth as f32,
+        }
+    }
+}
+
+/// Transition of an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    /// The limit was exceeded for the configured number of consecutive intervals
+    Raised,
+    /// The metric is back within the limit
+    Cleared,
+}
+
+/// Alert published on the `alert` topic.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub metric: Metric,
+    pub state: AlertState,
+    pub value: f32,
+    pub limit: f32,
+    /// Number of consecutive intervals the limit must be exceeded to raise the alert
+    pub intervals: u32,
+}
+
+#[derive(Debug)]
+struct Threshold {
+    metric: Metric,
+    limit: f32,
+    /// Number of consecutive samples exceeding the limit
+    exceeded: u32,
+    raised: bool,
+}
+
+/// Tracks the configured limits over consecutive heartbeat samples.
+#[derive(Debug)]
+pub struct AlertMonitor {
+    thresholds: Vec<Threshold>,
+    intervals: u32,
+}
+
+impl AlertMonitor {
+    pub fn new(config: &AlertsConfig) -> AlertMonitor {
+        let limits = [
+            (Metric::MemoryUsage, config.memory_usage),
+            (Metric::CpuUsage, config.cpu_usage),
+            (
+                Metric::QueueDepth,
+                config.queue_depth.map(|depth| depth as f32),
+            ),
+        ];
+        let thresholds = limits
+            .into_iter()
+            .filter_map(|(metric, limit)| {
+                limit.map(|limit| Threshold {
+                    metric,
+                    limit,
+                    exceeded: 0,
+                    raised: false,
+                })
+            })
+            .collect();
+
+        AlertMonitor {
+            thresholds,
+            intervals: config.intervals,
+        }
+    }
+
+    /// Returns `true` if no limit is configured.
+    pub fn is_empty(&self) -> bool {
+        self.thresholds.is_empty()
+    }
+
+    /// Checks a heartbeat sample and returns the alerts raised or cleared by it.
+    pub fn update(&mut self, sample: &Sample) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for threshold in &mut self.thresholds {
+            let value = sample.get(threshold.metric);
+            let state = if value > threshold.limit {
+                threshold.exceeded = threshold.exceeded.saturating_add(1);
+                (!threshold.raised && threshold.exceeded >= self.intervals)
+                    .then_some(AlertState::Raised)
+            } else {
+                threshold.exceeded = 0;
+                threshold.raised.then_some(AlertState::Cleared)
+            };
+
+            if let Some(state) = state {
+                threshold.raised = state == AlertState::Raised;
+                alerts.push(Alert {
+                    metric: threshold.metric,
+                    state,
+                    value,
+                    limit: threshold.limit,
+                    intervals: self.intervals,
+                });
+            }
+        }
+        alerts
+    }
+}
@@ -0,0 +1,94 @@
+use super::*;
+
+fn memory(value: f32) -> Sample {
+    Sample {
+        memory_usage: value,
+        ..Sample::default()
+    }
+}
+
+#[test]
+fn test_no_limits() {
+    let monitor = AlertMonitor::new(&AlertsConfig::default());
+    assert!(monitor.is_empty());
+}
+
+#[test]
+fn test_raise_and_clear() {
+    let mut monitor = AlertMonitor::new(&AlertsConfig {
+        memory_usage: Some(90.0),
+        intervals: 3,
+        ..AlertsConfig::default()
+    });
+    assert!(!monitor.is_empty());
+
+    // Raised only after three consecutive intervals above the limit
+    assert!(monitor.update(&memory(95.0)).is_empty());
+    assert!(monitor.update(&memory(95.0)).is_empty());
+    assert_eq!(
+        monitor.update(&memory(96.0)),
+        vec![Alert {
+            metric: Metric::MemoryUsage,
+            state: AlertState::Raised,
+            value: 96.0,
+            limit: 90.0,
+            intervals: 3,
+        }]
+    );
+    // Not repeated while the limit is still exceeded
+    assert!(monitor.update(&memory(97.0)).is_empty());
+
+    let alerts = monitor.update(&memory(80.0));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].state, AlertState::Cleared);
+    assert!(monitor.update(&memory(80.0)).is_empty());
+}
+
+#[test]
+fn test_interrupted_streak() {
+    let mut monitor = AlertMonitor::new(&AlertsConfig {
+        memory_usage: Some(90.0),
+        intervals: 2,
+        ..AlertsConfig::default()
+    });
+
+    assert!(monitor.update(&memory(95.0)).is_empty());
+    // Equal to the limit isn't exceeding it and restarts the count
+    assert!(monitor.update(&memory(90.0)).is_empty());
+    assert!(monitor.update(&memory(95.0)).is_empty());
+    assert_eq!(monitor.update(&memory(95.0)).len(), 1);
+}
+
+#[test]
+fn test_independent_metrics() {
+    let mut monitor = AlertMonitor::new(&AlertsConfig {
+        cpu_usage: Some(80.0),
+        queue_depth: Some(100),
+        intervals: 1,
+        ..AlertsConfig::default()
+    });
+
+    // Memory usage has no limit configured
+    let sample = Sample {
+        memory_usage: 100.0,
+        cpu_usage: 85.0,
+        queue_depth: 150,
+    };
+    let alerts = monitor.update(&sample);
+    let metrics: Vec<_> = alerts.iter().map(|alert| alert.metric).collect();
+    assert_eq!(metrics, [Metric::CpuUsage, Metric::QueueDepth]);
+
+    let alerts = monitor.update(&Sample {
+        queue_depth: 150,
+        ..sample
+    });
+    assert!(alerts.is_empty());
+
+    let alerts = monitor.update(&Sample::default());
+    assert_eq!(alerts.len(), 2);
+    assert!(
+        alerts
+            .iter()
+            .all(|alert| alert.state == AlertState::Cleared)
+    );
+}
@@ -0,0 +1,87 @@
+//! Statistics per K-Bus channel
+//!
+//! Changes of every input channel and commands received for every output channel
+//! are counted since the start of the bridge and reported on `stats/channels` on
+//! request, which helps to spot chattering sensors (many changes) and dead
+//! channels (no change at all) during commissioning and maintenance.
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::timestamp;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct InputStats {
+    channel: u16,
+    changes: u64,
+    /// Changes not published because a later change was queued (see [`super::coalesce`])
+    coalesced: u64,
+    /// Time of the last change in the configured timestamp format
+    last_change: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct OutputStats {
+    channel: u16,
+    commands: u64,
+}
+
+/// Counters of all input and output channels.
+#[derive(Debug)]
+pub struct ChannelStats {
+    inputs: Vec<InputStats>,
+    outputs: Vec<OutputStats>,
+}
+
+impl ChannelStats {
+    pub fn new(input_channels: usize, output_channels: usize) -> ChannelStats {
+        ChannelStats {
+            inputs: (0..input_channels)
+                .map(|channel| InputStats {
+                    channel: channel as u16,
+                    ..InputStats::default()
+                })
+                .collect(),
+            outputs: (0..output_channels)
+                .map(|channel| OutputStats {
+                    channel: channel as u16,
+                    ..OutputStats::default()
+                })
+                .collect(),
+        }
+    }
+
+    /// Records a published change of an input channel.
+    pub fn on_input_change(&mut self, channel: u16) {
+        if let Some(input) = self.inputs.get_mut(usize::from(channel)) {
+            input.changes += 1;
+            input.last_change = Some(timestamp::now());
+        }
+    }
+
+    /// Records a change of an input channel dropped in favor of a later one.
+    pub fn on_input_coalesced(&mut self, channel: u16) {
+        if let Some(input) = self.inputs.get_mut(usize::from(channel)) {
+            input.coalesced += 1;
+        }
+    }
+
+    /// Records an accepted command for an output channel.
+    pub fn on_output_command(&mut self, channel: u16) {
+        if let Some(output) = self.outputs.get_mut(usize::from(channel)) {
+            output.commands += 1;
+        }
+    }
+
+    /// Returns the report published on `stats/channels`.
+    pub fn report(&self) -> serde_json::Value {
+        json!({
+            "timestamp": timestamp::now(),
+            "inputs": self.inputs,
+            "outputs": self.outputs,
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use super::*;
+
+#[test]
+fn test_report() {
+    let mut stats = ChannelStats::new(4, 2);
+    stats.on_input_change(1);
+    stats.on_input_change(1);
+    stats.on_input_change(3);
+    stats.on_output_command(0);
+    stats.on_input_coalesced(1);
+    // Channels outside of the process images are ignored
+    stats.on_input_change(4);
+    stats.on_output_command(2);
+
+    let report = stats.report();
+    let inputs = report["inputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 4);
+    assert_eq!(inputs[1]["channel"], 1);
+    assert_eq!(inputs[1]["changes"], 2);
+    assert_eq!(inputs[1]["coalesced"], 1);
+    assert!(inputs[1]["last_change"].is_string());
+    // A dead channel has no changes and no last change
+    assert_eq!(inputs[0]["changes"], 0);
+    assert!(inputs[0]["last_change"].is_null());
+    assert_eq!(inputs[3]["changes"], 1);
+
+    let outputs = report["outputs"].as_array().unwrap();
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[0]["commands"], 1);
+    assert_eq!(outputs[1]["commands"], 0);
+}
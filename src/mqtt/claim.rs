@@ -0,0 +1,101 @@
+//! Broker-based claim of the device identity
+//!
+//! If two bridges are deployed with the same identity, both would drive the same
+//! outputs. With claims enabled, every instance listens on the retained `claim`
+//! topic and only the instance holding the claim drives outputs. The holder
+//! refreshes its claim every interval, other instances stay on standby and take
+//! over once the claim isn't refreshed for [`STALE_INTERVALS`] intervals.
+
+use std::{
+    process,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+/// Number of claim intervals after which a claim not refreshed is considered stale.
+pub const STALE_INTERVALS: u32 = 3;
+
+/// Payload of the `claim` topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimMessage {
+    /// Unique id of the claiming instance
+    pub instance: String,
+    /// Start time of the claiming instance, the earlier one wins a conflict
+    pub since: DateTime<Utc>,
+}
+
+/// Claim state of this instance.
+#[derive(Debug)]
+pub struct Claim {
+    own: ClaimMessage,
+    interval: Duration,
+    started: Instant,
+    /// Last claim of another instance and its reception time
+    foreign: Option<(ClaimMessage, Instant)>,
+}
+
+impl Claim {
+    /// Creates the claim state of an instance running on the device with the given MAC.
+    pub fn new(mac: &str, interval: Duration, now: Instant) -> Claim {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Claim {
+            own: ClaimMessage {
+                instance: format!("{mac}-{}-{nanos:x}", process::id()),
+                since: Utc::now(),
+            },
+            interval,
+            started: now,
+            foreign: None,
+        }
+    }
+
+    /// Returns the claim refresh interval.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the claim message of this instance.
+    pub fn message(&self) -> &ClaimMessage {
+        &self.own
+    }
+
+    /// Records a claim received on the `claim` topic.
+    pub fn on_message(&mut self, message: ClaimMessage, now: Instant) {
+        if message.instance != self.own.instance {
+            self.foreign = Some((message, now));
+        }
+    }
+
+    /// Forgets the claim of another instance released on its shutdown.
+    pub fn on_release(&mut self) {
+        self.foreign = None;
+    }
+
+    /// Returns the conflicting instance holding the claim, if any.
+    pub fn holder(&self, now: Instant) -> Option<&ClaimMessage> {
+        let stale_after = self.interval * STALE_INTERVALS;
+        self.foreign
+            .as_ref()
+            .filter(|(_, received)| now.duration_since(*received) < stale_after)
+            .map(|(message, _)| message)
+            .filter(|message| {
+                (message.since, &message.instance) < (self.own.since, &self.own.instance)
+            })
+    }
+
+    /// Returns `true` if this instance holds the claim and may drive outputs.
+    ///
+    /// During the first interval the retained claim of a running instance is awaited,
+    /// so a new instance never drives outputs before it knows about the others.
+    pub fn is_holder(&self, now: Instant) -> bool {
+        now.duration_since(self.started) >= self.interval && self.holder(now).is_none()
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::TimeDelta;
+
+use super::*;
+
+const INTERVAL: Duration = Duration::from_secs(10);
+
+fn foreign(claim: &Claim, since: TimeDelta) -> ClaimMessage {
+    ClaimMessage {
+        instance: "other".to_owned(),
+        since: claim.message().since + since,
+    }
+}
+
+#[test]
+fn test_claim_without_conflict() {
+    let start = Instant::now();
+    let mut claim = Claim::new("00:30:de:00:00:01", INTERVAL, start);
+    // Waiting for the retained claim of a running instance
+    assert!(!claim.is_holder(start));
+    assert!(claim.is_holder(start + INTERVAL));
+
+    // Own claims are ignored
+    claim.on_message(claim.message().clone(), start + INTERVAL);
+    assert!(claim.is_holder(start + INTERVAL));
+}
+
+#[test]
+fn test_claim_held_by_earlier_instance() {
+    let start = Instant::now();
+    let mut claim = Claim::new("00:30:de:00:00:01", INTERVAL, start);
+    let other = foreign(&claim, TimeDelta::seconds(-60));
+    claim.on_message(other.clone(), start);
+
+    assert_eq!(claim.holder(start + INTERVAL), Some(&other));
+    assert!(!claim.is_holder(start + INTERVAL));
+
+    // Released on shutdown of the holder
+    claim.on_release();
+    assert!(claim.is_holder(start + INTERVAL));
+    claim.on_message(other.clone(), start);
+
+    // Takeover once the claim isn't refreshed anymore
+    let stale = start + INTERVAL * STALE_INTERVALS;
+    assert_eq!(claim.holder(stale), None);
+    assert!(claim.is_holder(stale));
+}
+
+#[test]
+fn test_claim_of_later_instance() {
+    let start = Instant::now();
+    let mut claim = Claim::new("00:30:de:00:00:01", INTERVAL, start);
+    claim.on_message(foreign(&claim, TimeDelta::seconds(60)), start);
+
+    assert_eq!(claim.holder(start + INTERVAL), None);
+    assert!(claim.is_holder(start + INTERVAL));
+}
+
+#[test]
+fn test_claim_same_start_time() {
+    let start = Instant::now();
+    let mut claim = Claim::new("00:30:de:00:00:01", INTERVAL, start);
+    let mut other = foreign(&claim, TimeDelta::zero());
+
+    // Instance id breaks the tie
+    other.instance = "0".to_owned();
+    claim.on_message(other, start);
+    assert!(!claim.is_holder(start + INTERVAL));
+
+    let mut other = foreign(&claim, TimeDelta::zero());
+    other.instance = "z".to_owned();
+    claim.on_message(other, start);
+    assert!(claim.is_holder(start + INTERVAL));
+}
@@ -0,0 +1,141 @@
+//! Clearing the retained topics of a device
+//!
+//! The retained `status`, `metadata`, channel state and alarm topics of a
+//! decommissioned device stay on the broker and show up as ghost entries in
+//! dashboards. `cleanup-retained` subscribes to everything below the topic prefix
+//! of the device (and its Tasmota topics), collects the retained messages the
+//! broker sends for the subscriptions and clears them with empty retained
+//! messages. The bridge must not run meanwhile, it would publish them again.
+//!
+//! The bridge clears a previous topic prefix on startup the same way, keeping the
+//! topics of the current prefix.
+
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::{Context, anyhow};
+use rumqttc::{
+    AsyncClient, Event, MqttOptions, Outgoing, Packet, Publish, QoS, SubscribeReasonCode,
+};
+use tokio::time;
+
+use super::tasmota_topics;
+use crate::config::Config;
+
+#[cfg(test)]
+mod tests;
+
+/// Time without further messages after which the broker is considered to have
+/// sent all retained messages of the subscriptions
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+/// Time the whole cleanup may take
+const CLEANUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the topic filters covering all retained topics of the device.
+pub(super) fn topic_filters(config: &Config, topic_prefix: &str) -> Vec<String> {
+    let mut filters = vec![format!("{topic_prefix}/#")];
+    if let Some(tasmota) = tasmota_topics(config) {
+        filters.extend(tasmota.topic_filters());
+    }
+    filters
+}
+
+/// Retained topics received for the subscriptions.
+#[derive(Debug, Default)]
+struct RetainedTopics {
+    topics: BTreeSet<String>,
+}
+
+impl RetainedTopics {
+    /// Returns the received topics, without those below `keep_prefix`.
+    fn into_topics(self, keep_prefix: Option<&str>) -> Vec<String> {
+        let keep = keep_prefix.map(|prefix| format!("{prefix}/"));
+        self.topics
+            .into_iter()
+            .filter(|topic| keep.as_ref().is_none_or(|keep| !topic.starts_with(keep)))
+            .collect()
+    }
+
+    /// Notes a received message, an empty payload is a topic cleared meanwhile.
+    fn on_publish(&mut self, publish: &Publish) {
+        if publish.payload.is_empty() {
+            self.topics.remove(&publish.topic);
+        } else if publish.retain {
+            self.topics.insert(publish.topic.clone());
+        }
+    }
+}
+
+/// Clears all retained topics of the device, returns the cleared topics.
+///
+/// `mqtt_options` must not set a last will, it would be published again.
+pub async fn cleanup_retained(
+    mqtt_options: MqttOptions,
+    config: &Config,
+    topic_prefix: &str,
+) -> Result<Vec<String>, anyhow::Error> {
+    clear_retained(mqtt_options, &topic_filters(config, topic_prefix), None).await
+}
+
+/// Clears the retained topics matching `filters`, except those below `keep_prefix`,
+/// returns the cleared topics.
+///
+/// `mqtt_options` must not set a last will, it would be published again.
+pub(super) async fn clear_retained(
+    mqtt_options: MqttOptions,
+    filters: &[String],
+    keep_prefix: Option<&str>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
+    for filter in filters {
+        client.subscribe(filter, QoS::AtLeastOnce).await?;
+    }
+
+    let cleanup = async {
+        // Retained messages follow the SUBACK of their subscription
+        let mut retained = RetainedTopics::default();
+        let mut pending_subacks = filters.len();
+        loop {
+            let event = if pending_subacks > 0 {
+                event_loop.poll().await?
+            } else {
+                match time::timeout(SETTLE_TIME, event_loop.poll()).await {
+                    Ok(event) => event?,
+                    Err(_) => break,
+                }
+            };
+            match event {
+                Event::Incoming(Packet::SubAck(suback)) => {
+                    if suback.return_codes.contains(&SubscribeReasonCode::Failure) {
+                        return Err(anyhow!("subscription rejected by the broker"));
+                    }
+                    pending_subacks = pending_subacks.saturating_sub(1);
+                }
+                Event::Incoming(Packet::Publish(publish)) => retained.on_publish(&publish),
+                _ => {}
+            }
+        }
+
+        let topics = retained.into_topics(keep_prefix);
+        let publish = async {
+            for topic in &topics {
+                client
+                    .publish(topic, QoS::AtLeastOnce, true, Vec::new())
+                    .await?;
+            }
+            client.disconnect().await?;
+            Ok::<(), anyhow::Error>(())
+        };
+        let poll = async {
+            loop {
+                if let Event::Outgoing(Outgoing::Disconnect) = event_loop.poll().await? {
+                    return Ok::<(), anyhow::Error>(());
+                }
+            }
+        };
+        tokio::try_join!(publish, poll)?;
+        Ok(topics)
+    };
+    time::timeout(CLEANUP_TIMEOUT, cleanup)
+        .await
+        .context("timed out clearing retained topics")?
+}
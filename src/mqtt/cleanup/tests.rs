@@ -0,0 +1,52 @@
+use super::*;
+use crate::config::TopicProfile;
+
+fn publish(topic: &str, retain: bool, payload: &str) -> Publish {
+    let mut publish = Publish::new(topic, QoS::AtLeastOnce, payload);
+    publish.retain = retain;
+    publish
+}
+
+#[test]
+fn test_topic_filters() {
+    let mut config = Config {
+        device_name: "pfc200".to_owned(),
+        ..Config::default()
+    };
+    assert_eq!(topic_filters(&config, "pfc200/mac"), ["pfc200/mac/#"]);
+
+    config.mqtt.topic_profile = TopicProfile::Tasmota;
+    config.topic_root = Some("acme".to_owned());
+    assert_eq!(
+        topic_filters(&config, "acme/pfc200/mac"),
+        [
+            "acme/pfc200/mac/#",
+            "acme/tele/pfc200/#",
+            "acme/stat/pfc200/#"
+        ]
+    );
+}
+
+#[test]
+fn test_retained_topics() {
+    let mut retained = RetainedTopics::default();
+    retained.on_publish(&publish("pfc200/status", true, "online"));
+    retained.on_publish(&publish("pfc200/metadata", true, "{}"));
+    // Live messages aren't retained, cleared topics are gone
+    retained.on_publish(&publish("pfc200/heartbeat", false, "{}"));
+    retained.on_publish(&publish("pfc200/metadata", false, ""));
+    assert_eq!(retained.into_topics(None), ["pfc200/status"]);
+}
+
+#[test]
+fn test_retained_topics_keep_prefix() {
+    // The previous prefix without the MAC contains the current one
+    let mut retained = RetainedTopics::default();
+    retained.on_publish(&publish("pfc200/status", true, "online"));
+    retained.on_publish(&publish("pfc200/00:30:de:00:00:01/status", true, "online"));
+    retained.on_publish(&publish("pfc200/00:30:de:00:00:01x/status", true, "online"));
+    assert_eq!(
+        retained.into_topics(Some("pfc200/00:30:de:00:00:01")),
+        ["pfc200/00:30:de:00:00:01x/status", "pfc200/status"]
+    );
+}
@@ -0,0 +1,1379 @@
+//! MQTT connection handling
+//!
+//! The event loop of the connection: subscriptions, decoding and routing of the
+//! received commands to the K-Bus and Modbus tasks, rate limits, claims and the
+//! responses to requests. The [`CommandQueues`] connect it to the other tasks.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    str::from_utf8,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow};
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use rumqttc::{
+    AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, PubAck, PubComp, Publish, QoS,
+    SubAck, SubscribeFilter, SubscribeReasonCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::{mpsc::UnboundedSender, oneshot},
+    time::{self, Interval, interval},
+};
+use tracing::{debug, error, info, info_span, instrument, trace, warn};
+
+use crate::{
+    config::{Config, ModbusConfig, OutputsConfig, RetainedCommands, SigningConfig},
+    kbus::{self, KBusCommand, KBusEvent, OUTPUT_SIZE, OutputWrite, ProcessImage, SoeDump},
+    modbus::{ModbusCommand, ModbusValue},
+    report::ErrorReport,
+    shutdown::{self, ShutdownReason},
+    throttle::warn_throttled,
+    timestamp, update,
+    utils::hex_dump,
+};
+
+use super::{
+    aggregator::{Aggregator, Forward},
+    claim::{Claim, ClaimMessage},
+    handshake::{HandshakeTracker, StalledPublish},
+    payloads,
+    publisher::MqttPublisher,
+    rejections::RejectionStats,
+    router::{RejectReason, Route, TopicRouter},
+    signature,
+    startup::StartupQueue,
+    stats::{
+        CHANNEL_STATS, MQTT_MESSAGES_DROPPED, MQTT_MESSAGES_PROCESSED, MQTT_MESSAGES_RECEIVED,
+        MQTT_MESSAGES_REJECTED, MQTT_PUBLISHES_STALLED, MQTT_SUBSCRIPTIONS_FAILED, OUTPUTS_SHADOW,
+    },
+    tasmota_topics,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Time without any event loop activity after which no more requests are assumed
+/// to be queued in the client during shutdown drain
+const DRAIN_IDLE_TIME: Duration = Duration::from_millis(100);
+
+/// Maximum length of the correlation id of an output command, it's logged with every
+/// message of the command
+const MAX_COMMAND_ID_LENGTH: usize = 64;
+
+/// Interval of checking the publishes for stalled handshakes
+const HANDSHAKE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval of checking whether the K-Bus is running while output commands are queued
+const STARTUP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+const fn decode_value(payload: &[u8]) -> Option<bool> {
+    match payload {
+        b"true" | b"on" | b"ON" | b"\x01" => Some(true),
+        b"false" | b"off" | b"OFF" | b"\x00" => Some(false),
+        _ => None,
+    }
+}
+
+/// Output command payload, either a plain value or JSON with an optional timestamp
+/// and correlation id, e.g. `{"value": true, "timestamp": "2025-03-03T06:00:00Z", "id": "a1"}`.
+///
+/// Holding registers of Modbus devices take a `u16` value.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputCommand<T = bool> {
+    value: T,
+    #[serde(default)]
+    timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    id: Option<String>,
+    /// Verified on the raw payload (see [`signature`])
+    #[serde(default, rename = "signature")]
+    _signature: Option<String>,
+}
+
+fn decode_output_command(payload: &[u8]) -> Option<OutputCommand> {
+    if let Some(value) = decode_value(payload) {
+        return Some(OutputCommand {
+            value,
+            timestamp: None,
+            id: None,
+            _signature: None,
+        });
+    }
+    serde_json::from_slice(payload)
+        .ok()
+        .filter(|command: &OutputCommand| {
+            command
+                .id
+                .as_ref()
+                .is_none_or(|id| id.len() <= MAX_COMMAND_ID_LENGTH)
+        })
+}
+
+fn decode_register_command(payload: &[u8]) -> Option<OutputCommand<u16>> {
+    if let Some(value) = from_utf8(payload).ok().and_then(|s| s.parse().ok()) {
+        return Some(OutputCommand {
+            value,
+            timestamp: None,
+            id: None,
+            _signature: None,
+        });
+    }
+    serde_json::from_slice(payload).ok()
+}
+
+/// Returns the configuration of a `bridge/config/set` payload received on `topic` at `now`.
+///
+/// With `[signing]`, the update must be a signed command with the TOML or JSON
+/// configuration as a string `value`, so a client without the key can't remove the
+/// `[signing]` section.
+fn decode_config_update(
+    signing: Option<&SigningConfig>,
+    topic: &str,
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let Some(signing) = signing else {
+        return Ok(payload.to_vec());
+    };
+    signature::verify(signing, topic, payload, now)?;
+    let command: serde_json::Value = serde_json::from_slice(payload)?;
+    command["value"]
+        .as_str()
+        .map(|config| config.as_bytes().to_vec())
+        .context("signed configuration update without a string value")
+}
+
+/// Checks that a command issued at `timestamp` is not older than `max_age` at `now`.
+///
+/// Timestamps in the future (clock skew between the sender and the device) are accepted.
+fn check_command_age(
+    timestamp: DateTime<Utc>,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    let age = (now - timestamp).to_std().unwrap_or_default();
+    if age > max_age {
+        return Err(anyhow!(
+            "stale command: issued {}s ago, maximum age is {}s",
+            age.as_secs(),
+            max_age.as_secs()
+        ));
+    }
+    Ok(())
+}
+
+fn dump_payload(image: &ProcessImage) -> serde_json::Value {
+    json!({
+        "timestamp": timestamp::now(),
+        "inputs": {
+            "size": image.inputs.len(),
+            "hex": hex_dump(&image.inputs),
+        },
+        "outputs": {
+            "size": image.outputs.len(),
+            "hex": hex_dump(&image.outputs),
+        },
+    })
+}
+
+/// Sequence of events dump, oldest change first.
+fn soe_payload(dump: &SoeDump) -> serde_json::Value {
+    let events: Vec<_> = dump
+        .entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "cycle": entry.cycle,
+                "timestamp": timestamp::at(entry.time),
+                "channel": entry.channel,
+                "value": entry.value,
+            })
+        })
+        .collect();
+    json!({
+        "timestamp": timestamp::now(),
+        "overwritten": dump.overwritten,
+        "events": events,
+    })
+}
+
+/// Response to a `bridge/ping` echoing its payload with the time of the bridge.
+fn pong_payload(payload: &str) -> serde_json::Value {
+    json!({ "payload": payload, "timestamp": timestamp::now() })
+}
+
+/// Encoding of the data returned for a process image read request.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ReadFormat {
+    #[default]
+    Hex,
+    Base64,
+    Array,
+}
+
+/// Payload of a `bridge/read` request.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadRequest {
+    /// Byte offset in the input process image
+    offset: u32,
+    /// Number of bytes to read
+    length: usize,
+    /// Encoding of the returned data
+    #[serde(default)]
+    format: ReadFormat,
+}
+
+/// Returns the response to a raw bit write or read at `offset`.
+fn bit_payload(offset: u16, result: Result<bool, anyhow::Error>) -> serde_json::Value {
+    match result {
+        Ok(value) => json!({
+            "timestamp": timestamp::now(),
+            "offset": offset,
+            "value": value,
+        }),
+        Err(err) => json!({
+            "timestamp": timestamp::now(),
+            "offset": offset,
+            "error": format!("{err:#}"),
+        }),
+    }
+}
+
+fn read_payload(
+    request: &ReadRequest,
+    result: Result<Vec<u8>, anyhow::Error>,
+) -> serde_json::Value {
+    let data = match result {
+        Ok(data) => data,
+        Err(err) => {
+            return json!({
+                "timestamp": timestamp::now(),
+                "offset": request.offset,
+                "length": request.length,
+                "error": format!("{err:#}"),
+            });
+        }
+    };
+
+    let data = match request.format {
+        ReadFormat::Hex => json!(
+            data.iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        ),
+        ReadFormat::Base64 => json!(BASE64_STANDARD.encode(&data)),
+        ReadFormat::Array => json!(data),
+    };
+
+    json!({
+        "timestamp": timestamp::now(),
+        "offset": request.offset,
+        "length": request.length,
+        "data": data,
+    })
+}
+
+/// Limits the number of accepted messages per second (fixed one second windows).
+struct RateLimiter {
+    max_rate: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    fn new(max_rate: u32, now: Instant) -> RateLimiter {
+        RateLimiter {
+            max_rate,
+            window_start: now,
+            count: 0,
+        }
+    }
+
+    /// Returns `true` if a message received at `now` is within the limit.
+    fn allow(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+        if self.count < self.max_rate {
+            self.count += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Lowers the QoS level by one, returns `None` for QoS0.
+const fn lower_qos(qos: QoS) -> Option<QoS> {
+    match qos {
+        QoS::ExactlyOnce => Some(QoS::AtLeastOnce),
+        QoS::AtLeastOnce => Some(QoS::AtMostOnce),
+        QoS::AtMostOnce => None,
+    }
+}
+
+/// Result of a subscription filter in a SUBACK.
+#[derive(Debug, PartialEq, Eq)]
+enum SubscribeOutcome {
+    /// Subscribed with the requested QoS
+    Subscribed(QoS),
+    /// Subscribed, but the broker granted a lower QoS than requested
+    Downgraded(QoS),
+    /// Rejected, retried with the lower QoS
+    Retry(QoS),
+    /// Rejected even with QoS0
+    Failed,
+}
+
+/// Returns the outcome of a filter subscribed with `requested` QoS and acknowledged with `code`.
+fn subscribe_outcome(requested: QoS, code: SubscribeReasonCode) -> SubscribeOutcome {
+    match code {
+        SubscribeReasonCode::Success(granted) if granted < requested => {
+            SubscribeOutcome::Downgraded(granted)
+        }
+        SubscribeReasonCode::Success(granted) => SubscribeOutcome::Subscribed(granted),
+        SubscribeReasonCode::Failure => match lower_qos(requested) {
+            Some(qos) => SubscribeOutcome::Retry(qos),
+            None => SubscribeOutcome::Failed,
+        },
+    }
+}
+
+/// Command queues of the tasks controlling outputs.
+#[derive(Debug, Clone)]
+pub struct CommandQueues {
+    pub kbus: UnboundedSender<KBusCommand>,
+    /// Write commands for the Modbus task, if Modbus is enabled
+    pub modbus: Option<UnboundedSender<ModbusCommand>>,
+}
+
+pub(super) struct MqttEventLoop {
+    event_loop: EventLoop,
+    router: TopicRouter,
+    kbus_commands: UnboundedSender<KBusCommand>,
+    /// Write commands for the Modbus task, if Modbus is enabled
+    modbus_commands: Option<UnboundedSender<ModbusCommand>>,
+    publisher: MqttPublisher,
+    retained_commands: RetainedCommands,
+    retained_max_age: Duration,
+    command_max_age: Option<Duration>,
+    rejections: RejectionStats,
+    rejections_interval: Duration,
+    max_payload_size: usize,
+    rate_limiter: Option<RateLimiter>,
+    /// Pending publish handshakes, if `ack_timeout` is set
+    handshakes: Option<HandshakeTracker>,
+    pub(super) claim: Option<Claim>,
+    /// Whether outputs are currently enabled in the K-Bus task
+    pub(super) outputs_enabled: bool,
+    /// Shadow mode, output commands are accepted but not written
+    shadow: bool,
+    /// Output settings, for the custom payloads of commands
+    outputs_config: OutputsConfig,
+    /// Output commands received before the K-Bus is running
+    startup: StartupQueue,
+    /// Aggregator mode, messages of other bridges are queued for the forwarding loop
+    aggregator: Option<(Aggregator, UnboundedSender<Forward>)>,
+    /// SUBSCRIBE requests queued in the client, not yet sent to the broker
+    queued_subscriptions: VecDeque<Vec<SubscribeFilter>>,
+    /// SUBSCRIBE packets sent to the broker, waiting for SUBACK, by packet id
+    pending_subscriptions: HashMap<u16, Vec<SubscribeFilter>>,
+    /// Configuration file and current configuration, if remote updates are enabled
+    config_update: Option<(PathBuf, Config)>,
+    /// Shared key of signed write commands, unsigned ones are rejected if set
+    signing: Option<SigningConfig>,
+    /// Set once a configuration update was staged, the bridge shuts down to apply it
+    restart: bool,
+    /// Requests for the self-update loop, if self-update is enabled
+    pub(super) update_requests: Option<UnboundedSender<()>>,
+}
+
+impl MqttEventLoop {
+    pub(super) fn new(
+        event_loop: EventLoop,
+        topic_prefix: String,
+        commands: CommandQueues,
+        publisher: MqttPublisher,
+        config: &Config,
+        device_id: &str,
+        aggregator: Option<(Aggregator, UnboundedSender<Forward>)>,
+    ) -> MqttEventLoop {
+        let mut router = TopicRouter::new(&topic_prefix, OUTPUT_SIZE);
+        if let Some(modbus) = &config.modbus {
+            router = router.with_modbus_devices(&modbus.devices);
+        }
+        if let Some(tasmota) = tasmota_topics(config) {
+            router = router.with_tasmota(&tasmota.command_prefix());
+        }
+        if config.mqtt.raw_bits {
+            router = router.with_raw_bits();
+        }
+        if !config.outputs.groups.is_empty() {
+            router = router.with_output_groups(&config.outputs.groups);
+        }
+        if !config.alarms.is_empty() {
+            router = router.with_alarms(&config.alarms);
+        }
+        let config_update = config
+            .file
+            .clone()
+            .filter(|_| config.remote_config.enabled)
+            .map(|file| (file, config.clone()));
+        let shadow = config.outputs.shadow;
+        OUTPUTS_SHADOW.store(shadow, Ordering::Relaxed);
+        let startup = StartupQueue::new(config.outputs.startup_max_age);
+        let outputs_config = config.outputs.clone();
+        let signing = config.signing.clone();
+        let config = &config.mqtt;
+        MqttEventLoop {
+            event_loop,
+            router,
+            kbus_commands: commands.kbus,
+            modbus_commands: commands.modbus,
+            publisher,
+            retained_commands: config.retained_commands,
+            retained_max_age: config.retained_max_age,
+            command_max_age: config.command_max_age,
+            rejections: RejectionStats::default(),
+            rejections_interval: config.rejections_interval,
+            max_payload_size: config.max_payload_size,
+            rate_limiter: (config.max_message_rate > 0)
+                .then(|| RateLimiter::new(config.max_message_rate, now())),
+            handshakes: (!config.ack_timeout.is_zero())
+                .then(|| HandshakeTracker::new(config.ack_timeout)),
+            claim: (!config.claim_interval.is_zero())
+                .then(|| Claim::new(device_id, config.claim_interval, now())),
+            outputs_enabled: config.claim_interval.is_zero(),
+            shadow,
+            outputs_config,
+            startup,
+            aggregator,
+            queued_subscriptions: VecDeque::new(),
+            pending_subscriptions: HashMap::new(),
+            config_update,
+            signing,
+            restart: false,
+            update_requests: None,
+        }
+    }
+
+    /// Queues a SUBSCRIBE request for the given filters.
+    ///
+    /// Doesn't wait for the client request queue, so it's safe to call while
+    /// handling event loop notifications.
+    pub(super) fn subscribe(&mut self, filters: Vec<SubscribeFilter>) -> Result<(), anyhow::Error> {
+        self.publisher
+            .client
+            .try_subscribe_many(filters.clone())
+            .context("failed to queue MQTT subscription")?;
+        // Requests are sent in order, the packet id is known once it goes out
+        self.queued_subscriptions.push_back(filters);
+        Ok(())
+    }
+
+    fn on_subscribe_sent(&mut self, pkid: u16) {
+        if let Some(filters) = self.queued_subscriptions.pop_front() {
+            self.pending_subscriptions.insert(pkid, filters);
+        }
+    }
+
+    /// Checks the results of a SUBSCRIBE request.
+    ///
+    /// Return codes of the SUBACK follow the order of filters in the SUBSCRIBE packet.
+    /// Rejected filters are retried with a lower QoS; a filter rejected even with QoS0
+    /// is reported on the status topic as `degraded`.
+    fn on_suback(&mut self, suback: &SubAck) -> Result<(), anyhow::Error> {
+        let Some(filters) = self.pending_subscriptions.remove(&suback.pkid) else {
+            warn!(pkid = suback.pkid, "unexpected SUBACK");
+            return Ok(());
+        };
+
+        let mut retries = Vec::new();
+        for (filter, code) in filters.into_iter().zip(&suback.return_codes) {
+            match subscribe_outcome(filter.qos, *code) {
+                SubscribeOutcome::Downgraded(granted) => {
+                    warn!(
+                        topic = filter.path,
+                        requested = ?filter.qos,
+                        ?granted,
+                        "broker does not support requested subscription QoS, falling back"
+                    );
+                }
+                SubscribeOutcome::Subscribed(granted) => {
+                    info!(topic = filter.path, ?granted, "subscribed");
+                }
+                SubscribeOutcome::Retry(qos) => {
+                    warn!(
+                        topic = filter.path,
+                        rejected = ?filter.qos,
+                        retry = ?qos,
+                        "subscription rejected by broker, retrying with lower QoS"
+                    );
+                    retries.push(SubscribeFilter::new(filter.path, qos));
+                }
+                SubscribeOutcome::Failed => {
+                    error!(topic = filter.path, "subscription rejected by broker");
+                    MQTT_SUBSCRIPTIONS_FAILED.fetch_add(1, Ordering::Relaxed);
+                    self.publish_status("degraded");
+                }
+            }
+        }
+
+        if !retries.is_empty() {
+            self.subscribe(retries)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes a retained status from a separate task, the event loop must keep
+    /// polling for the publish to make progress.
+    fn publish_status(&self, status: &'static str) {
+        let publisher = self.publisher.clone();
+        tokio::spawn(async move {
+            if let Err(err) = publisher
+                .publish("status", QoS::ExactlyOnce, true, status.to_owned())
+                .await
+            {
+                warn!(
+                    error = format!("{err:#}"),
+                    status, "failed to publish status"
+                );
+            }
+        });
+    }
+
+    /// Checks the incoming message against the payload size and message rate limits.
+    ///
+    /// Messages exceeding the limits are dropped before any further processing, so a
+    /// flooding publisher can't overload the bridge.
+    fn within_limits(&mut self, topic: &str, payload: &[u8]) -> bool {
+        if self.max_payload_size > 0 && payload.len() > self.max_payload_size {
+            debug!(
+                topic,
+                size = payload.len(),
+                "message dropped, payload too large"
+            );
+            return false;
+        }
+        if let Some(rate_limiter) = &mut self.rate_limiter {
+            if !rate_limiter.allow(now()) {
+                debug!(topic, "message dropped, rate limit exceeded");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Logs and counts a rejected message.
+    ///
+    /// Only the first rejection on a topic per reporting interval is logged as a warning,
+    /// so a flooding publisher can't flood the log.
+    fn on_rejected(&mut self, topic: &str, payload: &[u8], err: &anyhow::Error) {
+        MQTT_MESSAGES_REJECTED.fetch_add(1, Ordering::Relaxed);
+
+        let reason = err
+            .downcast_ref::<RejectReason>()
+            .map_or("invalid_command", RejectReason::code);
+        let message_rejected = format!("{err:#}");
+        let payload = String::from_utf8_lossy(payload);
+        if self.rejections.record(topic, reason) {
+            warn!(message_rejected, reason, topic, %payload);
+        } else {
+            debug!(message_rejected, reason, topic, %payload);
+        }
+    }
+
+    /// Writes the output commands queued during startup once the K-Bus is running.
+    ///
+    /// Expired commands are dropped. Returns `false` while the K-Bus isn't running yet.
+    fn flush_startup_queue(&mut self) -> Result<bool, anyhow::Error> {
+        for write in self.startup.expire(now()) {
+            warn_throttled!(
+                "startup_expired",
+                ?write,
+                "output command queued during startup expired"
+            );
+        }
+        if !kbus::is_running() {
+            return Ok(false);
+        }
+        for write in self.startup.drain() {
+            info!(?write, "writing output command queued during startup");
+            self.kbus_commands
+                .send(KBusCommand::Output(write))
+                .context("K-Bus command queue closed")?;
+        }
+        Ok(true)
+    }
+
+    /// Publishes the rejected messages statistics of the last interval on `security/rejections`.
+    fn publish_rejections(&mut self) {
+        let Some(report) = self.rejections.take_report(self.rejections_interval) else {
+            return;
+        };
+        warn!(
+            total = report["total"].as_u64(),
+            "messages rejected in the last {:?}", self.rejections_interval
+        );
+
+        if let Err(err) = self.publisher.publish_background(
+            "security/rejections",
+            QoS::AtLeastOnce,
+            false,
+            report.to_string(),
+        ) {
+            warn!(
+                error = format!("{err:#}"),
+                "failed to publish rejection statistics"
+            );
+        }
+    }
+
+    /// Reports the publishes whose handshake stalled or completed after stalling,
+    /// on `diagnostics` with QoS0 so the report doesn't need an inflight slot.
+    fn report_stalled(&self, publishes: Vec<StalledPublish>) {
+        for publish in publishes {
+            if publish.stalled {
+                MQTT_PUBLISHES_STALLED.fetch_add(1, Ordering::Relaxed);
+                warn!(?publish, "broker stalls the publish handshake");
+            } else {
+                info!(?publish, "stalled publish handshake completed");
+            }
+            let mut payload = json!(publish);
+            payload["diagnostic"] = json!("publish_stalled");
+            payload["timestamp"] = timestamp::now();
+            if let Err(err) = self.publisher.publish_background(
+                "diagnostics",
+                QoS::AtMostOnce,
+                false,
+                payload.to_string(),
+            ) {
+                warn!(error = format!("{err:#}"), "failed to publish diagnostic");
+            }
+        }
+    }
+
+    /// Re-evaluates the claim of the device identity.
+    ///
+    /// Enables or disables outputs in the K-Bus task when the claim is gained or lost,
+    /// and refreshes the retained claim while holding it.
+    fn update_claim(&mut self, refresh: bool) -> Result<(), anyhow::Error> {
+        let Some(claim) = &self.claim else {
+            return Ok(());
+        };
+
+        let now = now();
+        let holder = claim.is_holder(now);
+        if holder != self.outputs_enabled {
+            if holder {
+                info!(
+                    instance = claim.message().instance,
+                    "claim acquired, outputs enabled"
+                );
+            } else if let Some(other) = claim.holder(now) {
+                error!(
+                    instance = claim.message().instance,
+                    holder = other.instance,
+                    "device identity claimed by another instance, outputs disabled"
+                );
+            }
+            self.kbus_commands
+                .send(KBusCommand::OutputsEnabled(holder))
+                .context("K-Bus command queue closed")?;
+            self.outputs_enabled = holder;
+        }
+
+        if holder && refresh {
+            let publisher = self.publisher.clone();
+            let payload = serde_json::to_string(claim.message())?;
+            tokio::spawn(async move {
+                if let Err(err) = publisher
+                    .publish("claim", QoS::AtLeastOnce, true, payload)
+                    .await
+                {
+                    warn!(error = format!("{err:#}"), "failed to publish claim");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies the retained command policy to an output command.
+    fn check_retained(&self, timestamp: Option<DateTime<Utc>>) -> Result<(), anyhow::Error> {
+        match self.retained_commands {
+            RetainedCommands::Accept => Ok(()),
+            RetainedCommands::Ignore => Err(anyhow!("retained command ignored")),
+            RetainedCommands::Fresh => {
+                let timestamp = timestamp.context("retained command without timestamp")?;
+                check_command_age(timestamp, self.retained_max_age, Utc::now())
+            }
+        }
+    }
+
+    /// Applies the signature check, the retained command policy and the maximum
+    /// command age to an output or Modbus write command.
+    fn check_command<T>(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        command: &OutputCommand<T>,
+        retain: bool,
+    ) -> Result<(), anyhow::Error> {
+        self.check_signature(topic, payload)?;
+        if retain {
+            self.check_retained(command.timestamp)?;
+        }
+        if let (Some(max_age), Some(timestamp)) = (self.command_max_age, command.timestamp) {
+            check_command_age(timestamp, max_age, Utc::now())?;
+        }
+        if let Ok(payload) = from_utf8(payload) {
+            info!(topic, payload, retain);
+        } else {
+            info!(topic, ?payload, retain);
+        }
+        Ok(())
+    }
+
+    /// Verifies the signature of a command with `[signing]` configured.
+    fn check_signature(&self, topic: &str, payload: &[u8]) -> Result<(), anyhow::Error> {
+        match &self.signing {
+            Some(signing) => signature::verify(signing, topic, payload, Utc::now()),
+            None => Ok(()),
+        }
+    }
+
+    /// Validates a configuration update and writes it to the configuration file.
+    ///
+    /// Passwords redacted in the update are kept from the current configuration.
+    fn update_config(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> Result<(), anyhow::Error> {
+        let Some((file, current)) = &self.config_update else {
+            return Err(anyhow!("remote configuration updates disabled"));
+        };
+        // A retained update would be applied again on every start
+        if retain {
+            return Err(anyhow!("retained configuration update"));
+        }
+        let payload = decode_config_update(self.signing.as_ref(), topic, payload, Utc::now())?;
+        let mut config = update::parse(&payload, current.profile.as_deref())?;
+        config.unredact(current);
+        update::stage(file, &config)?;
+        self.restart = true;
+        Ok(())
+    }
+
+    /// Publishes the result of a configuration update on `config/result`.
+    ///
+    /// Queued directly in the client, the message must be sent while the bridge
+    /// shuts down to apply the update.
+    fn publish_config_result(&self, result: &Result<(), anyhow::Error>) {
+        let payload = match result {
+            Ok(()) => json!({ "status": "applied", "timestamp": timestamp::now() }),
+            Err(err) => json!({
+                "status": "rejected",
+                "error": format!("{err:#}"),
+                "timestamp": timestamp::now(),
+            }),
+        };
+        if let Err(err) = self.publisher.client.try_publish(
+            self.publisher.full_topic("config/result"),
+            QoS::AtLeastOnce,
+            false,
+            payload.to_string(),
+        ) {
+            warn!(error = %err, "failed to publish configuration update result");
+        }
+    }
+
+    /// Queues a write command for the Modbus task.
+    fn write_modbus(&self, command: ModbusCommand) -> Result<(), anyhow::Error> {
+        if !self.outputs_enabled {
+            return Err(anyhow!(
+                "outputs disabled, device identity not claimed by this instance"
+            ));
+        }
+        if self.shadow {
+            // Still rejected if Modbus is disabled, like a real write
+            self.modbus_commands.as_ref().context("Modbus disabled")?;
+            info!(?command, "shadow mode, Modbus write not executed");
+            return Ok(());
+        }
+        self.modbus_commands
+            .as_ref()
+            .context("Modbus disabled")?
+            .send(command)
+            .context("Modbus command queue closed")
+    }
+
+    fn on_mqtt_message(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> Result<(), anyhow::Error> {
+        if let Some((aggregator, forwards)) = &self.aggregator {
+            if let Some(forward) = aggregator.forward(topic, payload, retain) {
+                return forwards
+                    .send(forward)
+                    .context("aggregator forwarding queue closed");
+            }
+        }
+
+        match self.router.route(topic)? {
+            Route::Output { channel } => {
+                if !self.outputs_enabled {
+                    return Err(anyhow!(
+                        "outputs disabled, device identity not claimed by this instance"
+                    ));
+                }
+                let command = self
+                    .outputs_config
+                    .payload(channel)
+                    .and_then(|custom| payloads::parse(custom, channel, payload))
+                    .map(|value| OutputCommand {
+                        value,
+                        timestamp: None,
+                        id: None,
+                        _signature: None,
+                    })
+                    .or_else(|| decode_output_command(payload));
+                if let Some(command) = command {
+                    let write = OutputWrite::new(
+                        KBusEvent {
+                            channel,
+                            value: command.value,
+                        },
+                        command.id.clone(),
+                    );
+                    let _command_span = info_span!("command", id = write.id).entered();
+                    self.check_command(topic, payload, &command, retain)?;
+                    // Queued commands are written first to keep the order
+                    if !kbus::is_running() || !self.startup.is_empty() {
+                        info!(?write, "K-Bus not running yet, output command queued");
+                        self.startup.push(write, now())?;
+                    } else {
+                        info!(?write, "output command");
+                        self.kbus_commands
+                            .send(KBusCommand::Output(write))
+                            .context("K-Bus command queue closed")?;
+                    }
+                    CHANNEL_STATS.lock().unwrap().on_output_command(channel);
+                    Ok(())
+                } else {
+                    Err(anyhow!("invalid payload"))
+                }
+            }
+            Route::Dump => {
+                info!(topic, "process image dump requested");
+                // Not queued, the K-Bus may be unavailable for long if it's not required
+                if !kbus::is_running() {
+                    return Err(RejectReason::NotReady.into());
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                self.kbus_commands
+                    .send(KBusCommand::Dump(reply_tx))
+                    .context("K-Bus command queue closed")?;
+                self.respond("dump", reply_rx, |image| dump_payload(&image));
+                Ok(())
+            }
+            Route::Soe => {
+                info!(topic, "sequence of events requested");
+                if !kbus::is_running() {
+                    return Err(RejectReason::NotReady.into());
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                self.kbus_commands
+                    .send(KBusCommand::Soe(reply_tx))
+                    .context("K-Bus command queue closed")?;
+                self.respond("soe", reply_rx, |dump| soe_payload(&dump));
+                Ok(())
+            }
+            Route::Read => {
+                let request: ReadRequest =
+                    serde_json::from_slice(payload).context("invalid read request")?;
+                info!(topic, ?request);
+                if !kbus::is_running() {
+                    return Err(RejectReason::NotReady.into());
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                self.kbus_commands
+                    .send(KBusCommand::Read {
+                        offset: request.offset,
+                        length: request.length,
+                        reply: reply_tx,
+                    })
+                    .context("K-Bus command queue closed")?;
+                self.respond("read", reply_rx, move |result| {
+                    read_payload(&request, result)
+                });
+                Ok(())
+            }
+            Route::OutputBit { offset } => {
+                let command = decode_output_command(payload).context("invalid payload")?;
+                // A retained write would be repeated on every subscription
+                if retain {
+                    return Err(anyhow!("retained raw bit write"));
+                }
+                self.check_command(topic, payload, &command, retain)?;
+                let value = command.value;
+                if !kbus::is_running() {
+                    return Err(RejectReason::NotReady.into());
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                self.kbus_commands
+                    .send(KBusCommand::WriteBit {
+                        offset: offset.into(),
+                        value,
+                        reply: reply_tx,
+                    })
+                    .context("K-Bus command queue closed")?;
+                self.respond(
+                    format!("output/bit/{offset}/state"),
+                    reply_rx,
+                    move |result| bit_payload(offset, result.map(|()| value)),
+                );
+                Ok(())
+            }
+            Route::InputBit { offset } => {
+                if retain {
+                    return Err(anyhow!("retained raw bit read"));
+                }
+                info!(topic, offset, "raw input bit read");
+                if !kbus::is_running() {
+                    return Err(RejectReason::NotReady.into());
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                self.kbus_commands
+                    .send(KBusCommand::ReadBit {
+                        offset: offset.into(),
+                        reply: reply_tx,
+                    })
+                    .context("K-Bus command queue closed")?;
+                self.respond(
+                    format!("input/bit/{offset}/state"),
+                    reply_rx,
+                    move |result| bit_payload(offset, result),
+                );
+                Ok(())
+            }
+            Route::Stats => {
+                info!(topic, "channel statistics requested");
+                let report = CHANNEL_STATS.lock().unwrap().report();
+                self.publisher.publish_background(
+                    "stats/channels",
+                    QoS::AtLeastOnce,
+                    false,
+                    report.to_string(),
+                )
+            }
+            Route::Ping => {
+                let payload = from_utf8(payload).context("invalid payload")?;
+                // A retained ping would be answered on every subscription
+                if retain {
+                    return Err(anyhow!("retained ping"));
+                }
+                debug!(topic, payload, "ping");
+                self.publisher.publish_background(
+                    "bridge/pong",
+                    QoS::AtLeastOnce,
+                    false,
+                    pong_payload(payload).to_string(),
+                )
+            }
+            Route::Shadow => {
+                let enabled = decode_output_command(payload)
+                    .context("invalid payload")?
+                    .value;
+                self.check_signature(topic, payload)?;
+                // A retained request would override the configuration on every start
+                if retain {
+                    return Err(anyhow!("retained shadow mode request"));
+                }
+                self.kbus_commands
+                    .send(KBusCommand::Shadow(enabled))
+                    .context("K-Bus command queue closed")?;
+                if enabled {
+                    info!("shadow mode enabled, outputs are not written");
+                } else {
+                    warn!("shadow mode disabled, outputs are written");
+                }
+                self.shadow = enabled;
+                OUTPUTS_SHADOW.store(enabled, Ordering::Relaxed);
+                Ok(())
+            }
+            Route::Claim => {
+                let Some(claim) = &mut self.claim else {
+                    return Ok(());
+                };
+                // Empty payload clears the retained claim on shutdown of the holder
+                if payload.is_empty() {
+                    claim.on_release();
+                } else {
+                    let message: ClaimMessage =
+                        serde_json::from_slice(payload).context("invalid claim")?;
+                    claim.on_message(message, now());
+                }
+                self.update_claim(false)
+            }
+            Route::ConfigSet => {
+                let result = self.update_config(topic, payload, retain);
+                self.publish_config_result(&result);
+                match result {
+                    Ok(()) => info!("configuration updated, restarting to apply it"),
+                    // Not rejected with the payload logged, it may contain passwords
+                    Err(err) => {
+                        MQTT_MESSAGES_REJECTED.fetch_add(1, Ordering::Relaxed);
+                        warn!(error = format!("{err:#}"), "configuration update rejected");
+                    }
+                }
+                Ok(())
+            }
+            Route::Update => {
+                let requests = self
+                    .update_requests
+                    .as_ref()
+                    .context("self-update disabled")?;
+                self.check_signature(topic, payload)?;
+                // A retained request would update and restart the bridge on every start
+                if retain {
+                    return Err(anyhow!("retained self-update request"));
+                }
+                info!("self-update requested");
+                requests.send(()).context("self-update queue closed")
+            }
+            Route::AlarmAck { alarm } => {
+                self.check_signature(topic, payload)?;
+                // A retained acknowledgment would acknowledge every future alarm
+                if retain {
+                    return Err(anyhow!("retained alarm acknowledgment"));
+                }
+                let alarms = self.publisher.alarms.as_ref().context("alarms disabled")?;
+                let (event, summary) = {
+                    let mut alarms = alarms.lock().unwrap();
+                    let Some(event) = alarms.acknowledge(alarm) else {
+                        debug!(topic, "alarm not waiting for an acknowledgment");
+                        return Ok(());
+                    };
+                    (event, alarms.summary())
+                };
+                event.log();
+                self.publisher.publish_background(
+                    "alarms",
+                    QoS::AtLeastOnce,
+                    false,
+                    event.payload().to_string(),
+                )?;
+                self.publisher.publish_background(
+                    "alarms/active",
+                    QoS::AtLeastOnce,
+                    true,
+                    summary.to_string(),
+                )
+            }
+            Route::ModbusCoil { device, address } => {
+                let command = decode_output_command(payload).context("invalid payload")?;
+                self.check_command(topic, payload, &command, retain)?;
+                self.write_modbus(ModbusCommand {
+                    device,
+                    address,
+                    value: ModbusValue::Bit(command.value),
+                })
+            }
+            Route::ModbusHolding { device, address } => {
+                let command = decode_register_command(payload).context("invalid payload")?;
+                self.check_command(topic, payload, &command, retain)?;
+                self.write_modbus(ModbusCommand {
+                    device,
+                    address,
+                    value: ModbusValue::Register(command.value),
+                })
+            }
+        }
+    }
+
+    /// Waits for the K-Bus task reply and publishes the formatted response on `topic`.
+    ///
+    /// The reply is awaited in a separate task, the event loop must keep polling
+    /// meanwhile. The response is published with the background priority.
+    fn respond<T, F>(&self, topic: impl Into<String>, reply_rx: oneshot::Receiver<T>, format: F)
+    where
+        T: Send + 'static,
+        F: FnOnce(T) -> serde_json::Value + Send + 'static,
+    {
+        let publisher = self.publisher.clone();
+        let topic = topic.into();
+        tokio::spawn(async move {
+            let Ok(reply) = reply_rx.await else {
+                warn!(topic, "K-Bus task dropped request");
+                return;
+            };
+            let payload = format(reply).to_string();
+            if let Err(err) = publisher.publish_background(&topic, QoS::AtLeastOnce, false, payload)
+            {
+                warn!(
+                    error = format!("{err:#}"),
+                    topic, "failed to publish response"
+                );
+            }
+        });
+    }
+
+    /// Keeps polling the event loop while `publish` queues the final messages, until
+    /// all queued requests are sent and QoS1/2 publishes are acknowledged by the broker.
+    ///
+    /// Gives up after `timeout`, the remaining messages are dropped.
+    pub(super) async fn drain(
+        &mut self,
+        publish: impl Future<Output = Result<(), anyhow::Error>>,
+        timeout: Duration,
+    ) -> Result<(), anyhow::Error> {
+        if timeout.is_zero() {
+            return Ok(());
+        }
+
+        let drain = async {
+            tokio::pin!(publish);
+            let mut published = false;
+            loop {
+                tokio::select! {
+                    res = &mut publish, if !published => {
+                        res?;
+                        published = true;
+                    }
+                    res = time::timeout(DRAIN_IDLE_TIME, self.poll()) => match res {
+                        Ok(notification) => {
+                            trace!(?notification, "draining");
+                        }
+                        // No request was waiting to be sent, done once all acks arrived
+                        Err(_) if published && self.event_loop.state.inflight() == 0 => {
+                            return Ok::<(), anyhow::Error>(());
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+        };
+
+        match time::timeout(timeout, drain).await {
+            Ok(res) => res.context("failed to drain pending MQTT messages"),
+            Err(_) => {
+                warn!(
+                    inflight = self.event_loop.state.inflight(),
+                    "timed out draining pending MQTT messages"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<Event, anyhow::Error> {
+        self.event_loop
+            .poll()
+            .await
+            .context("failed to poll MQTT event loop")
+    }
+}
+
+/// Returns the current time of the tokio clock, which is paused and advanced
+/// manually in tests of time-based features.
+fn now() -> Instant {
+    time::Instant::now().into_std()
+}
+
+/// Waits for the next tick of an optional timer, never completes if there's no timer.
+async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[instrument(name = "sub", skip_all, err)]
+pub(super) async fn mqtt_event_loop(event_loop: &mut MqttEventLoop) -> Result<(), anyhow::Error> {
+    let rejections_interval = event_loop.rejections_interval;
+    let mut rejections_timer = (!rejections_interval.is_zero()).then(|| {
+        time::interval_at(
+            time::Instant::now() + rejections_interval,
+            rejections_interval,
+        )
+    });
+    // The first claim is evaluated after one interval, once the retained claim
+    // of another instance had time to arrive
+    let mut claim_timer = event_loop.claim.as_ref().map(|claim| {
+        let interval = claim.interval();
+        time::interval_at(time::Instant::now() + interval, interval)
+    });
+    // Checked until the K-Bus is running and the commands queued before are written
+    let mut startup_timer = Some(interval(STARTUP_CHECK_INTERVAL));
+    let mut handshake_timer = event_loop
+        .handshakes
+        .is_some()
+        .then(|| interval(HANDSHAKE_CHECK_INTERVAL));
+
+    loop {
+        let notification = tokio::select! {
+            notification = event_loop.poll() => notification?,
+            _ = tick(&mut rejections_timer) => {
+                event_loop.publish_rejections();
+                continue;
+            }
+            _ = tick(&mut claim_timer) => {
+                event_loop.update_claim(true)?;
+                continue;
+            }
+            _ = tick(&mut startup_timer) => {
+                if event_loop.flush_startup_queue()? {
+                    startup_timer = None;
+                }
+                continue;
+            }
+            _ = tick(&mut handshake_timer) => {
+                if let Some(handshakes) = &mut event_loop.handshakes {
+                    let stalled = handshakes.check(now());
+                    event_loop.report_stalled(stalled);
+                }
+                continue;
+            }
+        };
+        trace!(?notification);
+        match notification {
+            Event::Incoming(Packet::Publish(Publish {
+                topic,
+                payload,
+                retain,
+                ..
+            })) => {
+                MQTT_MESSAGES_RECEIVED.fetch_add(1, Ordering::Relaxed);
+
+                if !event_loop.within_limits(&topic, &payload) {
+                    MQTT_MESSAGES_DROPPED.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                let transformed = match &event_loop.publisher.transform {
+                    Some(transform) => match transform.incoming(&topic, &payload) {
+                        Ok(Some(message)) => Some(message),
+                        Ok(None) => {
+                            debug!(topic, "dropped by transform script");
+                            MQTT_MESSAGES_DROPPED.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        Err(err) => {
+                            event_loop.on_rejected(&topic, &payload, &err);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                let (topic, payload) = transformed
+                    .as_ref()
+                    .map_or((topic.as_str(), &payload[..]), |message| {
+                        (message.topic.as_str(), &message.payload[..])
+                    });
+
+                if let Err(err) = event_loop.on_mqtt_message(topic, payload, retain) {
+                    event_loop.on_rejected(topic, payload, &err);
+                } else {
+                    MQTT_MESSAGES_PROCESSED.fetch_add(1, Ordering::Relaxed);
+                }
+                if event_loop.restart {
+                    shutdown::initiate(ShutdownReason::Restart);
+                    return Ok(());
+                }
+            }
+            Event::Outgoing(Outgoing::Subscribe(pkid)) => event_loop.on_subscribe_sent(pkid),
+            Event::Incoming(Packet::SubAck(suback)) => event_loop.on_suback(&suback)?,
+            Event::Outgoing(Outgoing::Publish(pkid)) => {
+                if let Some(handshakes) = &mut event_loop.handshakes {
+                    handshakes.on_publish(pkid, now());
+                }
+            }
+            Event::Outgoing(Outgoing::PubRel(pkid)) => {
+                if let Some(handshakes) = &mut event_loop.handshakes {
+                    handshakes.on_pubrel(pkid, now());
+                }
+            }
+            Event::Incoming(
+                Packet::PubAck(PubAck { pkid, .. }) | Packet::PubComp(PubComp { pkid, .. }),
+            ) => {
+                if let Some(handshakes) = &mut event_loop.handshakes {
+                    let completed = handshakes.on_complete(pkid, now());
+                    event_loop.report_stalled(completed.into_iter().collect());
+                }
+            }
+            Event::Incoming(_) | Event::Outgoing(_) => {}
+        }
+    }
+}
+
+/// Publishes the report of a fatal task error retained on `last_error`.
+///
+/// Uses a new connection, the one of the MQTT task is closed by then (or its
+/// failure is the reported error). Gives up after `timeout`.
+pub async fn publish_last_error(
+    mqtt_options: MqttOptions,
+    topic_prefix: &str,
+    report: &ErrorReport,
+    timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
+    client
+        .publish(
+            format!("{topic_prefix}/last_error"),
+            QoS::AtLeastOnce,
+            true,
+            serde_json::to_vec(report)?,
+        )
+        .await?;
+    client.disconnect().await?;
+
+    let publish = async {
+        loop {
+            if let Event::Outgoing(Outgoing::Disconnect) = event_loop.poll().await? {
+                return Ok::<(), anyhow::Error>(());
+            }
+        }
+    };
+    time::timeout(timeout, publish)
+        .await
+        .context("timed out publishing last error")?
+}
+
+/// Returns the command topics of the writable coils and holding registers, relative to the prefix.
+pub(super) fn modbus_subscriptions(config: &ModbusConfig) -> impl Iterator<Item = String> + '_ {
+    config.devices.iter().flat_map(|device| {
+        let coils = device
+            .coils
+            .map(|_| format!("modbus/{}/coil/+", device.name));
+        let holding_registers = device
+            .holding_registers
+            .map(|_| format!("modbus/{}/holding/+", device.name));
+        coils.into_iter().chain(holding_registers)
+    })
+}
+
+/// Returns the prefix of command subscriptions shared by the bridges of `group`.
+///
+/// The broker delivers each message of a shared subscription to one subscriber of
+/// the group, with its original topic, so routing of commands is unaffected.
+pub(super) fn share_prefix(group: Option<&str>) -> String {
+    group
+        .map(|group| format!("$share/{group}/"))
+        .unwrap_or_default()
+}
@@ -0,0 +1,257 @@
+use chrono::TimeDelta;
+
+use super::*;
+use crate::kbus::SoeEntry;
+
+#[test]
+fn test_decode_output_command() {
+    assert_eq!(
+        decode_output_command(b"ON"),
+        Some(OutputCommand {
+            value: true,
+            timestamp: None,
+            id: None,
+            _signature: None,
+        })
+    );
+    assert_eq!(
+        decode_output_command(br#"{"value": false}"#),
+        Some(OutputCommand {
+            value: false,
+            timestamp: None,
+            id: None,
+            _signature: None,
+        })
+    );
+
+    let command =
+        decode_output_command(br#"{"value": true, "timestamp": "2025-03-03T06:00:00+01:00"}"#)
+            .unwrap();
+    assert!(command.value);
+    assert_eq!(
+        command.timestamp,
+        Some("2025-03-03T05:00:00Z".parse().unwrap())
+    );
+
+    let command = decode_output_command(br#"{"value": true, "id": "a1"}"#).unwrap();
+    assert_eq!(command.id.as_deref(), Some("a1"));
+    let id = "x".repeat(MAX_COMMAND_ID_LENGTH + 1);
+    let payload = format!(r#"{{"value": true, "id": "{id}"}}"#);
+    assert_eq!(decode_output_command(payload.as_bytes()), None);
+
+    assert_eq!(decode_output_command(b"maybe"), None);
+    assert_eq!(decode_output_command(br#"{"value": 1}"#), None);
+    assert_eq!(
+        decode_output_command(br#"{"value": true, "extra": 1}"#),
+        None
+    );
+}
+
+#[test]
+fn test_decode_config_update() {
+    let topic = "pfc200/bridge/config/set";
+    let now = Utc::now();
+    let toml = b"device_name = \"pfc\"\n";
+    assert_eq!(
+        decode_config_update(None, topic, toml, now).unwrap(),
+        toml.to_vec()
+    );
+
+    // Unsigned updates are rejected, they could remove the signing key
+    let signing = SigningConfig {
+        key: "0123456789abcdef".to_owned(),
+        max_age: Duration::from_secs(60),
+    };
+    let err = decode_config_update(Some(&signing), topic, toml, now).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RejectReason>(),
+        Some(&RejectReason::InvalidSignature)
+    );
+
+    let value = json!("device_name = \"pfc\"\n");
+    let timestamp = now.to_rfc3339();
+    let signature = signature::sign(&signing.key, topic, &value, &timestamp);
+    let payload = json!({ "value": value, "timestamp": timestamp, "signature": signature });
+    assert_eq!(
+        decode_config_update(Some(&signing), topic, payload.to_string().as_bytes(), now).unwrap(),
+        toml.to_vec()
+    );
+
+    // Signed, but the configuration isn't a string
+    let value = json!({ "device_name": "pfc" });
+    let signature = signature::sign(&signing.key, topic, &value, &timestamp);
+    let payload = json!({ "value": value, "timestamp": timestamp, "signature": signature });
+    assert!(
+        decode_config_update(Some(&signing), topic, payload.to_string().as_bytes(), now).is_err()
+    );
+}
+
+#[test]
+fn test_read_request() {
+    let request: ReadRequest = serde_json::from_str(r#"{"offset": 12, "length": 4}"#).unwrap();
+    assert_eq!((request.offset, request.length), (12, 4));
+    assert!(matches!(request.format, ReadFormat::Hex));
+
+    let request: ReadRequest =
+        serde_json::from_str(r#"{"offset": 0, "length": 1, "format": "base64"}"#).unwrap();
+    assert!(matches!(request.format, ReadFormat::Base64));
+    let request: ReadRequest =
+        serde_json::from_str(r#"{"offset": 0, "length": 1, "format": "array"}"#).unwrap();
+    assert!(matches!(request.format, ReadFormat::Array));
+
+    for invalid in [
+        r#"{"offset": 0}"#,
+        r#"{"offset": -1, "length": 1}"#,
+        r#"{"offset": 0, "length": 1, "format": "binary"}"#,
+        r#"{"offset": 0, "length": 1, "count": 2}"#,
+    ] {
+        assert!(
+            serde_json::from_str::<ReadRequest>(invalid).is_err(),
+            "{invalid}"
+        );
+    }
+}
+
+#[test]
+fn test_read_payload() {
+    let request = |format| ReadRequest {
+        offset: 12,
+        length: 3,
+        format,
+    };
+    let data = || Ok(vec![0x01, 0xab, 0xff]);
+
+    let payload = read_payload(&request(ReadFormat::Hex), data());
+    assert_eq!(payload["offset"], 12);
+    assert_eq!(payload["length"], 3);
+    assert_eq!(payload["data"], "01abff");
+    assert!(payload["timestamp"].is_string());
+    assert_eq!(
+        read_payload(&request(ReadFormat::Base64), data())["data"],
+        "Aav/"
+    );
+    assert_eq!(
+        read_payload(&request(ReadFormat::Array), data())["data"],
+        json!([1, 171, 255])
+    );
+
+    let payload = read_payload(
+        &request(ReadFormat::Hex),
+        Err(anyhow!("failed to read from K-Bus")),
+    );
+    assert_eq!(payload["error"], "failed to read from K-Bus");
+    assert!(payload.get("data").is_none());
+}
+
+#[test]
+fn test_subscribe_outcome() {
+    // A rejected subscription steps down one QoS level per retry
+    let mut qos = QoS::ExactlyOnce;
+    let mut retries = Vec::new();
+    loop {
+        match subscribe_outcome(qos, SubscribeReasonCode::Failure) {
+            SubscribeOutcome::Retry(lower) => {
+                retries.push(lower);
+                qos = lower;
+            }
+            outcome => {
+                assert_eq!(outcome, SubscribeOutcome::Failed);
+                break;
+            }
+        }
+    }
+    assert_eq!(retries, [QoS::AtLeastOnce, QoS::AtMostOnce]);
+
+    assert_eq!(
+        subscribe_outcome(
+            QoS::ExactlyOnce,
+            SubscribeReasonCode::Success(QoS::AtLeastOnce)
+        ),
+        SubscribeOutcome::Downgraded(QoS::AtLeastOnce)
+    );
+    assert_eq!(
+        subscribe_outcome(
+            QoS::AtLeastOnce,
+            SubscribeReasonCode::Success(QoS::AtLeastOnce)
+        ),
+        SubscribeOutcome::Subscribed(QoS::AtLeastOnce)
+    );
+}
+
+#[test]
+fn test_check_command_age() {
+    let now: DateTime<Utc> = "2025-03-03T06:00:00Z".parse().unwrap();
+    let max_age = Duration::from_secs(60);
+
+    assert!(check_command_age(now, max_age, now).is_ok());
+    assert!(check_command_age(now - TimeDelta::seconds(60), max_age, now).is_ok());
+    assert!(check_command_age(now - TimeDelta::seconds(61), max_age, now).is_err());
+    // Clock skew, the command comes from the future
+    assert!(check_command_age(now + TimeDelta::seconds(10), max_age, now).is_ok());
+}
+
+#[test]
+fn test_rate_limiter() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(2, start);
+    assert!(limiter.allow(start));
+    assert!(limiter.allow(start + Duration::from_millis(100)));
+    assert!(!limiter.allow(start + Duration::from_millis(200)));
+    assert!(!limiter.allow(start + Duration::from_millis(999)));
+    // New window
+    assert!(limiter.allow(start + Duration::from_secs(1)));
+    assert!(limiter.allow(start + Duration::from_millis(1500)));
+    assert!(!limiter.allow(start + Duration::from_millis(1600)));
+}
+
+#[test]
+fn test_decode_register_command() {
+    assert_eq!(
+        decode_register_command(b"1234"),
+        Some(OutputCommand {
+            value: 1234,
+            timestamp: None,
+            id: None,
+            _signature: None,
+        })
+    );
+    assert_eq!(
+        decode_register_command(br#"{"value": 65535}"#).map(|command| command.value),
+        Some(65535)
+    );
+    assert_eq!(decode_register_command(b"65536"), None);
+    assert_eq!(decode_register_command(b"-1"), None);
+    assert_eq!(decode_register_command(b"on"), None);
+}
+
+#[test]
+fn test_pong_payload() {
+    let pong = pong_payload("42");
+    assert_eq!(pong["payload"], "42");
+    assert!(pong["timestamp"].is_string());
+}
+
+#[test]
+fn test_soe_payload() {
+    let time = Utc::now();
+    let payload = soe_payload(&SoeDump {
+        entries: vec![SoeEntry {
+            cycle: 42,
+            time,
+            channel: 3,
+            value: true,
+        }],
+        overwritten: 7,
+    });
+    assert_eq!(payload["overwritten"], 7);
+    assert_eq!(payload["events"][0]["cycle"], 42);
+    assert_eq!(payload["events"][0]["timestamp"], timestamp::at(time));
+    assert_eq!(payload["events"][0]["channel"], 3);
+    assert_eq!(payload["events"][0]["value"], true);
+}
+
+#[test]
+fn test_share_prefix() {
+    assert_eq!(share_prefix(None), "");
+    assert_eq!(share_prefix(Some("bridges")), "$share/bridges/");
+}
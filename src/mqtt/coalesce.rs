@@ -0,0 +1,36 @@
+//! Coalescing of input changes under backpressure
+//!
+//! If the broker is slower than the inputs change, the publish queue grows and
+//! every intermediate state would still be published, long after it's obsolete.
+//! Once more events are queued than `mqtt.coalesce_threshold`, the backlog is
+//! taken at once and only the latest change of every input channel is kept, in
+//! the position of that change. Other events (outputs, derived signals, Modbus
+//! values, ...) are always kept.
+
+use std::collections::HashSet;
+
+use crate::kbus::InputEvent;
+
+#[cfg(test)]
+mod tests;
+
+/// Drops the input changes of `events` followed by a later change of the same channel.
+///
+/// Returns the remaining events in order and the channels of the dropped changes.
+pub fn coalesce(events: Vec<InputEvent>) -> (Vec<InputEvent>, Vec<u16>) {
+    let mut latest = HashSet::new();
+    let mut dropped = Vec::new();
+    let mut kept: Vec<_> = events
+        .into_iter()
+        .rev()
+        .filter(|event| match event {
+            InputEvent::Channel(event) if !latest.insert(event.channel) => {
+                dropped.push(event.channel);
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    kept.reverse();
+    (kept, dropped)
+}
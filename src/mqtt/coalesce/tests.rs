@@ -0,0 +1,56 @@
+use super::*;
+use crate::kbus::{KBusEvent, OutputWrite};
+
+fn channel(channel: u16, value: bool) -> InputEvent {
+    InputEvent::Channel(KBusEvent { channel, value })
+}
+
+fn summary(events: &[InputEvent]) -> Vec<(&'static str, u16, bool)> {
+    events
+        .iter()
+        .map(|event| match event {
+            InputEvent::Channel(event) => ("input", event.channel, event.value),
+            InputEvent::Output(write) => ("output", write.event.channel, write.event.value),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_coalesce() {
+    let output = InputEvent::Output(OutputWrite::new(
+        KBusEvent {
+            channel: 1,
+            value: true,
+        },
+        None,
+    ));
+    let events = vec![
+        channel(1, true),
+        channel(2, true),
+        channel(1, false),
+        output,
+        channel(1, true),
+        channel(3, false),
+    ];
+
+    let (kept, dropped) = coalesce(events);
+    // Latest change of every channel in its position, other events untouched
+    assert_eq!(
+        summary(&kept),
+        [
+            ("input", 2, true),
+            ("output", 1, true),
+            ("input", 1, true),
+            ("input", 3, false),
+        ]
+    );
+    assert_eq!(dropped, [1, 1]);
+}
+
+#[test]
+fn test_coalesce_nothing() {
+    let (kept, dropped) = coalesce(vec![channel(1, true), channel(2, false)]);
+    assert_eq!(summary(&kept), [("input", 1, true), ("input", 2, false)]);
+    assert!(dropped.is_empty());
+}
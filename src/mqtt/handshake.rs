@@ -0,0 +1,130 @@
+//! Tracking of publish acknowledgments
+//!
+//! QoS1 and QoS2 publishes, e.g. the `status` topic, occupy a slot of the client's
+//! limited inflight window until the broker completes their handshake (PUBACK, or
+//! PUBREC and PUBCOMP). A broker stalling a handshake silently wedges the window:
+//! once it's full, every further publish waits. Every publish is tracked from its
+//! PUBLISH packet until its handshake completes, one pending for longer than the
+//! timeout is reported once as stalled and again once it completes.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+/// Packet of the broker a pending publish waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitingFor {
+    /// PUBACK (QoS1) or PUBREC (QoS2)
+    Ack,
+    /// PUBCOMP, after PUBREC was received and PUBREL sent (QoS2)
+    Pubcomp,
+}
+
+/// A publish whose handshake didn't complete within the timeout, or completed
+/// after being reported as stalled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StalledPublish {
+    /// Packet identifier of the publish
+    pub pkid: u16,
+    pub waiting_for: WaitingFor,
+    /// Whether the handshake is still pending, `false` once it completed
+    pub stalled: bool,
+    /// Time since the publish was sent
+    #[serde(with = "humantime_serde")]
+    pub pending: Duration,
+}
+
+#[derive(Debug)]
+struct Pending {
+    sent: Instant,
+    waiting_for: WaitingFor,
+    stalled: bool,
+}
+
+/// Pending handshakes of the publishes, by packet identifier.
+#[derive(Debug)]
+pub struct HandshakeTracker {
+    timeout: Duration,
+    pending: HashMap<u16, Pending>,
+}
+
+impl HandshakeTracker {
+    pub fn new(timeout: Duration) -> HandshakeTracker {
+        HandshakeTracker {
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Starts tracking a sent PUBLISH, QoS0 publishes (`pkid` 0) aren't acknowledged.
+    ///
+    /// A publish retransmitted after a reconnect is tracked again from `now`.
+    pub fn on_publish(&mut self, pkid: u16, now: Instant) {
+        if pkid == 0 {
+            return;
+        }
+        let pending = self.pending.entry(pkid).or_insert(Pending {
+            sent: now,
+            waiting_for: WaitingFor::Ack,
+            stalled: false,
+        });
+        pending.sent = now;
+        pending.waiting_for = WaitingFor::Ack;
+    }
+
+    /// Notes a sent PUBREL, the publish now waits for PUBCOMP.
+    ///
+    /// PUBRELs retransmitted after a reconnect are tracked even without the
+    /// PUBLISH.
+    pub fn on_pubrel(&mut self, pkid: u16, now: Instant) {
+        self.pending
+            .entry(pkid)
+            .or_insert(Pending {
+                sent: now,
+                waiting_for: WaitingFor::Pubcomp,
+                stalled: false,
+            })
+            .waiting_for = WaitingFor::Pubcomp;
+    }
+
+    /// Ends tracking a publish on its PUBACK or PUBCOMP, returns it if it was
+    /// reported as stalled.
+    pub fn on_complete(&mut self, pkid: u16, now: Instant) -> Option<StalledPublish> {
+        let pending = self.pending.remove(&pkid)?;
+        pending.stalled.then(|| StalledPublish {
+            pkid,
+            waiting_for: pending.waiting_for,
+            stalled: false,
+            pending: now.saturating_duration_since(pending.sent),
+        })
+    }
+
+    /// Returns the publishes pending for longer than the timeout, each once.
+    pub fn check(&mut self, now: Instant) -> Vec<StalledPublish> {
+        let mut stalled: Vec<_> = self
+            .pending
+            .iter_mut()
+            .filter(|(_, pending)| {
+                !pending.stalled && now.saturating_duration_since(pending.sent) >= self.timeout
+            })
+            .map(|(&pkid, pending)| {
+                pending.stalled = true;
+                StalledPublish {
+                    pkid,
+                    waiting_for: pending.waiting_for,
+                    stalled: true,
+                    pending: now.saturating_duration_since(pending.sent),
+                }
+            })
+            .collect();
+        stalled.sort_by_key(|publish| publish.pkid);
+        stalled
+    }
+}
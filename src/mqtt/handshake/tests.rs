@@ -0,0 +1,122 @@
+use super::*;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn test_completed_in_time() {
+    let start = Instant::now();
+    let mut handshakes = HandshakeTracker::new(TIMEOUT);
+
+    // QoS0 isn't acknowledged
+    handshakes.on_publish(0, start);
+    // QoS1
+    handshakes.on_publish(1, start);
+    // QoS2
+    handshakes.on_publish(2, start);
+    handshakes.on_pubrel(2, start + Duration::from_secs(1));
+
+    assert_eq!(
+        handshakes.on_complete(1, start + Duration::from_secs(1)),
+        None
+    );
+    assert_eq!(
+        handshakes.on_complete(2, start + Duration::from_secs(2)),
+        None
+    );
+    assert!(handshakes.check(start + TIMEOUT).is_empty());
+    // Unknown packet identifier
+    assert_eq!(handshakes.on_complete(3, start + TIMEOUT), None);
+}
+
+#[test]
+fn test_stalled() {
+    let start = Instant::now();
+    let mut handshakes = HandshakeTracker::new(TIMEOUT);
+    handshakes.on_publish(5, start);
+    handshakes.on_pubrel(5, start + Duration::from_secs(1));
+    handshakes.on_publish(6, start + Duration::from_secs(10));
+
+    assert!(
+        handshakes
+            .check(start + TIMEOUT - Duration::from_secs(1))
+            .is_empty()
+    );
+    assert_eq!(
+        handshakes.check(start + TIMEOUT),
+        [StalledPublish {
+            pkid: 5,
+            waiting_for: WaitingFor::Pubcomp,
+            stalled: true,
+            pending: TIMEOUT,
+        }]
+    );
+    // Reported once
+    let later = start + Duration::from_secs(45);
+    assert_eq!(
+        handshakes.check(later),
+        [StalledPublish {
+            pkid: 6,
+            waiting_for: WaitingFor::Ack,
+            stalled: true,
+            pending: Duration::from_secs(35),
+        }]
+    );
+    assert!(handshakes.check(later).is_empty());
+
+    // The late completion clears it
+    assert_eq!(
+        handshakes.on_complete(5, start + Duration::from_secs(60)),
+        Some(StalledPublish {
+            pkid: 5,
+            waiting_for: WaitingFor::Pubcomp,
+            stalled: false,
+            pending: Duration::from_secs(60),
+        })
+    );
+    assert_eq!(
+        handshakes.on_complete(5, start + Duration::from_secs(61)),
+        None
+    );
+}
+
+#[test]
+fn test_retransmitted() {
+    let start = Instant::now();
+    let mut handshakes = HandshakeTracker::new(TIMEOUT);
+    handshakes.on_publish(7, start);
+    assert_eq!(handshakes.check(start + TIMEOUT).len(), 1);
+
+    // Sent again after a reconnect, still cleared on completion
+    let reconnect = start + Duration::from_secs(40);
+    handshakes.on_publish(7, reconnect);
+    assert!(handshakes.check(reconnect + TIMEOUT).is_empty());
+    assert!(
+        handshakes
+            .on_complete(7, reconnect + Duration::from_secs(1))
+            .is_some_and(|publish| !publish.stalled)
+    );
+
+    // A PUBREL retransmitted without its PUBLISH
+    handshakes.on_pubrel(8, reconnect);
+    let stalled = handshakes.check(reconnect + TIMEOUT);
+    assert_eq!(stalled[0].waiting_for, WaitingFor::Pubcomp);
+}
+
+#[test]
+fn test_payload() {
+    let publish = StalledPublish {
+        pkid: 5,
+        waiting_for: WaitingFor::Pubcomp,
+        stalled: true,
+        pending: Duration::from_secs(30),
+    };
+    assert_eq!(
+        serde_json::to_value(&publish).unwrap(),
+        serde_json::json!({
+            "pkid": 5,
+            "waiting_for": "pubcomp",
+            "stalled": true,
+            "pending": "30s",
+        })
+    );
+}
@@ -0,0 +1,57 @@
+//! Custom payloads of channels
+//!
+//! Devices and controllers often expect specific literals like `ON`/`OFF` or a
+//! fixed JSON layout. Channels with a custom payload publish their value as
+//! `payload_on`/`payload_off`, embedded in the template if set, instead of the
+//! payload of the profile. Output commands matching the custom payload are
+//! accepted besides the standard payloads.
+
+use std::str::from_utf8;
+
+use crate::{
+    config::{ChannelPayload, PAYLOAD_CHANNEL, PAYLOAD_TIMESTAMP, PAYLOAD_VALUE},
+    timestamp,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Returns the payload publishing `value` of `channel`.
+pub fn render(payload: &ChannelPayload, channel: u16, value: bool) -> String {
+    let value = if value {
+        &payload.payload_on
+    } else {
+        &payload.payload_off
+    };
+    let Some(template) = &payload.template else {
+        return value.clone();
+    };
+    let mut rendered = template
+        .replace(PAYLOAD_VALUE, value)
+        .replace(PAYLOAD_CHANNEL, &channel.to_string());
+    if rendered.contains(PAYLOAD_TIMESTAMP) {
+        rendered = rendered.replace(PAYLOAD_TIMESTAMP, &timestamp::now_text());
+    }
+    rendered
+}
+
+/// Parses the value of a command on `channel`, `None` if it doesn't match the
+/// custom payload.
+pub fn parse(payload: &ChannelPayload, channel: u16, bytes: &[u8]) -> Option<bool> {
+    let text = from_utf8(bytes).ok()?;
+    let value = match &payload.template {
+        Some(template) => {
+            let template = template.replace(PAYLOAD_CHANNEL, &channel.to_string());
+            let (prefix, suffix) = template.split_once(PAYLOAD_VALUE)?;
+            text.strip_prefix(prefix)?.strip_suffix(suffix)?.to_owned()
+        }
+        None => text.to_owned(),
+    };
+    if value == payload.payload_on {
+        Some(true)
+    } else if value == payload.payload_off {
+        Some(false)
+    } else {
+        None
+    }
+}
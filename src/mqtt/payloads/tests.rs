@@ -0,0 +1,62 @@
+use crate::config::ChannelRange;
+
+use super::*;
+
+fn on_off(template: Option<&str>) -> ChannelPayload {
+    ChannelPayload {
+        channels: ChannelRange { first: 0, last: 7 },
+        payload_on: "ON".to_owned(),
+        payload_off: "OFF".to_owned(),
+        template: template.map(str::to_owned),
+    }
+}
+
+#[test]
+fn test_render() {
+    let payload = on_off(None);
+    assert_eq!(render(&payload, 3, true), "ON");
+    assert_eq!(render(&payload, 3, false), "OFF");
+
+    let payload = on_off(Some(r#"{"id": {channel}, "state": "{value}"}"#));
+    assert_eq!(render(&payload, 3, true), r#"{"id": 3, "state": "ON"}"#);
+
+    let payload = on_off(Some(r#"{"state": "{value}", "time": "{timestamp}"}"#));
+    let rendered: serde_json::Value = serde_json::from_str(&render(&payload, 3, false)).unwrap();
+    assert_eq!(rendered["state"], "OFF");
+    assert!(
+        rendered["time"]
+            .as_str()
+            .is_some_and(|time| !time.is_empty())
+    );
+}
+
+#[test]
+fn test_parse() {
+    let payload = on_off(None);
+    assert_eq!(parse(&payload, 3, b"ON"), Some(true));
+    assert_eq!(parse(&payload, 3, b"OFF"), Some(false));
+    assert_eq!(parse(&payload, 3, b"on"), None);
+    assert_eq!(parse(&payload, 3, b"\xff"), None);
+
+    let payload = on_off(Some(r#"{"id": {channel}, "state": "{value}"}"#));
+    assert_eq!(
+        parse(&payload, 3, br#"{"id": 3, "state": "ON"}"#),
+        Some(true)
+    );
+    assert_eq!(
+        parse(&payload, 3, br#"{"id": 3, "state": "OFF"}"#),
+        Some(false)
+    );
+    // Another channel, value or layout
+    assert_eq!(parse(&payload, 4, br#"{"id": 3, "state": "ON"}"#), None);
+    assert_eq!(parse(&payload, 3, br#"{"id": 3, "state": "DIM"}"#), None);
+    assert_eq!(parse(&payload, 3, br#"{"id":3,"state":"ON"}"#), None);
+
+    // Rendered payloads are parsed back
+    for value in [true, false] {
+        assert_eq!(
+            parse(&payload, 5, render(&payload, 5, value).as_bytes()),
+            Some(value)
+        );
+    }
+}
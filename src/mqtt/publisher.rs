@@ -0,0 +1,615 @@
+//! Publish pipeline
+//!
+//! Formats the input events in the configured payload profile and publishes them,
+//! with the messages of other tasks queued in the background so a slow broker never
+//! blocks the event loop.
+
+use std::sync::{Arc, Mutex, atomic::Ordering};
+
+use anyhow::Context;
+use bitvec::prelude::*;
+use rumqttc::{AsyncClient, QoS};
+use serde_json::json;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{Instrument, Span, debug, info, info_span, instrument, trace, warn};
+
+use crate::{
+    config::{Config, InputsConfig, OutputsConfig, PayloadProfile},
+    kbus::{INPUT_SIZE, InputEvent},
+    shutdown,
+    throttle::warn_throttled,
+    timestamp,
+};
+
+use super::{
+    alarms::Annunciator,
+    coalesce, payloads,
+    stats::{
+        CHANNEL_STATS, INPUT_COALESCED, INPUT_QUEUE_DEPTH, INPUT_SEQUENCE, MQTT_MESSAGES_DROPPED,
+        MQTT_MESSAGES_SENT,
+    },
+    tasmota::Tasmota,
+    transform::Transform,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Version of the collection/telemetry message layout of the WAGO Cloud profile
+const WAGO_CLOUD_PROTOCOL_VERSION: &str = "1.0";
+
+/// Formats the topic (relative to the prefix) and payload of an input event.
+///
+/// `sequence` is the number of the event since the bridge started, included in the
+/// `json` profile so consumers can detect lost messages. Output verification
+/// failures are diagnostics, published on `verify_failed` in every profile, as are
+/// dead input channels on `diagnostics`.
+/// Written outputs are mirrored on `output/<n>/state` in every profile, as JSON
+/// with a timestamp and the correlation id of the command unless the profile is
+/// `plain`.
+fn input_message(profile: PayloadProfile, event: &InputEvent, sequence: u64) -> (String, String) {
+    // Topic in the per-channel profiles, collection and key in the `wago_cloud` profile
+    let (topic, collection, key, value) = match event {
+        InputEvent::Channel(event) => (
+            format!("input/{}", event.channel),
+            "inputs",
+            format!("input_{}", event.channel),
+            json!(event.value),
+        ),
+        InputEvent::Derived(event) => (
+            format!("derived/{}", event.name),
+            "derived",
+            event.name.to_string(),
+            json!(event.value),
+        ),
+        InputEvent::Modbus(event) => (
+            format!("modbus/{}/{}/{}", event.device, event.kind(), event.address),
+            "modbus",
+            format!("{}_{}_{}", event.device, event.kind(), event.address),
+            json!(event.value),
+        ),
+        InputEvent::ModbusAggregate(event) => {
+            let mut payload = json!(event.aggregate);
+            payload["timestamp"] = timestamp::now();
+            let topic = format!(
+                "modbus/{}/register/{}/aggregate",
+                event.device, event.address
+            );
+            return (topic, payload.to_string());
+        }
+        InputEvent::Output(write) | InputEvent::Shadowed(write) => {
+            let topic = format!("output/{}/{}", write.event.channel, output_suffix(event));
+            let payload = match profile {
+                PayloadProfile::Plain => json!(write.event.value),
+                PayloadProfile::Json | PayloadProfile::WagoCloud => json!({
+                    "value": write.event.value,
+                    "timestamp": timestamp::now(),
+                    "id": write.id,
+                }),
+            };
+            return (topic, payload.to_string());
+        }
+        InputEvent::StateDump(dump) => return ("debug/state".to_owned(), dump.to_string()),
+        InputEvent::KBusAvailable(available) => {
+            let status = if *available {
+                "available"
+            } else {
+                "unavailable"
+            };
+            return ("kbus/status".to_owned(), status.to_owned());
+        }
+        InputEvent::VerifyFailed(failed) => {
+            let mut payload = json!(failed);
+            payload["timestamp"] = timestamp::now();
+            return ("verify_failed".to_owned(), payload.to_string());
+        }
+        InputEvent::DeadChannel(dead) => {
+            let mut payload = json!(dead);
+            payload["diagnostic"] = json!("dead_channel");
+            payload["timestamp"] = timestamp::now();
+            return ("diagnostics".to_owned(), payload.to_string());
+        }
+    };
+
+    match profile {
+        PayloadProfile::Plain => (topic, value.to_string()),
+        PayloadProfile::Json => {
+            let payload = json!({
+                "value": value,
+                "timestamp": timestamp::now(),
+                "sequence": sequence,
+            });
+            (topic, payload.to_string())
+        }
+        PayloadProfile::WagoCloud => {
+            let payload = json!({
+                "version": WAGO_CLOUD_PROTOCOL_VERSION,
+                "timestamp": timestamp::now(),
+                "collections": [{
+                    "key": collection,
+                    "variables": [{ "key": key, "value": value }],
+                }],
+            });
+            ("telemetry".to_owned(), payload.to_string())
+        }
+    }
+}
+
+/// Returns the last topic level of an output acknowledgement, `shadow` for
+/// commands accepted in shadow mode.
+fn output_suffix(event: &InputEvent) -> &'static str {
+    match event {
+        InputEvent::Shadowed(_) => "shadow",
+        _ => "state",
+    }
+}
+
+/// Returns the custom payload of an input channel or output state event, if configured.
+fn custom_payload(
+    inputs_config: &InputsConfig,
+    outputs_config: &OutputsConfig,
+    event: &InputEvent,
+) -> Option<String> {
+    match event {
+        InputEvent::Channel(event) => inputs_config
+            .payload(event.channel)
+            .map(|payload| payloads::render(payload, event.channel, event.value)),
+        InputEvent::Output(write) => outputs_config
+            .payload(write.event.channel)
+            .map(|payload| payloads::render(payload, write.event.channel, write.event.value)),
+        _ => None,
+    }
+}
+
+/// A message published with lower priority than input events.
+#[derive(Debug)]
+pub(super) struct BackgroundMessage {
+    /// Full topic including the prefix
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    payload: String,
+}
+
+#[derive(Clone)]
+pub(super) struct MqttPublisher {
+    pub(super) client: AsyncClient,
+    topic_prefix: String,
+    pub(super) transform: Option<Arc<Transform>>,
+    /// Queue of heartbeats and diagnostics, published after pending input events
+    background: UnboundedSender<BackgroundMessage>,
+    /// Alarms evaluated on the published input changes, if configured
+    pub(super) alarms: Option<Arc<Mutex<Annunciator>>>,
+}
+
+impl MqttPublisher {
+    pub(super) fn new(
+        client: AsyncClient,
+        topic_prefix: String,
+        transform: Option<Arc<Transform>>,
+        background: UnboundedSender<BackgroundMessage>,
+    ) -> MqttPublisher {
+        MqttPublisher {
+            client,
+            topic_prefix,
+            transform,
+            background,
+            alarms: None,
+        }
+    }
+
+    /// Evaluates the alarms on the published input changes.
+    pub(super) fn with_alarms(mut self, annunciator: Annunciator) -> MqttPublisher {
+        self.alarms = Some(Arc::new(Mutex::new(annunciator)));
+        self
+    }
+
+    /// Returns the summary of the active alarms, `None` without alarms.
+    pub(super) fn alarm_summary(&self) -> Option<serde_json::Value> {
+        let alarms = self.alarms.as_ref()?;
+        Some(alarms.lock().unwrap().summary())
+    }
+
+    /// Updates the alarms with an input change and publishes their transitions on
+    /// `alarms`, followed by the retained summary on `alarms/active`.
+    async fn update_alarms(&self, channel: u16, value: bool) -> Result<(), anyhow::Error> {
+        let Some(alarms) = &self.alarms else {
+            return Ok(());
+        };
+        let (events, summary) = {
+            let mut alarms = alarms.lock().unwrap();
+            let events = alarms.on_input(channel, value);
+            if events.is_empty() {
+                return Ok(());
+            }
+            (events, alarms.summary())
+        };
+        for event in events {
+            event.log();
+            self.publish(
+                "alarms",
+                QoS::AtLeastOnce,
+                false,
+                event.payload().to_string(),
+            )
+            .await?;
+        }
+        self.publish("alarms/active", QoS::AtLeastOnce, true, summary.to_string())
+            .await
+    }
+
+    /// Applies the outgoing transform script, `None` if the message is dropped.
+    fn transform(&self, topic: String, payload: String) -> Option<(String, Vec<u8>)> {
+        let Some(transform) = &self.transform else {
+            return Some((topic, payload.into_bytes()));
+        };
+        match transform.outgoing(&topic, payload.as_bytes()) {
+            Ok(Some(message)) => Some((message.topic, message.payload)),
+            Ok(None) => {
+                debug!(topic, "dropped by transform script");
+                None
+            }
+            Err(err) => {
+                warn_throttled!(
+                    "transform",
+                    topic,
+                    error = format!("{err:#}"),
+                    "transform script failed"
+                );
+                MQTT_MESSAGES_DROPPED.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub(super) fn full_topic(&self, topic: &str) -> String {
+        let topic_prefix = &self.topic_prefix;
+        if topic_prefix.is_empty() {
+            topic.to_owned()
+        } else {
+            format!("{topic_prefix}/{topic}")
+        }
+    }
+
+    pub(super) async fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: String,
+    ) -> Result<(), anyhow::Error> {
+        self.publish_to(self.full_topic(topic), qos, retain, payload)
+            .await
+    }
+
+    /// Publishes like [`MqttPublisher::publish`] on a full topic, e.g. outside of the prefix.
+    pub(super) async fn publish_to(
+        &self,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: String,
+    ) -> Result<(), anyhow::Error> {
+        info!(topic, payload);
+        let Some((topic, payload)) = self.transform(topic, payload) else {
+            return Ok(());
+        };
+        self.client.publish(topic, qos, retain, payload).await?;
+
+        MQTT_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Queues a message, e.g. a heartbeat or diagnostics, for the publish loop.
+    ///
+    /// Queued messages are only published when no input event is pending, so a burst
+    /// of them can't delay the publication of input state changes.
+    pub(super) fn publish_background(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: String,
+    ) -> Result<(), anyhow::Error> {
+        self.publish_background_to(self.full_topic(topic), qos, retain, payload)
+    }
+
+    /// Queues a message like [`MqttPublisher::publish_background`] on a full topic.
+    pub(super) fn publish_background_to(
+        &self,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: String,
+    ) -> Result<(), anyhow::Error> {
+        self.background
+            .send(BackgroundMessage {
+                topic,
+                qos,
+                retain,
+                payload,
+            })
+            .context("background publish queue closed")
+    }
+
+    /// Publishes like [`MqttPublisher::publish`], but logs only at trace level.
+    ///
+    /// Intended for frequent periodic messages which would flood the log.
+    pub(super) async fn publish_quiet(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: String,
+    ) -> Result<(), anyhow::Error> {
+        let topic = self.full_topic(topic);
+
+        trace!(topic, payload);
+        let Some((topic, payload)) = self.transform(topic, payload) else {
+            return Ok(());
+        };
+        self.client.publish(topic, qos, retain, payload).await?;
+
+        MQTT_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Publishes on a full topic with QoS0, non-retained, skipping logging and statistics.
+    ///
+    /// Intended for high-frequency channels where per-message overhead matters.
+    async fn publish_fast(&self, topic: String, payload: String) -> Result<(), anyhow::Error> {
+        let Some((topic, payload)) = self.transform(topic, payload) else {
+            return Ok(());
+        };
+        self.client
+            .publish(topic, QoS::AtMostOnce, false, payload)
+            .await?;
+        Ok(())
+    }
+}
+
+fn fast_channels(inputs_config: &InputsConfig) -> BitVec {
+    let mut fast_channels = bitvec![0; INPUT_SIZE];
+    for &channel in &inputs_config.fast {
+        fast_channels.set(usize::from(channel), true);
+    }
+    fast_channels
+}
+
+/// Topics and payloads of input events, built once for publishing.
+pub(super) struct InputFormat<'a> {
+    payload_profile: PayloadProfile,
+    inputs_config: &'a InputsConfig,
+    outputs_config: &'a OutputsConfig,
+    fast_channels: BitVec,
+    tasmota: Option<&'a Tasmota>,
+}
+
+impl<'a> InputFormat<'a> {
+    pub(super) fn new(config: &'a Config, tasmota: Option<&'a Tasmota>) -> InputFormat<'a> {
+        InputFormat {
+            payload_profile: config.mqtt.payload_profile,
+            inputs_config: &config.inputs,
+            outputs_config: &config.outputs,
+            fast_channels: fast_channels(&config.inputs),
+            tasmota,
+        }
+    }
+
+    /// Returns the full topic and payload of an input event.
+    fn message(
+        &self,
+        mqtt_publisher: &MqttPublisher,
+        event: &InputEvent,
+        sequence: u64,
+    ) -> (String, String) {
+        if let Some(message) = self.tasmota.and_then(|tasmota| tasmota.message(event)) {
+            return message;
+        }
+        let (topic, payload) = input_message(self.payload_profile, event, sequence);
+        let topic = self.grouped_topic(event).unwrap_or(topic);
+        let payload =
+            custom_payload(self.inputs_config, self.outputs_config, event).unwrap_or(payload);
+        (mqtt_publisher.full_topic(&topic), payload)
+    }
+}
+
+impl InputFormat<'_> {
+    /// Returns the topic of a grouped input channel or output state, the
+    /// `wago_cloud` profile publishes inputs as telemetry regardless.
+    fn grouped_topic(&self, event: &InputEvent) -> Option<String> {
+        match event {
+            InputEvent::Channel(event) if self.payload_profile != PayloadProfile::WagoCloud => self
+                .inputs_config
+                .group(event.channel)
+                .map(|grouped| format!("input/{}", grouped.path())),
+            InputEvent::Output(write) | InputEvent::Shadowed(write) => self
+                .outputs_config
+                .group(write.event.channel)
+                .map(|grouped| format!("output/{}/{}", grouped.path(), output_suffix(event))),
+            _ => None,
+        }
+    }
+}
+
+async fn publish_input(
+    mqtt_publisher: &MqttPublisher,
+    format: &InputFormat<'_>,
+    event: &InputEvent,
+) -> Result<(), anyhow::Error> {
+    match event {
+        InputEvent::Channel(event) => {
+            CHANNEL_STATS.lock().unwrap().on_input_change(event.channel);
+        }
+        InputEvent::VerifyFailed(failed) => {
+            // Logged here rather than in the K-Bus cycle, which only sends the event
+            warn_throttled!(
+                &format!("verify_failed/{}", failed.output),
+                ?failed,
+                "output verification failed"
+            );
+        }
+        InputEvent::DeadChannel(dead) if dead.dead => {
+            warn!(?dead, "input channel without expected activity");
+        }
+        InputEvent::DeadChannel(alive) => {
+            info!(?alive, "input channel active again");
+        }
+        _ => {}
+    }
+    let fast = match event {
+        InputEvent::Channel(event) => format
+            .fast_channels
+            .get(usize::from(event.channel))
+            .is_some_and(|fast| *fast),
+        InputEvent::Derived(_)
+        | InputEvent::Output(_)
+        | InputEvent::Shadowed(_)
+        | InputEvent::Modbus(_)
+        | InputEvent::ModbusAggregate(_)
+        | InputEvent::VerifyFailed(_)
+        | InputEvent::DeadChannel(_)
+        | InputEvent::StateDump(_)
+        | InputEvent::KBusAvailable(_) => false,
+    };
+    let sequence = INPUT_SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+    let (topic, payload) = format.message(mqtt_publisher, event, sequence);
+    // Output states and the K-Bus status are retained, so HMIs know the actual
+    // state when they connect
+    let (retain, span) = match event {
+        InputEvent::Output(write) => (true, info_span!("command", id = write.id)),
+        InputEvent::Shadowed(write) => (false, info_span!("command", id = write.id)),
+        InputEvent::KBusAvailable(_) => (true, Span::none()),
+        _ => (false, Span::none()),
+    };
+    if fast {
+        mqtt_publisher.publish_fast(topic, payload).await?;
+    } else {
+        mqtt_publisher
+            .publish_to(topic, QoS::AtLeastOnce, retain, payload)
+            .instrument(span)
+            .await?;
+    }
+    // Alarms follow the input change which raised or cleared them
+    if let InputEvent::Channel(event) = event {
+        mqtt_publisher
+            .update_alarms(event.channel, event.value)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Takes the queued input events after `event` and drops the obsolete input changes
+/// (see [`coalesce`]).
+fn coalesce_backlog(
+    event: InputEvent,
+    input_events: &mut UnboundedReceiver<InputEvent>,
+) -> Vec<InputEvent> {
+    let mut backlog = vec![event];
+    while let Ok(event) = input_events.try_recv() {
+        backlog.push(event);
+    }
+    let (events, dropped) = coalesce::coalesce(backlog);
+    let mut stats = CHANNEL_STATS.lock().unwrap();
+    for &channel in &dropped {
+        stats.on_input_coalesced(channel);
+    }
+    INPUT_COALESCED.fetch_add(dropped.len() as u64, Ordering::Relaxed);
+    debug!(
+        published = events.len(),
+        coalesced = dropped.len(),
+        "input backlog coalesced"
+    );
+    events
+}
+
+/// Publishes input events and, with lower priority, the queued background messages.
+///
+/// Both queues are served from a single loop, the client queues all requests in
+/// order, so only input events taking precedence here guarantee that heartbeats
+/// and diagnostics never delay an input state change. With more input events
+/// queued than `coalesce_threshold` (unless 0), only the latest change of every
+/// input channel is published.
+#[instrument(name = "pub", skip_all, err)]
+pub(super) async fn mqtt_publish_loop(
+    mqtt_publisher: &MqttPublisher,
+    format: &InputFormat<'_>,
+    coalesce_threshold: usize,
+    input_events: &mut UnboundedReceiver<InputEvent>,
+    background: &mut UnboundedReceiver<BackgroundMessage>,
+) -> Result<(), anyhow::Error> {
+    info!("Starting MQTT publish task");
+
+    loop {
+        tokio::select! {
+            biased;
+            event = input_events.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                if coalesce_threshold == 0 || input_events.len() < coalesce_threshold {
+                    INPUT_QUEUE_DEPTH.store(input_events.len(), Ordering::Relaxed);
+                    publish_input(mqtt_publisher, format, &event).await?;
+                    continue;
+                }
+                let backlog = coalesce_backlog(event, input_events);
+                let mut remaining = backlog.len();
+                for event in backlog {
+                    remaining -= 1;
+                    INPUT_QUEUE_DEPTH.store(remaining + input_events.len(), Ordering::Relaxed);
+                    publish_input(mqtt_publisher, format, &event).await?;
+                }
+            }
+            Some(message) = background.recv() => {
+                mqtt_publisher
+                    .publish_to(message.topic, message.qos, message.retain, message.payload)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Publishes the input events still queued on shutdown and the final `offline` status
+/// with the reason of the shutdown.
+///
+/// If `release_claim` is set, the retained claim of the device identity is cleared,
+/// so a standby instance can take over immediately.
+pub(super) async fn publish_on_shutdown(
+    mqtt_publisher: &MqttPublisher,
+    format: &InputFormat<'_>,
+    input_events: &mut UnboundedReceiver<InputEvent>,
+    release_claim: bool,
+) -> Result<(), anyhow::Error> {
+    while let Ok(event) = input_events.try_recv() {
+        publish_input(mqtt_publisher, format, &event).await?;
+    }
+
+    if release_claim {
+        mqtt_publisher
+            .publish("claim", QoS::AtLeastOnce, true, String::new())
+            .await?;
+    }
+
+    let status = json!({
+        "status": "offline",
+        "reason": shutdown::reason(),
+        "timestamp": timestamp::now(),
+    });
+    if let Some(tasmota) = format.tasmota {
+        mqtt_publisher
+            .publish_to(
+                tasmota.lwt_topic(),
+                QoS::ExactlyOnce,
+                true,
+                "Offline".to_owned(),
+            )
+            .await?;
+    }
+    mqtt_publisher
+        .publish("status", QoS::ExactlyOnce, true, status.to_string())
+        .await
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use super::*;
+use crate::{
+    config::{ChannelPayload, ChannelRange, GroupedChannel},
+    kbus::{DeadChannel, DerivedEvent, KBusEvent, OutputWrite, VerifyFailed},
+    modbus::{ModbusAggregate, ModbusEvent, ModbusValue, aggregate::Aggregate},
+};
+
+#[test]
+fn test_input_message_plain() {
+    let event = InputEvent::Channel(KBusEvent {
+        channel: 5,
+        value: true,
+    });
+    let (topic, payload) = input_message(PayloadProfile::Plain, &event, 1);
+    assert_eq!(topic, "input/5");
+    assert_eq!(payload, "true");
+
+    let event = InputEvent::Derived(DerivedEvent {
+        name: "alarm".into(),
+        value: false,
+    });
+    let (topic, payload) = input_message(PayloadProfile::Plain, &event, 1);
+    assert_eq!(topic, "derived/alarm");
+    assert_eq!(payload, "false");
+}
+
+#[test]
+fn test_input_message_json() {
+    let event = InputEvent::Channel(KBusEvent {
+        channel: 5,
+        value: true,
+    });
+    let (topic, payload) = input_message(PayloadProfile::Json, &event, 42);
+    assert_eq!(topic, "input/5");
+
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["value"], true);
+    assert_eq!(payload["sequence"], 42);
+    assert!(payload["timestamp"].is_string());
+
+    let event = InputEvent::Modbus(ModbusEvent {
+        device: "meter".to_owned(),
+        address: 3,
+        value: ModbusValue::Register(230),
+    });
+    let (topic, payload) = input_message(PayloadProfile::Json, &event, 43);
+    assert_eq!(topic, "modbus/meter/register/3");
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["value"], 230);
+    assert_eq!(payload["sequence"], 43);
+}
+
+#[test]
+fn test_input_message_wago_cloud() {
+    let event = InputEvent::Channel(KBusEvent {
+        channel: 5,
+        value: true,
+    });
+    let (topic, payload) = input_message(PayloadProfile::WagoCloud, &event, 1);
+    assert_eq!(topic, "telemetry");
+
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["version"], WAGO_CLOUD_PROTOCOL_VERSION);
+    assert!(payload["timestamp"].is_string());
+    assert_eq!(payload["collections"][0]["key"], "inputs");
+    assert_eq!(payload["collections"][0]["variables"][0]["key"], "input_5");
+    assert_eq!(payload["collections"][0]["variables"][0]["value"], true);
+}
+
+#[test]
+fn test_grouped_topic() {
+    let mut config = Config::default();
+    config.inputs.groups = vec![GroupedChannel {
+        channel: 12,
+        group: "hvac".to_owned(),
+        name: Some("fan_feedback".to_owned()),
+    }];
+    config.outputs.groups = vec![GroupedChannel {
+        channel: 3,
+        group: "lighting".to_owned(),
+        name: None,
+    }];
+    let input = |channel| {
+        InputEvent::Channel(KBusEvent {
+            channel,
+            value: true,
+        })
+    };
+    let output = |channel| {
+        InputEvent::Output(OutputWrite::new(
+            KBusEvent {
+                channel,
+                value: true,
+            },
+            None,
+        ))
+    };
+
+    let format = InputFormat::new(&config, None);
+    assert_eq!(
+        format.grouped_topic(&input(12)).as_deref(),
+        Some("input/hvac/fan_feedback")
+    );
+    assert_eq!(format.grouped_topic(&input(3)), None);
+    assert_eq!(
+        format.grouped_topic(&output(3)).as_deref(),
+        Some("output/lighting/3/state")
+    );
+    assert_eq!(format.grouped_topic(&output(12)), None);
+
+    // Inputs are published as telemetry in the wago_cloud profile
+    config.mqtt.payload_profile = PayloadProfile::WagoCloud;
+    let format = InputFormat::new(&config, None);
+    assert_eq!(format.grouped_topic(&input(12)), None);
+    assert!(format.grouped_topic(&output(3)).is_some());
+}
+
+#[test]
+fn test_input_message_modbus() {
+    let event = InputEvent::Modbus(ModbusEvent {
+        device: "meter".to_owned(),
+        address: 3,
+        value: ModbusValue::Register(230),
+    });
+    let (topic, payload) = input_message(PayloadProfile::Plain, &event, 1);
+    assert_eq!(topic, "modbus/meter/register/3");
+    assert_eq!(payload, "230");
+
+    let (topic, payload) = input_message(PayloadProfile::WagoCloud, &event, 1);
+    assert_eq!(topic, "telemetry");
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["collections"][0]["key"], "modbus");
+    assert_eq!(
+        payload["collections"][0]["variables"][0]["key"],
+        "meter_register_3"
+    );
+    assert_eq!(payload["collections"][0]["variables"][0]["value"], 230);
+}
+
+#[test]
+fn test_input_message_verify_failed() {
+    let event = InputEvent::VerifyFailed(VerifyFailed {
+        output: 4,
+        input: 12,
+        expected: true,
+    });
+    for profile in [PayloadProfile::Plain, PayloadProfile::WagoCloud] {
+        let (topic, payload) = input_message(profile, &event, 1);
+        assert_eq!(topic, "verify_failed");
+
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["output"], 4);
+        assert_eq!(payload["input"], 12);
+        assert_eq!(payload["expected"], true);
+        assert!(payload["timestamp"].is_string());
+    }
+}
+
+#[test]
+fn test_input_message_dead_channel() {
+    let event = InputEvent::DeadChannel(DeadChannel {
+        channel: 5,
+        dead: true,
+        expect_activity_within: Duration::from_secs(86400),
+    });
+    let (topic, payload) = input_message(PayloadProfile::Json, &event, 1);
+    assert_eq!(topic, "diagnostics");
+
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["diagnostic"], "dead_channel");
+    assert_eq!(payload["channel"], 5);
+    assert_eq!(payload["dead"], true);
+    assert_eq!(payload["expect_activity_within"], "1day");
+    assert!(payload["timestamp"].is_string());
+}
+
+#[test]
+fn test_input_message_output_state() {
+    let event = InputEvent::Output(OutputWrite::new(
+        KBusEvent {
+            channel: 3,
+            value: true,
+        },
+        Some("a1".to_owned()),
+    ));
+    let (topic, payload) = input_message(PayloadProfile::Plain, &event, 1);
+    assert_eq!(topic, "output/3/state");
+    assert_eq!(payload, "true");
+
+    for profile in [PayloadProfile::Json, PayloadProfile::WagoCloud] {
+        let (topic, payload) = input_message(profile, &event, 1);
+        assert_eq!(topic, "output/3/state");
+
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["value"], true);
+        assert_eq!(payload["id"], "a1");
+        assert!(payload["timestamp"].is_string());
+    }
+}
+
+#[test]
+fn test_input_message_output_shadow() {
+    let event = InputEvent::Shadowed(OutputWrite::new(
+        KBusEvent {
+            channel: 3,
+            value: true,
+        },
+        Some("a1".to_owned()),
+    ));
+    let (topic, payload) = input_message(PayloadProfile::Plain, &event, 1);
+    assert_eq!(topic, "output/3/shadow");
+    assert_eq!(payload, "true");
+
+    let (topic, payload) = input_message(PayloadProfile::Json, &event, 1);
+    assert_eq!(topic, "output/3/shadow");
+    let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+    assert_eq!(payload["id"], "a1");
+}
+
+#[test]
+fn test_custom_payload() {
+    let on_off = |channels| ChannelPayload {
+        channels,
+        payload_on: "ON".to_owned(),
+        payload_off: "OFF".to_owned(),
+        template: None,
+    };
+    let inputs_config = InputsConfig {
+        payloads: vec![on_off(ChannelRange { first: 0, last: 3 })],
+        ..InputsConfig::default()
+    };
+    let outputs_config = OutputsConfig {
+        payloads: vec![on_off(ChannelRange { first: 4, last: 4 })],
+        ..OutputsConfig::default()
+    };
+    let payload = |event| custom_payload(&inputs_config, &outputs_config, &event);
+
+    let input = |channel| {
+        InputEvent::Channel(KBusEvent {
+            channel,
+            value: true,
+        })
+    };
+    assert_eq!(payload(input(3)).as_deref(), Some("ON"));
+    assert_eq!(payload(input(4)), None);
+
+    let output = |channel| {
+        InputEvent::Output(OutputWrite::new(
+            KBusEvent {
+                channel,
+                value: false,
+            },
+            None,
+        ))
+    };
+    assert_eq!(payload(output(4)).as_deref(), Some("OFF"));
+    assert_eq!(payload(output(3)), None);
+}
+
+#[test]
+fn test_input_message_modbus_aggregate() {
+    let event = InputEvent::ModbusAggregate(ModbusAggregate {
+        device: "meter".to_owned(),
+        address: 3,
+        aggregate: Aggregate {
+            min: 225,
+            max: 232,
+            mean: 229.5,
+            samples: 120,
+        },
+    });
+    for profile in [PayloadProfile::Plain, PayloadProfile::WagoCloud] {
+        let (topic, payload) = input_message(profile, &event, 1);
+        assert_eq!(topic, "modbus/meter/register/3/aggregate");
+
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["min"], 225);
+        assert_eq!(payload["max"], 232);
+        assert_eq!(payload["mean"], 229.5);
+        assert_eq!(payload["samples"], 120);
+        assert!(payload["timestamp"].is_string());
+    }
+}
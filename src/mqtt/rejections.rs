@@ -0,0 +1,75 @@
+//! Aggregated statistics of rejected incoming messages
+//!
+//! Rejections are counted per topic and reason and periodically reported on
+//! `security/rejections`, which helps to spot misconfigured or malicious
+//! publishers targeting the bridge's namespace without flooding the log.
+
+use std::{collections::BTreeMap, mem, time::Duration};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::timestamp;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of distinct topics tracked per interval, rejections on other
+/// topics are only counted, so a publisher can't exhaust memory with random topics.
+const MAX_TOPICS: usize = 100;
+
+#[derive(Debug, Default, Serialize)]
+struct TopicRejections {
+    count: u64,
+    reasons: BTreeMap<&'static str, u64>,
+}
+
+/// Rejected messages collected during one reporting interval.
+#[derive(Debug, Default)]
+pub struct RejectionStats {
+    topics: BTreeMap<String, TopicRejections>,
+    /// Rejections on topics exceeding `MAX_TOPICS`
+    other: u64,
+}
+
+impl RejectionStats {
+    /// Records a rejected message.
+    ///
+    /// Returns `true` for the first rejection on the topic in the current interval.
+    pub fn record(&mut self, topic: &str, reason: &'static str) -> bool {
+        let first = !self.topics.contains_key(topic);
+        if first && self.topics.len() >= MAX_TOPICS {
+            self.other += 1;
+            return false;
+        }
+
+        let rejections = self.topics.entry(topic.to_owned()).or_default();
+        rejections.count += 1;
+        *rejections.reasons.entry(reason).or_default() += 1;
+        first
+    }
+
+    /// Returns the number of rejected messages in the current interval.
+    pub fn total(&self) -> u64 {
+        self.topics.values().map(|t| t.count).sum::<u64>() + self.other
+    }
+
+    /// Returns the report of the current interval and starts a new one.
+    ///
+    /// Returns `None` if no message was rejected.
+    pub fn take_report(&mut self, interval: Duration) -> Option<serde_json::Value> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+
+        let stats = mem::take(self);
+        Some(json!({
+            "timestamp": timestamp::now(),
+            "interval": interval.as_secs(),
+            "total": total,
+            "topics": stats.topics,
+            "other": stats.other,
+        }))
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+#[test]
+fn test_record() {
+    let mut stats = RejectionStats::default();
+    assert!(stats.record("dev/mac/output/99", "channel_out_of_range"));
+    assert!(!stats.record("dev/mac/output/99", "channel_out_of_range"));
+    assert!(!stats.record("dev/mac/output/99", "invalid_command"));
+    assert!(stats.record("dev/mac/output/1", "invalid_command"));
+    assert_eq!(stats.total(), 4);
+
+    let report = stats.take_report(Duration::from_secs(60)).unwrap();
+    assert_eq!(report["interval"], 60);
+    assert_eq!(report["total"], 4);
+    assert_eq!(report["other"], 0);
+    assert_eq!(report["topics"]["dev/mac/output/99"]["count"], 3);
+    assert_eq!(
+        report["topics"]["dev/mac/output/99"]["reasons"]["channel_out_of_range"],
+        2
+    );
+    assert_eq!(
+        report["topics"]["dev/mac/output/99"]["reasons"]["invalid_command"],
+        1
+    );
+
+    // A new interval starts after the report
+    assert_eq!(stats.total(), 0);
+    assert!(stats.take_report(Duration::from_secs(60)).is_none());
+    assert!(stats.record("dev/mac/output/99", "channel_out_of_range"));
+}
+
+#[test]
+fn test_record_topic_limit() {
+    let mut stats = RejectionStats::default();
+    for i in 0..MAX_TOPICS {
+        assert!(stats.record(&format!("dev/mac/output/{i}"), "invalid_command"));
+    }
+    assert!(!stats.record("dev/mac/output/new", "invalid_command"));
+    // Known topics are still tracked
+    assert!(!stats.record("dev/mac/output/0", "invalid_command"));
+
+    let report = stats.take_report(Duration::from_secs(60)).unwrap();
+    assert_eq!(report["total"], MAX_TOPICS + 2);
+    assert_eq!(report["other"], 1);
+    assert_eq!(report["topics"]["dev/mac/output/0"]["count"], 2);
+}
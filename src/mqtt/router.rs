@@ -0,0 +1,285 @@
+//! Strict parser for incoming MQTT command topics
+//!
+//! Topics are matched level by level against the device prefix and the known
+//! command topics. Every rejected topic gets a [`RejectReason`], so invalid
+//! commands are refused up front instead of being dropped later by the K-Bus task.
+
+use std::fmt;
+
+use crate::config::{AlarmConfig, GroupedChannel, ModbusDeviceConfig, ModbusRange};
+
+#[cfg(test)]
+mod tests;
+
+/// A command topic recognized by the router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// `output/<n>`, `output/<group>/<name>` or `cmnd/<device>/POWER<n + 1>` - set
+    /// the output channel
+    Output { channel: u16 },
+    /// `bridge/dump` - process image dump request
+    Dump,
+    /// `bridge/read` - process image region read request
+    Read,
+    /// `bridge/soe` - sequence of events dump request
+    Soe,
+    /// `output/bit/<offset>` - write a bit of the output process image (`mqtt.raw_bits`)
+    OutputBit { offset: u16 },
+    /// `input/bit/<offset>` - read a bit of the input process image (`mqtt.raw_bits`)
+    InputBit { offset: u16 },
+    /// `bridge/stats` - channel statistics request
+    Stats,
+    /// `bridge/ping` - echo request for round-trip measurements
+    Ping,
+    /// `bridge/shadow` - enable or disable the output shadow mode
+    Shadow,
+    /// `claim` - claim of the device identity (see [`super::claim`])
+    Claim,
+    /// `bridge/config/set` - configuration update (see [`crate::update`])
+    ConfigSet,
+    /// `bridge/update` - self-update of the bridge binary
+    Update,
+    /// `modbus/<name>/coil/<address>` - write the coil of a Modbus device
+    ModbusCoil { device: usize, address: u16 },
+    /// `modbus/<name>/holding/<address>` - write the holding register of a Modbus device
+    ModbusHolding { device: usize, address: u16 },
+    /// `alarms/<name>/ack` - acknowledge the alarm (see [`super::alarms`])
+    AlarmAck { alarm: usize },
+}
+
+/// Reason why a topic was rejected by the router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The topic is not under the device prefix.
+    ForeignPrefix,
+    /// The topic is under the device prefix but isn't a known command topic.
+    UnknownTopic,
+    /// The channel level is not a canonical decimal number.
+    InvalidChannel,
+    /// The channel doesn't exist in the output process image or the address
+    /// isn't configured for the Modbus device.
+    ChannelOutOfRange,
+    /// The K-Bus isn't running yet and the output command can't be queued.
+    NotReady,
+    /// The write command isn't signed with the shared key (see [`super::signature`]).
+    InvalidSignature,
+}
+
+impl RejectReason {
+    /// Short machine-readable reason code, e.g. for statistics.
+    pub const fn code(&self) -> &'static str {
+        match self {
+            RejectReason::ForeignPrefix => "foreign_prefix",
+            RejectReason::UnknownTopic => "unknown_topic",
+            RejectReason::InvalidChannel => "invalid_channel",
+            RejectReason::ChannelOutOfRange => "channel_out_of_range",
+            RejectReason::NotReady => "not_ready",
+            RejectReason::InvalidSignature => "invalid_signature",
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            RejectReason::ForeignPrefix => "topic outside of the device prefix",
+            RejectReason::UnknownTopic => "unknown topic",
+            RejectReason::InvalidChannel => "invalid channel number",
+            RejectReason::ChannelOutOfRange => "channel out of range",
+            RejectReason::NotReady => "K-Bus not running yet",
+            RejectReason::InvalidSignature => "invalid command signature",
+        };
+        f.write_str(description)
+    }
+}
+
+impl std::error::Error for RejectReason {}
+
+/// Maps incoming topics to [`Route`]s.
+#[derive(Debug, Clone)]
+pub struct TopicRouter {
+    prefix: String,
+    output_channels: usize,
+    /// Prefix of the Tasmota command topics (`cmnd/<topic>/`), if enabled
+    tasmota_commands: Option<String>,
+    /// Whether the raw bit offset topics are enabled
+    raw_bits: bool,
+    /// Output channels switched on `output/<group>/<name>`
+    output_groups: Vec<GroupedChannel>,
+    /// Names and writable ranges of the Modbus devices
+    modbus_devices: Vec<(String, Option<ModbusRange>, Option<ModbusRange>)>,
+    /// Names of the alarms acknowledged on `alarms/<name>/ack`
+    alarms: Vec<String>,
+}
+
+impl TopicRouter {
+    /// Creates a router for topics under `prefix` with `output_channels` valid output channels.
+    pub fn new(prefix: &str, output_channels: usize) -> TopicRouter {
+        TopicRouter {
+            prefix: prefix.to_owned(),
+            output_channels,
+            tasmota_commands: None,
+            raw_bits: false,
+            output_groups: Vec::new(),
+            modbus_devices: Vec::new(),
+            alarms: Vec::new(),
+        }
+    }
+
+    /// Adds the Tasmota command topics `<command_prefix>POWER<i>` of the outputs,
+    /// see [`super::tasmota::Tasmota::command_prefix`].
+    pub fn with_tasmota(mut self, command_prefix: &str) -> TopicRouter {
+        self.tasmota_commands = Some(command_prefix.to_owned());
+        self
+    }
+
+    /// Adds the raw bit offset topics `output/bit/<offset>` and `input/bit/<offset>`.
+    pub fn with_raw_bits(mut self) -> TopicRouter {
+        self.raw_bits = true;
+        self
+    }
+
+    /// Adds the `output/<group>/<name>` topics of the grouped output channels.
+    pub fn with_output_groups(mut self, groups: &[GroupedChannel]) -> TopicRouter {
+        self.output_groups = groups.to_vec();
+        self
+    }
+
+    /// Adds the coil and holding register topics of the Modbus devices.
+    pub fn with_modbus_devices(mut self, devices: &[ModbusDeviceConfig]) -> TopicRouter {
+        self.modbus_devices = devices
+            .iter()
+            .map(|device| (device.name.clone(), device.coils, device.holding_registers))
+            .collect();
+        self
+    }
+
+    /// Adds the acknowledgment topics of the alarms.
+    pub fn with_alarms(mut self, alarms: &[AlarmConfig]) -> TopicRouter {
+        self.alarms = alarms.iter().map(|alarm| alarm.name.clone()).collect();
+        self
+    }
+
+    /// Parses the topic into a route.
+    pub fn route(&self, topic: &str) -> Result<Route, RejectReason> {
+        if let Some(command) = self
+            .tasmota_commands
+            .as_deref()
+            .and_then(|commands| topic.strip_prefix(commands))
+        {
+            return self.parse_power(command);
+        }
+
+        let mut levels = topic.split('/');
+
+        // Match the prefix level by level, "dev/mac" must not match "dev/macx/..."
+        for prefix_level in self.prefix.split('/') {
+            if levels.next() != Some(prefix_level) {
+                return Err(RejectReason::ForeignPrefix);
+            }
+        }
+
+        let levels: Vec<&str> = levels.collect();
+        match levels.as_slice() {
+            ["output", channel] => self.parse_channel(channel),
+            ["output", "bit", offset] if self.raw_bits => Ok(Route::OutputBit {
+                offset: parse_number(offset)?,
+            }),
+            ["input", "bit", offset] if self.raw_bits => Ok(Route::InputBit {
+                offset: parse_number(offset)?,
+            }),
+            ["output", group, name] => self.parse_grouped(group, name),
+            ["bridge", "dump"] => Ok(Route::Dump),
+            ["bridge", "read"] => Ok(Route::Read),
+            ["bridge", "soe"] => Ok(Route::Soe),
+            ["bridge", "stats"] => Ok(Route::Stats),
+            ["bridge", "ping"] => Ok(Route::Ping),
+            ["bridge", "shadow"] => Ok(Route::Shadow),
+            ["claim"] => Ok(Route::Claim),
+            ["bridge", "config", "set"] => Ok(Route::ConfigSet),
+            ["bridge", "update"] => Ok(Route::Update),
+            ["modbus", name, kind @ ("coil" | "holding"), address] => {
+                self.parse_modbus(name, kind, address)
+            }
+            ["alarms", name, "ack"] => self
+                .alarms
+                .iter()
+                .position(|alarm| alarm == name)
+                .map(|alarm| Route::AlarmAck { alarm })
+                .ok_or(RejectReason::UnknownTopic),
+            _ => Err(RejectReason::UnknownTopic),
+        }
+    }
+
+    fn parse_channel(&self, level: &str) -> Result<Route, RejectReason> {
+        let channel = parse_number(level)?;
+        if usize::from(channel) >= self.output_channels {
+            return Err(RejectReason::ChannelOutOfRange);
+        }
+        Ok(Route::Output { channel })
+    }
+
+    fn parse_grouped(&self, group: &str, name: &str) -> Result<Route, RejectReason> {
+        let path = format!("{group}/{name}");
+        self.output_groups
+            .iter()
+            .find(|grouped| grouped.path() == path)
+            .map(|grouped| Route::Output {
+                channel: grouped.channel,
+            })
+            .ok_or(RejectReason::UnknownTopic)
+    }
+
+    /// Parses a Tasmota command, only `POWER<i>` (case-insensitive, `POWER` is `POWER1`)
+    /// sets an output.
+    fn parse_power(&self, command: &str) -> Result<Route, RejectReason> {
+        let index = command
+            .get(..5)
+            .filter(|name| name.eq_ignore_ascii_case("power"))
+            .map(|_| &command[5..])
+            .ok_or(RejectReason::UnknownTopic)?;
+        let channel = match index {
+            "" => 0,
+            index => parse_number(index)?
+                .checked_sub(1)
+                .ok_or(RejectReason::InvalidChannel)?,
+        };
+        if usize::from(channel) >= self.output_channels {
+            return Err(RejectReason::ChannelOutOfRange);
+        }
+        Ok(Route::Output { channel })
+    }
+
+    fn parse_modbus(&self, name: &str, kind: &str, level: &str) -> Result<Route, RejectReason> {
+        let (device, (_, coils, holding_registers)) = self
+            .modbus_devices
+            .iter()
+            .enumerate()
+            .find(|(_, (device, _, _))| device == name)
+            .ok_or(RejectReason::UnknownTopic)?;
+        let address = parse_number(level)?;
+        let (range, route) = if kind == "coil" {
+            (coils, Route::ModbusCoil { device, address })
+        } else {
+            (holding_registers, Route::ModbusHolding { device, address })
+        };
+        match range {
+            Some(range) if range.contains(address) => Ok(route),
+            Some(_) => Err(RejectReason::ChannelOutOfRange),
+            None => Err(RejectReason::UnknownTopic),
+        }
+    }
+}
+
+/// Parses a channel or address level as a canonical `u16`.
+fn parse_number(level: &str) -> Result<u16, RejectReason> {
+    // Only canonical numbers: no signs, whitespace or leading zeros
+    let canonical = !level.is_empty()
+        && level.bytes().all(|b| b.is_ascii_digit())
+        && (level == "0" || !level.starts_with('0'));
+    if !canonical {
+        return Err(RejectReason::InvalidChannel);
+    }
+
+    level.parse().map_err(|_| RejectReason::ChannelOutOfRange)
+}
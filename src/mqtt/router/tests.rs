@@ -0,0 +1,291 @@
+use super::*;
+
+fn router() -> TopicRouter {
+    TopicRouter::new("pfc200/00:30:de:00:00:01", 90)
+}
+
+#[test]
+fn test_route_commands() {
+    let router = router();
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/0"),
+        Ok(Route::Output { channel: 0 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/89"),
+        Ok(Route::Output { channel: 89 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/dump"),
+        Ok(Route::Dump)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/read"),
+        Ok(Route::Read)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/soe"),
+        Ok(Route::Soe)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/stats"),
+        Ok(Route::Stats)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/ping"),
+        Ok(Route::Ping)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/shadow"),
+        Ok(Route::Shadow)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/claim"),
+        Ok(Route::Claim)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/config/set"),
+        Ok(Route::ConfigSet)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/bridge/update"),
+        Ok(Route::Update)
+    );
+}
+
+#[test]
+fn test_reject_foreign_prefix() {
+    let router = router();
+    assert_eq!(
+        router.route("other/00:30:de:00:00:01/output/1"),
+        Err(RejectReason::ForeignPrefix)
+    );
+    // Prefix must match whole levels
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01x/output/1"),
+        Err(RejectReason::ForeignPrefix)
+    );
+    assert_eq!(router.route("pfc200"), Err(RejectReason::ForeignPrefix));
+}
+
+#[test]
+fn test_reject_unknown_topic() {
+    let router = router();
+    for topic in [
+        "pfc200/00:30:de:00:00:01",
+        "pfc200/00:30:de:00:00:01/",
+        "pfc200/00:30:de:00:00:01/output",
+        "pfc200/00:30:de:00:00:01/output/1/extra",
+        "pfc200/00:30:de:00:00:01/input/1",
+        "pfc200/00:30:de:00:00:01/bridge/unknown",
+    ] {
+        assert_eq!(
+            router.route(topic),
+            Err(RejectReason::UnknownTopic),
+            "{topic}"
+        );
+    }
+}
+
+#[test]
+fn test_reject_invalid_channel() {
+    let router = router();
+    for channel in ["", "+1", "-1", " 1", "01", "1a", "0x1"] {
+        let topic = format!("pfc200/00:30:de:00:00:01/output/{channel}");
+        assert_eq!(
+            router.route(&topic),
+            Err(RejectReason::InvalidChannel),
+            "{topic}"
+        );
+    }
+}
+
+#[test]
+fn test_reject_channel_out_of_range() {
+    let router = router();
+    for channel in ["90", "65535", "65536", "99999999999"] {
+        let topic = format!("pfc200/00:30:de:00:00:01/output/{channel}");
+        assert_eq!(
+            router.route(&topic),
+            Err(RejectReason::ChannelOutOfRange),
+            "{topic}"
+        );
+    }
+}
+
+#[test]
+fn test_route_modbus() {
+    let devices = [ModbusDeviceConfig {
+        name: "meter".to_owned(),
+        slave: 1,
+        discrete_inputs: None,
+        input_registers: None,
+        coils: Some(ModbusRange {
+            address: 16,
+            count: 4,
+        }),
+        holding_registers: None,
+    }];
+    let router = router().with_modbus_devices(&devices);
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/modbus/meter/coil/19"),
+        Ok(Route::ModbusCoil {
+            device: 0,
+            address: 19
+        })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/modbus/meter/coil/20"),
+        Err(RejectReason::ChannelOutOfRange)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/modbus/meter/coil/016"),
+        Err(RejectReason::InvalidChannel)
+    );
+    // No holding registers configured
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/modbus/meter/holding/16"),
+        Err(RejectReason::UnknownTopic)
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/modbus/other/coil/16"),
+        Err(RejectReason::UnknownTopic)
+    );
+}
+
+#[test]
+fn test_route_tasmota() {
+    let tasmota_router = router().with_tasmota("cmnd/pfc200/");
+    for (topic, channel) in [
+        ("cmnd/pfc200/POWER", 0),
+        ("cmnd/pfc200/POWER1", 0),
+        ("cmnd/pfc200/Power2", 1),
+        ("cmnd/pfc200/power90", 89),
+    ] {
+        assert_eq!(
+            tasmota_router.route(topic),
+            Ok(Route::Output { channel }),
+            "{topic}"
+        );
+    }
+    // Bridge topics are still routed
+    assert_eq!(
+        tasmota_router.route("pfc200/00:30:de:00:00:01/output/0"),
+        Ok(Route::Output { channel: 0 })
+    );
+
+    for (topic, reason) in [
+        ("cmnd/pfc200/POWER0", RejectReason::InvalidChannel),
+        ("cmnd/pfc200/POWER01", RejectReason::InvalidChannel),
+        ("cmnd/pfc200/POWER91", RejectReason::ChannelOutOfRange),
+        ("cmnd/pfc200/Dimmer", RejectReason::UnknownTopic),
+        ("cmnd/pfc200x/POWER1", RejectReason::ForeignPrefix),
+        ("cmnd/other/POWER1", RejectReason::ForeignPrefix),
+    ] {
+        assert_eq!(tasmota_router.route(topic), Err(reason), "{topic}");
+    }
+    // Not routed without the Tasmota topics
+    assert_eq!(
+        router().route("cmnd/pfc200/POWER1"),
+        Err(RejectReason::ForeignPrefix)
+    );
+}
+
+#[test]
+fn test_route_raw_bits() {
+    // Unknown unless enabled
+    assert_eq!(
+        router().route("pfc200/00:30:de:00:00:01/output/bit/100"),
+        Err(RejectReason::UnknownTopic)
+    );
+
+    let router = router().with_raw_bits();
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/bit/100"),
+        Ok(Route::OutputBit { offset: 100 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/input/bit/0"),
+        Ok(Route::InputBit { offset: 0 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/input/bit/01"),
+        Err(RejectReason::InvalidChannel)
+    );
+    // Channels are unchanged
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/1"),
+        Ok(Route::Output { channel: 1 })
+    );
+}
+
+#[test]
+fn test_route_output_groups() {
+    let router = router().with_output_groups(&[
+        GroupedChannel {
+            channel: 3,
+            group: "lighting".to_owned(),
+            name: Some("hall".to_owned()),
+        },
+        GroupedChannel {
+            channel: 4,
+            group: "lighting".to_owned(),
+            name: None,
+        },
+    ]);
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/lighting/hall"),
+        Ok(Route::Output { channel: 3 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/lighting/4"),
+        Ok(Route::Output { channel: 4 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/lighting/stairs"),
+        Err(RejectReason::UnknownTopic)
+    );
+    // State topics aren't commands
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/lighting/hall/state"),
+        Err(RejectReason::UnknownTopic)
+    );
+    // The numbered topic still switches the channel
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/output/3"),
+        Ok(Route::Output { channel: 3 })
+    );
+}
+
+#[test]
+fn test_route_alarm_ack() {
+    let router = router().with_alarms(&[
+        AlarmConfig {
+            name: "estop".to_owned(),
+            condition: "in1".to_owned(),
+            severity: Default::default(),
+            message: String::new(),
+            latching: true,
+        },
+        AlarmConfig {
+            name: "door".to_owned(),
+            condition: "in2".to_owned(),
+            severity: Default::default(),
+            message: String::new(),
+            latching: false,
+        },
+    ]);
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/alarms/door/ack"),
+        Ok(Route::AlarmAck { alarm: 1 })
+    );
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/alarms/pump/ack"),
+        Err(RejectReason::UnknownTopic)
+    );
+    // The published alarms aren't commands
+    assert_eq!(
+        router.route("pfc200/00:30:de:00:00:01/alarms/active"),
+        Err(RejectReason::UnknownTopic)
+    );
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::*;
+
+const TOPIC: &str = "pfc200/00:30:de:00:00:01/output/5";
+const TIMESTAMP: &str = "2025-03-03T06:00:00Z";
+
+fn config() -> SigningConfig {
+    SigningConfig {
+        key: "0123456789abcdef".to_owned(),
+        max_age: Duration::from_secs(30),
+    }
+}
+
+fn now() -> DateTime<Utc> {
+    "2025-03-03T06:00:10Z".parse().unwrap()
+}
+
+fn signed(value: serde_json::Value, timestamp: &str) -> Vec<u8> {
+    let signature = sign(&config().key, TOPIC, &value, timestamp);
+    json!({ "value": value, "timestamp": timestamp, "signature": signature })
+        .to_string()
+        .into_bytes()
+}
+
+fn reason(result: Result<(), anyhow::Error>) -> String {
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RejectReason>(),
+        Some(&RejectReason::InvalidSignature)
+    );
+    format!("{err:#}")
+}
+
+#[test]
+fn test_sign() {
+    // Same as `openssl dgst -sha256 -hmac <key>` over the message, see README
+    assert_eq!(
+        sign("0123456789abcdef", "a/output/5", &json!(true), TIMESTAMP),
+        "362f3510db3dcdec0d6d712b7d893c927703f8d1b3d207d6ff39669d25aaeb94"
+    );
+}
+
+#[test]
+fn test_verify() {
+    verify(&config(), TOPIC, &signed(json!(true), TIMESTAMP), now()).unwrap();
+    // Registers of Modbus devices
+    verify(&config(), TOPIC, &signed(json!(1234), TIMESTAMP), now()).unwrap();
+    // The id isn't signed
+    let mut command: serde_json::Value =
+        serde_json::from_slice(&signed(json!(true), TIMESTAMP)).unwrap();
+    command["id"] = json!("a1");
+    verify(&config(), TOPIC, command.to_string().as_bytes(), now()).unwrap();
+}
+
+#[test]
+fn test_reject_unsigned() {
+    assert!(reason(verify(&config(), TOPIC, b"true", now())).contains("unsigned"));
+    let payload = json!({ "value": true, "timestamp": TIMESTAMP }).to_string();
+    assert!(reason(verify(&config(), TOPIC, payload.as_bytes(), now())).contains("unsigned"));
+}
+
+#[test]
+fn test_reject_tampered() {
+    let payload = signed(json!(true), TIMESTAMP);
+    // Another topic, value or key
+    let other_topic = "pfc200/00:30:de:00:00:01/output/6";
+    assert!(reason(verify(&config(), other_topic, &payload, now())).contains("mismatch"));
+    let tampered = String::from_utf8(payload.clone())
+        .unwrap()
+        .replace("true", "false");
+    assert!(reason(verify(&config(), TOPIC, tampered.as_bytes(), now())).contains("mismatch"));
+    let other_key = SigningConfig {
+        key: "fedcba9876543210".to_owned(),
+        ..config()
+    };
+    assert!(reason(verify(&other_key, TOPIC, &payload, now())).contains("mismatch"));
+
+    let payload = json!({ "value": true, "timestamp": TIMESTAMP, "signature": "xyz" });
+    reason(verify(
+        &config(),
+        TOPIC,
+        payload.to_string().as_bytes(),
+        now(),
+    ));
+}
+
+#[test]
+fn test_reject_expired() {
+    // Replayed later, or issued with a clock far ahead
+    let payload = signed(json!(true), "2025-03-03T05:59:00Z");
+    assert!(reason(verify(&config(), TOPIC, &payload, now())).contains("expired"));
+    let payload = signed(json!(true), "2025-03-03T06:01:00Z");
+    assert!(reason(verify(&config(), TOPIC, &payload, now())).contains("expired"));
+    // Within the maximum age either way
+    verify(
+        &config(),
+        TOPIC,
+        &signed(json!(true), "2025-03-03T06:00:30Z"),
+        now(),
+    )
+    .unwrap();
+}
@@ -0,0 +1,70 @@
+//! Output commands received before the K-Bus is running
+//!
+//! Opening and starting the K-Bus takes a while, MQTT messages can arrive before.
+//! Output commands of this window are queued with their arrival time and written
+//! in order once the K-Bus runs, commands older than the maximum age are dropped
+//! instead of switching outputs long after they were sent.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::kbus::OutputWrite;
+
+use super::router::RejectReason;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of queued commands, later commands are rejected.
+const MAX_PENDING: usize = 1000;
+
+/// Output commands waiting for the K-Bus to run.
+#[derive(Debug)]
+pub struct StartupQueue {
+    max_age: Duration,
+    pending: VecDeque<(Instant, OutputWrite)>,
+}
+
+impl StartupQueue {
+    /// Creates a queue keeping commands for `max_age`, zero rejects all commands.
+    pub fn new(max_age: Duration) -> StartupQueue {
+        StartupQueue {
+            max_age,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Queues a command received at `now`.
+    pub fn push(&mut self, write: OutputWrite, now: Instant) -> Result<(), anyhow::Error> {
+        if self.max_age.is_zero() || self.pending.len() >= MAX_PENDING {
+            return Err(RejectReason::NotReady.into());
+        }
+        self.pending.push_back((now, write));
+        Ok(())
+    }
+
+    /// Removes and returns the commands older than the maximum age.
+    pub fn expire(&mut self, now: Instant) -> Vec<OutputWrite> {
+        let mut expired = Vec::new();
+        while let Some((received, _)) = self.pending.front() {
+            if now.saturating_duration_since(*received) <= self.max_age {
+                break;
+            }
+            if let Some((_, write)) = self.pending.pop_front() {
+                expired.push(write);
+            }
+        }
+        expired
+    }
+
+    /// Removes and returns all queued commands in the order they were received.
+    pub fn drain(&mut self) -> impl Iterator<Item = OutputWrite> + '_ {
+        self.pending.drain(..).map(|(_, write)| write)
+    }
+}
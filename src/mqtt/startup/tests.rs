@@ -0,0 +1,62 @@
+use super::*;
+use crate::kbus::KBusEvent;
+
+fn event(channel: u16) -> OutputWrite {
+    OutputWrite::new(
+        KBusEvent {
+            channel,
+            value: true,
+        },
+        None,
+    )
+}
+
+#[test]
+fn test_startup_queue() {
+    let start = Instant::now();
+    let mut queue = StartupQueue::new(Duration::from_secs(5));
+    assert!(queue.is_empty());
+    queue.push(event(0), start).unwrap();
+    queue
+        .push(event(1), start + Duration::from_secs(2))
+        .unwrap();
+    queue
+        .push(event(2), start + Duration::from_secs(4))
+        .unwrap();
+
+    // Commands not older than the maximum age are kept
+    assert!(queue.expire(start + Duration::from_secs(5)).is_empty());
+    let expired = queue.expire(start + Duration::from_secs(8));
+    assert_eq!(
+        expired.iter().map(|e| e.event.channel).collect::<Vec<_>>(),
+        [0, 1]
+    );
+
+    // The remaining commands are returned in order
+    queue
+        .push(event(3), start + Duration::from_secs(8))
+        .unwrap();
+    assert_eq!(
+        queue.drain().map(|e| e.event.channel).collect::<Vec<_>>(),
+        [2, 3]
+    );
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_startup_queue_reject() {
+    // Without a maximum age, commands are rejected until the K-Bus runs
+    let mut queue = StartupQueue::new(Duration::ZERO);
+    let err = queue.push(event(0), Instant::now()).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RejectReason>(),
+        Some(&RejectReason::NotReady)
+    );
+    assert!(queue.is_empty());
+
+    let mut queue = StartupQueue::new(Duration::from_secs(5));
+    for channel in 0..MAX_PENDING {
+        queue.push(event(channel as u16), Instant::now()).unwrap();
+    }
+    assert!(queue.push(event(0), Instant::now()).is_err());
+}
@@ -0,0 +1,173 @@
+//! Counters of the MQTT tasks
+//!
+//! Message counters, input queue depth and channel statistics shared by the
+//! client and the publisher, reported in the heartbeat and on the `stats` topics.
+
+use std::{
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sysinfo::System;
+
+use crate::{
+    kbus::{self, INPUT_SIZE, OUTPUT_SIZE},
+    metrics::SystemMetrics,
+    state, supervisor, timestamp,
+};
+
+use super::{alerts::Sample, channel_stats::ChannelStats};
+
+#[cfg(test)]
+mod tests;
+
+pub(super) static APP_START_TIME: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Changes and commands per K-Bus channel, reported on `stats/channels`
+pub(super) static CHANNEL_STATS: LazyLock<Mutex<ChannelStats>> =
+    LazyLock::new(|| Mutex::new(ChannelStats::new(INPUT_SIZE, OUTPUT_SIZE)));
+
+pub(super) static MQTT_MESSAGES_SENT: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_MESSAGES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_MESSAGES_PROCESSED: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_MESSAGES_REJECTED: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_MESSAGES_DROPPED: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_SUBSCRIPTIONS_FAILED: AtomicU64 = AtomicU64::new(0);
+pub(super) static MQTT_PUBLISHES_STALLED: AtomicU64 = AtomicU64::new(0);
+/// Snapshot of the MQTT message counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttStats {
+    pub sent: u64,
+    pub received: u64,
+    pub processed: u64,
+    pub rejected: u64,
+    pub dropped: u64,
+    pub subscriptions_failed: u64,
+    pub publishes_stalled: u64,
+}
+
+impl MqttStats {
+    /// Returns the current values of the counters.
+    pub fn current() -> MqttStats {
+        MqttStats {
+            sent: MQTT_MESSAGES_SENT.load(Ordering::Relaxed),
+            received: MQTT_MESSAGES_RECEIVED.load(Ordering::Relaxed),
+            processed: MQTT_MESSAGES_PROCESSED.load(Ordering::Relaxed),
+            rejected: MQTT_MESSAGES_REJECTED.load(Ordering::Relaxed),
+            dropped: MQTT_MESSAGES_DROPPED.load(Ordering::Relaxed),
+            subscriptions_failed: MQTT_SUBSCRIPTIONS_FAILED.load(Ordering::Relaxed),
+            publishes_stalled: MQTT_PUBLISHES_STALLED.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets the counters to the values persisted before a restart.
+    pub fn restore(&self) {
+        MQTT_MESSAGES_SENT.store(self.sent, Ordering::Relaxed);
+        MQTT_MESSAGES_RECEIVED.store(self.received, Ordering::Relaxed);
+        MQTT_MESSAGES_PROCESSED.store(self.processed, Ordering::Relaxed);
+        MQTT_MESSAGES_REJECTED.store(self.rejected, Ordering::Relaxed);
+        MQTT_MESSAGES_DROPPED.store(self.dropped, Ordering::Relaxed);
+        MQTT_SUBSCRIPTIONS_FAILED.store(self.subscriptions_failed, Ordering::Relaxed);
+        MQTT_PUBLISHES_STALLED.store(self.publishes_stalled, Ordering::Relaxed);
+    }
+}
+
+/// Input events waiting to be published, as seen by the publish loop on the last event
+pub(super) static INPUT_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+/// Input changes not published because a later change of the channel was queued
+pub(super) static INPUT_COALESCED: AtomicU64 = AtomicU64::new(0);
+/// Sequence number of the last published input event, restarts at 1 with the bridge
+pub(super) static INPUT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of input events waiting to be published.
+pub fn input_queue_depth() -> usize {
+    INPUT_QUEUE_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Whether the outputs are in shadow mode, reported in the heartbeat
+pub(super) static OUTPUTS_SHADOW: AtomicBool = AtomicBool::new(false);
+
+/// Collects tokio runtime metrics.
+///
+/// Per-worker statistics are only available when built with `--cfg tokio_unstable`.
+fn runtime_metrics() -> serde_json::Value {
+    let metrics = tokio::runtime::Handle::current().metrics();
+
+    #[allow(unused_mut)]
+    let mut runtime = json!({
+        "workers": metrics.num_workers(),
+        "alive_tasks": metrics.num_alive_tasks(),
+        "global_queue_depth": metrics.global_queue_depth(),
+    });
+
+    #[cfg(tokio_unstable)]
+    {
+        let workers: Vec<_> = (0..metrics.num_workers())
+            .map(|worker| {
+                json!({
+                    "polls": metrics.worker_poll_count(worker),
+                    "busy_ms": metrics.worker_total_busy_duration(worker).as_millis() as u64,
+                    "mean_poll_us": metrics.worker_mean_poll_time(worker).as_micros() as u64,
+                    "local_queue_depth": metrics.worker_local_queue_depth(worker),
+                })
+            })
+            .collect();
+        runtime["spawned_tasks"] = json!(metrics.spawned_tasks_count());
+        runtime["blocking_threads"] = json!(metrics.num_blocking_threads());
+        runtime["worker_stats"] = json!(workers);
+    }
+
+    runtime
+}
+
+/// Combines the latest system metrics with the input event queue depth.
+pub(super) fn sample_usage(metrics: &SystemMetrics) -> Sample {
+    Sample {
+        memory_usage: metrics.memory_usage,
+        cpu_usage: metrics.cpu_usage,
+        queue_depth: INPUT_QUEUE_DEPTH.load(Ordering::Relaxed),
+    }
+}
+
+/// System statistics published on `telemetry/system`, also part of the heartbeat.
+pub(super) fn telemetry(usage: &Sample) -> serde_json::Value {
+    json!({
+        "timestamp": timestamp::now(),
+        "app_uptime": APP_START_TIME.elapsed().as_secs(),
+        "system_uptime": System::uptime(),
+        "cpu_usage": usage.cpu_usage,
+        "memory_usage": usage.memory_usage,
+        "queue_depth": usage.queue_depth,
+        "runtime": runtime_metrics(),
+        "tasks": supervisor::health(),
+        "dal": kbus::timing::report(),
+        "kbus_errors": kbus::errors::DAL_ERRORS.report(),
+    })
+}
+
+pub(super) fn heartbeat(usage: &Sample) -> serde_json::Value {
+    let stats = MqttStats::current();
+
+    let mut heartbeat = telemetry(usage);
+    heartbeat["restart_count"] = json!(state::restart_count());
+    heartbeat["coalesced"] = json!(INPUT_COALESCED.load(Ordering::Relaxed));
+    heartbeat["sequence"] = json!(INPUT_SEQUENCE.load(Ordering::Relaxed));
+    heartbeat["shadow"] = json!(OUTPUTS_SHADOW.load(Ordering::Relaxed));
+    heartbeat["mqtt_stats"] = json!({
+        "sent": stats.sent,
+        "received": stats.received,
+        "processed": stats.processed,
+        "rejected": stats.rejected,
+        "dropped": stats.dropped,
+        "subscriptions_failed": stats.subscriptions_failed,
+        "publishes_stalled": stats.publishes_stalled,
+        "total": stats.received + stats.sent
+    });
+    heartbeat
+}
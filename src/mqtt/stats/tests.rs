@@ -0,0 +1,74 @@
+use super::*;
+
+#[test]
+fn test_sample_usage() {
+    let metrics = SystemMetrics {
+        memory_usage: 42.5,
+        cpu_usage: 12.0,
+    };
+    let sample = sample_usage(&metrics);
+    assert_eq!(sample.memory_usage, 42.5);
+    assert_eq!(sample.cpu_usage, 12.0);
+    assert_eq!(sample.queue_depth, input_queue_depth());
+}
+
+#[test]
+fn test_mqtt_stats_current() {
+    let before = MqttStats::current();
+    MQTT_MESSAGES_REJECTED.fetch_add(1, Ordering::Relaxed);
+    MQTT_SUBSCRIPTIONS_FAILED.fetch_add(2, Ordering::Relaxed);
+
+    let after = MqttStats::current();
+    assert!(after.rejected > before.rejected);
+    assert!(after.subscriptions_failed >= before.subscriptions_failed + 2);
+}
+
+#[tokio::test]
+async fn test_telemetry() {
+    let usage = Sample {
+        memory_usage: 42.5,
+        cpu_usage: 12.0,
+        queue_depth: 3,
+    };
+    let telemetry = telemetry(&usage);
+    assert_eq!(telemetry["cpu_usage"], 12.0);
+    for key in [
+        "timestamp",
+        "system_uptime",
+        "runtime",
+        "dal",
+        "kbus_errors",
+    ] {
+        assert!(telemetry.get(key).is_some(), "missing {key}");
+    }
+    // The MQTT counters stay in the heartbeat
+    assert!(telemetry.get("mqtt_stats").is_none());
+}
+
+#[tokio::test]
+async fn test_heartbeat() {
+    let usage = Sample {
+        memory_usage: 42.5,
+        cpu_usage: 12.0,
+        queue_depth: 3,
+    };
+    let heartbeat = heartbeat(&usage);
+    assert_eq!(heartbeat["memory_usage"], 42.5);
+    assert_eq!(heartbeat["cpu_usage"], 12.0);
+    assert_eq!(heartbeat["queue_depth"], 3);
+    for key in [
+        "timestamp",
+        "app_uptime",
+        "restart_count",
+        "runtime",
+        "tasks",
+    ] {
+        assert!(heartbeat.get(key).is_some(), "missing {key}");
+    }
+
+    let stats = &heartbeat["mqtt_stats"];
+    assert_eq!(
+        stats["total"].as_u64().unwrap(),
+        stats["received"].as_u64().unwrap() + stats["sent"].as_u64().unwrap()
+    );
+}
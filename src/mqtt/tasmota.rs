@@ -0,0 +1,137 @@
+//! Channels in the Tasmota topic convention
+//!
+//! With `mqtt.topic_profile = "tasmota"`, the outputs are switched and reported like
+//! the relays of a Tasmota device with the topic `device_name`, and input changes
+//! like its detached switches, so ecosystems built around the convention (e.g.
+//! openHAB or Home Assistant setups) use the bridge without any mapping. Channels
+//! are numbered from 1 like Tasmota relays, output 0 is `POWER1`. All other topics
+//! of the bridge stay under the topic prefix. With a `topic_root`, the Tasmota
+//! topics are prefixed with it too, like a Tasmota `FullTopic` of
+//! `<root>/%prefix%/%topic%/`.
+
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use serde_json::{Map, Value, json};
+
+use crate::{kbus::InputEvent, timestamp};
+
+#[cfg(test)]
+mod tests;
+
+/// Last published state of the outputs, reported on `tele/<topic>/STATE`
+static POWER_STATES: Mutex<BTreeMap<u16, bool>> = Mutex::new(BTreeMap::new());
+
+/// Tasmota topics of the device.
+#[derive(Debug, Clone)]
+pub struct Tasmota {
+    /// Topic root with a trailing `/`, empty without it
+    root: String,
+    topic: String,
+}
+
+const fn on_off(value: bool) -> &'static str {
+    if value { "ON" } else { "OFF" }
+}
+
+/// Returns the Tasmota name of a channel, numbered from 1.
+fn channel_key(name: &str, channel: u16) -> String {
+    format!("{name}{}", u32::from(channel) + 1)
+}
+
+impl Tasmota {
+    pub fn new(topic: &str) -> Tasmota {
+        Tasmota {
+            root: String::new(),
+            topic: topic.to_owned(),
+        }
+    }
+
+    /// Prefixes the topics with the `topic_root`, if configured.
+    pub fn with_root(mut self, root: Option<&str>) -> Tasmota {
+        self.root = root.map(|root| format!("{root}/")).unwrap_or_default();
+        self
+    }
+
+    /// Returns the prefix of the command topics, e.g. for the router.
+    pub fn command_prefix(&self) -> String {
+        format!("{}cmnd/{}/", self.root, self.topic)
+    }
+
+    /// Returns the subscription of the command topics.
+    pub fn command_filter(&self) -> String {
+        format!("{}+", self.command_prefix())
+    }
+
+    /// Returns the topic of the retained `Online`/`Offline` availability.
+    pub fn lwt_topic(&self) -> String {
+        format!("{}tele/{}/LWT", self.root, self.topic)
+    }
+
+    /// Returns the filters of the topics the device publishes on.
+    pub fn topic_filters(&self) -> [String; 2] {
+        [
+            format!("{}tele/{}/#", self.root, self.topic),
+            format!("{}stat/{}/#", self.root, self.topic),
+        ]
+    }
+
+    /// Returns the topic of the periodic state.
+    pub fn state_topic(&self) -> String {
+        format!("{}tele/{}/STATE", self.root, self.topic)
+    }
+
+    /// Returns the topic and payload of an input event, `None` for events published
+    /// on the bridge topics.
+    ///
+    /// Input changes are published on `stat/<topic>/RESULT` as `{"Switch<i>": {"Action": "ON"}}`,
+    /// output states on `stat/<topic>/POWER<i>` as `ON`/`OFF`.
+    pub fn message(&self, event: &InputEvent) -> Option<(String, String)> {
+        match event {
+            InputEvent::Channel(event) => {
+                let payload = json!({
+                    channel_key("Switch", event.channel): { "Action": on_off(event.value) },
+                });
+                Some((
+                    format!("{}stat/{}/RESULT", self.root, self.topic),
+                    payload.to_string(),
+                ))
+            }
+            InputEvent::Output(write) => {
+                let (channel, value) = (write.event.channel, write.event.value);
+                POWER_STATES.lock().unwrap().insert(channel, value);
+                let topic = format!(
+                    "{}stat/{}/{}",
+                    self.root,
+                    self.topic,
+                    channel_key("POWER", channel)
+                );
+                Some((topic, on_off(value).to_owned()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Formats the uptime like Tasmota, e.g. `1T02:03:04`.
+fn format_uptime(uptime: Duration) -> String {
+    let seconds = uptime.as_secs();
+    format!(
+        "{}T{:02}:{:02}:{:02}",
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Returns the payload of `tele/<topic>/STATE` with the outputs written so far.
+pub fn state(uptime: Duration) -> Value {
+    let mut state = Map::new();
+    state.insert("Time".to_owned(), timestamp::now());
+    state.insert("Uptime".to_owned(), json!(format_uptime(uptime)));
+    state.insert("UptimeSec".to_owned(), json!(uptime.as_secs()));
+    for (&channel, &value) in POWER_STATES.lock().unwrap().iter() {
+        state.insert(channel_key("POWER", channel), json!(on_off(value)));
+    }
+    Value::Object(state)
+}
@@ -0,0 +1,51 @@
+use serde_json::json;
+
+use super::*;
+use crate::kbus::{KBusEvent, OutputWrite};
+
+#[test]
+fn test_topics() {
+    let tasmota = Tasmota::new("pfc200");
+    assert_eq!(tasmota.command_prefix(), "cmnd/pfc200/");
+    assert_eq!(tasmota.command_filter(), "cmnd/pfc200/+");
+    assert_eq!(tasmota.lwt_topic(), "tele/pfc200/LWT");
+    assert_eq!(tasmota.state_topic(), "tele/pfc200/STATE");
+
+    let tasmota = Tasmota::new("pfc200").with_root(Some("acme/plant1"));
+    assert_eq!(tasmota.command_filter(), "acme/plant1/cmnd/pfc200/+");
+    assert_eq!(tasmota.lwt_topic(), "acme/plant1/tele/pfc200/LWT");
+    assert_eq!(tasmota.state_topic(), "acme/plant1/tele/pfc200/STATE");
+}
+
+#[test]
+fn test_message() {
+    let tasmota = Tasmota::new("pfc200");
+    let event = InputEvent::Channel(KBusEvent {
+        channel: 0,
+        value: true,
+    });
+    let (topic, payload) = tasmota.message(&event).unwrap();
+    assert_eq!(topic, "stat/pfc200/RESULT");
+    assert_eq!(
+        serde_json::from_str::<Value>(&payload).unwrap(),
+        json!({ "Switch1": { "Action": "ON" } })
+    );
+
+    let event = InputEvent::Output(OutputWrite::new(
+        KBusEvent {
+            channel: 41,
+            value: false,
+        },
+        None,
+    ));
+    let (topic, payload) = tasmota.message(&event).unwrap();
+    assert_eq!(topic, "stat/pfc200/POWER42");
+    assert_eq!(payload, "OFF");
+
+    // Published output states are reported on the state
+    let state = state(Duration::from_secs(93784));
+    assert_eq!(state["POWER42"], "OFF");
+    assert_eq!(state["Uptime"], "1T02:03:04");
+    assert_eq!(state["UptimeSec"], 93784);
+    assert!(state["Time"].is_string());
+}
@@ -0,0 +1,42 @@
+use super::*;
+use crate::config::GroupedChannel;
+
+#[test]
+fn test_metadata() {
+    let identity = Identity {
+        source: IdentitySource::Mac,
+        id: "00:30:de:00:00:01".to_owned(),
+    };
+    let config = Config {
+        device_name: "pfc200".to_owned(),
+        ..Config::default()
+    };
+    let metadata = metadata(&config, &identity);
+    assert_eq!(metadata["device_name"], "pfc200");
+    assert_eq!(metadata["id"], "00:30:de:00:00:01");
+    assert_eq!(metadata["identity_source"], "mac");
+    assert_eq!(metadata["mac"], "00:30:de:00:00:01");
+    assert_eq!(metadata["version"], env!("CARGO_PKG_VERSION"));
+    assert!(metadata.get("groups").is_none());
+
+    let identity = Identity {
+        source: IdentitySource::MachineId,
+        id: "3f2a9c01b7de".to_owned(),
+    };
+    let hashed = super::metadata(&config, &identity);
+    assert_eq!(hashed["id"], "3f2a9c01b7de");
+    assert_eq!(hashed["identity_source"], "machine-id");
+    assert!(hashed.get("mac").is_none());
+
+    let mut config = config;
+    config.inputs.groups = vec![GroupedChannel {
+        channel: 12,
+        group: "hvac".to_owned(),
+        name: Some("fan_feedback".to_owned()),
+    }];
+    let grouped = super::metadata(&config, &identity);
+    assert_eq!(
+        grouped["groups"],
+        json!({"input": {"12": "hvac/fan_feedback"}, "output": {}})
+    );
+}
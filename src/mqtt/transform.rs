@@ -0,0 +1,150 @@
+//! User scripts transforming MQTT messages
+//!
+//! A [Rhai](https://rhai.rs) script can define the functions `incoming(topic, payload)`
+//! and `outgoing(topic, payload)`, called for every received and published message
+//! with the full topic and the payload as a string. They return either a new payload
+//! string, a map with `topic` and/or `payload` replacing the given ones, or `()` to
+//! drop the message. This adapts the bridge to legacy broker conventions, e.g.
+//! `1`/`0` payloads or a different topic tree, without changing the code.
+
+use std::{path::Path, str::from_utf8};
+
+use anyhow::{Context, anyhow};
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of operations per call, stops runaway scripts.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A message after transformation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Direction of a transformed message, the name of the script function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl Direction {
+    const fn function(self) -> &'static str {
+        match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+        }
+    }
+}
+
+/// A compiled transformation script.
+#[derive(Debug)]
+pub struct Transform {
+    engine: Engine,
+    ast: AST,
+    incoming: bool,
+    outgoing: bool,
+}
+
+impl Transform {
+    /// Compiles the script in the given file.
+    pub fn load(path: &Path) -> Result<Transform, anyhow::Error> {
+        let script = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read transform script {}", path.display()))?;
+        Transform::compile(&script)
+            .with_context(|| format!("invalid transform script {}", path.display()))
+    }
+
+    /// Compiles the script, which must define `incoming` and/or `outgoing`.
+    pub fn compile(script: &str) -> Result<Transform, anyhow::Error> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(script)?;
+
+        let defines = |direction: Direction| {
+            ast.iter_functions()
+                .any(|f| f.name == direction.function() && f.params.len() == 2)
+        };
+        let incoming = defines(Direction::Incoming);
+        let outgoing = defines(Direction::Outgoing);
+        if !incoming && !outgoing {
+            return Err(anyhow!(
+                "script defines neither incoming(topic, payload) nor outgoing(topic, payload)"
+            ));
+        }
+
+        Ok(Transform {
+            engine,
+            ast,
+            incoming,
+            outgoing,
+        })
+    }
+
+    /// Transforms a received message, `None` if it should be dropped.
+    pub fn incoming(&self, topic: &str, payload: &[u8]) -> Result<Option<Message>, anyhow::Error> {
+        self.apply(self.incoming, Direction::Incoming, topic, payload)
+    }
+
+    /// Transforms a message before publishing, `None` if it should be dropped.
+    pub fn outgoing(&self, topic: &str, payload: &[u8]) -> Result<Option<Message>, anyhow::Error> {
+        self.apply(self.outgoing, Direction::Outgoing, topic, payload)
+    }
+
+    fn apply(
+        &self,
+        defined: bool,
+        direction: Direction,
+        topic: &str,
+        payload: &[u8],
+    ) -> Result<Option<Message>, anyhow::Error> {
+        let unchanged = || Message {
+            topic: topic.to_owned(),
+            payload: payload.to_vec(),
+        };
+        // Binary payloads are passed through, scripts only handle strings
+        let (true, Ok(payload_str)) = (defined, from_utf8(payload)) else {
+            return Ok(Some(unchanged()));
+        };
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                direction.function(),
+                (topic.to_owned(), payload_str.to_owned()),
+            )
+            .with_context(|| format!("{}() failed", direction.function()))?;
+
+        if result.is_unit() {
+            return Ok(None);
+        }
+        if result.is_string() {
+            return Ok(Some(Message {
+                payload: result.into_string().unwrap_or_default().into_bytes(),
+                ..unchanged()
+            }));
+        }
+        let Some(mut map) = result.try_cast::<Map>() else {
+            return Err(anyhow!(
+                "{}() must return a string, a map or ()",
+                direction.function()
+            ));
+        };
+        let mut message = unchanged();
+        if let Some(topic) = map.remove("topic") {
+            message.topic = topic
+                .into_string()
+                .map_err(|ty| anyhow!("topic must be a string, got {ty}"))?;
+        }
+        if let Some(payload) = map.remove("payload") {
+            message.payload = payload.to_string().into_bytes();
+        }
+        Ok(Some(message))
+    }
+}
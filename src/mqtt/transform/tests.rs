@@ -0,0 +1,86 @@
+use super::*;
+
+const SCRIPT: &str = r#"
+    fn incoming(topic, payload) {
+        if topic.starts_with("legacy/relay") {
+            let channel = topic.sub_string(12);
+            return #{ topic: "pfc200/output/" + channel, payload: payload == "1" };
+        }
+        if topic == "legacy/ignored" {
+            return ();
+        }
+        payload
+    }
+
+    fn outgoing(topic, payload) {
+        if payload == "true" { "1" } else if payload == "false" { "0" } else { payload }
+    }
+"#;
+
+fn message(topic: &str, payload: &str) -> Option<Message> {
+    Some(Message {
+        topic: topic.to_owned(),
+        payload: payload.as_bytes().to_vec(),
+    })
+}
+
+#[test]
+fn test_incoming() {
+    let transform = Transform::compile(SCRIPT).unwrap();
+    assert_eq!(
+        transform.incoming("legacy/relay3", b"1").unwrap(),
+        message("pfc200/output/3", "true")
+    );
+    assert_eq!(transform.incoming("legacy/ignored", b"1").unwrap(), None);
+    assert_eq!(
+        transform.incoming("pfc200/output/1", b"on").unwrap(),
+        message("pfc200/output/1", "on")
+    );
+    // Binary payloads are passed through
+    assert_eq!(
+        transform.incoming("legacy/ignored", b"\xff").unwrap(),
+        Some(Message {
+            topic: "legacy/ignored".to_owned(),
+            payload: vec![0xff],
+        })
+    );
+}
+
+#[test]
+fn test_outgoing() {
+    let transform = Transform::compile(SCRIPT).unwrap();
+    assert_eq!(
+        transform.outgoing("pfc200/input/1", b"true").unwrap(),
+        message("pfc200/input/1", "1")
+    );
+    assert_eq!(
+        transform.outgoing("pfc200/status", b"online").unwrap(),
+        message("pfc200/status", "online")
+    );
+}
+
+#[test]
+fn test_single_direction() {
+    let transform = Transform::compile("fn outgoing(topic, payload) { () }").unwrap();
+    assert_eq!(transform.outgoing("pfc200/input/1", b"true").unwrap(), None);
+    assert_eq!(
+        transform.incoming("pfc200/output/1", b"true").unwrap(),
+        message("pfc200/output/1", "true")
+    );
+}
+
+#[test]
+fn test_errors() {
+    assert!(Transform::compile("fn other(topic, payload) { payload }").is_err());
+    assert!(Transform::compile("fn incoming(topic, payload) {").is_err());
+
+    let transform = Transform::compile(
+        r#"
+        fn incoming(topic, payload) { 42 }
+        fn outgoing(topic, payload) { loop {} }
+        "#,
+    )
+    .unwrap();
+    assert!(transform.incoming("pfc200/output/1", b"on").is_err());
+    assert!(transform.outgoing("pfc200/input/1", b"true").is_err());
+}
@@ -0,0 +1,85 @@
+//! Waiting for the network on startup
+//!
+//! On boot, the bridge may start before the network is configured. The MQTT task
+//! fails on the first connection error and the bridge exits, so the service manager
+//! would restart it over and over. Instead, the bridge waits up to
+//! `mqtt.startup_wait` for an interface with an address and for the host it
+//! connects to (broker or proxy) to resolve before the MQTT client is created.
+
+use std::time::Duration;
+
+use pnet::datalink::{self, NetworkInterface};
+use tokio::{
+    net::lookup_host,
+    time::{Instant, sleep},
+};
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+#[cfg(test)]
+mod tests;
+
+/// Interval of checking the network while waiting
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Returns whether an interface other than loopback is up and has an address.
+fn has_network(interfaces: &[NetworkInterface]) -> bool {
+    interfaces
+        .iter()
+        .any(|interface| interface.is_up() && !interface.is_loopback() && !interface.ips.is_empty())
+}
+
+/// Returns the host and port the MQTT client connects to first.
+fn connect_address(config: &Config) -> (String, u16) {
+    match &config.mqtt.proxy {
+        Some(proxy) => (proxy.host.clone(), proxy.port),
+        None => config.broker_address(),
+    }
+}
+
+/// Returns whether `host` resolves to at least one address.
+async fn resolves(host: &str, port: u16) -> bool {
+    lookup_host((host, port))
+        .await
+        .is_ok_and(|mut addresses| addresses.next().is_some())
+}
+
+/// Waits up to `mqtt.startup_wait` for the network and the DNS name of the broker.
+///
+/// Returns whether the network is ready. On timeout, the bridge starts anyway and
+/// a failing connection makes it exit as before.
+pub async fn wait(config: &Config) -> bool {
+    let timeout = config.mqtt.startup_wait;
+    if timeout.is_zero() {
+        return true;
+    }
+    // The embedded broker is reached locally
+    let local = config.broker.is_some();
+    let (host, port) = connect_address(config);
+    let deadline = Instant::now() + timeout;
+    let mut logged = false;
+    loop {
+        let network = local || has_network(&datalink::interfaces());
+        if network && resolves(&host, port).await {
+            if logged {
+                info!(host, "network ready");
+            }
+            return true;
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                host,
+                ?timeout,
+                network,
+                "network not ready, starting anyway"
+            );
+            return false;
+        }
+        if !logged {
+            info!(host, network, "waiting for network");
+            logged = true;
+        }
+        sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,79 @@
+use pnet::{datalink::MacAddr, ipnetwork::IpNetwork};
+
+use super::*;
+use crate::config::{MqttConfig, ProxyConfig};
+
+fn interface(name: &str, flags: u32, ips: Vec<IpNetwork>) -> NetworkInterface {
+    NetworkInterface {
+        name: name.to_owned(),
+        description: String::new(),
+        index: 1,
+        mac: Some(MacAddr::zero()),
+        ips,
+        flags,
+    }
+}
+
+#[test]
+fn test_has_network() {
+    let up = libc::IFF_UP as u32;
+    let loopback = interface(
+        "lo",
+        up | libc::IFF_LOOPBACK as u32,
+        vec!["127.0.0.1/8".parse().unwrap()],
+    );
+    assert!(!has_network(&[loopback]));
+    // Up, but no address assigned yet
+    assert!(!has_network(&[interface("br0", up, vec![])]));
+    assert!(!has_network(&[interface(
+        "br0",
+        0,
+        vec!["192.168.1.17/24".parse().unwrap()]
+    )]));
+    assert!(has_network(&[interface(
+        "br0",
+        up,
+        vec!["192.168.1.17/24".parse().unwrap()]
+    )]));
+}
+
+#[test]
+fn test_connect_address() {
+    let mut config = Config {
+        mqtt: MqttConfig {
+            broker_host: "broker.example.com".to_owned(),
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert_eq!(
+        connect_address(&config),
+        ("broker.example.com".to_owned(), 1883)
+    );
+
+    config.mqtt.proxy = Some(ProxyConfig {
+        host: "proxy.example.com".to_owned(),
+        port: 3128,
+        username: None,
+        password: None,
+    });
+    assert_eq!(
+        connect_address(&config),
+        ("proxy.example.com".to_owned(), 3128)
+    );
+}
+
+#[tokio::test]
+async fn test_wait() {
+    let config = Config {
+        mqtt: MqttConfig {
+            broker_host: "localhost".to_owned(),
+            startup_wait: Duration::ZERO,
+            ..MqttConfig::default()
+        },
+        ..Config::default()
+    };
+    assert!(wait(&config).await);
+
+    assert!(resolves("localhost", 1883).await);
+}
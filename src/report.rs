@@ -0,0 +1,43 @@
+//! Reports of fatal task errors
+//!
+//! An [`ErrorReport`] captures the error chain of a failed task, so it can be
+//! published on the retained `last_error` topic before the bridge exits and
+//! analyzed later even if the logs of the device aren't collected.
+
+use serde::Serialize;
+
+use crate::{kbus::KBusError, timestamp};
+
+#[cfg(test)]
+mod tests;
+
+/// A fatal error of a bridge task.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// Name of the failed task
+    pub task: &'static str,
+    /// Error with all causes, as logged
+    pub error: String,
+    /// Error messages from the outermost context to the root cause
+    pub chain: Vec<String>,
+    /// K-Bus error in the chain, if the task failed on the K-Bus
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kbus_error: Option<serde_json::Value>,
+    /// Time of the failure in the configured timestamp format
+    pub timestamp: serde_json::Value,
+}
+
+impl ErrorReport {
+    pub fn new(task: &'static str, err: &anyhow::Error) -> ErrorReport {
+        ErrorReport {
+            task,
+            error: format!("{err:#}"),
+            chain: err.chain().map(ToString::to_string).collect(),
+            kbus_error: err
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<KBusError>())
+                .and_then(|kbus_error| serde_json::to_value(kbus_error).ok()),
+            timestamp: timestamp::now(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::Context;
+
+use super::*;
+
+#[test]
+fn test_error_report() {
+    let err = Err::<(), _>(anyhow::anyhow!("connection refused"))
+        .context("failed to poll MQTT event loop")
+        .unwrap_err();
+    let report = ErrorReport::new("mqtt", &err);
+    assert_eq!(report.task, "mqtt");
+    assert_eq!(
+        report.error,
+        "failed to poll MQTT event loop: connection refused"
+    );
+    assert_eq!(
+        report.chain,
+        ["failed to poll MQTT event loop", "connection refused"]
+    );
+    assert_eq!(report.kbus_error, None);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["task"], "mqtt");
+    assert!(json["timestamp"].is_string());
+    assert!(json.get("kbus_error").is_none());
+}
+
+#[test]
+fn test_error_report_kbus() {
+    let err = Err::<(), _>(KBusError::DeviceNotFound)
+        .context("failed to create K-Bus instance")
+        .unwrap_err();
+    let report = ErrorReport::new("kbus", &err);
+    assert_eq!(report.chain.len(), 2);
+    assert_eq!(
+        report.kbus_error,
+        Some(serde_json::json!("device_not_found"))
+    );
+}
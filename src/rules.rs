@@ -0,0 +1,251 @@
+//! Derived signals defined by logical expressions over input channels
+//!
+//! Rules are configured as `name = "expression"` pairs, e.g. `alarm = "in3 && !in7"`.
+//! Expressions support input channel references (`inN`), the `true`/`false` literals,
+//! negation (`!`), conjunction (`&&`), disjunction (`||`) and parentheses. `!` binds
+//! stronger than `&&`, which binds stronger than `||`. Parentheses and negations nest
+//! at most [`MAX_DEPTH`] levels deep and an expression has at most [`MAX_OPERATORS`]
+//! `&&`/`||` operators, which keeps parsing and evaluation recursion bounded.
+
+use std::{iter::Peekable, str::CharIndices, sync::Arc};
+
+use anyhow::{Context, anyhow};
+use bitvec::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum nesting of parentheses and negations in an expression.
+pub const MAX_DEPTH: usize = 32;
+
+/// Maximum number of binary operators in an expression.
+pub const MAX_OPERATORS: usize = 256;
+
+/// A parsed logical expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// Constant value
+    Const(bool),
+    /// State of the input channel
+    Input(u16),
+    /// Logical negation
+    Not(Box<Expr>),
+    /// Logical conjunction
+    And(Box<Expr>, Box<Expr>),
+    /// Logical disjunction
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses an expression.
+    pub fn parse(source: &str) -> Result<Expr, anyhow::Error> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+            depth: 0,
+            operators: 0,
+        };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        if let Some((pos, c)) = parser.chars.next() {
+            return Err(anyhow!("unexpected character '{c}' at position {pos}"));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression against the input process image.
+    ///
+    /// Channels outside of the process image evaluate to `false`.
+    pub fn eval(&self, inputs: &BitSlice<u8>) -> bool {
+        match self {
+            Expr::Const(value) => *value,
+            Expr::Input(channel) => inputs.get(usize::from(*channel)).is_some_and(|bit| *bit),
+            Expr::Not(expr) => !expr.eval(inputs),
+            Expr::And(lhs, rhs) => lhs.eval(inputs) && rhs.eval(inputs),
+            Expr::Or(lhs, rhs) => lhs.eval(inputs) || rhs.eval(inputs),
+        }
+    }
+
+    /// Calls `f` for every input channel referenced by the expression.
+    pub fn for_each_input(&self, f: &mut impl FnMut(u16)) {
+        match self {
+            Expr::Const(_) => {}
+            Expr::Input(channel) => f(*channel),
+            Expr::Not(expr) => expr.for_each_input(f),
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+                lhs.for_each_input(f);
+                rhs.for_each_input(f);
+            }
+        }
+    }
+
+    /// Returns the highest input channel referenced by the expression.
+    pub fn max_channel(&self) -> Option<u16> {
+        match self {
+            Expr::Const(_) => None,
+            Expr::Input(channel) => Some(*channel),
+            Expr::Not(expr) => expr.max_channel(),
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => lhs.max_channel().max(rhs.max_channel()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+    operators: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    /// Consumes `token` if it is next in the input (after whitespace).
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let Some(&(pos, _)) = self.chars.peek() else {
+            return false;
+        };
+        if self.source[pos..].starts_with(token) {
+            for _ in token.chars() {
+                self.chars.next();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Enters a parenthesis or negation, failing past [`MAX_DEPTH`].
+    fn enter(&mut self) -> Result<(), anyhow::Error> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(anyhow!("expression nested deeper than {MAX_DEPTH} levels"));
+        }
+        Ok(())
+    }
+
+    /// Counts a binary operator, failing past [`MAX_OPERATORS`].
+    fn operator(&mut self) -> Result<(), anyhow::Error> {
+        self.operators += 1;
+        if self.operators > MAX_OPERATORS {
+            return Err(anyhow!(
+                "expression has more than {MAX_OPERATORS} operators"
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, anyhow::Error> {
+        let mut expr = self.parse_and()?;
+        while self.eat("||") {
+            self.operator()?;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, anyhow::Error> {
+        let mut expr = self.parse_unary()?;
+        while self.eat("&&") {
+            self.operator()?;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, anyhow::Error> {
+        if self.eat("!") {
+            self.enter()?;
+            let expr = self.parse_unary()?;
+            self.depth -= 1;
+            Ok(Expr::Not(Box::new(expr)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, anyhow::Error> {
+        if self.eat("(") {
+            self.enter()?;
+            let expr = self.parse_or()?;
+            if !self.eat(")") {
+                return Err(anyhow!("missing closing parenthesis"));
+            }
+            self.depth -= 1;
+            return Ok(expr);
+        }
+
+        self.skip_whitespace();
+        let Some(&(start, _)) = self.chars.peek() else {
+            return Err(anyhow!("unexpected end of expression"));
+        };
+        let mut end = start;
+        while let Some((pos, c)) = self.chars.next_if(|(_, c)| c.is_ascii_alphanumeric()) {
+            end = pos + c.len_utf8();
+        }
+
+        match &self.source[start..end] {
+            "" => Err(anyhow!(
+                "unexpected character '{}' at position {start}",
+                self.source[start..].chars().next().unwrap_or_default()
+            )),
+            "true" => Ok(Expr::Const(true)),
+            "false" => Ok(Expr::Const(false)),
+            word => {
+                let channel = word
+                    .strip_prefix("in")
+                    .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                    .ok_or_else(|| anyhow!("unknown identifier '{word}' at position {start}"))?
+                    .parse()
+                    .with_context(|| format!("invalid input channel '{word}'"))?;
+                Ok(Expr::Input(channel))
+            }
+        }
+    }
+}
+
+/// A named derived signal together with its last published value.
+#[derive(Debug)]
+pub struct Rule {
+    name: Arc<str>,
+    expr: Expr,
+    last: Option<bool>,
+}
+
+impl Rule {
+    /// Creates a rule from its name and expression source.
+    pub fn new(name: &str, source: &str) -> Result<Rule, anyhow::Error> {
+        let expr = Expr::parse(source).with_context(|| format!("invalid rule '{name}'"))?;
+        Ok(Rule {
+            name: name.into(),
+            expr,
+            last: None,
+        })
+    }
+
+    /// Returns the rule name.
+    pub fn name(&self) -> &Arc<str> {
+        &self.name
+    }
+
+    /// Returns the parsed expression.
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// Evaluates the rule, returning the new value if it changed since the last call.
+    ///
+    /// The first evaluation always reports the value.
+    pub fn update(&mut self, inputs: &BitSlice<u8>) -> Option<bool> {
+        let value = self.expr.eval(inputs);
+        if self.last == Some(value) {
+            None
+        } else {
+            self.last = Some(value);
+            Some(value)
+        }
+    }
+}
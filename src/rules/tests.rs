@@ -0,0 +1,92 @@
+use super::*;
+
+fn inputs(bits: &[usize]) -> BitVec<u8> {
+    let mut inputs = bitvec![u8, LocalBits; 0; 16];
+    for &bit in bits {
+        inputs.set(bit, true);
+    }
+    inputs
+}
+
+#[test]
+fn test_parse_precedence() {
+    let expr = Expr::parse("in1 || in2 && !in3").unwrap();
+    assert_eq!(
+        expr,
+        Expr::Or(
+            Box::new(Expr::Input(1)),
+            Box::new(Expr::And(
+                Box::new(Expr::Input(2)),
+                Box::new(Expr::Not(Box::new(Expr::Input(3))))
+            ))
+        )
+    );
+
+    let expr = Expr::parse("(in1||in2)&&true").unwrap();
+    assert_eq!(
+        expr,
+        Expr::And(
+            Box::new(Expr::Or(Box::new(Expr::Input(1)), Box::new(Expr::Input(2)))),
+            Box::new(Expr::Const(true))
+        )
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Expr::parse("").is_err());
+    assert!(Expr::parse("in").is_err());
+    assert!(Expr::parse("in3 &&").is_err());
+    assert!(Expr::parse("(in3 && in4").is_err());
+    assert!(Expr::parse("in3 in4").is_err());
+    assert!(Expr::parse("out3").is_err());
+    assert!(Expr::parse("in3 & in4").is_err());
+    assert!(Expr::parse("in99999999").is_err());
+}
+
+#[test]
+fn test_parse_limits() {
+    let nested = |depth| format!("{}in1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(Expr::parse(&nested(MAX_DEPTH)).unwrap(), Expr::Input(1));
+    assert!(Expr::parse(&nested(MAX_DEPTH + 1)).is_err());
+    assert!(Expr::parse(&nested(100_000)).is_err());
+
+    let negated = |depth| format!("{}in1", "!".repeat(depth));
+    assert!(Expr::parse(&negated(MAX_DEPTH)).is_ok());
+    assert!(Expr::parse(&negated(100_000)).is_err());
+
+    let chained = |operators| format!("in1{}", " && in2".repeat(operators));
+    assert!(Expr::parse(&chained(MAX_OPERATORS)).is_ok());
+    assert!(Expr::parse(&chained(100_000)).is_err());
+}
+
+#[test]
+fn test_eval() {
+    let expr = Expr::parse("in3 && !in7").unwrap();
+    assert!(!expr.eval(&inputs(&[])));
+    assert!(expr.eval(&inputs(&[3])));
+    assert!(!expr.eval(&inputs(&[3, 7])));
+
+    // Channels outside of the process image are false
+    let expr = Expr::parse("!in100").unwrap();
+    assert!(expr.eval(&inputs(&[])));
+}
+
+#[test]
+fn test_max_channel() {
+    assert_eq!(Expr::parse("true").unwrap().max_channel(), None);
+    assert_eq!(
+        Expr::parse("in3 || !(in12 && in7)").unwrap().max_channel(),
+        Some(12)
+    );
+}
+
+#[test]
+fn test_rule_reports_changes_only() {
+    let mut rule = Rule::new("alarm", "in3 && !in7").unwrap();
+    assert_eq!(rule.update(&inputs(&[])), Some(false));
+    assert_eq!(rule.update(&inputs(&[7])), None);
+    assert_eq!(rule.update(&inputs(&[3])), Some(true));
+    assert_eq!(rule.update(&inputs(&[3, 1])), None);
+    assert_eq!(rule.update(&inputs(&[3, 7])), Some(false));
+}
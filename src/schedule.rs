@@ -0,0 +1,198 @@
+//! Local output schedules
+//!
+//! Schedules drive output channels directly from the bridge, independently of
+//! the MQTT connection, so basic automation keeps working when the broker is down.
+//! A schedule either fires at a fixed local time of day (optionally limited to some
+//! weekdays) or repeatedly at a fixed interval.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{Datelike, Local, NaiveDateTime, TimeDelta};
+use tokio::{sync::mpsc::UnboundedSender, time::sleep};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument};
+
+use crate::{
+    config::{ScheduleConfig, SupervisorConfig},
+    kbus::{KBusCommand, KBusEvent, OutputWrite},
+    shutdown::{self, ShutdownReason},
+    supervisor,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Number of days searched for the next/previous time-of-day fire (one week).
+const SEARCH_DAYS: i64 = 7;
+/// Maximum sleep between checks, so wall clock adjustments (e.g. NTP sync after boot)
+/// are picked up in a timely manner.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+impl ScheduleConfig {
+    /// Returns the first time strictly after `now` at which a time-of-day schedule fires.
+    ///
+    /// Returns `None` for interval schedules.
+    pub fn next_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let at = self.at?;
+        (0..=SEARCH_DAYS)
+            .map(|day| (now.date() + TimeDelta::days(day)).and_time(at))
+            .find(|time| *time > now && self.runs_on(time))
+    }
+
+    /// Returns the last time at or before `now` at which a time-of-day schedule fired.
+    ///
+    /// Returns `None` for interval schedules.
+    pub fn previous_before(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let at = self.at?;
+        (0..=SEARCH_DAYS)
+            .map(|day| (now.date() - TimeDelta::days(day)).and_time(at))
+            .find(|time| *time <= now && self.runs_on(time))
+    }
+
+    fn runs_on(&self, time: &NaiveDateTime) -> bool {
+        self.days.is_empty() || self.days.contains(&time.weekday())
+    }
+}
+
+/// Sends the output value of the schedule to the K-Bus task.
+fn apply(
+    schedule: &ScheduleConfig,
+    kbus_commands: &UnboundedSender<KBusCommand>,
+) -> Result<(), anyhow::Error> {
+    info!(
+        output = schedule.output,
+        value = schedule.value,
+        "schedule fired"
+    );
+    kbus_commands
+        .send(KBusCommand::Output(OutputWrite::new(
+            KBusEvent {
+                channel: schedule.output,
+                value: schedule.value,
+            },
+            None,
+        )))
+        .context("K-Bus command queue closed")
+}
+
+/// Converts local wall clock time to a duration from now, clamping past times to zero.
+fn duration_until(time: NaiveDateTime, now: NaiveDateTime) -> Duration {
+    (time - now).to_std().unwrap_or_default()
+}
+
+fn local_now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+/// Restores the state of outputs driven by time-of-day schedules.
+///
+/// For every output, the most recent schedule that should already have fired is applied,
+/// so a bridge restarted at noon turns on an output scheduled for 06:00 - 20:00.
+fn restore_outputs(
+    schedules: &[ScheduleConfig],
+    kbus_commands: &UnboundedSender<KBusCommand>,
+    now: NaiveDateTime,
+) -> Result<(), anyhow::Error> {
+    let mut latest: Vec<(&ScheduleConfig, NaiveDateTime)> = Vec::new();
+    for schedule in schedules {
+        let Some(fired) = schedule.previous_before(now) else {
+            continue;
+        };
+        match latest.iter_mut().find(|(s, _)| s.output == schedule.output) {
+            Some(entry) if entry.1 < fired => *entry = (schedule, fired),
+            Some(_) => {}
+            None => latest.push((schedule, fired)),
+        }
+    }
+
+    for (schedule, _) in latest {
+        apply(schedule, kbus_commands)?;
+    }
+    Ok(())
+}
+
+async fn schedule_loop(
+    schedules: Vec<ScheduleConfig>,
+    kbus_commands: UnboundedSender<KBusCommand>,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    info!(
+        "starting schedule task with {} schedule(s)",
+        schedules.len()
+    );
+
+    let start = local_now();
+    restore_outputs(&schedules, &kbus_commands, start)?;
+
+    // Next fire time of every schedule in local wall clock time
+    let mut next_fires: Vec<Option<NaiveDateTime>> = schedules
+        .iter()
+        .map(|schedule| match schedule.every {
+            Some(every) => TimeDelta::from_std(every).ok().map(|every| start + every),
+            None => schedule.next_after(start),
+        })
+        .collect();
+
+    loop {
+        let Some(next) = next_fires.iter().flatten().min().copied() else {
+            info!("no more scheduled events");
+            cancellation_token.cancelled().await;
+            return Ok(());
+        };
+
+        tokio::select! {
+            _ = sleep(duration_until(next, local_now()).min(MAX_SLEEP)) => {},
+            _ = cancellation_token.cancelled() => return Ok(()),
+        }
+
+        // The clock may have been adjusted while sleeping, fire everything that is due
+        let now = local_now();
+        for (schedule, next_fire) in schedules.iter().zip(next_fires.iter_mut()) {
+            let Some(fire) = *next_fire else {
+                continue;
+            };
+            if fire > now {
+                continue;
+            }
+
+            apply(schedule, &kbus_commands)?;
+            *next_fire = match schedule.every {
+                Some(every) => TimeDelta::from_std(every).ok().map(|every| now + every),
+                None => schedule.next_after(now),
+            };
+        }
+    }
+}
+
+/// Entry point task function for local output schedules.
+///
+/// # Arguments
+///
+/// * `schedules` - Validated schedules from the configuration
+/// * `kbus_commands` - Channel for sending output commands to the K-Bus task
+/// * `supervisor_config` - Restarting of the task after a failure
+/// * `cancellation_token` - Token to signal when this task should terminate
+#[instrument(name = "schedule", skip_all, err)]
+pub async fn schedule_task(
+    schedules: Vec<ScheduleConfig>,
+    kbus_commands: UnboundedSender<KBusCommand>,
+    supervisor_config: SupervisorConfig,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let result = supervisor::supervise("schedule", &supervisor_config, &cancellation_token, || {
+        schedule_loop(
+            schedules.clone(),
+            kbus_commands.clone(),
+            cancellation_token.clone(),
+        )
+    })
+    .await;
+
+    if result.is_err() {
+        shutdown::initiate(ShutdownReason::Task);
+    }
+    cancellation_token.cancel();
+
+    result
+}
@@ -0,0 +1,108 @@
+use chrono::{NaiveDate, NaiveTime, Weekday};
+use tokio::sync::mpsc::unbounded_channel;
+
+use super::*;
+
+fn time_of_day(output: u16, value: bool, at: &str, days: Vec<Weekday>) -> ScheduleConfig {
+    ScheduleConfig {
+        output,
+        value,
+        at: Some(at.parse::<NaiveTime>().unwrap()),
+        days,
+        every: None,
+    }
+}
+
+fn datetime(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    // 2025-03-03 is a Monday
+    NaiveDate::from_ymd_opt(2025, 3, day)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+}
+
+#[test]
+fn test_next_after() {
+    let schedule = time_of_day(4, true, "06:00", vec![]);
+    assert_eq!(
+        schedule.next_after(datetime(3, 5, 0)),
+        Some(datetime(3, 6, 0))
+    );
+    // Strictly after now
+    assert_eq!(
+        schedule.next_after(datetime(3, 6, 0)),
+        Some(datetime(4, 6, 0))
+    );
+    assert_eq!(
+        schedule.next_after(datetime(3, 12, 0)),
+        Some(datetime(4, 6, 0))
+    );
+}
+
+#[test]
+fn test_next_after_weekdays() {
+    let schedule = time_of_day(4, true, "06:00", vec![Weekday::Mon, Weekday::Fri]);
+    // Monday after 06:00 -> Friday
+    assert_eq!(
+        schedule.next_after(datetime(3, 7, 0)),
+        Some(datetime(7, 6, 0))
+    );
+    // Friday after 06:00 -> next Monday
+    assert_eq!(
+        schedule.next_after(datetime(7, 7, 0)),
+        Some(datetime(10, 6, 0))
+    );
+}
+
+#[test]
+fn test_previous_before() {
+    let schedule = time_of_day(4, true, "06:00", vec![]);
+    assert_eq!(
+        schedule.previous_before(datetime(3, 6, 0)),
+        Some(datetime(3, 6, 0))
+    );
+    assert_eq!(
+        schedule.previous_before(datetime(3, 5, 0)),
+        Some(datetime(2, 6, 0))
+    );
+
+    // Tuesday -> previous Friday (2025-02-28)
+    let schedule = time_of_day(4, true, "06:00", vec![Weekday::Fri]);
+    let friday = NaiveDate::from_ymd_opt(2025, 2, 28)
+        .unwrap()
+        .and_hms_opt(6, 0, 0)
+        .unwrap();
+    assert_eq!(schedule.previous_before(datetime(4, 5, 0)), Some(friday));
+}
+
+#[test]
+fn test_interval_schedule_has_no_time_of_day() {
+    let schedule = ScheduleConfig {
+        output: 1,
+        value: true,
+        at: None,
+        days: vec![],
+        every: Some(Duration::from_secs(60)),
+    };
+    assert_eq!(schedule.next_after(datetime(3, 5, 0)), None);
+    assert_eq!(schedule.previous_before(datetime(3, 5, 0)), None);
+}
+
+#[test]
+fn test_restore_outputs_applies_latest_per_output() {
+    let schedules = vec![
+        time_of_day(4, true, "06:00", vec![]),
+        time_of_day(4, false, "20:00", vec![]),
+        time_of_day(5, true, "21:00", vec![]),
+    ];
+    let (tx, mut rx) = unbounded_channel();
+
+    restore_outputs(&schedules, &tx, datetime(3, 12, 0)).unwrap();
+
+    let mut applied = Vec::new();
+    while let Ok(KBusCommand::Output(write)) = rx.try_recv() {
+        applied.push((write.event.channel, write.event.value));
+    }
+    // Output 4 on since 06:00, output 5 on since yesterday 21:00
+    assert_eq!(applied, vec![(4, true), (5, true)]);
+}
@@ -0,0 +1,92 @@
+//! Self-update of the bridge binary
+//!
+//! On `bridge/update`, the binary is downloaded from the configured URL together
+//! with its Ed25519 signature (base64 encoded, from `<url>.sig`) and verified
+//! against the configured public key. The verified binary replaces the running
+//! one, which is kept as `<binary>.previous`, and the bridge shuts down to be
+//! restarted with the new binary by its service manager (e.g. systemd with
+//! `Restart=always`).
+
+use std::{env, fs, io::Read, os::unix::fs::PermissionsExt, path::Path, time::Duration};
+
+use anyhow::{Context, anyhow};
+use base64::prelude::*;
+use ring::signature::{ED25519, UnparsedPublicKey};
+use tracing::info;
+
+use crate::{config::SelfUpdateConfig, utils::path_with_suffix};
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum size of the downloaded binary.
+const MAX_BINARY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Maximum size of the downloaded signature.
+const MAX_SIGNATURE_SIZE: u64 = 1024;
+
+/// Timeout of each download.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+fn download(url: &str, limit: u64) -> Result<Vec<u8>, anyhow::Error> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .with_context(|| format!("failed to download {url}"))?;
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(limit + 1)
+        .read_to_end(&mut data)
+        .with_context(|| format!("failed to download {url}"))?;
+    if data.len() as u64 > limit {
+        return Err(anyhow!("{url} exceeds the maximum size of {limit} bytes"));
+    }
+    Ok(data)
+}
+
+/// Verifies the base64 encoded Ed25519 `signature` of `binary`.
+pub fn verify(binary: &[u8], signature: &[u8], public_key: &str) -> Result<(), anyhow::Error> {
+    let public_key = BASE64_STANDARD
+        .decode(public_key)
+        .context("invalid public key")?;
+    let signature = BASE64_STANDARD
+        .decode(signature.trim_ascii())
+        .context("invalid signature encoding")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(binary, &signature)
+        .map_err(|_| anyhow!("signature verification failed"))
+}
+
+/// Replaces the executable at `exe` with `binary`, keeping it as `<exe>.previous`.
+///
+/// The new binary is written next to the executable and renamed over it, so the
+/// executable is never missing or truncated.
+pub fn install(exe: &Path, binary: &[u8]) -> Result<(), anyhow::Error> {
+    let previous = path_with_suffix(exe, ".previous");
+    fs::copy(exe, &previous).with_context(|| format!("failed to back up {}", exe.display()))?;
+
+    let new = path_with_suffix(exe, ".new");
+    fs::write(&new, binary).with_context(|| format!("failed to write {}", new.display()))?;
+    fs::set_permissions(&new, fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("failed to make {} executable", new.display()))?;
+    fs::rename(&new, exe).with_context(|| format!("failed to replace {}", exe.display()))
+}
+
+/// Downloads, verifies and installs the binary from the configured source.
+pub async fn update(config: SelfUpdateConfig) -> Result<(), anyhow::Error> {
+    let exe = env::current_exe().context("failed to locate the running binary")?;
+    tokio::task::spawn_blocking(move || {
+        let binary = download(&config.url, MAX_BINARY_SIZE)?;
+        let signature = download(&format!("{}.sig", config.url), MAX_SIGNATURE_SIZE)?;
+        verify(&binary, &signature, &config.public_key)?;
+        install(&exe, &binary)?;
+        info!(size = binary.len(), "installed {}", exe.display());
+        Ok(())
+    })
+    .await
+    .context("self-update task failed")?
+}
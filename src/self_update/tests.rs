@@ -0,0 +1,42 @@
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use tempfile::tempdir;
+
+use super::*;
+
+#[test]
+fn test_verify() {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let public_key = BASE64_STANDARD.encode(key_pair.public_key());
+
+    let binary = b"\x7fELF binary";
+    let signature = BASE64_STANDARD.encode(key_pair.sign(binary));
+    // Trailing newline of the signature file is ignored
+    let signature = format!("{signature}\n");
+    verify(binary, signature.as_bytes(), &public_key).unwrap();
+
+    assert!(verify(b"\x7fELF modified", signature.as_bytes(), &public_key).is_err());
+    assert!(verify(binary, b"not base64!", &public_key).is_err());
+}
+
+#[test]
+fn test_install() {
+    let dir = tempdir().unwrap();
+    let exe = dir.path().join("kbus_mqtt_bridge");
+    fs::write(&exe, b"old").unwrap();
+
+    install(&exe, b"new").unwrap();
+    assert_eq!(fs::read(&exe).unwrap(), b"new");
+    assert_eq!(
+        fs::read(dir.path().join("kbus_mqtt_bridge.previous")).unwrap(),
+        b"old"
+    );
+    assert_eq!(
+        fs::metadata(&exe).unwrap().permissions().mode() & 0o777,
+        0o755
+    );
+    assert!(!dir.path().join("kbus_mqtt_bridge.new").exists());
+}
@@ -0,0 +1,71 @@
+//! Reasons of the bridge shutdown
+//!
+//! The task initiating the shutdown records its reason with [`initiate`] before
+//! it cancels the other tasks, so the final `offline` status tells why the bridge
+//! went offline and the process exits with a code the service manager can act on.
+
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+static REASON: OnceLock<ShutdownReason> = OnceLock::new();
+
+/// Why the bridge shut down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownReason {
+    /// SIGTERM or Ctrl+C
+    Signal,
+    /// A configuration update or self-update was installed, the bridge must be
+    /// restarted by its service manager to apply it
+    Restart,
+    /// The configuration couldn't be loaded or is invalid
+    Config,
+    /// The K-Bus couldn't be opened
+    #[serde(rename = "kbus_init")]
+    KBusInit,
+    /// The K-Bus task failed
+    #[serde(rename = "kbus")]
+    KBus,
+    /// The MQTT task failed, e.g. the broker connection was lost
+    Mqtt,
+    /// Any other task failed
+    Task,
+    /// A second signal was received while shutting down, the process exited
+    /// without waiting for the tasks
+    Forced,
+}
+
+impl ShutdownReason {
+    /// Exit code of the process, 0 only for a shutdown by signal.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ShutdownReason::Signal => 0,
+            ShutdownReason::Task => 1,
+            ShutdownReason::Config => 2,
+            ShutdownReason::KBusInit => 3,
+            ShutdownReason::KBus => 4,
+            ShutdownReason::Mqtt => 5,
+            ShutdownReason::Restart => 6,
+            ShutdownReason::Forced => 7,
+        }
+    }
+
+    /// Whether the bridge shut down because of an error.
+    pub fn is_failure(self) -> bool {
+        !matches!(self, ShutdownReason::Signal | ShutdownReason::Restart)
+    }
+}
+
+/// Records the reason of the shutdown, only the first call has an effect.
+pub fn initiate(reason: ShutdownReason) {
+    let _ = REASON.set(reason);
+}
+
+/// Returns the recorded reason of the shutdown, if it was initiated.
+pub fn reason() -> Option<ShutdownReason> {
+    REASON.get().copied()
+}
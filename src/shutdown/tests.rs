@@ -0,0 +1,30 @@
+use std::collections::HashSet;
+
+use super::*;
+
+#[test]
+fn test_exit_codes() {
+    let reasons = [
+        ShutdownReason::Signal,
+        ShutdownReason::Restart,
+        ShutdownReason::Config,
+        ShutdownReason::KBusInit,
+        ShutdownReason::KBus,
+        ShutdownReason::Mqtt,
+        ShutdownReason::Task,
+        ShutdownReason::Forced,
+    ];
+    let codes: HashSet<_> = reasons.iter().map(|reason| reason.exit_code()).collect();
+    assert_eq!(codes.len(), reasons.len());
+    for reason in reasons {
+        assert_eq!(reason.exit_code() == 0, reason == ShutdownReason::Signal);
+        assert_eq!(
+            reason.is_failure(),
+            !matches!(reason, ShutdownReason::Signal | ShutdownReason::Restart)
+        );
+    }
+    assert_eq!(
+        serde_json::to_value(ShutdownReason::KBusInit).unwrap(),
+        "kbus_init"
+    );
+}
@@ -0,0 +1,223 @@
+//! Persistent state surviving restarts
+//!
+//! The MQTT message counters are kept in a small JSON state file, so the statistics
+//! published on the heartbeat keep growing monotonically across restarts instead of
+//! confusing dashboards. The file is flushed periodically and on shutdown; counts of
+//! the last flush interval are lost if the bridge crashes.
+//!
+//! The file also records the topic prefix and Tasmota topics of the last run, so
+//! retained topics left under a previous prefix can be cleared after `device_name`
+//! changed.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    config::SupervisorConfig,
+    mqtt::MqttStats,
+    shutdown::{self, ShutdownReason},
+    supervisor,
+};
+
+#[cfg(test)]
+mod tests;
+
+static RESTART_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of restarts recorded in the state file (0 without persistence).
+pub fn restart_count() -> u64 {
+    RESTART_COUNT.load(Ordering::Relaxed)
+}
+
+/// Topic prefix the bridge published under, restored from the state file until set
+static TOPIC_PREFIX: Mutex<Option<String>> = Mutex::new(None);
+
+/// Filters of the Tasmota topics the bridge published, restored from the state file until set
+static TASMOTA_FILTERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Returns the topic prefix recorded in the state file, if it differs from `current`.
+pub fn previous_topic_prefix(current: &str) -> Option<String> {
+    TOPIC_PREFIX
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|prefix| prefix != current)
+}
+
+/// Returns the Tasmota topic filters recorded in the state file which aren't in `current`.
+pub fn previous_tasmota_filters(current: &[String]) -> Vec<String> {
+    TASMOTA_FILTERS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|filter| !current.contains(filter))
+        .cloned()
+        .collect()
+}
+
+/// Sets the topic prefix and Tasmota topic filters written to the state file.
+pub fn set_topic_prefix(prefix: &str, tasmota_filters: &[String]) {
+    *TOPIC_PREFIX.lock().unwrap() = Some(prefix.to_owned());
+    *TASMOTA_FILTERS.lock().unwrap() = tasmota_filters.to_vec();
+}
+
+/// Contents of the state file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// Number of times the bridge was started with this state file, excluding the first start
+    pub restart_count: u64,
+    /// MQTT message counters
+    pub mqtt_stats: MqttStats,
+    /// Topic prefix of the last run
+    pub topic_prefix: Option<String>,
+    /// Filters of the Tasmota topics of the last run, outside the topic prefix
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasmota_filters: Vec<String>,
+}
+
+impl State {
+    /// Returns the current state of the running bridge.
+    pub fn current() -> State {
+        State {
+            restart_count: restart_count(),
+            mqtt_stats: MqttStats::current(),
+            topic_prefix: TOPIC_PREFIX.lock().unwrap().clone(),
+            tasmota_filters: TASMOTA_FILTERS.lock().unwrap().clone(),
+        }
+    }
+
+    /// Loads the state file, a missing file yields the initial state.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<State>, anyhow::Error> {
+        let path = path.as_ref();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read state file: {}", path.display()));
+            }
+        };
+
+        let state = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse state file: {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Writes the state file.
+    ///
+    /// The state is written to a temporary file first and renamed afterwards, so a
+    /// power loss during the write doesn't leave a truncated file behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let contents = serde_json::to_string_pretty(self).context("Failed to serialize state")?;
+        fs::write(&tmp_path, contents)
+            .with_context(|| format!("Failed to write state file: {}", path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace state file: {}", path.display()))
+    }
+}
+
+/// Restores the counters from the state file and records the restart.
+///
+/// An unreadable or corrupted state file is logged and replaced, so it can't
+/// prevent the bridge from starting.
+pub fn restore<P: AsRef<Path>>(path: P) -> Result<(), anyhow::Error> {
+    let path = path.as_ref();
+    let state = match State::load(path) {
+        Ok(Some(state)) => {
+            let restart_count = state.restart_count + 1;
+            info!(restart_count, "restored state from {}", path.display());
+            State {
+                restart_count,
+                ..state
+            }
+        }
+        Ok(None) => State::default(),
+        Err(err) => {
+            warn!("{err:#}, starting with initial state");
+            State::default()
+        }
+    };
+
+    RESTART_COUNT.store(state.restart_count, Ordering::Relaxed);
+    state.mqtt_stats.restore();
+    *TOPIC_PREFIX.lock().unwrap() = state.topic_prefix.clone();
+    TASMOTA_FILTERS
+        .lock()
+        .unwrap()
+        .clone_from(&state.tasmota_filters);
+    state.save(path)
+}
+
+async fn state_loop(
+    path: PathBuf,
+    flush_interval: Duration,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    if flush_interval.is_zero() {
+        info!("Periodic state flush disabled (interval=0)");
+        cancellation_token.cancelled().await;
+        return Ok(());
+    }
+
+    let mut timer = interval(flush_interval);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately, the state was just written on restore
+    timer.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => State::current().save(&path)?,
+            _ = cancellation_token.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Entry point task function for periodically flushing the state file.
+///
+/// The final flush on shutdown is left to the caller, after all other tasks
+/// finished updating the counters.
+///
+/// # Arguments
+///
+/// * `path` - Path of the state file
+/// * `flush_interval` - Interval between flushes (0 to only flush on shutdown)
+/// * `supervisor_config` - Restarting of the task after a failure
+/// * `cancellation_token` - Token to signal when this task should terminate
+#[instrument(name = "state", skip_all, err)]
+pub async fn state_task(
+    path: PathBuf,
+    flush_interval: Duration,
+    supervisor_config: SupervisorConfig,
+    cancellation_token: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let result = supervisor::supervise("state", &supervisor_config, &cancellation_token, || {
+        state_loop(path.clone(), flush_interval, cancellation_token.clone())
+    })
+    .await;
+
+    if result.is_err() {
+        shutdown::initiate(ShutdownReason::Task);
+    }
+    cancellation_token.cancel();
+
+    result
+}
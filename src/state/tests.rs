@@ -0,0 +1,80 @@
+use tempfile::tempdir;
+
+use super::*;
+
+#[test]
+fn test_save_and_load() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    assert_eq!(State::load(&path).unwrap(), None);
+
+    let state = State {
+        restart_count: 3,
+        mqtt_stats: MqttStats {
+            sent: 100,
+            received: 20,
+            rejected: 1,
+            ..MqttStats::default()
+        },
+        topic_prefix: Some("pfc200/00:30:de:00:00:01".to_owned()),
+        tasmota_filters: vec!["tele/pfc200/#".to_owned()],
+    };
+    state.save(&path).unwrap();
+    assert_eq!(State::load(&path).unwrap(), Some(state));
+
+    // No temporary file is left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_load_missing_fields() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    // Fields added in newer versions default to zero
+    fs::write(&path, r#"{"restart_count": 2, "mqtt_stats": {"sent": 5}}"#).unwrap();
+    let state = State::load(&path).unwrap().unwrap();
+    assert_eq!(state.restart_count, 2);
+    assert_eq!(state.mqtt_stats.sent, 5);
+    assert_eq!(state.mqtt_stats.received, 0);
+    assert_eq!(state.topic_prefix, None);
+}
+
+#[test]
+fn test_topic_prefix() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    fs::write(
+        &path,
+        r#"{"topic_prefix": "old/00:30:de:00:00:01", "tasmota_filters": ["tele/old/#", "stat/old/#"]}"#,
+    )
+    .unwrap();
+    restore(&path).unwrap();
+    assert_eq!(
+        previous_topic_prefix("new/00:30:de:00:00:01").as_deref(),
+        Some("old/00:30:de:00:00:01")
+    );
+    assert_eq!(previous_topic_prefix("old/00:30:de:00:00:01"), None);
+
+    let current = ["tele/new/#".to_owned(), "stat/old/#".to_owned()];
+    assert_eq!(previous_tasmota_filters(&current), ["tele/old/#"]);
+
+    set_topic_prefix("new/00:30:de:00:00:01", &[]);
+    assert_eq!(previous_topic_prefix("new/00:30:de:00:00:01"), None);
+    assert!(previous_tasmota_filters(&[]).is_empty());
+    State::current().save(&path).unwrap();
+    let state = State::load(&path).unwrap().unwrap();
+    assert_eq!(state.topic_prefix.as_deref(), Some("new/00:30:de:00:00:01"));
+    assert!(state.tasmota_filters.is_empty());
+}
+
+#[test]
+fn test_load_corrupted() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    fs::write(&path, "{\"restart_count\": ").unwrap();
+    assert!(State::load(&path).is_err());
+}
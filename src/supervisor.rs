@@ -0,0 +1,132 @@
+//! Health of the bridge tasks
+//!
+//! Every task reports its state here, so the heartbeat tells which part of the
+//! bridge is running, restarting or failed, and with which error. The K-Bus, MQTT
+//! and Modbus tasks own their channels and connections, a failure of them shuts
+//! the bridge down as before. Auxiliary tasks (schedules, state file flushes and
+//! system metrics) are restarted after `supervisor.restart_delay` instead, up to
+//! `supervisor.max_restarts` times before their failure shuts the bridge down too.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::Serialize;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::config::SupervisorConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// Health of the tasks started so far, by task name
+static TASKS: Mutex<BTreeMap<&'static str, TaskHealth>> = Mutex::new(BTreeMap::new());
+
+/// State of a task as reported in the heartbeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Failed and waiting for the restart delay
+    Restarting,
+    /// Finished without an error, e.g. on shutdown
+    Stopped,
+    Failed,
+}
+
+/// Health of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskHealth {
+    pub state: TaskState,
+    /// Number of restarts after a failure
+    pub restarts: u32,
+    /// Error of the last failure, kept after a restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+fn update(task: &'static str, f: impl FnOnce(&mut TaskHealth)) {
+    let mut tasks = TASKS.lock().unwrap();
+    let health = tasks.entry(task).or_insert(TaskHealth {
+        state: TaskState::Running,
+        restarts: 0,
+        last_error: None,
+    });
+    f(health);
+}
+
+/// Records that `task` is running.
+pub fn started(task: &'static str) {
+    update(task, |health| health.state = TaskState::Running);
+}
+
+/// Records the result of `task`.
+pub fn finished(task: &'static str, result: &Result<(), anyhow::Error>) {
+    update(task, |health| match result {
+        Ok(()) => health.state = TaskState::Stopped,
+        Err(err) => {
+            health.state = TaskState::Failed;
+            health.last_error = Some(format!("{err:#}"));
+        }
+    });
+}
+
+/// Returns the health of all tasks started so far.
+pub fn health() -> BTreeMap<&'static str, TaskHealth> {
+    TASKS.lock().unwrap().clone()
+}
+
+/// Runs a restartable task, restarting it after a failure until it finished
+/// without an error, was cancelled or failed more than `config.max_restarts` times.
+///
+/// Returns the last error if the task wasn't restarted anymore.
+///
+/// # Arguments
+///
+/// * `task` - Name of the task in the heartbeat
+/// * `config` - Restart settings
+/// * `cancellation_token` - Token cancelling the restart delay, also passed to the task
+/// * `run` - Starts a new run of the task
+pub async fn supervise<F, Fut>(
+    task: &'static str,
+    config: &SupervisorConfig,
+    cancellation_token: &CancellationToken,
+    mut run: F,
+) -> Result<(), anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), anyhow::Error>>,
+{
+    let mut restarts = 0;
+    loop {
+        started(task);
+        let result = run().await;
+        finished(task, &result);
+        let Err(err) = result else {
+            return Ok(());
+        };
+        if restarts >= config.max_restarts || cancellation_token.is_cancelled() {
+            return Err(err);
+        }
+
+        restarts += 1;
+        warn!(
+            task,
+            restarts,
+            delay = ?config.restart_delay,
+            error = format!("{err:#}"),
+            "task failed, restarting"
+        );
+        update(task, |health| {
+            health.state = TaskState::Restarting;
+            health.restarts = restarts;
+        });
+        tokio::select! {
+            _ = sleep(config.restart_delay) => {}
+            _ = cancellation_token.cancelled() => {
+                update(task, |health| health.state = TaskState::Stopped);
+                return Ok(());
+            }
+        }
+    }
+}
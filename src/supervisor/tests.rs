@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde_json::json;
+use tokio::time::Instant;
+
+use super::*;
+
+fn config() -> SupervisorConfig {
+    SupervisorConfig {
+        max_restarts: 2,
+        restart_delay: Duration::from_secs(1),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_supervise_restarts() {
+    let cancellation_token = CancellationToken::new();
+    let start = Instant::now();
+    let mut runs = 0;
+    let result = supervise("test_restarts", &config(), &cancellation_token, || {
+        runs += 1;
+        async move { Err(anyhow!("run {runs} failed")) }
+    })
+    .await;
+
+    assert_eq!(result.unwrap_err().to_string(), "run 3 failed");
+    assert_eq!(runs, 3);
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+    assert_eq!(
+        json!(health()["test_restarts"]),
+        json!({"state": "failed", "restarts": 2, "last_error": "run 3 failed"})
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_supervise_recovers() {
+    let cancellation_token = CancellationToken::new();
+    let mut runs = 0;
+    let result = supervise("test_recovers", &config(), &cancellation_token, || {
+        runs += 1;
+        async move {
+            if runs == 1 {
+                Err(anyhow!("first run failed"))
+            } else {
+                Ok(())
+            }
+        }
+    })
+    .await;
+
+    result.unwrap();
+    let health = &health()["test_recovers"];
+    assert_eq!(health.state, TaskState::Stopped);
+    assert_eq!(health.restarts, 1);
+    assert_eq!(health.last_error.as_deref(), Some("first run failed"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_supervise_cancelled() {
+    let cancellation_token = CancellationToken::new();
+    let task_handle = tokio::spawn({
+        let cancellation_token = cancellation_token.clone();
+        async move {
+            supervise("test_cancelled", &config(), &cancellation_token, || async {
+                Err(anyhow!("failed"))
+            })
+            .await
+        }
+    });
+
+    // Cancelled during the restart delay
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(health()["test_cancelled"].state, TaskState::Restarting);
+    cancellation_token.cancel();
+    task_handle.await.unwrap().unwrap();
+    assert_eq!(health()["test_cancelled"].state, TaskState::Stopped);
+}
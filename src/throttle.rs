@@ -0,0 +1,102 @@
+//! Deduplication of repeated warnings
+//!
+//! Some warnings can repeat many times per second, e.g. while a publisher floods
+//! the bridge with invalid commands or a Modbus device keeps failing. Warnings
+//! logged with [`warn_throttled!`] are logged once per key and minute; the next
+//! warning after the minute reports the number of suppressed ones in the
+//! `repeated` field.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Interval in which repeated warnings are suppressed.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of tracked keys, warnings with further keys aren't throttled.
+const MAX_KEYS: usize = 1000;
+
+static WARNINGS: LazyLock<Mutex<Throttle>> = LazyLock::new(|| Mutex::new(Throttle::new(INTERVAL)));
+
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    suppressed: u64,
+}
+
+/// Suppression windows of the warnings by key.
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Duration,
+    windows: HashMap<String, Window>,
+}
+
+impl Throttle {
+    pub fn new(interval: Duration) -> Throttle {
+        Throttle {
+            interval,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Records a warning at `now`.
+    ///
+    /// Returns the number of warnings suppressed since the last logged one if the
+    /// warning is logged, `None` if it's suppressed.
+    pub fn check(&mut self, key: &str, now: Instant) -> Option<u64> {
+        if let Some(window) = self.windows.get_mut(key) {
+            if now.saturating_duration_since(window.start) < self.interval {
+                window.suppressed += 1;
+                return None;
+            }
+            let suppressed = window.suppressed;
+            *window = Window {
+                start: now,
+                suppressed: 0,
+            };
+            return Some(suppressed);
+        }
+
+        if self.windows.len() >= MAX_KEYS {
+            let interval = self.interval;
+            self.windows
+                .retain(|_, window| now.saturating_duration_since(window.start) < interval);
+            if self.windows.len() >= MAX_KEYS {
+                return Some(0);
+            }
+        }
+        self.windows.insert(
+            key.to_owned(),
+            Window {
+                start: now,
+                suppressed: 0,
+            },
+        );
+        Some(0)
+    }
+}
+
+/// Records a warning with the given key in the global throttle, see [`Throttle::check`].
+pub fn check(key: &str) -> Option<u64> {
+    WARNINGS.lock().unwrap().check(key, Instant::now())
+}
+
+/// Logs a warning like `warn!`, but at most once per `key` and minute.
+///
+/// The first warning after suppressed ones has a `repeated` field with their number.
+macro_rules! warn_throttled {
+    ($key:expr, $($arg:tt)+) => {
+        match $crate::throttle::check($key) {
+            Some(0) => ::tracing::warn!($($arg)+),
+            Some(repeated) => ::tracing::warn!(repeated, $($arg)+),
+            None => {}
+        }
+    };
+}
+
+pub(crate) use warn_throttled;
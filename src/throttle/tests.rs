@@ -0,0 +1,39 @@
+use super::*;
+
+#[test]
+fn test_throttle() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut throttle = Throttle::new(Duration::from_secs(60));
+
+    assert_eq!(throttle.check("invalid_channel", at(0)), Some(0));
+    assert_eq!(throttle.check("invalid_channel", at(1)), None);
+    assert_eq!(throttle.check("invalid_channel", at(59)), None);
+    // Other keys are throttled independently
+    assert_eq!(throttle.check("outputs_disabled", at(30)), Some(0));
+
+    // The first warning after the interval reports the suppressed ones
+    assert_eq!(throttle.check("invalid_channel", at(60)), Some(2));
+    assert_eq!(throttle.check("invalid_channel", at(61)), None);
+    assert_eq!(throttle.check("invalid_channel", at(200)), Some(1));
+    assert_eq!(throttle.check("invalid_channel", at(300)), Some(0));
+}
+
+#[test]
+fn test_throttle_max_keys() {
+    let start = Instant::now();
+    let mut throttle = Throttle::new(Duration::from_secs(60));
+    for key in 0..MAX_KEYS {
+        assert_eq!(throttle.check(&key.to_string(), start), Some(0));
+    }
+
+    // Warnings with further keys are logged, but not tracked
+    assert_eq!(throttle.check("new", start), Some(0));
+    assert_eq!(throttle.check("new", start), Some(0));
+    assert_eq!(throttle.check("0", start), None);
+
+    // Expired windows make room for new keys
+    let later = start + Duration::from_secs(60);
+    assert_eq!(throttle.check("new", later), Some(0));
+    assert_eq!(throttle.check("new", later), None);
+}
@@ -0,0 +1,49 @@
+//! Timestamps of published payloads
+//!
+//! Historians differ in the timestamp format they expect, so the format is
+//! configured once on startup with [`init`] and applies to every published
+//! payload (heartbeat, events and diagnostics). RFC 3339 in UTC is used until then.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Local, Utc};
+use serde_json::{Value, json};
+
+use crate::config::TimestampFormat;
+
+#[cfg(test)]
+mod tests;
+
+static FORMAT: OnceLock<TimestampFormat> = OnceLock::new();
+
+/// Sets the format of published timestamps, only the first call has an effect.
+pub fn init(format: TimestampFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Formats `time` as a JSON string (RFC 3339) or number (epoch milliseconds).
+pub fn format(format: TimestampFormat, time: DateTime<Utc>) -> Value {
+    match format {
+        TimestampFormat::Rfc3339Utc => json!(time.to_rfc3339()),
+        TimestampFormat::Rfc3339Local => json!(time.with_timezone(&Local).to_rfc3339()),
+        TimestampFormat::EpochMillis => json!(time.timestamp_millis()),
+    }
+}
+
+/// Returns the current time in the configured format.
+pub fn now() -> Value {
+    at(Utc::now())
+}
+
+/// Returns `time` in the configured format, e.g. of a recorded event.
+pub fn at(time: DateTime<Utc>) -> Value {
+    format(FORMAT.get().copied().unwrap_or_default(), time)
+}
+
+/// Returns the current time in the configured format as a plain text payload.
+pub fn now_text() -> String {
+    match now() {
+        Value::String(text) => text,
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::TimeZone;
+
+use super::*;
+
+#[test]
+fn test_format() {
+    let time = Utc.with_ymd_and_hms(2025, 3, 3, 6, 0, 0).unwrap();
+    assert_eq!(
+        format(TimestampFormat::Rfc3339Utc, time),
+        json!("2025-03-03T06:00:00+00:00")
+    );
+    assert_eq!(
+        format(TimestampFormat::EpochMillis, time),
+        json!(1_740_981_600_000_i64)
+    );
+
+    let local = format(TimestampFormat::Rfc3339Local, time);
+    let parsed = DateTime::parse_from_rfc3339(local.as_str().unwrap()).unwrap();
+    assert_eq!(parsed, time);
+}
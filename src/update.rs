@@ -0,0 +1,83 @@
+//! Remote configuration updates
+//!
+//! A new configuration received on `bridge/config/set` is validated and written to
+//! the configuration file, keeping the previous one next to it (`<file>.previous`).
+//! There's no hot reload: the bridge shuts down and is started with the new
+//! configuration by its service manager. The update is on trial until the bridge
+//! ran for the grace period, if it fails earlier (including an unloadable file),
+//! the previous configuration is restored for the next start.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::from_utf8,
+};
+
+use anyhow::Context;
+use tracing::{info, warn};
+
+use crate::{config::Config, utils::path_with_suffix};
+
+#[cfg(test)]
+mod tests;
+
+/// Parses and validates a configuration update, TOML or JSON (starting with `{`).
+///
+/// The `profile` of the running bridge is applied to TOML updates with profiles,
+/// the effective configuration is written to the file.
+pub fn parse(payload: &[u8], profile: Option<&str>) -> Result<Config, anyhow::Error> {
+    let text = from_utf8(payload).context("configuration is not valid UTF-8")?;
+    let config: Config = if text.trim_start().starts_with('{') {
+        serde_json::from_str(text).context("invalid JSON configuration")?
+    } else {
+        Config::from_toml_str(text, profile).context("invalid TOML configuration")?
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+/// Returns the path of the configuration restored if an update fails.
+fn previous_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".previous")
+}
+
+/// Writes the updated configuration to `path`, putting the update on trial.
+///
+/// The current file is kept for a rollback, unless a previous update is still on
+/// trial: then the configuration before it is the last one known to work.
+pub fn stage(path: &Path, config: &Config) -> Result<(), anyhow::Error> {
+    let previous = previous_path(path);
+    if !previous.exists() {
+        fs::copy(path, &previous)
+            .with_context(|| format!("Failed to back up config file: {}", path.display()))?;
+    }
+
+    let contents = toml::to_string(config).context("Failed to serialize configuration")?;
+    let tmp_path = path_with_suffix(path, ".tmp");
+    fs::write(&tmp_path, contents)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace config file: {}", path.display()))
+}
+
+/// Returns whether the configuration at `path` is an update on trial.
+pub fn is_on_trial(path: &Path) -> bool {
+    previous_path(path).exists()
+}
+
+/// Keeps the updated configuration after it ran for the grace period.
+pub fn confirm(path: &Path) -> Result<(), anyhow::Error> {
+    let previous = previous_path(path);
+    fs::remove_file(&previous)
+        .with_context(|| format!("Failed to remove config backup: {}", previous.display()))?;
+    info!("configuration update confirmed: {}", path.display());
+    Ok(())
+}
+
+/// Restores the previous configuration after the update failed.
+pub fn rollback(path: &Path) -> Result<(), anyhow::Error> {
+    fs::rename(previous_path(path), path)
+        .with_context(|| format!("Failed to restore config file: {}", path.display()))?;
+    warn!("configuration update rolled back: {}", path.display());
+    Ok(())
+}
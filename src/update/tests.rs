@@ -0,0 +1,71 @@
+use tempfile::tempdir;
+
+use super::*;
+
+const CONFIG: &str = r#"
+    device_name = "pfc200"
+
+    [mqtt]
+    broker_host = "mqtt.example.com"
+    command_max_age = "30s"
+    "#;
+
+#[test]
+fn test_parse() {
+    let config = parse(CONFIG.as_bytes(), None).unwrap();
+    assert_eq!(config.device_name, "pfc200");
+
+    // The published effective configuration is accepted back
+    let json = serde_json::to_string(&config.redacted()).unwrap();
+    let config = parse(json.as_bytes(), None).unwrap();
+    assert_eq!(config.mqtt.broker_host, "mqtt.example.com");
+
+    assert!(parse(b"[mqtt", None).is_err());
+    assert!(parse(b"{\"mqtt\": {}}", None).is_err());
+    // The profile of the running bridge is applied
+    let profiles = format!("{CONFIG}\n[profile.lab.mqtt]\nbroker_host = \"lab.example.com\"");
+    let config = parse(profiles.as_bytes(), Some("lab")).unwrap();
+    assert_eq!(config.mqtt.broker_host, "lab.example.com");
+
+    // Valid syntax, but fails validation
+    assert!(parse(b"[mqtt]\nbroker_host = \"\"", None).is_err());
+}
+
+#[test]
+fn test_stage_confirm() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, CONFIG).unwrap();
+    assert!(!is_on_trial(&path));
+
+    let mut config = parse(CONFIG.as_bytes(), None).unwrap();
+    config.device_name = "updated".to_owned();
+    stage(&path, &config).unwrap();
+    assert!(is_on_trial(&path));
+
+    let staged = Config::from_toml(&path, None).unwrap();
+    assert_eq!(staged.device_name, "updated");
+    assert_eq!(staged.mqtt.command_max_age, config.mqtt.command_max_age);
+
+    confirm(&path).unwrap();
+    assert!(!is_on_trial(&path));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_stage_rollback() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, CONFIG).unwrap();
+
+    let mut config = parse(CONFIG.as_bytes(), None).unwrap();
+    config.device_name = "first".to_owned();
+    stage(&path, &config).unwrap();
+    // A second update on trial keeps the last configuration known to work
+    config.device_name = "second".to_owned();
+    stage(&path, &config).unwrap();
+
+    rollback(&path).unwrap();
+    assert!(!is_on_trial(&path));
+    assert_eq!(fs::read_to_string(&path).unwrap(), CONFIG);
+}
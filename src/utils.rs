@@ -2,7 +2,15 @@
 ///
 /// This module provides utilities for system configuration and constants
 /// used throughout the application, particularly for scheduler settings.
-use std::io;
+use std::{
+    ffi::OsString,
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(test)]
+mod tests;
 
 /// Scheduling policies available for process scheduling.
 ///
@@ -74,3 +82,32 @@ pub fn configure_scheduler(policy: SchedPolicy, priority: i32) -> Result<(), io:
         Ok(())
     }
 }
+
+/// Returns `path` with `suffix` appended to the file name, e.g. `config.toml.previous`.
+pub fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Number of bytes per line produced by [`hex_dump`].
+const HEX_DUMP_LINE_WIDTH: usize = 16;
+
+/// Formats a byte slice as a list of hex dump lines.
+///
+/// Each line starts with the byte offset of its first byte followed by up to
+/// 16 space-separated bytes, e.g. `"0010: 01 ff 00"`.
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_DUMP_LINE_WIDTH)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let mut out = format!("{:04x}:", line * HEX_DUMP_LINE_WIDTH);
+            for byte in chunk {
+                // Writing to a String never fails
+                let _ = write!(out, " {byte:02x}");
+            }
+            out
+        })
+        .collect()
+}
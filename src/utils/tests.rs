@@ -0,0 +1,19 @@
+use super::*;
+
+#[test]
+fn test_hex_dump_empty() {
+    assert!(hex_dump(&[]).is_empty());
+}
+
+#[test]
+fn test_hex_dump_lines() {
+    let bytes: Vec<u8> = (0..20).collect();
+    let lines = hex_dump(&bytes);
+    assert_eq!(
+        lines,
+        vec![
+            "0000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f",
+            "0010: 10 11 12 13",
+        ]
+    );
+}